@@ -0,0 +1,5 @@
+INSERT INTO alert_subscriptions (subscription_id, user_id, camera_id, kind, email, created_at) VALUES
+    (1, 1, NULL, 'motion', 'admin@example.com', '2024-10-21 03:00:00'),
+    (2, 1, 2, 'camera_offline', 'admin@example.com', '2024-10-21 03:01:00'),
+    (3, 3, NULL, 'motion', 'joedaly@example.com', '2024-10-21 03:02:00'),
+    (4, 3, 2, 'camera_offline', 'joedaly@example.com', '2024-10-21 03:03:00');
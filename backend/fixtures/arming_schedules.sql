@@ -0,0 +1,4 @@
+INSERT INTO arming_schedules (schedule_id, camera_id, mode, day_of_week, start_minute, end_minute, created_at) VALUES
+    (1, 2, 'night', 0, 0, 420, '2024-10-21 03:10:00'),
+    (2, 2, 'night', 0, 1320, 1440, '2024-10-21 03:11:00'),
+    (3, 2, 'away', 5, 480, 1080, '2024-10-21 03:12:00');
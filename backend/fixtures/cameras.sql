@@ -1,3 +1,4 @@
-INSERT INTO cameras (camera_id, name, ip_address, last_connected, is_active) VALUES
-    (1, 'Front Door', '127.0.0.1:40000', '2024-10-20 17:56:18', true),
-    (2, 'Kitchen', '127.0.0.1:40001', '2024-10-20 17:57:22', true);
+-- Pairing tokens are 'front_door_pairing_token' and 'kitchen_pairing_token'
+INSERT INTO cameras (camera_id, name, ip_address, last_connected, is_active, token_hash) VALUES
+    (1, 'Front Door', '127.0.0.1:40000', '2024-10-20 17:56:18', true, 'aebdf3e0f0a98da078d1da3b15b4a95bd29fce6c9969c76446d093cd053d25c3'),
+    (2, 'Kitchen', '127.0.0.1:40001', '2024-10-20 17:57:22', true, 'bfe84b419e1bde00146cd561caa7cfe4f54e6a418991fa644326a2fd78bc3948');
@@ -0,0 +1,6 @@
+INSERT INTO events (event_id, camera_id, kind, start_time, end_time, video_id, snapshot_path, details, acknowledged) VALUES
+    (1, 1, 'motion', '2024-10-21 02:58:32', '2024-10-21 03:01:12', 1, '/home/piotrpdev/oko/backend/videos/2024-10-21_02-58-32_000000000Z_motion.jpg', NULL, true),
+    (2, 2, 'motion', '2024-10-21 02:57:56', '2024-10-21 03:03:23', 2, NULL, NULL, false),
+    (3, 2, 'camera_offline', '2024-10-21 03:05:00', NULL, NULL, NULL, NULL, false),
+    (4, NULL, 'login_failed', '2024-10-21 03:06:00', NULL, NULL, NULL, 'joedaly from 127.0.0.1', false),
+    (5, 1, 'camera_online', '2024-10-21 03:07:00', NULL, NULL, NULL, NULL, false);
@@ -0,0 +1,4 @@
+INSERT INTO motion_zones (zone_id, camera_id, name, kind, points, sensitivity, created_at) VALUES
+    (1, 1, 'Tree', 'exclude', '[{"x":0.75,"y":0.0},{"x":1.0,"y":0.0},{"x":1.0,"y":0.5},{"x":0.75,"y":0.5}]', NULL, '2024-10-21 03:30:00'),
+    (2, 2, 'Back door', 'include', '[{"x":0.0,"y":0.0},{"x":0.5,"y":0.0},{"x":0.5,"y":1.0},{"x":0.0,"y":1.0}]', 80, '2024-10-21 03:31:00'),
+    (3, 2, 'Window', 'include', '[{"x":0.5,"y":0.0},{"x":1.0,"y":0.0},{"x":1.0,"y":0.5}]', NULL, '2024-10-21 03:32:00');
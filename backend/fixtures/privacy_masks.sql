@@ -0,0 +1,3 @@
+INSERT INTO privacy_masks (mask_id, camera_id, name, points, created_at) VALUES
+    (1, 1, 'Neighbours'' window', '[{"x":0.0,"y":0.0},{"x":0.25,"y":0.0},{"x":0.25,"y":0.25},{"x":0.0,"y":0.25}]', '2024-10-21 03:20:00'),
+    (2, 1, 'Street', '[{"x":0.5,"y":0.75},{"x":1.0,"y":0.75},{"x":1.0,"y":1.0}]', '2024-10-21 03:21:00');
@@ -0,0 +1,6 @@
+UPDATE videos SET poster_path = '/home/piotrpdev/oko/backend/videos/1_poster.jpg' WHERE video_id = 1;
+
+INSERT INTO video_thumbnails (thumbnail_id, video_id, file_path, offset_seconds) VALUES
+    (1, 1, '/home/piotrpdev/oko/backend/videos/1_thumbnail_0.jpg', 0.0),
+    (2, 1, '/home/piotrpdev/oko/backend/videos/1_thumbnail_1.jpg', 10.0),
+    (3, 2, '/home/piotrpdev/oko/backend/videos/2_thumbnail_0.jpg', 0.0);
@@ -1,3 +1,3 @@
-INSERT INTO videos (video_id, camera_id, file_path, start_time, end_time, file_size) VALUES
-    (1, 1, '/home/piotrpdev/oko/backend/videos/1.mp4', '2024-10-21 02:58:32', '2024-10-21 03:01:12', 6762403),
-    (2, 2, '/home/piotrpdev/oko/backend/videos/2.mp4', '2024-10-21 02:57:56', '2024-10-21 03:03:23', 6905856);
+INSERT INTO videos (video_id, camera_id, file_path, start_time, end_time, file_size, format) VALUES
+    (1, 1, '/home/piotrpdev/oko/backend/videos/1.mp4', '2024-10-21 02:58:32', '2024-10-21 03:01:12', 6762403, 'mp4'),
+    (2, 2, '/home/piotrpdev/oko/backend/videos/2.mp4', '2024-10-21 02:57:56', '2024-10-21 03:03:23', 6905856, 'mp4');
@@ -0,0 +1,7 @@
+INSERT INTO webhooks (webhook_id, url, secret, enabled, created_at) VALUES
+    (1, 'http://127.0.0.1:9000/oko', 'first_webhook_secret', true, '2024-10-21 02:50:00'),
+    (2, 'http://127.0.0.1:9001/oko', 'second_webhook_secret', false, '2024-10-21 02:51:00');
+
+INSERT INTO webhook_deliveries (delivery_id, webhook_id, kind, payload, attempts, delivered, status_code, error, created_at, last_attempt_at) VALUES
+    (1, 1, 'test', '{"kind":"test","timestamp":1729479000}', 1, true, 200, NULL, '2024-10-21 02:50:00', '2024-10-21 02:50:00'),
+    (2, 1, 'camera_offline', '{"kind":"camera_offline","timestamp":1729479300}', 2, false, 500, 'Received 500 Internal Server Error', '2024-10-21 02:55:00', '2024-10-21 02:55:01');
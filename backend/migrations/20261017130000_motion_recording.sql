@@ -0,0 +1,4 @@
+ALTER TABLE camera_settings ADD COLUMN recording_mode TEXT NOT NULL DEFAULT 'continuous' CHECK(recording_mode IN ('continuous', 'motion'));
+ALTER TABLE camera_settings ADD COLUMN motion_sensitivity INTEGER NOT NULL DEFAULT 50 CHECK(motion_sensitivity >= 1 AND motion_sensitivity <= 100);
+ALTER TABLE camera_settings ADD COLUMN motion_pre_roll_seconds INTEGER NOT NULL DEFAULT 5 CHECK(motion_pre_roll_seconds >= 0 AND motion_pre_roll_seconds <= 60);
+ALTER TABLE camera_settings ADD COLUMN motion_post_roll_seconds INTEGER NOT NULL DEFAULT 10 CHECK(motion_post_roll_seconds >= 0 AND motion_post_roll_seconds <= 300);
@@ -0,0 +1 @@
+ALTER TABLE camera_settings ADD COLUMN segment_length_seconds INTEGER NOT NULL DEFAULT 300 CHECK(segment_length_seconds >= 10 AND segment_length_seconds <= 3600);
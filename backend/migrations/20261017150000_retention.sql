@@ -0,0 +1,17 @@
+ALTER TABLE videos ADD COLUMN keep_forever BOOLEAN NOT NULL DEFAULT FALSE;
+
+-- NULL means videos are kept until the disk quota is reached
+ALTER TABLE camera_settings ADD COLUMN max_video_age_days INTEGER CHECK(max_video_age_days IS NULL OR max_video_age_days >= 1);
+
+-- There is only ever one global storage policy
+CREATE TABLE IF NOT EXISTS storage_policy (
+    policy_id INTEGER NOT NULL PRIMARY KEY CHECK(policy_id = 1),
+    max_total_size_bytes INTEGER CHECK(max_total_size_bytes IS NULL OR max_total_size_bytes >= 0),
+    last_modified TIMESTAMP NOT NULL,
+    modified_by INTEGER,
+    FOREIGN KEY (modified_by) REFERENCES users(user_id) ON DELETE SET NULL
+);
+
+INSERT INTO storage_policy (policy_id, max_total_size_bytes, last_modified, modified_by) VALUES (1, NULL, CURRENT_TIMESTAMP, NULL);
+
+CREATE INDEX IF NOT EXISTS idx_videos_start_time ON videos (start_time);
@@ -0,0 +1,3 @@
+ALTER TABLE camera_settings ADD COLUMN recording_format TEXT NOT NULL DEFAULT 'mp4' CHECK(recording_format IN ('mp4', 'mjpeg', 'mpeg4'));
+-- Videos recorded before this were always MPEG-4 Part 2 in an AVI container
+ALTER TABLE videos ADD COLUMN format TEXT NOT NULL DEFAULT 'mpeg4' CHECK(format IN ('mp4', 'mjpeg', 'mpeg4'));
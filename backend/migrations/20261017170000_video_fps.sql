@@ -0,0 +1,2 @@
+-- Measured from the timestamps of the recorded frames, NULL for videos recorded before this or with less than two frames
+ALTER TABLE videos ADD COLUMN average_fps REAL;
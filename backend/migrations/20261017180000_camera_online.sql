@@ -0,0 +1,2 @@
+-- Set while the camera has a WebSocket connection open, see `last_connected` for when it was opened
+ALTER TABLE cameras ADD COLUMN is_online BOOLEAN NOT NULL DEFAULT FALSE;
@@ -0,0 +1,2 @@
+-- SHA-256 (hex) of the secret the camera sends in its handshake, cameras without one can't connect until re-added
+ALTER TABLE cameras ADD COLUMN token_hash TEXT;
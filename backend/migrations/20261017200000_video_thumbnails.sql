@@ -0,0 +1,13 @@
+-- Image shown before the video is played, NULL for videos recorded before this
+ALTER TABLE videos ADD COLUMN poster_path TEXT CHECK(poster_path IS NULL OR LENGTH(poster_path) <= 4096);
+
+-- Small images taken periodically during a video, for scrubbing
+CREATE TABLE IF NOT EXISTS video_thumbnails (
+    thumbnail_id INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+    video_id INTEGER NOT NULL,
+    file_path TEXT NOT NULL CHECK(LENGTH(file_path) <= 4096),
+    offset_seconds REAL NOT NULL CHECK(offset_seconds >= 0),
+    FOREIGN KEY (video_id) REFERENCES videos(video_id) ON DELETE CASCADE
+);
+
+CREATE INDEX IF NOT EXISTS idx_video_thumbnails_video ON video_thumbnails (video_id);
@@ -0,0 +1,17 @@
+-- Things that happened to a camera (or the server), pushed to viewers and kept for review
+CREATE TABLE IF NOT EXISTS events (
+    event_id INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+    -- NULL for events that aren't about a camera, e.g. failed logins
+    camera_id INTEGER,
+    kind TEXT NOT NULL CHECK(kind IN ('motion', 'camera_offline', 'camera_online', 'login_failed')),
+    start_time TIMESTAMP NOT NULL,
+    end_time TIMESTAMP,
+    video_id INTEGER,
+    snapshot_path TEXT CHECK(snapshot_path IS NULL OR LENGTH(snapshot_path) <= 4096),
+    details TEXT CHECK(details IS NULL OR LENGTH(details) <= 1024),
+    acknowledged BOOLEAN NOT NULL DEFAULT false,
+    FOREIGN KEY (camera_id) REFERENCES cameras(camera_id) ON DELETE CASCADE,
+    FOREIGN KEY (video_id) REFERENCES videos(video_id) ON DELETE SET NULL
+);
+
+CREATE INDEX IF NOT EXISTS idx_events_camera ON events (camera_id);
@@ -0,0 +1,27 @@
+-- URLs that are sent signed JSON POSTs when something happens, managed by admins
+CREATE TABLE IF NOT EXISTS webhooks (
+    webhook_id INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+    url TEXT NOT NULL CHECK(LENGTH(url) <= 2048),
+    -- Key for the HMAC-SHA256 signature, has to be stored as is to sign payloads
+    secret TEXT NOT NULL CHECK(LENGTH(secret) <= 256),
+    enabled BOOLEAN NOT NULL DEFAULT true,
+    created_at TIMESTAMP NOT NULL
+);
+
+-- Every payload sent (or being retried) to a webhook
+CREATE TABLE IF NOT EXISTS webhook_deliveries (
+    delivery_id INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+    webhook_id INTEGER NOT NULL,
+    kind TEXT NOT NULL CHECK(kind IN ('camera_offline', 'motion', 'recording_finished', 'test')),
+    payload TEXT NOT NULL,
+    attempts INTEGER NOT NULL DEFAULT 0 CHECK(attempts >= 0),
+    delivered BOOLEAN NOT NULL DEFAULT false,
+    -- Of the last attempt, NULL if no response was received
+    status_code INTEGER,
+    error TEXT,
+    created_at TIMESTAMP NOT NULL,
+    last_attempt_at TIMESTAMP,
+    FOREIGN KEY (webhook_id) REFERENCES webhooks(webhook_id) ON DELETE CASCADE
+);
+
+CREATE INDEX IF NOT EXISTS idx_webhook_deliveries_webhook ON webhook_deliveries (webhook_id);
@@ -0,0 +1,33 @@
+-- There is only ever one SMTP relay, no emails are sent while host is NULL
+CREATE TABLE IF NOT EXISTS smtp_settings (
+    smtp_id INTEGER NOT NULL PRIMARY KEY CHECK(smtp_id = 1),
+    host TEXT CHECK(host IS NULL OR LENGTH(host) <= 255),
+    port INTEGER NOT NULL DEFAULT 587 CHECK(port BETWEEN 1 AND 65535),
+    security TEXT NOT NULL DEFAULT 'starttls' CHECK(security IN ('none', 'starttls', 'tls')),
+    username TEXT CHECK(username IS NULL OR LENGTH(username) <= 255),
+    -- Has to be stored as is to log in to the relay
+    password TEXT CHECK(password IS NULL OR LENGTH(password) <= 255),
+    from_address TEXT CHECK(from_address IS NULL OR LENGTH(from_address) <= 254),
+    -- At most one alert is sent per camera in this many seconds
+    min_alert_interval_seconds INTEGER NOT NULL DEFAULT 300 CHECK(min_alert_interval_seconds >= 0),
+    last_modified TIMESTAMP NOT NULL,
+    modified_by INTEGER,
+    FOREIGN KEY (modified_by) REFERENCES users(user_id) ON DELETE SET NULL
+);
+
+INSERT INTO smtp_settings (smtp_id, host, port, security, username, password, from_address, min_alert_interval_seconds, last_modified, modified_by) VALUES (1, NULL, 587, 'starttls', NULL, NULL, NULL, 300, CURRENT_TIMESTAMP, NULL);
+
+-- Users are emailed when one of these events happens on a camera they can view
+CREATE TABLE IF NOT EXISTS alert_subscriptions (
+    subscription_id INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+    user_id INTEGER NOT NULL,
+    -- NULL means every camera the user can view
+    camera_id INTEGER,
+    kind TEXT NOT NULL CHECK(kind IN ('motion', 'camera_offline')),
+    email TEXT NOT NULL CHECK(LENGTH(email) <= 254),
+    created_at TIMESTAMP NOT NULL,
+    FOREIGN KEY (user_id) REFERENCES users(user_id) ON DELETE CASCADE,
+    FOREIGN KEY (camera_id) REFERENCES cameras(camera_id) ON DELETE CASCADE
+);
+
+CREATE INDEX IF NOT EXISTS idx_alert_subscriptions_user ON alert_subscriptions (user_id);
@@ -0,0 +1,28 @@
+-- There is only ever one system-wide arming mode, motion is ignored while disarmed
+CREATE TABLE IF NOT EXISTS arming_state (
+    arming_id INTEGER NOT NULL PRIMARY KEY CHECK(arming_id = 1),
+    mode TEXT NOT NULL DEFAULT 'away' CHECK(mode IN ('disarmed', 'home', 'away', 'night')),
+    last_modified TIMESTAMP NOT NULL,
+    modified_by INTEGER,
+    FOREIGN KEY (modified_by) REFERENCES users(user_id) ON DELETE SET NULL
+);
+
+-- Armed by default so motion keeps working like before
+INSERT INTO arming_state (arming_id, mode, last_modified, modified_by) VALUES (1, 'away', CURRENT_TIMESTAMP, NULL);
+
+-- Weekly windows (in UTC) during which a camera is armed in a mode,
+-- cameras without any are armed all the time in every mode except disarmed
+CREATE TABLE IF NOT EXISTS arming_schedules (
+    schedule_id INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+    camera_id INTEGER NOT NULL,
+    mode TEXT NOT NULL CHECK(mode IN ('home', 'away', 'night')),
+    -- 0 is Monday
+    day_of_week INTEGER NOT NULL CHECK(day_of_week BETWEEN 0 AND 6),
+    -- Minutes since midnight, the end is exclusive
+    start_minute INTEGER NOT NULL CHECK(start_minute BETWEEN 0 AND 1439),
+    end_minute INTEGER NOT NULL CHECK(end_minute BETWEEN 1 AND 1440 AND end_minute > start_minute),
+    created_at TIMESTAMP NOT NULL,
+    FOREIGN KEY (camera_id) REFERENCES cameras(camera_id) ON DELETE CASCADE
+);
+
+CREATE INDEX IF NOT EXISTS idx_arming_schedules_camera_id ON arming_schedules (camera_id);
@@ -0,0 +1,12 @@
+-- Regions blacked out of every frame before it is shown, recorded or used for motion detection
+CREATE TABLE IF NOT EXISTS privacy_masks (
+    mask_id INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+    camera_id INTEGER NOT NULL,
+    name TEXT NOT NULL CHECK(LENGTH(name) <= 64),
+    -- JSON array of {"x": ..., "y": ...} between 0 and 1, relative to the frame size
+    points TEXT NOT NULL CHECK(json_valid(points)),
+    created_at TIMESTAMP NOT NULL,
+    FOREIGN KEY (camera_id) REFERENCES cameras(camera_id) ON DELETE CASCADE
+);
+
+CREATE INDEX IF NOT EXISTS idx_privacy_masks_camera_id ON privacy_masks (camera_id);
@@ -0,0 +1,18 @@
+-- Parts of a camera's view where motion counts (include) or is ignored (exclude)
+CREATE TABLE IF NOT EXISTS motion_zones (
+    zone_id INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+    camera_id INTEGER NOT NULL,
+    name TEXT NOT NULL CHECK(LENGTH(name) <= 64),
+    kind TEXT NOT NULL CHECK(kind IN ('include', 'exclude')),
+    -- JSON array of {"x": ..., "y": ...} between 0 and 1, relative to the frame size
+    points TEXT NOT NULL CHECK(json_valid(points)),
+    -- Like camera_settings.motion_sensitivity but for the zone alone, NULL uses the camera's
+    sensitivity INTEGER CHECK(sensitivity IS NULL OR sensitivity BETWEEN 1 AND 100),
+    created_at TIMESTAMP NOT NULL,
+    FOREIGN KEY (camera_id) REFERENCES cameras(camera_id) ON DELETE CASCADE
+);
+
+CREATE INDEX IF NOT EXISTS idx_motion_zones_camera_id ON motion_zones (camera_id);
+
+-- The include zone that triggered a motion event
+ALTER TABLE events ADD COLUMN zone_id INTEGER REFERENCES motion_zones(zone_id) ON DELETE SET NULL;
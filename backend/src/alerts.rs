@@ -0,0 +1,293 @@
+use std::collections::HashMap;
+
+use lettre::{
+    message::{header::ContentType, Attachment, Mailbox, MultiPart, SinglePart},
+    transport::smtp::authentication::Credentials,
+    AsyncSmtpTransport, AsyncTransport, Message, Tokio1Executor,
+};
+use sqlx::SqlitePool;
+use time::{format_description::well_known::Rfc3339, Duration, OffsetDateTime};
+use tokio::sync::broadcast;
+use tokio_util::sync::CancellationToken;
+use tracing::{debug, error, warn};
+
+use crate::{
+    db::{AlertSubscription, Camera, Event, EventKind, Model, SmtpSecurity, SmtpSettings},
+    web::ApiChannelMessage,
+};
+
+const SMTP_TIMEOUT: tokio::time::Duration = tokio::time::Duration::from_secs(10);
+const SNAPSHOT_FILE_NAME: &str = "snapshot.jpg";
+
+/// Remembers when each camera was last alerted about, so a busy camera doesn't flood inboxes.
+#[derive(Debug, Default)]
+struct RateLimiter {
+    last_alerts: HashMap<i64, OffsetDateTime>,
+}
+
+impl RateLimiter {
+    /// Whether an alert about `camera_id` can be sent at `now`, if so it is counted as sent.
+    fn try_alert(&mut self, camera_id: i64, now: OffsetDateTime, min_interval: Duration) -> bool {
+        if self
+            .last_alerts
+            .get(&camera_id)
+            .is_some_and(|last_alert| now - *last_alert < min_interval)
+        {
+            return false;
+        }
+
+        self.last_alerts.insert(camera_id, now);
+
+        true
+    }
+}
+
+/// Whether `email` is a single address alerts can be sent to/from, e.g. `oko@example.com`
+pub fn is_valid_email(email: &str) -> bool {
+    email.parse::<Mailbox>().is_ok()
+}
+
+/// `None` if users can't subscribe to `kind`
+fn subject(camera_name: &str, kind: EventKind) -> Option<String> {
+    match kind {
+        EventKind::Motion => Some(format!("Motion detected by {camera_name}")),
+        EventKind::CameraOffline => Some(format!("{camera_name} went offline")),
+        EventKind::CameraOnline | EventKind::LoginFailed => None,
+    }
+}
+
+/// The alert email for `event`, with `snapshot` attached as a JPEG if there is one.
+fn build_message(
+    from: &Mailbox,
+    to: &str,
+    camera_name: &str,
+    event: &Event,
+    snapshot: Option<Vec<u8>>,
+) -> Result<Message, Box<dyn std::error::Error + Send + Sync>> {
+    let subject = subject(camera_name, event.kind).ok_or("No alert for this kind of event")?;
+
+    let text = SinglePart::plain(format!(
+        "{subject} at {} (UTC).\n\nSee event {} in Oko for more.\n",
+        event.start_time.format(&Rfc3339)?,
+        event.event_id
+    ));
+
+    let builder = Message::builder()
+        .from(from.clone())
+        .to(to.parse()?)
+        .subject(subject);
+
+    let message = match snapshot {
+        Some(snapshot) => builder.multipart(
+            MultiPart::mixed().singlepart(text).singlepart(
+                Attachment::new(SNAPSHOT_FILE_NAME.to_string())
+                    .body(snapshot, ContentType::parse("image/jpeg")?),
+            ),
+        )?,
+        None => builder.singlepart(text)?,
+    };
+
+    Ok(message)
+}
+
+fn transport(
+    settings: &SmtpSettings,
+    host: &str,
+) -> Result<AsyncSmtpTransport<Tokio1Executor>, Box<dyn std::error::Error + Send + Sync>> {
+    let builder = match settings.security {
+        SmtpSecurity::None => AsyncSmtpTransport::<Tokio1Executor>::builder_dangerous(host),
+        SmtpSecurity::Starttls => AsyncSmtpTransport::<Tokio1Executor>::starttls_relay(host)?,
+        SmtpSecurity::Tls => AsyncSmtpTransport::<Tokio1Executor>::relay(host)?,
+    }
+    .port(u16::try_from(settings.port)?)
+    .timeout(Some(SMTP_TIMEOUT));
+
+    let builder = match &settings.username {
+        Some(username) => builder.credentials(Credentials::new(
+            username.clone(),
+            settings.password.clone().unwrap_or_default(),
+        )),
+        None => builder,
+    };
+
+    Ok(builder.build())
+}
+
+/// Emails the users subscribed to motion and camera offline events until shutdown.
+///
+/// Nothing is sent while no SMTP relay is configured.
+pub async fn continuously_send(
+    db: SqlitePool,
+    mut api_channel_rx: broadcast::Receiver<ApiChannelMessage>,
+    shutdown_token: CancellationToken,
+) {
+    let mut rate_limiter = RateLimiter::default();
+
+    loop {
+        let api_msg = tokio::select! {
+            api_msg = api_channel_rx.recv() => match api_msg {
+                Ok(api_msg) => api_msg,
+                Err(broadcast::error::RecvError::Lagged(skipped)) => {
+                    warn!("Email alerts fell behind, skipped {skipped} messages");
+                    continue;
+                }
+                Err(broadcast::error::RecvError::Closed) => break,
+            },
+            () = shutdown_token.cancelled() => break,
+        };
+
+        let ApiChannelMessage::EventCreated(event) = api_msg else {
+            continue;
+        };
+
+        let Some(camera_id) = event.camera_id else {
+            continue;
+        };
+
+        if !AlertSubscription::is_supported(event.kind) {
+            continue;
+        }
+
+        if let Err(e) = alert(&db, &mut rate_limiter, camera_id, &event).await {
+            error!("Error sending alerts for event {}: {e:?}", event.event_id);
+        }
+    }
+}
+
+async fn alert(
+    db: &SqlitePool,
+    rate_limiter: &mut RateLimiter,
+    camera_id: i64,
+    event: &Event,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let settings = SmtpSettings::get(db).await?;
+
+    let (Some(host), Some(from_address)) = (&settings.host, &settings.from_address) else {
+        return Ok(());
+    };
+
+    let subscriptions = AlertSubscription::list_for_event(db, camera_id, event.kind).await?;
+
+    if subscriptions.is_empty() {
+        return Ok(());
+    }
+
+    if !rate_limiter.try_alert(
+        camera_id,
+        OffsetDateTime::now_utc(),
+        Duration::seconds(settings.min_alert_interval_seconds),
+    ) {
+        debug!("Not alerting about camera {camera_id} again so soon");
+        return Ok(());
+    }
+
+    let camera = Camera::get_using_id(db, camera_id).await?;
+
+    let snapshot = match &event.snapshot_path {
+        Some(snapshot_path) => match tokio::fs::read(snapshot_path).await {
+            Ok(snapshot) => Some(snapshot),
+            Err(e) => {
+                warn!("Error reading snapshot {snapshot_path}, sending alert without it: {e:?}");
+                None
+            }
+        },
+        None => None,
+    };
+
+    let from: Mailbox = from_address.parse()?;
+    let transport = transport(&settings, host)?;
+
+    let mut emails: Vec<&str> = subscriptions
+        .iter()
+        .map(|subscription| subscription.email.as_str())
+        .collect();
+    emails.sort_unstable();
+    emails.dedup();
+
+    // One email per address, so subscribers don't see each other's addresses
+    for email in emails {
+        let message = match build_message(&from, email, &camera.name, event, snapshot.clone()) {
+            Ok(message) => message,
+            Err(e) => {
+                error!("Error building alert for {email}: {e:?}");
+                continue;
+            }
+        };
+
+        match transport.send(message).await {
+            Ok(_) => debug!("Sent alert for event {} to {email}", event.event_id),
+            Err(e) => error!("Error sending alert to {email}: {e:?}"),
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rate_limit() {
+        let mut rate_limiter = RateLimiter::default();
+        let min_interval = Duration::minutes(5);
+        let start = OffsetDateTime::UNIX_EPOCH;
+
+        assert!(rate_limiter.try_alert(1, start, min_interval));
+        assert!(!rate_limiter.try_alert(1, start + Duration::minutes(1), min_interval));
+        // Other cameras aren't affected
+        assert!(rate_limiter.try_alert(2, start + Duration::minutes(1), min_interval));
+        assert!(rate_limiter.try_alert(1, start + min_interval, min_interval));
+
+        let mut unlimited = RateLimiter::default();
+        assert!(unlimited.try_alert(1, start, Duration::ZERO));
+        assert!(unlimited.try_alert(1, start, Duration::ZERO));
+    }
+
+    #[test]
+    fn valid_emails() {
+        assert!(is_valid_email("admin@example.com"));
+        assert!(is_valid_email("Oko <oko@example.com>"));
+        assert!(!is_valid_email("admin"));
+        assert!(!is_valid_email(""));
+        assert!(!is_valid_email("admin@example.com, guest@example.com"));
+    }
+
+    #[test]
+    fn message() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        let from: Mailbox = "Oko <oko@example.com>".parse()?;
+        let event = Event::new(Some(1), EventKind::Motion, OffsetDateTime::UNIX_EPOCH);
+
+        let message = build_message(
+            &from,
+            "admin@example.com",
+            "Front Door",
+            &event,
+            Some(vec![0xff, 0xd8, 0xff]),
+        )?;
+        let formatted = String::from_utf8_lossy(&message.formatted()).to_string();
+
+        assert!(formatted.contains("Subject: Motion detected by Front Door"));
+        assert!(formatted.contains("To: admin@example.com"));
+        assert!(formatted.contains("Content-Type: image/jpeg"));
+        assert!(formatted.contains(SNAPSHOT_FILE_NAME));
+
+        let offline_event = Event::new(Some(1), EventKind::CameraOffline, event.start_time);
+        let offline_message = build_message(
+            &from,
+            "admin@example.com",
+            "Front Door",
+            &offline_event,
+            None,
+        )?;
+        let offline_formatted = String::from_utf8_lossy(&offline_message.formatted()).to_string();
+
+        assert!(offline_formatted.contains("Subject: Front Door went offline"));
+        assert!(!offline_formatted.contains("image/jpeg"));
+
+        let login_failed = Event::new(None, EventKind::LoginFailed, event.start_time);
+        assert!(build_message(&from, "admin@example.com", "", &login_failed, None).is_err());
+
+        Ok(())
+    }
+}
@@ -0,0 +1,358 @@
+use std::path::{Path, PathBuf};
+
+use clap::Parser;
+use serde::{Deserialize, Serialize};
+use time::Duration;
+
+use crate::mqtt::MqttConfig;
+
+/// Used when no config file is passed and this file exists in the working directory.
+pub const DEFAULT_CONFIG_PATH: &str = "./oko.toml";
+
+const DEFAULT_HTTP_PORT: u16 = 3080;
+const DEFAULT_HTTPS_PORT: u16 = 3443;
+const DEFAULT_PROD_DATABASE_URL: &str = "sqlite://oko.db";
+const DEFAULT_DEV_DATABASE_URL: &str = "sqlite://data.db";
+const DEFAULT_VIDEO_PATH: &str = "./videos/";
+const DEFAULT_TLS_CERT_PATH: &str = "./certs/oko.internal.crt";
+const DEFAULT_TLS_KEY_PATH: &str = "./certs/oko.internal.key";
+const DEFAULT_SESSION_DURATION_SECONDS: i64 = Duration::days(1).whole_seconds();
+const DEFAULT_MDNS_SERVICE_NAME: &str = "_http._tcp.local";
+const DEFAULT_MQTT_PORT: u16 = 1883;
+const DEFAULT_MQTT_TOPIC_PREFIX: &str = "oko";
+const DEFAULT_MQTT_DISCOVERY_PREFIX: &str = "homeassistant";
+
+/// Server configuration.
+///
+/// Values are layered, each layer overriding the previous one:
+/// 1. built-in defaults
+/// 2. the TOML config file (`--config`, `OKO_CONFIG` or `./oko.toml`)
+/// 3. `OKO_*` environment variables
+/// 4. command line flags
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub http_port: u16,
+    pub https_enabled: bool,
+    pub https_port: u16,
+    pub database_url: String,
+    pub video_path: PathBuf,
+    pub tls_cert_path: PathBuf,
+    pub tls_key_path: PathBuf,
+    pub session_duration_seconds: i64,
+    pub mdns_service_name: String,
+    /// Broker to publish camera status/events to, `None` disables MQTT
+    pub mqtt_host: Option<String>,
+    pub mqtt_port: u16,
+    pub mqtt_username: Option<String>,
+    pub mqtt_password: Option<String>,
+    /// Topics are `<prefix>/status` and `<prefix>/camera/<camera_id>/...`
+    pub mqtt_topic_prefix: String,
+    /// Home Assistant's discovery prefix
+    pub mqtt_discovery_prefix: String,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        let database_url = if cfg!(debug_assertions) {
+            DEFAULT_DEV_DATABASE_URL
+        } else {
+            DEFAULT_PROD_DATABASE_URL
+        };
+
+        Self {
+            http_port: DEFAULT_HTTP_PORT,
+            https_enabled: true,
+            https_port: DEFAULT_HTTPS_PORT,
+            database_url: database_url.to_string(),
+            video_path: PathBuf::from(DEFAULT_VIDEO_PATH),
+            tls_cert_path: PathBuf::from(DEFAULT_TLS_CERT_PATH),
+            tls_key_path: PathBuf::from(DEFAULT_TLS_KEY_PATH),
+            session_duration_seconds: DEFAULT_SESSION_DURATION_SECONDS,
+            mdns_service_name: DEFAULT_MDNS_SERVICE_NAME.to_string(),
+            mqtt_host: None,
+            mqtt_port: DEFAULT_MQTT_PORT,
+            mqtt_username: None,
+            mqtt_password: None,
+            mqtt_topic_prefix: DEFAULT_MQTT_TOPIC_PREFIX.to_string(),
+            mqtt_discovery_prefix: DEFAULT_MQTT_DISCOVERY_PREFIX.to_string(),
+        }
+    }
+}
+
+/// Command line flags, every flag can also be set using the environment variable next to it.
+#[derive(Debug, Clone, Default, Parser)]
+#[command(version, about)]
+pub struct Cli {
+    /// Path to a TOML config file
+    #[arg(short, long, env = "OKO_CONFIG")]
+    pub config: Option<PathBuf>,
+    #[arg(long, env = "OKO_HTTP_PORT")]
+    pub http_port: Option<u16>,
+    #[arg(long, env = "OKO_HTTPS_PORT")]
+    pub https_port: Option<u16>,
+    /// Don't start the HTTPS server
+    #[arg(long, env = "OKO_NO_HTTPS")]
+    pub no_https: bool,
+    #[arg(long, env = "OKO_DATABASE_URL")]
+    pub database_url: Option<String>,
+    #[arg(long, env = "OKO_VIDEO_PATH")]
+    pub video_path: Option<PathBuf>,
+    #[arg(long, env = "OKO_TLS_CERT_PATH")]
+    pub tls_cert_path: Option<PathBuf>,
+    #[arg(long, env = "OKO_TLS_KEY_PATH")]
+    pub tls_key_path: Option<PathBuf>,
+    #[arg(long, env = "OKO_SESSION_DURATION_SECONDS")]
+    pub session_duration_seconds: Option<i64>,
+    #[arg(long, env = "OKO_MDNS_SERVICE_NAME")]
+    pub mdns_service_name: Option<String>,
+    /// Enables publishing to this MQTT broker
+    #[arg(long, env = "OKO_MQTT_HOST")]
+    pub mqtt_host: Option<String>,
+    #[arg(long, env = "OKO_MQTT_PORT")]
+    pub mqtt_port: Option<u16>,
+    #[arg(long, env = "OKO_MQTT_USERNAME")]
+    pub mqtt_username: Option<String>,
+    #[arg(long, env = "OKO_MQTT_PASSWORD", hide_env_values = true)]
+    pub mqtt_password: Option<String>,
+    #[arg(long, env = "OKO_MQTT_TOPIC_PREFIX")]
+    pub mqtt_topic_prefix: Option<String>,
+    #[arg(long, env = "OKO_MQTT_DISCOVERY_PREFIX")]
+    pub mqtt_discovery_prefix: Option<String>,
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum ConfigError {
+    #[error("Failed to read config file {0:?}: {1}")]
+    Read(PathBuf, #[source] std::io::Error),
+    #[error("Failed to parse config file {0:?}: {1}")]
+    Parse(PathBuf, #[source] toml::de::Error),
+    #[error("Session duration must be positive")]
+    InvalidSessionDuration,
+    #[error("MQTT prefixes must not be empty or contain wildcards: {0:?}")]
+    InvalidMqttPrefix(String),
+}
+
+impl Config {
+    /// Builds the config from the defaults, the config file and the flags/environment.
+    pub fn load(cli: &Cli) -> Result<Self, ConfigError> {
+        let config = match &cli.config {
+            Some(path) => Self::from_file(path)?,
+            None if Path::new(DEFAULT_CONFIG_PATH).exists() => {
+                Self::from_file(Path::new(DEFAULT_CONFIG_PATH))?
+            }
+            None => Self::default(),
+        };
+
+        config.with_overrides(cli).validated()
+    }
+
+    pub fn from_file(path: &Path) -> Result<Self, ConfigError> {
+        let contents =
+            std::fs::read_to_string(path).map_err(|e| ConfigError::Read(path.to_owned(), e))?;
+
+        toml::from_str(&contents).map_err(|e| ConfigError::Parse(path.to_owned(), e))
+    }
+
+    /// Applies the flags/environment variables that were set on top of `self`.
+    #[must_use]
+    pub fn with_overrides(mut self, cli: &Cli) -> Self {
+        if let Some(http_port) = cli.http_port {
+            self.http_port = http_port;
+        }
+
+        if cli.no_https {
+            self.https_enabled = false;
+        }
+
+        if let Some(https_port) = cli.https_port {
+            self.https_port = https_port;
+        }
+
+        if let Some(database_url) = &cli.database_url {
+            self.database_url.clone_from(database_url);
+        }
+
+        if let Some(video_path) = &cli.video_path {
+            self.video_path.clone_from(video_path);
+        }
+
+        if let Some(tls_cert_path) = &cli.tls_cert_path {
+            self.tls_cert_path.clone_from(tls_cert_path);
+        }
+
+        if let Some(tls_key_path) = &cli.tls_key_path {
+            self.tls_key_path.clone_from(tls_key_path);
+        }
+
+        if let Some(session_duration_seconds) = cli.session_duration_seconds {
+            self.session_duration_seconds = session_duration_seconds;
+        }
+
+        if let Some(mdns_service_name) = &cli.mdns_service_name {
+            self.mdns_service_name.clone_from(mdns_service_name);
+        }
+
+        if cli.mqtt_host.is_some() {
+            self.mqtt_host.clone_from(&cli.mqtt_host);
+        }
+
+        if let Some(mqtt_port) = cli.mqtt_port {
+            self.mqtt_port = mqtt_port;
+        }
+
+        if cli.mqtt_username.is_some() {
+            self.mqtt_username.clone_from(&cli.mqtt_username);
+        }
+
+        if cli.mqtt_password.is_some() {
+            self.mqtt_password.clone_from(&cli.mqtt_password);
+        }
+
+        if let Some(mqtt_topic_prefix) = &cli.mqtt_topic_prefix {
+            self.mqtt_topic_prefix.clone_from(mqtt_topic_prefix);
+        }
+
+        if let Some(mqtt_discovery_prefix) = &cli.mqtt_discovery_prefix {
+            self.mqtt_discovery_prefix.clone_from(mqtt_discovery_prefix);
+        }
+
+        self
+    }
+
+    fn validated(self) -> Result<Self, ConfigError> {
+        if self.session_duration_seconds <= 0 {
+            return Err(ConfigError::InvalidSessionDuration);
+        }
+
+        for mqtt_prefix in [&self.mqtt_topic_prefix, &self.mqtt_discovery_prefix] {
+            if mqtt_prefix.is_empty() || mqtt_prefix.contains(['+', '#']) {
+                return Err(ConfigError::InvalidMqttPrefix(mqtt_prefix.clone()));
+            }
+        }
+
+        Ok(self)
+    }
+
+    /// `None` if the HTTPS server is disabled
+    #[must_use]
+    pub const fn https_port(&self) -> Option<u16> {
+        if self.https_enabled {
+            Some(self.https_port)
+        } else {
+            None
+        }
+    }
+
+    #[must_use]
+    pub const fn session_duration(&self) -> Duration {
+        Duration::seconds(self.session_duration_seconds)
+    }
+
+    /// `None` if no MQTT broker is configured
+    #[must_use]
+    pub fn mqtt(&self) -> Option<MqttConfig> {
+        let host = self.mqtt_host.clone()?;
+
+        Some(MqttConfig {
+            host,
+            port: self.mqtt_port,
+            credentials: self
+                .mqtt_username
+                .clone()
+                .map(|username| (username, self.mqtt_password.clone().unwrap_or_default())),
+            topic_prefix: self.mqtt_topic_prefix.clone(),
+            discovery_prefix: self.mqtt_discovery_prefix.clone(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_partial_file() -> Result<(), toml::de::Error> {
+        let config: Config = toml::from_str(
+            r#"
+            http_port = 8080
+            video_path = "/var/lib/oko/videos"
+            database_url = "sqlite:///var/lib/oko/oko.db"
+            "#,
+        )?;
+
+        assert_eq!(config.http_port, 8080);
+        assert_eq!(config.video_path, PathBuf::from("/var/lib/oko/videos"));
+        assert_eq!(config.database_url, "sqlite:///var/lib/oko/oko.db");
+        assert!(config.https_enabled);
+        assert_eq!(config.https_port, DEFAULT_HTTPS_PORT);
+        assert_eq!(config.mdns_service_name, DEFAULT_MDNS_SERVICE_NAME);
+
+        Ok(())
+    }
+
+    #[test]
+    fn unknown_field() {
+        let config: Result<Config, _> = toml::from_str("htp_port = 8080");
+
+        assert!(config.is_err());
+    }
+
+    #[test]
+    fn precedence() -> Result<(), toml::de::Error> {
+        let file_config: Config = toml::from_str(
+            r"
+            http_port = 8080
+            https_port = 8443
+            session_duration_seconds = 60
+            ",
+        )?;
+
+        let cli = Cli::parse_from(["oko", "--http-port", "9090", "--no-https"]);
+
+        let config = file_config.with_overrides(&cli);
+
+        assert_eq!(config.http_port, 9090);
+        assert!(!config.https_enabled);
+        assert_eq!(config.https_port, 8443);
+        assert_eq!(config.session_duration(), Duration::minutes(1));
+        assert_eq!(config.video_path, PathBuf::from(DEFAULT_VIDEO_PATH));
+
+        Ok(())
+    }
+
+    #[test]
+    fn mqtt() {
+        assert!(Config::default().mqtt().is_none());
+
+        let cli = Cli::parse_from([
+            "oko",
+            "--mqtt-host",
+            "broker.internal",
+            "--mqtt-username",
+            "oko",
+        ]);
+
+        let mqtt_config = Config::default().with_overrides(&cli).mqtt();
+
+        assert_eq!(
+            mqtt_config.as_ref().map(|mqtt| mqtt.port),
+            Some(DEFAULT_MQTT_PORT)
+        );
+        assert_eq!(
+            mqtt_config.and_then(|mqtt| mqtt.credentials),
+            Some(("oko".to_string(), String::new()))
+        );
+
+        let cli = Cli::parse_from(["oko", "--mqtt-topic-prefix", "oko/#"]);
+
+        assert!(Config::default().with_overrides(&cli).validated().is_err());
+    }
+
+    #[test]
+    fn invalid_session_duration() {
+        let cli = Cli::parse_from(["oko", "--session-duration-seconds", "0"]);
+
+        assert!(Config::default().with_overrides(&cli).validated().is_err());
+    }
+}
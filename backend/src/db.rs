@@ -1,23 +1,56 @@
 use sqlx::{Result, SqlitePool};
 
+pub use alert_subscription::AlertSubscription;
+pub use arming_schedule::ArmingSchedule;
+pub use arming_state::ArmingMode;
+pub use arming_state::ArmingState;
 pub use camera::Camera;
 pub use camera_permission::CameraPermission;
 pub use camera_permission_user_view::CameraPermissionUserView;
 pub use camera_permission_view::CameraPermissionView;
 pub use camera_setting::CameraSetting;
 pub use camera_setting::CameraSettingNoMeta;
+pub use camera_setting::RecordingMode;
+pub use event::Event;
+pub use event::EventFilter;
+pub use event::EventKind;
+pub use motion_zone::MotionZone;
+pub use motion_zone::MotionZoneKind;
+pub use polygon::PolygonPoint;
+pub use privacy_mask::PrivacyMask;
+pub use smtp_settings::SmtpSecurity;
+pub use smtp_settings::SmtpSettings;
+pub use storage_policy::StoragePolicy;
+pub use user::Role;
 pub use user::User;
 pub use video::Video;
+pub use video::VideoFormat;
 pub use video_camera_view::VideoCameraView;
+pub use video_thumbnail::VideoThumbnail;
+pub use webhook::Webhook;
+pub use webhook_delivery::WebhookDelivery;
+pub use webhook_delivery::WebhookKind;
 
+mod alert_subscription;
+mod arming_schedule;
+mod arming_state;
 mod camera;
 mod camera_permission;
 mod camera_permission_user_view;
 mod camera_permission_view;
 mod camera_setting;
+mod event;
+mod motion_zone;
+mod polygon;
+mod privacy_mask;
+mod smtp_settings;
+mod storage_policy;
 mod user;
 mod video;
 mod video_camera_view;
+mod video_thumbnail;
+mod webhook;
+mod webhook_delivery;
 
 #[allow(dead_code)]
 pub trait Model {
@@ -0,0 +1,252 @@
+use serde::{Deserialize, Serialize};
+use sqlx::{Result, SqlitePool};
+use time::OffsetDateTime;
+
+use super::{EventKind, Model};
+
+/// A user asking to be emailed when an event happens, see `crate::alerts`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AlertSubscription {
+    pub subscription_id: i64,
+    pub user_id: i64,
+    /// `None` means every camera the user can view
+    pub camera_id: Option<i64>,
+    /// Only `Motion` and `CameraOffline`
+    pub kind: EventKind,
+    pub email: String,
+    pub created_at: OffsetDateTime,
+}
+
+pub struct Default {
+    pub subscription_id: i64,
+    pub camera_id: Option<i64>,
+}
+
+impl Default {
+    #[allow(clippy::unused_self)]
+    pub fn created_at(&self) -> OffsetDateTime {
+        OffsetDateTime::now_utc()
+    }
+}
+
+impl Model for AlertSubscription {
+    type Default = Default;
+    const DEFAULT: Default = Default {
+        subscription_id: -1,
+        camera_id: None,
+    };
+
+    async fn create_using_self(&mut self, pool: &SqlitePool) -> Result<()> {
+        let result = sqlx::query!(
+            r#"
+            INSERT INTO alert_subscriptions (user_id, camera_id, kind, email, created_at)
+            VALUES (?, ?, ?, ?, ?)
+            RETURNING subscription_id
+            "#,
+            self.user_id,
+            self.camera_id,
+            self.kind,
+            self.email,
+            self.created_at
+        )
+        .fetch_one(pool)
+        .await?;
+
+        self.subscription_id = result.subscription_id;
+
+        Ok(())
+    }
+
+    async fn get_using_id(pool: &SqlitePool, id: i64) -> Result<Self> {
+        sqlx::query_as!(
+            AlertSubscription,
+            r#"
+            SELECT subscription_id, user_id, camera_id, kind as "kind: EventKind", email, created_at
+            FROM alert_subscriptions
+            WHERE subscription_id = ?
+            "#,
+            id
+        )
+        .fetch_one(pool)
+        .await
+    }
+
+    async fn update_using_self(&self, pool: &SqlitePool) -> Result<()> {
+        sqlx::query!(
+            r#"
+            UPDATE alert_subscriptions
+            SET camera_id = ?, kind = ?, email = ?
+            WHERE subscription_id = ?
+            RETURNING subscription_id
+            "#,
+            self.camera_id,
+            self.kind,
+            self.email,
+            self.subscription_id
+        )
+        .fetch_one(pool)
+        .await?;
+
+        Ok(())
+    }
+
+    async fn delete_using_id(pool: &SqlitePool, id: i64) -> Result<()> {
+        sqlx::query!(
+            r#"
+            DELETE
+            FROM alert_subscriptions
+            WHERE subscription_id = ?
+            RETURNING subscription_id
+            "#,
+            id
+        )
+        .fetch_one(pool)
+        .await?;
+
+        Ok(())
+    }
+}
+
+impl AlertSubscription {
+    /// Whether users can subscribe to `kind`
+    #[must_use]
+    pub const fn is_supported(kind: EventKind) -> bool {
+        matches!(kind, EventKind::Motion | EventKind::CameraOffline)
+    }
+
+    pub async fn list_for_user(pool: &SqlitePool, user_id: i64) -> Result<Vec<Self>> {
+        sqlx::query_as!(
+            AlertSubscription,
+            r#"
+            SELECT subscription_id, user_id, camera_id, kind as "kind: EventKind", email, created_at
+            FROM alert_subscriptions
+            WHERE user_id = ?
+            ORDER BY subscription_id ASC
+            "#,
+            user_id
+        )
+        .fetch_all(pool)
+        .await
+    }
+
+    /// Lists the subscriptions to `kind` on `camera_id` of users that can still view the camera
+    pub async fn list_for_event(
+        pool: &SqlitePool,
+        camera_id: i64,
+        kind: EventKind,
+    ) -> Result<Vec<Self>> {
+        sqlx::query_as!(
+            AlertSubscription,
+            r#"
+            SELECT s.subscription_id, s.user_id, s.camera_id, s.kind as "kind: EventKind", s.email,
+                   s.created_at
+            FROM alert_subscriptions s
+            JOIN camera_permissions cp ON cp.user_id = s.user_id AND cp.camera_id = ?
+            WHERE cp.can_view
+              AND s.kind = ?
+              AND (s.camera_id IS NULL OR s.camera_id = cp.camera_id)
+            ORDER BY s.subscription_id ASC
+            "#,
+            camera_id,
+            kind
+        )
+        .fetch_all(pool)
+        .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[sqlx::test(fixtures(
+        path = "../../fixtures",
+        scripts("users", "cameras", "camera_permissions", "alert_subscriptions")
+    ))]
+    async fn create(pool: SqlitePool) -> Result<()> {
+        let mut subscription = AlertSubscription {
+            subscription_id: AlertSubscription::DEFAULT.subscription_id,
+            user_id: 2,
+            camera_id: Some(1),
+            kind: EventKind::CameraOffline,
+            email: "piotrpdev@example.com".to_string(),
+            created_at: AlertSubscription::DEFAULT.created_at(),
+        };
+
+        subscription.create_using_self(&pool).await?;
+
+        assert_eq!(subscription.subscription_id, 5);
+
+        let returned_subscription = AlertSubscription::get_using_id(&pool, 5).await?;
+
+        assert_eq!(returned_subscription.user_id, 2);
+        assert_eq!(returned_subscription.camera_id, Some(1));
+        assert_eq!(returned_subscription.kind, EventKind::CameraOffline);
+        assert_eq!(returned_subscription.email, subscription.email);
+
+        Ok(())
+    }
+
+    #[sqlx::test(fixtures(
+        path = "../../fixtures",
+        scripts("users", "cameras", "camera_permissions", "alert_subscriptions")
+    ))]
+    async fn list_for_user(pool: SqlitePool) -> Result<()> {
+        let returned_subscriptions = AlertSubscription::list_for_user(&pool, 3).await?;
+
+        assert_eq!(returned_subscriptions.len(), 2);
+        assert!(returned_subscriptions
+            .iter()
+            .all(|subscription| subscription.user_id == 3));
+
+        assert!(AlertSubscription::list_for_user(&pool, 4).await?.is_empty());
+
+        Ok(())
+    }
+
+    #[sqlx::test(fixtures(
+        path = "../../fixtures",
+        scripts("users", "cameras", "camera_permissions", "alert_subscriptions")
+    ))]
+    async fn list_for_event(pool: SqlitePool) -> Result<()> {
+        let subscription_ids = |subscriptions: Vec<AlertSubscription>| -> Vec<i64> {
+            subscriptions
+                .into_iter()
+                .map(|subscription| subscription.subscription_id)
+                .collect()
+        };
+
+        let front_door_motion =
+            AlertSubscription::list_for_event(&pool, 1, EventKind::Motion).await?;
+        assert_eq!(subscription_ids(front_door_motion), [1, 3]);
+
+        // joedaly can't view the kitchen camera
+        let kitchen_motion = AlertSubscription::list_for_event(&pool, 2, EventKind::Motion).await?;
+        assert_eq!(subscription_ids(kitchen_motion), [1]);
+
+        let kitchen_offline =
+            AlertSubscription::list_for_event(&pool, 2, EventKind::CameraOffline).await?;
+        assert_eq!(subscription_ids(kitchen_offline), [2]);
+
+        let front_door_offline =
+            AlertSubscription::list_for_event(&pool, 1, EventKind::CameraOffline).await?;
+        assert!(front_door_offline.is_empty());
+
+        Ok(())
+    }
+
+    #[sqlx::test(fixtures(
+        path = "../../fixtures",
+        scripts("users", "cameras", "camera_permissions", "alert_subscriptions")
+    ))]
+    async fn deleted_user(pool: SqlitePool) -> Result<()> {
+        crate::db::User::delete_using_id(&pool, 3).await?;
+
+        assert!(AlertSubscription::list_for_user(&pool, 3).await?.is_empty());
+        assert!(AlertSubscription::get_using_id(&pool, 1).await.is_ok());
+
+        assert!(AlertSubscription::get_using_id(&pool, 3).await.is_err());
+
+        Ok(())
+    }
+}
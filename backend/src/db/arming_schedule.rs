@@ -0,0 +1,251 @@
+use serde::{Deserialize, Serialize};
+use sqlx::{Result, SqlitePool};
+use time::{OffsetDateTime, UtcOffset};
+
+use super::{ArmingMode, Model};
+
+/// Minutes in a day, the latest `end_minute`
+const MINUTES_PER_DAY: i64 = 24 * 60;
+
+/// A weekly window during which a camera is armed in `mode`, see `ArmingMode::arms`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ArmingSchedule {
+    pub schedule_id: i64,
+    pub camera_id: i64,
+    /// Never `Disarmed`
+    pub mode: ArmingMode,
+    /// 0 is Monday, of the UTC date
+    pub day_of_week: i64,
+    /// Minutes since midnight UTC
+    pub start_minute: i64,
+    /// Minutes since midnight UTC, exclusive
+    pub end_minute: i64,
+    pub created_at: OffsetDateTime,
+}
+
+pub struct Default {
+    pub schedule_id: i64,
+}
+
+impl Default {
+    #[allow(clippy::unused_self)]
+    pub fn created_at(&self) -> OffsetDateTime {
+        OffsetDateTime::now_utc()
+    }
+}
+
+impl Model for ArmingSchedule {
+    type Default = Default;
+    const DEFAULT: Default = Default { schedule_id: -1 };
+
+    async fn create_using_self(&mut self, pool: &SqlitePool) -> Result<()> {
+        let result = sqlx::query!(
+            r#"
+            INSERT INTO arming_schedules (camera_id, mode, day_of_week, start_minute, end_minute, created_at)
+            VALUES (?, ?, ?, ?, ?, ?)
+            RETURNING schedule_id
+            "#,
+            self.camera_id,
+            self.mode,
+            self.day_of_week,
+            self.start_minute,
+            self.end_minute,
+            self.created_at
+        )
+        .fetch_one(pool)
+        .await?;
+
+        self.schedule_id = result.schedule_id;
+
+        Ok(())
+    }
+
+    async fn get_using_id(pool: &SqlitePool, id: i64) -> Result<Self> {
+        sqlx::query_as!(
+            ArmingSchedule,
+            r#"
+            SELECT schedule_id, camera_id, mode as "mode: ArmingMode", day_of_week, start_minute,
+                   end_minute, created_at
+            FROM arming_schedules
+            WHERE schedule_id = ?
+            "#,
+            id
+        )
+        .fetch_one(pool)
+        .await
+    }
+
+    async fn update_using_self(&self, pool: &SqlitePool) -> Result<()> {
+        sqlx::query!(
+            r#"
+            UPDATE arming_schedules
+            SET mode = ?, day_of_week = ?, start_minute = ?, end_minute = ?
+            WHERE schedule_id = ?
+            RETURNING schedule_id
+            "#,
+            self.mode,
+            self.day_of_week,
+            self.start_minute,
+            self.end_minute,
+            self.schedule_id
+        )
+        .fetch_one(pool)
+        .await?;
+
+        Ok(())
+    }
+
+    async fn delete_using_id(pool: &SqlitePool, id: i64) -> Result<()> {
+        sqlx::query!(
+            r#"
+            DELETE
+            FROM arming_schedules
+            WHERE schedule_id = ?
+            RETURNING schedule_id
+            "#,
+            id
+        )
+        .fetch_one(pool)
+        .await?;
+
+        Ok(())
+    }
+}
+
+impl ArmingSchedule {
+    /// Whether the mode, day and times make sense, i.e. what the DB would accept
+    #[must_use]
+    pub fn is_valid(&self) -> bool {
+        self.mode != ArmingMode::Disarmed
+            && (0..7).contains(&self.day_of_week)
+            && (0..MINUTES_PER_DAY).contains(&self.start_minute)
+            && self.end_minute > self.start_minute
+            && self.end_minute <= MINUTES_PER_DAY
+    }
+
+    /// Whether the camera is armed in `mode` at `at` because of this schedule
+    #[must_use]
+    pub fn covers(&self, mode: ArmingMode, at: OffsetDateTime) -> bool {
+        let at = at.to_offset(UtcOffset::UTC);
+        let minute = i64::from(at.hour()) * 60 + i64::from(at.minute());
+
+        self.mode == mode
+            && self.day_of_week == i64::from(at.weekday().number_days_from_monday())
+            && (self.start_minute..self.end_minute).contains(&minute)
+    }
+
+    pub async fn list_for_camera(pool: &SqlitePool, camera_id: i64) -> Result<Vec<Self>> {
+        sqlx::query_as!(
+            ArmingSchedule,
+            r#"
+            SELECT schedule_id, camera_id, mode as "mode: ArmingMode", day_of_week, start_minute,
+                   end_minute, created_at
+            FROM arming_schedules
+            WHERE camera_id = ?
+            ORDER BY day_of_week ASC, start_minute ASC
+            "#,
+            camera_id
+        )
+        .fetch_all(pool)
+        .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use time::macros::datetime;
+
+    fn schedule(
+        mode: ArmingMode,
+        day_of_week: i64,
+        start_minute: i64,
+        end_minute: i64,
+    ) -> ArmingSchedule {
+        ArmingSchedule {
+            schedule_id: ArmingSchedule::DEFAULT.schedule_id,
+            camera_id: 1,
+            mode,
+            day_of_week,
+            start_minute,
+            end_minute,
+            created_at: ArmingSchedule::DEFAULT.created_at(),
+        }
+    }
+
+    #[test]
+    fn covers() {
+        // Mondays from 22:00 until midnight
+        let night = schedule(ArmingMode::Night, 0, 22 * 60, MINUTES_PER_DAY);
+
+        assert!(night.covers(ArmingMode::Night, datetime!(2024-10-21 22:00 UTC)));
+        assert!(night.covers(ArmingMode::Night, datetime!(2024-10-21 23:59 UTC)));
+        assert!(!night.covers(ArmingMode::Night, datetime!(2024-10-21 21:59 UTC)));
+        // A Tuesday
+        assert!(!night.covers(ArmingMode::Night, datetime!(2024-10-22 00:00 UTC)));
+        assert!(!night.covers(ArmingMode::Away, datetime!(2024-10-21 23:00 UTC)));
+        // Same moment in another time zone
+        assert!(night.covers(ArmingMode::Night, datetime!(2024-10-22 01:00 +2)));
+    }
+
+    #[test]
+    fn valid() {
+        assert!(schedule(ArmingMode::Home, 6, 0, MINUTES_PER_DAY).is_valid());
+        assert!(!schedule(ArmingMode::Disarmed, 0, 0, 60).is_valid());
+        assert!(!schedule(ArmingMode::Home, 7, 0, 60).is_valid());
+        assert!(!schedule(ArmingMode::Home, 0, 60, 60).is_valid());
+        assert!(!schedule(ArmingMode::Home, 0, 0, MINUTES_PER_DAY + 1).is_valid());
+        assert!(!schedule(ArmingMode::Home, 0, -1, 60).is_valid());
+    }
+
+    #[sqlx::test(fixtures(
+        path = "../../fixtures",
+        scripts("users", "cameras", "arming_schedules")
+    ))]
+    async fn create(pool: SqlitePool) -> Result<()> {
+        let mut new_schedule = schedule(ArmingMode::Home, 2, 60, 120);
+
+        new_schedule.create_using_self(&pool).await?;
+
+        assert_eq!(new_schedule.schedule_id, 4);
+
+        let returned_schedule = ArmingSchedule::get_using_id(&pool, 4).await?;
+
+        assert_eq!(returned_schedule.camera_id, 1);
+        assert_eq!(returned_schedule.mode, ArmingMode::Home);
+        assert_eq!(returned_schedule.day_of_week, 2);
+
+        Ok(())
+    }
+
+    #[sqlx::test(fixtures(
+        path = "../../fixtures",
+        scripts("users", "cameras", "arming_schedules")
+    ))]
+    async fn list_for_camera(pool: SqlitePool) -> Result<()> {
+        let returned_schedules = ArmingSchedule::list_for_camera(&pool, 2).await?;
+
+        let schedule_ids: Vec<i64> = returned_schedules
+            .iter()
+            .map(|schedule| schedule.schedule_id)
+            .collect();
+        assert_eq!(schedule_ids, [1, 2, 3]);
+
+        assert!(ArmingSchedule::list_for_camera(&pool, 1).await?.is_empty());
+
+        Ok(())
+    }
+
+    #[sqlx::test(fixtures(
+        path = "../../fixtures",
+        scripts("users", "cameras", "arming_schedules")
+    ))]
+    async fn deleted_camera(pool: SqlitePool) -> Result<()> {
+        crate::db::Camera::delete_using_id(&pool, 2).await?;
+
+        assert!(ArmingSchedule::list_for_camera(&pool, 2).await?.is_empty());
+
+        Ok(())
+    }
+}
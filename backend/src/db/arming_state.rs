@@ -0,0 +1,171 @@
+use serde::{Deserialize, Serialize};
+use sqlx::{Result, SqlitePool};
+use time::OffsetDateTime;
+
+use super::ArmingSchedule;
+
+/// Whether motion triggers events, recordings and notifications.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, sqlx::Type)]
+#[serde(rename_all = "lowercase")]
+#[sqlx(rename_all = "lowercase")]
+pub enum ArmingMode {
+    /// Motion is ignored on every camera
+    Disarmed,
+    Home,
+    Away,
+    Night,
+}
+
+impl ArmingMode {
+    /// Whether a camera with `schedules` is armed in this mode at `at`.
+    ///
+    /// Cameras without schedules are always armed, otherwise one of their schedules for this mode
+    /// has to cover `at`.
+    #[must_use]
+    pub fn arms(self, schedules: &[ArmingSchedule], at: OffsetDateTime) -> bool {
+        if self == Self::Disarmed {
+            return false;
+        }
+
+        schedules.is_empty() || schedules.iter().any(|s| s.covers(self, at))
+    }
+}
+
+/// The system-wide arming mode, there is only ever one row of this.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ArmingState {
+    pub arming_id: i64,
+    pub mode: ArmingMode,
+    pub last_modified: OffsetDateTime,
+    pub modified_by: Option<i64>,
+}
+
+pub struct Default {
+    pub arming_id: i64,
+}
+
+impl Default {
+    #[allow(clippy::unused_self)]
+    pub fn last_modified(&self) -> OffsetDateTime {
+        OffsetDateTime::now_utc()
+    }
+}
+
+impl ArmingState {
+    pub const DEFAULT: Default = Default { arming_id: 1 };
+
+    pub async fn get(pool: &SqlitePool) -> Result<Self> {
+        sqlx::query_as!(
+            ArmingState,
+            r#"
+            SELECT arming_id, mode as "mode: ArmingMode", last_modified, modified_by
+            FROM arming_state WHERE arming_id = ?
+            "#,
+            Self::DEFAULT.arming_id
+        )
+        .fetch_one(pool)
+        .await
+    }
+
+    pub async fn update_using_self(&self, pool: &SqlitePool) -> Result<()> {
+        sqlx::query!(
+            r#"
+            UPDATE arming_state
+            SET mode = ?, last_modified = ?, modified_by = ?
+            WHERE arming_id = ?
+            RETURNING arming_id
+            "#,
+            self.mode,
+            self.last_modified,
+            self.modified_by,
+            self.arming_id
+        )
+        .fetch_one(pool)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Whether motion on `camera_id` should be acted on at `at`, see `ArmingMode::arms`
+    pub async fn is_camera_armed(
+        pool: &SqlitePool,
+        camera_id: i64,
+        at: OffsetDateTime,
+    ) -> Result<bool> {
+        let state = Self::get(pool).await?;
+
+        if state.mode == ArmingMode::Disarmed {
+            return Ok(false);
+        }
+
+        let schedules = ArmingSchedule::list_for_camera(pool, camera_id).await?;
+
+        Ok(state.mode.arms(&schedules, at))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use time::macros::datetime;
+
+    #[sqlx::test(fixtures(path = "../../fixtures", scripts("users")))]
+    async fn get(pool: SqlitePool) -> Result<()> {
+        let returned_state = ArmingState::get(&pool).await?;
+
+        assert_eq!(returned_state.arming_id, ArmingState::DEFAULT.arming_id);
+        assert_eq!(returned_state.mode, ArmingMode::Away);
+        assert_eq!(returned_state.modified_by, None);
+
+        Ok(())
+    }
+
+    #[sqlx::test(fixtures(path = "../../fixtures", scripts("users")))]
+    async fn update(pool: SqlitePool) -> Result<()> {
+        let mut state = ArmingState::get(&pool).await?;
+
+        state.mode = ArmingMode::Night;
+        state.modified_by = Some(2);
+        state.update_using_self(&pool).await?;
+
+        let returned_state = ArmingState::get(&pool).await?;
+
+        assert_eq!(returned_state.mode, ArmingMode::Night);
+        assert_eq!(returned_state.modified_by, Some(2));
+
+        Ok(())
+    }
+
+    #[sqlx::test(fixtures(
+        path = "../../fixtures",
+        scripts("users", "cameras", "arming_schedules")
+    ))]
+    async fn is_camera_armed(pool: SqlitePool) -> Result<()> {
+        // A Monday
+        let monday_night = datetime!(2024-10-21 23:00 UTC);
+        let monday_noon = datetime!(2024-10-21 12:00 UTC);
+        let saturday_noon = datetime!(2024-10-26 12:00 UTC);
+
+        // The front door camera has no schedules
+        assert!(ArmingState::is_camera_armed(&pool, 1, monday_noon).await?);
+        assert!(!ArmingState::is_camera_armed(&pool, 2, monday_noon).await?);
+        assert!(ArmingState::is_camera_armed(&pool, 2, saturday_noon).await?);
+
+        let mut state = ArmingState::get(&pool).await?;
+        state.mode = ArmingMode::Night;
+        state.update_using_self(&pool).await?;
+
+        assert!(ArmingState::is_camera_armed(&pool, 1, monday_noon).await?);
+        assert!(!ArmingState::is_camera_armed(&pool, 2, monday_noon).await?);
+        assert!(ArmingState::is_camera_armed(&pool, 2, monday_night).await?);
+
+        state.mode = ArmingMode::Disarmed;
+        state.update_using_self(&pool).await?;
+
+        assert!(!ArmingState::is_camera_armed(&pool, 1, monday_noon).await?);
+        assert!(!ArmingState::is_camera_armed(&pool, 2, monday_night).await?);
+
+        Ok(())
+    }
+}
@@ -1,4 +1,5 @@
 use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
 use sqlx::{Result, SqlitePool};
 use time::OffsetDateTime;
 
@@ -13,6 +14,11 @@ pub struct Camera {
     pub ip_address: Option<String>,
     pub last_connected: Option<OffsetDateTime>,
     pub is_active: bool,
+    /// Whether the camera is currently connected, see `Camera::set_online`/`Camera::set_offline`
+    pub is_online: bool,
+    /// See `Camera::hash_pairing_token`, never sent to clients
+    #[serde(skip)]
+    pub token_hash: Option<String>,
 }
 
 pub struct Default {
@@ -20,6 +26,8 @@ pub struct Default {
     pub ip_address: Option<String>,
     pub last_connected: Option<OffsetDateTime>,
     pub is_active: bool,
+    pub is_online: bool,
+    pub token_hash: Option<String>,
 }
 
 impl Model for Camera {
@@ -29,19 +37,23 @@ impl Model for Camera {
         ip_address: None,
         last_connected: None,
         is_active: true,
+        is_online: false,
+        token_hash: None,
     };
 
     async fn create_using_self(&mut self, pool: &SqlitePool) -> Result<()> {
         let result = sqlx::query!(
             r#"
-            INSERT INTO cameras (name, ip_address, last_connected, is_active)
-            VALUES (?, ?, ?, ?)
+            INSERT INTO cameras (name, ip_address, last_connected, is_active, is_online, token_hash)
+            VALUES (?, ?, ?, ?, ?, ?)
             RETURNING camera_id
             "#,
             self.name,
             self.ip_address,
             self.last_connected,
-            self.is_active
+            self.is_active,
+            self.is_online,
+            self.token_hash
         )
         .fetch_one(pool)
         .await?;
@@ -65,11 +77,12 @@ impl Model for Camera {
         .await
     }
 
+    /// Doesn't change `is_online`, that is only done by the WebSocket connection of the camera
     async fn update_using_self(&self, pool: &SqlitePool) -> Result<()> {
         sqlx::query!(
             r#"
             UPDATE cameras
-            SET name = ?, ip_address = ?, last_connected = ?, is_active = ?
+            SET name = ?, ip_address = ?, last_connected = ?, is_active = ?, token_hash = ?
             WHERE camera_id = ?
             RETURNING camera_id
             "#,
@@ -77,6 +90,7 @@ impl Model for Camera {
             self.ip_address,
             self.last_connected,
             self.is_active,
+            self.token_hash,
             self.camera_id
         )
         .fetch_one(pool)
@@ -103,6 +117,39 @@ impl Model for Camera {
 }
 
 impl Camera {
+    /// Random secret handed to the camera when it is added, only the hash is stored.
+    #[must_use]
+    pub fn generate_pairing_token() -> String {
+        hex::encode(rand::random::<[u8; 32]>())
+    }
+
+    #[must_use]
+    pub fn hash_pairing_token(token: &str) -> String {
+        hex::encode(Sha256::digest(token.as_bytes()))
+    }
+
+    /// Whether `token` matches the stored hash, always `false` for cameras without a token.
+    #[must_use]
+    pub fn verify_pairing_token(&self, token: &str) -> bool {
+        self.token_hash
+            .as_ref()
+            .is_some_and(|token_hash| *token_hash == Self::hash_pairing_token(token))
+    }
+
+    /// Lists every camera, regardless of permissions
+    pub async fn list(pool: &SqlitePool) -> Result<Vec<Self>> {
+        sqlx::query_as!(
+            Camera,
+            r#"
+            SELECT *
+            FROM cameras
+            ORDER BY camera_id ASC
+            "#
+        )
+        .fetch_all(pool)
+        .await
+    }
+
     pub async fn list_accessible_to_user(
         db: &SqlitePool,
         user_id: i64,
@@ -110,7 +157,8 @@ impl Camera {
         sqlx::query_as!(
             CameraPermissionView,
             r#"
-            SELECT c.camera_id, c.name as camera_name, c.ip_address, cp.can_view, cp.can_control
+            SELECT c.camera_id, c.name as camera_name, c.ip_address, c.last_connected, c.is_online,
+                   cp.can_view, cp.can_control
             FROM cameras c
             JOIN camera_permissions cp ON c.camera_id = cp.camera_id
             WHERE cp.user_id = ? AND cp.can_view
@@ -121,6 +169,66 @@ impl Camera {
         .await
     }
 
+    /// Marks the camera as connected since `connected_at`.
+    pub async fn set_online(
+        pool: &SqlitePool,
+        camera_id: i64,
+        connected_at: OffsetDateTime,
+    ) -> Result<()> {
+        sqlx::query!(
+            r#"
+            UPDATE cameras
+            SET is_online = TRUE, last_connected = ?
+            WHERE camera_id = ?
+            RETURNING camera_id
+            "#,
+            connected_at,
+            camera_id
+        )
+        .fetch_one(pool)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Marks the camera as disconnected, unless it has reconnected since `connected_at`.
+    ///
+    /// Returns `false` if nothing changed, e.g. the camera reconnected before the old connection timed out.
+    pub async fn set_offline(
+        pool: &SqlitePool,
+        camera_id: i64,
+        connected_at: OffsetDateTime,
+    ) -> Result<bool> {
+        let result = sqlx::query!(
+            r#"
+            UPDATE cameras
+            SET is_online = FALSE
+            WHERE camera_id = ? AND last_connected = ?
+            RETURNING camera_id
+            "#,
+            camera_id,
+            connected_at
+        )
+        .fetch_optional(pool)
+        .await?;
+
+        Ok(result.is_some())
+    }
+
+    /// Marks every camera as disconnected, used on startup in case the server didn't shut down cleanly.
+    pub async fn set_all_offline(pool: &SqlitePool) -> Result<()> {
+        sqlx::query!(
+            r#"
+            UPDATE cameras
+            SET is_online = FALSE
+            "#
+        )
+        .execute(pool)
+        .await?;
+
+        Ok(())
+    }
+
     pub async fn get_using_ip(pool: &SqlitePool, ip_address: String) -> Result<Self> {
         sqlx::query_as!(
             Camera,
@@ -149,6 +257,8 @@ mod tests {
             ip_address: Camera::DEFAULT.ip_address,
             last_connected: Camera::DEFAULT.last_connected,
             is_active: Camera::DEFAULT.is_active,
+            is_online: Camera::DEFAULT.is_online,
+            token_hash: Some(Camera::hash_pairing_token("token")),
         };
 
         camera.create_using_self(&pool).await?;
@@ -161,6 +271,8 @@ mod tests {
         assert_eq!(returned_camera.ip_address, camera.ip_address);
         assert_eq!(returned_camera.last_connected, camera.last_connected);
         assert!(returned_camera.is_active);
+        assert!(!returned_camera.is_online);
+        assert!(returned_camera.verify_pairing_token("token"));
 
         Ok(())
     }
@@ -192,6 +304,8 @@ mod tests {
             ip_address: Some("192.168.0.24".to_string()),
             last_connected: Some(time::OffsetDateTime::from_unix_timestamp(1_729_443_378)?),
             is_active: false,
+            is_online: old_camera.is_online,
+            token_hash: old_camera.token_hash,
         };
 
         let updated = updated_camera.update_using_self(&pool).await;
@@ -229,6 +343,17 @@ mod tests {
         Ok(())
     }
 
+    #[sqlx::test(fixtures(path = "../../fixtures", scripts("cameras")))]
+    async fn list(pool: SqlitePool) -> Result<()> {
+        let returned_cameras = Camera::list(&pool).await?;
+
+        assert_eq!(returned_cameras.len(), 2);
+        assert_eq!(returned_cameras.first().unwrap().name, "Front Door");
+        assert_eq!(returned_cameras.last().unwrap().name, "Kitchen");
+
+        Ok(())
+    }
+
     #[sqlx::test(fixtures(
         path = "../../fixtures",
         scripts("users", "cameras", "camera_permissions")
@@ -246,6 +371,7 @@ mod tests {
         );
         assert!(returned_cameras.first().unwrap().can_view);
         assert!(!returned_cameras.first().unwrap().can_control);
+        assert!(!returned_cameras.first().unwrap().is_online);
 
         Ok(())
     }
@@ -263,4 +389,58 @@ mod tests {
 
         Ok(())
     }
+
+    #[sqlx::test(fixtures(path = "../../fixtures", scripts("cameras")))]
+    async fn online_offline(pool: SqlitePool) -> Result<(), Box<dyn std::error::Error>> {
+        let camera_id = 1;
+        let first_connected_at = OffsetDateTime::from_unix_timestamp(1_729_443_378)?;
+        let second_connected_at = OffsetDateTime::from_unix_timestamp(1_729_443_400)?;
+
+        Camera::set_online(&pool, camera_id, first_connected_at).await?;
+        // Reconnected before the first connection closed
+        Camera::set_online(&pool, camera_id, second_connected_at).await?;
+
+        assert!(!Camera::set_offline(&pool, camera_id, first_connected_at).await?);
+
+        let returned_camera = Camera::get_using_id(&pool, camera_id).await?;
+        assert!(returned_camera.is_online);
+        assert_eq!(returned_camera.last_connected, Some(second_connected_at));
+
+        assert!(Camera::set_offline(&pool, camera_id, second_connected_at).await?);
+
+        let returned_camera = Camera::get_using_id(&pool, camera_id).await?;
+        assert!(!returned_camera.is_online);
+        assert_eq!(returned_camera.last_connected, Some(second_connected_at));
+
+        Camera::set_online(&pool, 2, second_connected_at).await?;
+        Camera::set_all_offline(&pool).await?;
+
+        assert!(!Camera::get_using_id(&pool, 2).await?.is_online);
+
+        Ok(())
+    }
+
+    #[sqlx::test(fixtures(path = "../../fixtures", scripts("cameras")))]
+    async fn pairing_token(pool: SqlitePool) -> Result<()> {
+        let returned_camera = Camera::get_using_id(&pool, 1).await?;
+
+        assert!(returned_camera.verify_pairing_token("front_door_pairing_token"));
+        assert!(!returned_camera.verify_pairing_token("kitchen_pairing_token"));
+        assert!(!returned_camera.verify_pairing_token(""));
+
+        let token = Camera::generate_pairing_token();
+
+        assert_eq!(token.len(), 64);
+        assert_ne!(token, Camera::generate_pairing_token());
+        assert_ne!(Camera::hash_pairing_token(&token), token);
+
+        let tokenless_camera = Camera {
+            token_hash: None,
+            ..returned_camera
+        };
+
+        assert!(!tokenless_camera.verify_pairing_token(""));
+
+        Ok(())
+    }
 }
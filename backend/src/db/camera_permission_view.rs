@@ -1,10 +1,13 @@
 use serde::Serialize;
+use time::OffsetDateTime;
 
 #[derive(Debug, Serialize)]
 pub struct CameraPermissionView {
     pub camera_id: i64,
     pub camera_name: String,
     pub ip_address: Option<String>,
+    pub last_connected: Option<OffsetDateTime>,
+    pub is_online: bool,
     pub can_view: bool,
     pub can_control: bool,
 }
@@ -2,31 +2,54 @@ use serde::{Deserialize, Serialize};
 use sqlx::{Result, SqlitePool};
 use time::OffsetDateTime;
 
-use super::Model;
+use super::{Model, VideoFormat};
+use crate::Resolution;
+
+pub use oko_protocol::CameraSettingNoMeta;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, sqlx::Type)]
+#[serde(rename_all = "lowercase")]
+#[sqlx(rename_all = "lowercase")]
+pub enum RecordingMode {
+    /// Record every frame for as long as the camera is connected
+    Continuous,
+    /// Only record around motion events, see `motion_pre_roll_seconds`/`motion_post_roll_seconds`
+    Motion,
+}
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct CameraSetting {
     pub setting_id: i64,
     pub camera_id: i64,
     pub flashlight_enabled: bool,
-    pub resolution: String,
+    pub resolution: Resolution,
     pub framerate: i64,
     pub last_modified: OffsetDateTime,
     pub modified_by: Option<i64>,
+    pub recording_mode: RecordingMode,
+    /// 1 (least sensitive) to 100 (most sensitive)
+    pub motion_sensitivity: i64,
+    pub motion_pre_roll_seconds: i64,
+    pub motion_post_roll_seconds: i64,
+    /// Recordings are split into videos of (at most) this length
+    pub segment_length_seconds: i64,
+    /// Videos older than this are deleted, unless they are flagged as `keep_forever`
+    pub max_video_age_days: Option<i64>,
+    /// Format of new videos, existing videos keep the format they were recorded in
+    pub recording_format: VideoFormat,
 }
 
 // TODO: Add from trait for CameraSetting -> CameraSettingNoMeta
-// TODO: Use single shared definition for both camera and backend
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct CameraSettingNoMeta {
-    pub flashlight_enabled: bool,
-    pub resolution: String,
-    pub framerate: i64,
-}
-
 pub struct Default {
     pub setting_id: i64,
     pub flashlight_enabled: bool,
+    pub recording_mode: RecordingMode,
+    pub motion_sensitivity: i64,
+    pub motion_pre_roll_seconds: i64,
+    pub motion_post_roll_seconds: i64,
+    pub segment_length_seconds: i64,
+    pub max_video_age_days: Option<i64>,
+    pub recording_format: VideoFormat,
 }
 
 impl Default {
@@ -41,14 +64,23 @@ impl Model for CameraSetting {
     const DEFAULT: Default = Default {
         setting_id: -1,
         flashlight_enabled: false,
+        recording_mode: RecordingMode::Continuous,
+        motion_sensitivity: 50,
+        motion_pre_roll_seconds: 5,
+        motion_post_roll_seconds: 10,
+        segment_length_seconds: 300,
+        max_video_age_days: None,
+        recording_format: VideoFormat::Mp4,
     };
 
     async fn create_using_self(&mut self, pool: &SqlitePool) -> Result<()> {
         let result = sqlx::query!(
             r#"
             INSERT INTO camera_settings
-            (camera_id, flashlight_enabled, resolution, framerate, last_modified, modified_by)
-            VALUES (?, ?, ?, ?, ?, ?)
+            (camera_id, flashlight_enabled, resolution, framerate, last_modified, modified_by,
+             recording_mode, motion_sensitivity, motion_pre_roll_seconds, motion_post_roll_seconds,
+             segment_length_seconds, max_video_age_days, recording_format)
+            VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)
             RETURNING setting_id
             "#,
             self.camera_id,
@@ -56,7 +88,14 @@ impl Model for CameraSetting {
             self.resolution,
             self.framerate,
             self.last_modified,
-            self.modified_by
+            self.modified_by,
+            self.recording_mode,
+            self.motion_sensitivity,
+            self.motion_pre_roll_seconds,
+            self.motion_post_roll_seconds,
+            self.segment_length_seconds,
+            self.max_video_age_days,
+            self.recording_format
         )
         .fetch_one(pool)
         .await?;
@@ -70,8 +109,12 @@ impl Model for CameraSetting {
         sqlx::query_as!(
             CameraSetting,
             r#"
-            SELECT setting_id, camera_id, flashlight_enabled, resolution,
-                   framerate, last_modified, modified_by
+            SELECT setting_id, camera_id, flashlight_enabled,
+                   resolution as "resolution: Resolution", framerate, last_modified, modified_by,
+                   recording_mode as "recording_mode: RecordingMode",
+                   motion_sensitivity, motion_pre_roll_seconds, motion_post_roll_seconds,
+                   segment_length_seconds, max_video_age_days,
+                   recording_format as "recording_format: VideoFormat"
             FROM camera_settings WHERE setting_id = ?
             "#,
             id
@@ -86,7 +129,10 @@ impl Model for CameraSetting {
             UPDATE camera_settings
             SET flashlight_enabled = ?, resolution = ?,
                 framerate = ?, last_modified = ?,
-                modified_by = ?
+                modified_by = ?, recording_mode = ?,
+                motion_sensitivity = ?, motion_pre_roll_seconds = ?,
+                motion_post_roll_seconds = ?, segment_length_seconds = ?,
+                max_video_age_days = ?, recording_format = ?
             WHERE setting_id = ?
             RETURNING setting_id
             "#,
@@ -95,6 +141,13 @@ impl Model for CameraSetting {
             self.framerate,
             self.last_modified,
             self.modified_by,
+            self.recording_mode,
+            self.motion_sensitivity,
+            self.motion_pre_roll_seconds,
+            self.motion_post_roll_seconds,
+            self.segment_length_seconds,
+            self.max_video_age_days,
+            self.recording_format,
             self.setting_id
         )
         .fetch_one(pool)
@@ -125,7 +178,12 @@ impl CameraSetting {
         sqlx::query_as!(
             CameraSetting,
             r#"
-            SELECT *
+            SELECT setting_id, camera_id, flashlight_enabled,
+                   resolution as "resolution: Resolution", framerate, last_modified, modified_by,
+                   recording_mode as "recording_mode: RecordingMode",
+                   motion_sensitivity, motion_pre_roll_seconds, motion_post_roll_seconds,
+                   segment_length_seconds, max_video_age_days,
+                   recording_format as "recording_format: VideoFormat"
             FROM camera_settings
             WHERE camera_id = ?
             "#,
@@ -134,6 +192,23 @@ impl CameraSetting {
         .fetch_one(pool)
         .await
     }
+
+    pub async fn list(pool: &SqlitePool) -> Result<Vec<Self>> {
+        sqlx::query_as!(
+            CameraSetting,
+            r#"
+            SELECT setting_id, camera_id, flashlight_enabled,
+                   resolution as "resolution: Resolution", framerate, last_modified, modified_by,
+                   recording_mode as "recording_mode: RecordingMode",
+                   motion_sensitivity, motion_pre_roll_seconds, motion_post_roll_seconds,
+                   segment_length_seconds, max_video_age_days,
+                   recording_format as "recording_format: VideoFormat"
+            FROM camera_settings
+            "#
+        )
+        .fetch_all(pool)
+        .await
+    }
 }
 
 #[cfg(test)]
@@ -149,10 +224,17 @@ mod tests {
             setting_id: CameraSetting::DEFAULT.setting_id,
             camera_id: 1,
             flashlight_enabled: true,
-            resolution: "1920x1080".to_string(),
+            resolution: Resolution::Vga,
             framerate: 30,
             last_modified: CameraSetting::DEFAULT.last_modified(),
             modified_by: Some(1),
+            recording_mode: RecordingMode::Motion,
+            motion_sensitivity: 80,
+            motion_pre_roll_seconds: CameraSetting::DEFAULT.motion_pre_roll_seconds,
+            motion_post_roll_seconds: CameraSetting::DEFAULT.motion_post_roll_seconds,
+            segment_length_seconds: 600,
+            max_video_age_days: Some(30),
+            recording_format: VideoFormat::Mjpeg,
         };
 
         camera_setting.create_using_self(&pool).await?;
@@ -171,6 +253,26 @@ mod tests {
         assert_eq!(returned_setting.framerate, camera_setting.framerate);
         assert_eq!(returned_setting.last_modified, camera_setting.last_modified);
         assert_eq!(returned_setting.modified_by, camera_setting.modified_by);
+        assert_eq!(
+            returned_setting.recording_mode,
+            camera_setting.recording_mode
+        );
+        assert_eq!(
+            returned_setting.motion_sensitivity,
+            camera_setting.motion_sensitivity
+        );
+        assert_eq!(
+            returned_setting.segment_length_seconds,
+            camera_setting.segment_length_seconds
+        );
+        assert_eq!(
+            returned_setting.max_video_age_days,
+            camera_setting.max_video_age_days
+        );
+        assert_eq!(
+            returned_setting.recording_format,
+            camera_setting.recording_format
+        );
 
         Ok(())
     }
@@ -187,13 +289,20 @@ mod tests {
         assert_eq!(returned_setting.setting_id, setting_id);
         assert_eq!(returned_setting.camera_id, 1);
         assert!(!returned_setting.flashlight_enabled);
-        assert_eq!(returned_setting.resolution, "SVGA");
+        assert_eq!(returned_setting.resolution, Resolution::Svga);
         assert_eq!(returned_setting.framerate, 5);
         assert_eq!(
             returned_setting.last_modified,
             OffsetDateTime::from_unix_timestamp(1_729_530_153)?
         );
         assert_eq!(returned_setting.modified_by, Some(1));
+        assert_eq!(returned_setting.recording_mode, RecordingMode::Continuous);
+        assert_eq!(returned_setting.motion_sensitivity, 50);
+        assert_eq!(returned_setting.motion_pre_roll_seconds, 5);
+        assert_eq!(returned_setting.motion_post_roll_seconds, 10);
+        assert_eq!(returned_setting.segment_length_seconds, 300);
+        assert_eq!(returned_setting.max_video_age_days, None);
+        assert_eq!(returned_setting.recording_format, VideoFormat::Mp4);
 
         Ok(())
     }
@@ -209,10 +318,17 @@ mod tests {
             setting_id: old_camera_setting.setting_id,
             camera_id: old_camera_setting.camera_id,
             flashlight_enabled: true,
-            resolution: "1920x1080".to_string(),
+            resolution: Resolution::Vga,
             framerate: old_camera_setting.framerate,
             last_modified: OffsetDateTime::from_unix_timestamp(1_729_526_553)?,
             modified_by: Some(1),
+            recording_mode: RecordingMode::Motion,
+            motion_sensitivity: 20,
+            motion_pre_roll_seconds: 2,
+            motion_post_roll_seconds: 30,
+            segment_length_seconds: 60,
+            max_video_age_days: Some(7),
+            recording_format: VideoFormat::Mjpeg,
         };
 
         let updated = new_camera_setting.update_using_self(&pool).await;
@@ -233,6 +349,34 @@ mod tests {
             new_camera_setting.last_modified
         );
         assert_eq!(returned_setting.modified_by, new_camera_setting.modified_by);
+        assert_eq!(
+            returned_setting.recording_mode,
+            new_camera_setting.recording_mode
+        );
+        assert_eq!(
+            returned_setting.motion_sensitivity,
+            new_camera_setting.motion_sensitivity
+        );
+        assert_eq!(
+            returned_setting.motion_pre_roll_seconds,
+            new_camera_setting.motion_pre_roll_seconds
+        );
+        assert_eq!(
+            returned_setting.motion_post_roll_seconds,
+            new_camera_setting.motion_post_roll_seconds
+        );
+        assert_eq!(
+            returned_setting.segment_length_seconds,
+            new_camera_setting.segment_length_seconds
+        );
+        assert_eq!(
+            returned_setting.max_video_age_days,
+            new_camera_setting.max_video_age_days
+        );
+        assert_eq!(
+            returned_setting.recording_format,
+            new_camera_setting.recording_format
+        );
 
         Ok(())
     }
@@ -268,6 +412,17 @@ mod tests {
         assert_eq!(returned_settings.setting_id, 1);
         assert_eq!(returned_settings.camera_id, camera_id);
 
+        Ok(())
+    }
+    #[sqlx::test(fixtures(
+        path = "../../fixtures",
+        scripts("users", "cameras", "camera_settings")
+    ))]
+    async fn list(pool: SqlitePool) -> Result<()> {
+        let returned_settings = CameraSetting::list(&pool).await?;
+
+        assert_eq!(returned_settings.len(), 2);
+
         Ok(())
     }
 }
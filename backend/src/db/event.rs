@@ -0,0 +1,402 @@
+use serde::{Deserialize, Serialize};
+use sqlx::{Result, SqlitePool};
+use time::OffsetDateTime;
+
+use super::Model;
+
+/// Events listed when no limit is given
+const DEFAULT_PAGE_SIZE: i64 = 50;
+/// Most events that can be listed at once
+const MAX_PAGE_SIZE: i64 = 500;
+
+/// What happened.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, sqlx::Type)]
+#[serde(rename_all = "snake_case")]
+#[sqlx(rename_all = "snake_case")]
+pub enum EventKind {
+    /// Lasts until the motion recording stops
+    Motion,
+    CameraOffline,
+    CameraOnline,
+    /// Not about a camera, only shown to admins
+    LoginFailed,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Event {
+    pub event_id: i64,
+    /// `None` for events that aren't about a camera
+    pub camera_id: Option<i64>,
+    pub kind: EventKind,
+    pub start_time: OffsetDateTime,
+    /// `None` for events that are still happening or don't have a duration
+    pub end_time: Option<OffsetDateTime>,
+    /// Video recorded because of the event
+    pub video_id: Option<i64>,
+    /// JPEG of what the camera saw when the event started
+    pub snapshot_path: Option<String>,
+    /// Human readable context, e.g. the username of a failed login
+    pub details: Option<String>,
+    pub acknowledged: bool,
+    /// Motion zone that triggered a motion event, `None` if the camera has no include zones
+    pub zone_id: Option<i64>,
+}
+
+pub struct Default {
+    pub event_id: i64,
+    pub end_time: Option<OffsetDateTime>,
+    pub video_id: Option<i64>,
+    pub snapshot_path: Option<String>,
+    pub details: Option<String>,
+    pub acknowledged: bool,
+    pub zone_id: Option<i64>,
+}
+
+/// Which events to list, newest first
+#[derive(Debug, Clone, Copy, Default, Deserialize)]
+pub struct EventFilter {
+    pub camera_id: Option<i64>,
+    pub kind: Option<EventKind>,
+    pub acknowledged: Option<bool>,
+    /// Only events older than this one, i.e. the last `event_id` of the previous page
+    pub before: Option<i64>,
+    /// Capped at 500, 50 if not given
+    pub limit: Option<i64>,
+}
+
+impl Model for Event {
+    type Default = Default;
+    const DEFAULT: Default = Default {
+        event_id: -1,
+        end_time: None,
+        video_id: None,
+        snapshot_path: None,
+        details: None,
+        acknowledged: false,
+        zone_id: None,
+    };
+
+    async fn create_using_self(&mut self, pool: &SqlitePool) -> Result<()> {
+        let result = sqlx::query!(
+            r#"
+            INSERT INTO events
+            (camera_id, kind, start_time, end_time, video_id, snapshot_path, details, acknowledged,
+             zone_id)
+            VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?)
+            RETURNING event_id
+            "#,
+            self.camera_id,
+            self.kind,
+            self.start_time,
+            self.end_time,
+            self.video_id,
+            self.snapshot_path,
+            self.details,
+            self.acknowledged,
+            self.zone_id
+        )
+        .fetch_one(pool)
+        .await?;
+
+        self.event_id = result.event_id;
+
+        Ok(())
+    }
+
+    async fn get_using_id(pool: &SqlitePool, id: i64) -> Result<Self> {
+        sqlx::query_as!(
+            Event,
+            r#"
+            SELECT event_id, camera_id, kind as "kind: EventKind", start_time, end_time, video_id,
+                   snapshot_path, details, acknowledged, zone_id
+            FROM events WHERE event_id = ?
+            "#,
+            id
+        )
+        .fetch_one(pool)
+        .await
+    }
+
+    async fn update_using_self(&self, pool: &SqlitePool) -> Result<()> {
+        sqlx::query!(
+            r#"
+            UPDATE events
+            SET end_time = ?, video_id = ?, snapshot_path = ?, details = ?, acknowledged = ?
+            WHERE event_id = ?
+            RETURNING event_id
+            "#,
+            self.end_time,
+            self.video_id,
+            self.snapshot_path,
+            self.details,
+            self.acknowledged,
+            self.event_id
+        )
+        .fetch_one(pool)
+        .await?;
+
+        Ok(())
+    }
+
+    async fn delete_using_id(pool: &SqlitePool, id: i64) -> Result<()> {
+        sqlx::query!(
+            r#"
+            DELETE
+            FROM events
+            WHERE event_id = ?
+            RETURNING event_id
+            "#,
+            id
+        )
+        .fetch_one(pool)
+        .await?;
+
+        Ok(())
+    }
+}
+
+impl Event {
+    /// An event that started at `start_time` and hasn't been saved yet
+    #[must_use]
+    pub fn new(camera_id: Option<i64>, kind: EventKind, start_time: OffsetDateTime) -> Self {
+        Self {
+            event_id: Self::DEFAULT.event_id,
+            camera_id,
+            kind,
+            start_time,
+            end_time: Self::DEFAULT.end_time,
+            video_id: Self::DEFAULT.video_id,
+            snapshot_path: Self::DEFAULT.snapshot_path,
+            details: Self::DEFAULT.details,
+            acknowledged: Self::DEFAULT.acknowledged,
+            zone_id: Self::DEFAULT.zone_id,
+        }
+    }
+
+    /// Lists the events recorded in a video, oldest first
+    pub async fn list_for_video(pool: &SqlitePool, video_id: i64) -> Result<Vec<Self>> {
+        sqlx::query_as!(
+            Event,
+            r#"
+            SELECT event_id, camera_id, kind as "kind: EventKind", start_time, end_time, video_id,
+                   snapshot_path, details, acknowledged, zone_id
+            FROM events WHERE video_id = ?
+            ORDER BY event_id
+            "#,
+            video_id
+        )
+        .fetch_all(pool)
+        .await
+    }
+
+    /// Lists the events matching `filter` about cameras the user can view, newest first.
+    ///
+    /// Events that aren't about a camera are only listed for admins.
+    pub async fn list_for_user(
+        pool: &SqlitePool,
+        user_id: i64,
+        is_admin: bool,
+        filter: EventFilter,
+    ) -> Result<Vec<Self>> {
+        let limit = filter
+            .limit
+            .unwrap_or(DEFAULT_PAGE_SIZE)
+            .clamp(1, MAX_PAGE_SIZE);
+
+        sqlx::query_as!(
+            Event,
+            r#"
+            SELECT e.event_id, e.camera_id, e.kind as "kind: EventKind", e.start_time, e.end_time,
+                   e.video_id, e.snapshot_path, e.details, e.acknowledged, e.zone_id
+            FROM events e
+            LEFT JOIN camera_permissions cp ON e.camera_id = cp.camera_id AND cp.user_id = ?
+            WHERE (cp.can_view OR (e.camera_id IS NULL AND ?))
+              AND (? IS NULL OR e.camera_id = ?)
+              AND (? IS NULL OR e.kind = ?)
+              AND (? IS NULL OR e.acknowledged = ?)
+              AND (? IS NULL OR e.event_id < ?)
+            ORDER BY e.event_id DESC
+            LIMIT ?
+            "#,
+            user_id,
+            is_admin,
+            filter.camera_id,
+            filter.camera_id,
+            filter.kind,
+            filter.kind,
+            filter.acknowledged,
+            filter.acknowledged,
+            filter.before,
+            filter.before,
+            limit
+        )
+        .fetch_all(pool)
+        .await
+    }
+}
+
+#[allow(clippy::unwrap_used)]
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[sqlx::test(fixtures(path = "../../fixtures", scripts("cameras", "videos", "events")))]
+    async fn create(pool: SqlitePool) -> Result<()> {
+        let mut event = Event::new(Some(1), EventKind::Motion, OffsetDateTime::now_utc());
+        event.video_id = Some(1);
+
+        event.create_using_self(&pool).await?;
+
+        assert_eq!(event.event_id, 6);
+
+        let returned_event = Event::get_using_id(&pool, 6).await?;
+
+        assert_eq!(returned_event.camera_id, event.camera_id);
+        assert_eq!(returned_event.kind, EventKind::Motion);
+        assert_eq!(returned_event.video_id, Some(1));
+        assert_eq!(returned_event.end_time, None);
+        assert!(!returned_event.acknowledged);
+
+        Ok(())
+    }
+
+    #[sqlx::test(fixtures(path = "../../fixtures", scripts("cameras", "videos", "events")))]
+    async fn update(pool: SqlitePool) -> Result<()> {
+        let mut event = Event::get_using_id(&pool, 2).await?;
+        let end_time = OffsetDateTime::now_utc().replace_nanosecond(0).unwrap();
+
+        event.end_time = Some(end_time);
+        event.acknowledged = true;
+        event.update_using_self(&pool).await?;
+
+        let returned_event = Event::get_using_id(&pool, 2).await?;
+
+        assert_eq!(returned_event.end_time, Some(end_time));
+        assert!(returned_event.acknowledged);
+
+        Ok(())
+    }
+
+    #[sqlx::test(fixtures(
+        path = "../../fixtures",
+        scripts("cameras", "videos", "motion_zones", "events")
+    ))]
+    async fn zone(pool: SqlitePool) -> Result<()> {
+        let mut event = Event::new(Some(2), EventKind::Motion, OffsetDateTime::now_utc());
+        event.zone_id = Some(2);
+
+        event.create_using_self(&pool).await?;
+
+        assert_eq!(
+            Event::get_using_id(&pool, event.event_id).await?.zone_id,
+            Some(2)
+        );
+
+        crate::db::MotionZone::delete_using_id(&pool, 2).await?;
+
+        assert_eq!(
+            Event::get_using_id(&pool, event.event_id).await?.zone_id,
+            None
+        );
+
+        Ok(())
+    }
+
+    #[sqlx::test(fixtures(path = "../../fixtures", scripts("cameras", "videos", "events")))]
+    async fn deleted_video_is_unlinked(pool: SqlitePool) -> Result<()> {
+        crate::db::Video::delete_using_id(&pool, 1).await?;
+
+        let returned_event = Event::get_using_id(&pool, 1).await?;
+
+        assert_eq!(returned_event.video_id, None);
+
+        Ok(())
+    }
+
+    #[sqlx::test(fixtures(path = "../../fixtures", scripts("cameras", "videos", "events")))]
+    async fn list_for_video(pool: SqlitePool) -> Result<()> {
+        let mut event = Event::new(Some(1), EventKind::Motion, OffsetDateTime::now_utc());
+        event.video_id = Some(1);
+        event.create_using_self(&pool).await?;
+
+        let event_ids: Vec<i64> = Event::list_for_video(&pool, 1)
+            .await?
+            .into_iter()
+            .map(|event| event.event_id)
+            .collect();
+
+        assert_eq!(event_ids, [1, event.event_id]);
+        assert!(Event::list_for_video(&pool, 3).await?.is_empty());
+
+        Ok(())
+    }
+
+    #[sqlx::test(fixtures(
+        path = "../../fixtures",
+        scripts("users", "cameras", "camera_permissions", "videos", "events")
+    ))]
+    async fn list_for_user(pool: SqlitePool) -> Result<()> {
+        let event_ids = |events: Vec<Event>| -> Vec<i64> {
+            events.into_iter().map(|event| event.event_id).collect()
+        };
+
+        let admin_events = Event::list_for_user(&pool, 1, true, EventFilter::default()).await?;
+        assert_eq!(event_ids(admin_events), [5, 4, 3, 2, 1]);
+
+        // joedaly can only view the front door camera
+        let joedaly_events = Event::list_for_user(&pool, 3, false, EventFilter::default()).await?;
+        assert_eq!(event_ids(joedaly_events), [5, 1]);
+
+        let kitchen_motion_events = Event::list_for_user(
+            &pool,
+            1,
+            true,
+            EventFilter {
+                camera_id: Some(2),
+                kind: Some(EventKind::Motion),
+                ..EventFilter::default()
+            },
+        )
+        .await?;
+        assert_eq!(event_ids(kitchen_motion_events), [2]);
+
+        let unacknowledged_events = Event::list_for_user(
+            &pool,
+            1,
+            true,
+            EventFilter {
+                acknowledged: Some(false),
+                ..EventFilter::default()
+            },
+        )
+        .await?;
+        assert_eq!(event_ids(unacknowledged_events), [5, 4, 3, 2]);
+
+        Ok(())
+    }
+
+    #[sqlx::test(fixtures(
+        path = "../../fixtures",
+        scripts("users", "cameras", "camera_permissions", "videos", "events")
+    ))]
+    async fn list_pages(pool: SqlitePool) -> Result<()> {
+        let page = |before| EventFilter {
+            before,
+            limit: Some(2),
+            ..EventFilter::default()
+        };
+
+        let first_page = Event::list_for_user(&pool, 1, true, page(None)).await?;
+        assert_eq!(first_page.len(), 2);
+        assert_eq!(first_page.last().unwrap().event_id, 4);
+
+        let second_page = Event::list_for_user(&pool, 1, true, page(Some(4))).await?;
+        assert_eq!(second_page.first().unwrap().event_id, 3);
+        assert_eq!(second_page.last().unwrap().event_id, 2);
+
+        let last_page = Event::list_for_user(&pool, 1, true, page(Some(2))).await?;
+        assert_eq!(last_page.len(), 1);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,239 @@
+use serde::{Deserialize, Serialize};
+use sqlx::{types::Json, Result, SqlitePool};
+use time::OffsetDateTime;
+
+use super::{polygon::is_valid_polygon, Model, PolygonPoint};
+
+/// Longest name a zone can have
+const MAX_NAME_LENGTH: usize = 64;
+
+/// Whether motion in a zone counts or is ignored.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, sqlx::Type)]
+#[serde(rename_all = "lowercase")]
+#[sqlx(rename_all = "lowercase")]
+pub enum MotionZoneKind {
+    /// Once a camera has one of these, motion only counts inside them
+    Include,
+    /// Motion is always ignored here, e.g. a swaying tree
+    Exclude,
+}
+
+/// A part of a camera's view used by motion detection, see `crate::recording::MotionDetector`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MotionZone {
+    pub zone_id: i64,
+    pub camera_id: i64,
+    pub name: String,
+    pub kind: MotionZoneKind,
+    pub points: Json<Vec<PolygonPoint>>,
+    /// 1-100, how little of the zone has to change to count as motion,
+    /// `None` uses `CameraSetting::motion_sensitivity`
+    pub sensitivity: Option<i64>,
+    pub created_at: OffsetDateTime,
+}
+
+pub struct Default {
+    pub zone_id: i64,
+    pub sensitivity: Option<i64>,
+}
+
+impl Default {
+    #[allow(clippy::unused_self)]
+    pub fn created_at(&self) -> OffsetDateTime {
+        OffsetDateTime::now_utc()
+    }
+}
+
+impl Model for MotionZone {
+    type Default = Default;
+    const DEFAULT: Default = Default {
+        zone_id: -1,
+        sensitivity: None,
+    };
+
+    async fn create_using_self(&mut self, pool: &SqlitePool) -> Result<()> {
+        let result = sqlx::query!(
+            r#"
+            INSERT INTO motion_zones (camera_id, name, kind, points, sensitivity, created_at)
+            VALUES (?, ?, ?, ?, ?, ?)
+            RETURNING zone_id
+            "#,
+            self.camera_id,
+            self.name,
+            self.kind,
+            self.points,
+            self.sensitivity,
+            self.created_at
+        )
+        .fetch_one(pool)
+        .await?;
+
+        self.zone_id = result.zone_id;
+
+        Ok(())
+    }
+
+    async fn get_using_id(pool: &SqlitePool, id: i64) -> Result<Self> {
+        sqlx::query_as!(
+            MotionZone,
+            r#"
+            SELECT zone_id, camera_id, name, kind as "kind: MotionZoneKind",
+                   points as "points: Json<Vec<PolygonPoint>>", sensitivity, created_at
+            FROM motion_zones
+            WHERE zone_id = ?
+            "#,
+            id
+        )
+        .fetch_one(pool)
+        .await
+    }
+
+    async fn update_using_self(&self, pool: &SqlitePool) -> Result<()> {
+        sqlx::query!(
+            r#"
+            UPDATE motion_zones
+            SET name = ?, kind = ?, points = ?, sensitivity = ?
+            WHERE zone_id = ?
+            RETURNING zone_id
+            "#,
+            self.name,
+            self.kind,
+            self.points,
+            self.sensitivity,
+            self.zone_id
+        )
+        .fetch_one(pool)
+        .await?;
+
+        Ok(())
+    }
+
+    async fn delete_using_id(pool: &SqlitePool, id: i64) -> Result<()> {
+        sqlx::query!(
+            r#"
+            DELETE
+            FROM motion_zones
+            WHERE zone_id = ?
+            RETURNING zone_id
+            "#,
+            id
+        )
+        .fetch_one(pool)
+        .await?;
+
+        Ok(())
+    }
+}
+
+impl MotionZone {
+    /// Whether the name, polygon and sensitivity are what the DB/motion detection expect
+    #[must_use]
+    pub fn is_valid(&self) -> bool {
+        !self.name.is_empty()
+            && self.name.chars().count() <= MAX_NAME_LENGTH
+            && is_valid_polygon(&self.points)
+            && self
+                .sensitivity
+                .map_or(true, |sensitivity| (1..=100).contains(&sensitivity))
+    }
+
+    pub async fn list_for_camera(pool: &SqlitePool, camera_id: i64) -> Result<Vec<Self>> {
+        sqlx::query_as!(
+            MotionZone,
+            r#"
+            SELECT zone_id, camera_id, name, kind as "kind: MotionZoneKind",
+                   points as "points: Json<Vec<PolygonPoint>>", sensitivity, created_at
+            FROM motion_zones
+            WHERE camera_id = ?
+            ORDER BY zone_id ASC
+            "#,
+            camera_id
+        )
+        .fetch_all(pool)
+        .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn zone(kind: MotionZoneKind, sensitivity: Option<i64>) -> MotionZone {
+        MotionZone {
+            zone_id: MotionZone::DEFAULT.zone_id,
+            camera_id: 1,
+            name: "Driveway".to_string(),
+            kind,
+            points: Json(vec![
+                PolygonPoint { x: 0.0, y: 0.5 },
+                PolygonPoint { x: 1.0, y: 0.5 },
+                PolygonPoint { x: 1.0, y: 1.0 },
+            ]),
+            sensitivity,
+            created_at: MotionZone::DEFAULT.created_at(),
+        }
+    }
+
+    #[test]
+    fn valid() {
+        assert!(zone(MotionZoneKind::Include, None).is_valid());
+        assert!(zone(MotionZoneKind::Exclude, Some(100)).is_valid());
+        assert!(!zone(MotionZoneKind::Include, Some(0)).is_valid());
+
+        let unnamed_zone = MotionZone {
+            name: String::new(),
+            ..zone(MotionZoneKind::Include, None)
+        };
+        assert!(!unnamed_zone.is_valid());
+    }
+
+    #[sqlx::test(fixtures(path = "../../fixtures", scripts("users", "cameras", "motion_zones")))]
+    async fn create(pool: SqlitePool) -> Result<()> {
+        let mut new_zone = zone(MotionZoneKind::Include, Some(60));
+
+        new_zone.create_using_self(&pool).await?;
+
+        assert_eq!(new_zone.zone_id, 4);
+
+        let returned_zone = MotionZone::get_using_id(&pool, 4).await?;
+
+        assert_eq!(returned_zone.camera_id, 1);
+        assert_eq!(returned_zone.kind, MotionZoneKind::Include);
+        assert_eq!(returned_zone.points.0, new_zone.points.0);
+        assert_eq!(returned_zone.sensitivity, Some(60));
+
+        Ok(())
+    }
+
+    #[sqlx::test(fixtures(path = "../../fixtures", scripts("users", "cameras", "motion_zones")))]
+    async fn update(pool: SqlitePool) -> Result<()> {
+        let mut returned_zone = MotionZone::get_using_id(&pool, 1).await?;
+
+        returned_zone.kind = MotionZoneKind::Include;
+        returned_zone.sensitivity = Some(10);
+        returned_zone.update_using_self(&pool).await?;
+
+        let updated_zone = MotionZone::get_using_id(&pool, 1).await?;
+
+        assert_eq!(updated_zone.kind, MotionZoneKind::Include);
+        assert_eq!(updated_zone.sensitivity, Some(10));
+
+        Ok(())
+    }
+
+    #[sqlx::test(fixtures(path = "../../fixtures", scripts("users", "cameras", "motion_zones")))]
+    async fn list_for_camera(pool: SqlitePool) -> Result<()> {
+        let zone_ids: Vec<i64> = MotionZone::list_for_camera(&pool, 2)
+            .await?
+            .into_iter()
+            .map(|zone| zone.zone_id)
+            .collect();
+        assert_eq!(zone_ids, [2, 3]);
+
+        crate::db::Camera::delete_using_id(&pool, 2).await?;
+
+        assert!(MotionZone::list_for_camera(&pool, 2).await?.is_empty());
+
+        Ok(())
+    }
+}
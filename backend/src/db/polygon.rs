@@ -0,0 +1,56 @@
+use serde::{Deserialize, Serialize};
+
+/// Fewest points a polygon can have
+const MIN_POLYGON_POINTS: usize = 3;
+/// Most points a polygon can have, plenty for drawing around a window/road
+const MAX_POLYGON_POINTS: usize = 32;
+
+/// A corner of a polygon drawn over a camera's frames.
+///
+/// Both coordinates go from 0 to 1 relative to the frame size, with (0, 0) being the top left,
+/// so polygons stay in place when the resolution changes.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct PolygonPoint {
+    pub x: f64,
+    pub y: f64,
+}
+
+/// Whether `points` has a sensible amount of points, all within the frame
+pub fn is_valid_polygon(points: &[PolygonPoint]) -> bool {
+    (MIN_POLYGON_POINTS..=MAX_POLYGON_POINTS).contains(&points.len())
+        && points
+            .iter()
+            .all(|point| (0.0..=1.0).contains(&point.x) && (0.0..=1.0).contains(&point.y))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const fn point(x: f64, y: f64) -> PolygonPoint {
+        PolygonPoint { x, y }
+    }
+
+    #[test]
+    fn valid_polygons() {
+        assert!(is_valid_polygon(&[
+            point(0.0, 0.0),
+            point(1.0, 0.0),
+            point(1.0, 1.0)
+        ]));
+        assert!(!is_valid_polygon(&[point(0.0, 0.0), point(1.0, 0.0)]));
+        assert!(!is_valid_polygon(
+            &[point(0.5, 0.5); MAX_POLYGON_POINTS + 1]
+        ));
+        assert!(!is_valid_polygon(&[
+            point(0.0, 0.0),
+            point(1.5, 0.0),
+            point(1.0, 1.0)
+        ]));
+        assert!(!is_valid_polygon(&[
+            point(0.0, 0.0),
+            point(f64::NAN, 0.0),
+            point(1.0, 1.0)
+        ]));
+    }
+}
@@ -0,0 +1,231 @@
+use serde::{Deserialize, Serialize};
+use sqlx::{types::Json, Result, SqlitePool};
+use time::OffsetDateTime;
+
+use super::{polygon::is_valid_polygon, Model, PolygonPoint};
+
+/// Longest name a mask can have
+const MAX_NAME_LENGTH: usize = 64;
+
+/// A region of a camera's frames that is blacked out before anyone/anything sees it,
+/// see `crate::recording::apply_privacy_masks`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PrivacyMask {
+    pub mask_id: i64,
+    pub camera_id: i64,
+    /// e.g. "Neighbours' window"
+    pub name: String,
+    pub points: Json<Vec<PolygonPoint>>,
+    pub created_at: OffsetDateTime,
+}
+
+pub struct Default {
+    pub mask_id: i64,
+}
+
+impl Default {
+    #[allow(clippy::unused_self)]
+    pub fn created_at(&self) -> OffsetDateTime {
+        OffsetDateTime::now_utc()
+    }
+}
+
+impl Model for PrivacyMask {
+    type Default = Default;
+    const DEFAULT: Default = Default { mask_id: -1 };
+
+    async fn create_using_self(&mut self, pool: &SqlitePool) -> Result<()> {
+        let result = sqlx::query!(
+            r#"
+            INSERT INTO privacy_masks (camera_id, name, points, created_at)
+            VALUES (?, ?, ?, ?)
+            RETURNING mask_id
+            "#,
+            self.camera_id,
+            self.name,
+            self.points,
+            self.created_at
+        )
+        .fetch_one(pool)
+        .await?;
+
+        self.mask_id = result.mask_id;
+
+        Ok(())
+    }
+
+    async fn get_using_id(pool: &SqlitePool, id: i64) -> Result<Self> {
+        sqlx::query_as!(
+            PrivacyMask,
+            r#"
+            SELECT mask_id, camera_id, name, points as "points: Json<Vec<PolygonPoint>>", created_at
+            FROM privacy_masks
+            WHERE mask_id = ?
+            "#,
+            id
+        )
+        .fetch_one(pool)
+        .await
+    }
+
+    async fn update_using_self(&self, pool: &SqlitePool) -> Result<()> {
+        sqlx::query!(
+            r#"
+            UPDATE privacy_masks
+            SET name = ?, points = ?
+            WHERE mask_id = ?
+            RETURNING mask_id
+            "#,
+            self.name,
+            self.points,
+            self.mask_id
+        )
+        .fetch_one(pool)
+        .await?;
+
+        Ok(())
+    }
+
+    async fn delete_using_id(pool: &SqlitePool, id: i64) -> Result<()> {
+        sqlx::query!(
+            r#"
+            DELETE
+            FROM privacy_masks
+            WHERE mask_id = ?
+            RETURNING mask_id
+            "#,
+            id
+        )
+        .fetch_one(pool)
+        .await?;
+
+        Ok(())
+    }
+}
+
+impl PrivacyMask {
+    /// Whether the name and polygon are what the DB/`apply_privacy_masks` expect
+    #[must_use]
+    pub fn is_valid(&self) -> bool {
+        !self.name.is_empty()
+            && self.name.chars().count() <= MAX_NAME_LENGTH
+            && is_valid_polygon(&self.points)
+    }
+
+    pub async fn list_for_camera(pool: &SqlitePool, camera_id: i64) -> Result<Vec<Self>> {
+        sqlx::query_as!(
+            PrivacyMask,
+            r#"
+            SELECT mask_id, camera_id, name, points as "points: Json<Vec<PolygonPoint>>", created_at
+            FROM privacy_masks
+            WHERE camera_id = ?
+            ORDER BY mask_id ASC
+            "#,
+            camera_id
+        )
+        .fetch_all(pool)
+        .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn valid() {
+        let mask = PrivacyMask {
+            mask_id: PrivacyMask::DEFAULT.mask_id,
+            camera_id: 1,
+            name: "Window".to_string(),
+            points: Json(vec![
+                PolygonPoint { x: 0.0, y: 0.0 },
+                PolygonPoint { x: 1.0, y: 0.0 },
+                PolygonPoint { x: 1.0, y: 1.0 },
+            ]),
+            created_at: PrivacyMask::DEFAULT.created_at(),
+        };
+        assert!(mask.is_valid());
+
+        let unnamed_mask = PrivacyMask {
+            name: String::new(),
+            ..mask.clone()
+        };
+        assert!(!unnamed_mask.is_valid());
+
+        let line_mask = PrivacyMask {
+            points: Json(mask.points.0.iter().take(2).copied().collect()),
+            ..mask
+        };
+        assert!(!line_mask.is_valid());
+    }
+
+    #[sqlx::test(fixtures(path = "../../fixtures", scripts("users", "cameras", "privacy_masks")))]
+    async fn create(pool: SqlitePool) -> Result<()> {
+        let points = vec![
+            PolygonPoint { x: 0.1, y: 0.1 },
+            PolygonPoint { x: 0.9, y: 0.1 },
+            PolygonPoint { x: 0.5, y: 0.9 },
+        ];
+
+        let mut mask = PrivacyMask {
+            mask_id: PrivacyMask::DEFAULT.mask_id,
+            camera_id: 2,
+            name: "Window".to_string(),
+            points: Json(points.clone()),
+            created_at: PrivacyMask::DEFAULT.created_at(),
+        };
+
+        mask.create_using_self(&pool).await?;
+
+        assert_eq!(mask.mask_id, 3);
+
+        let returned_mask = PrivacyMask::get_using_id(&pool, 3).await?;
+
+        assert_eq!(returned_mask.camera_id, 2);
+        assert_eq!(returned_mask.name, mask.name);
+        assert_eq!(returned_mask.points.0, points);
+
+        Ok(())
+    }
+
+    #[sqlx::test(fixtures(path = "../../fixtures", scripts("users", "cameras", "privacy_masks")))]
+    async fn update(pool: SqlitePool) -> Result<()> {
+        let mut mask = PrivacyMask::get_using_id(&pool, 2).await?;
+
+        mask.name = "Road".to_string();
+        mask.points.0.push(PolygonPoint { x: 0.5, y: 1.0 });
+        mask.update_using_self(&pool).await?;
+
+        let returned_mask = PrivacyMask::get_using_id(&pool, 2).await?;
+
+        assert_eq!(returned_mask.name, "Road");
+        assert_eq!(returned_mask.points.0.len(), 4);
+
+        Ok(())
+    }
+
+    #[sqlx::test(fixtures(path = "../../fixtures", scripts("users", "cameras", "privacy_masks")))]
+    async fn list_for_camera(pool: SqlitePool) -> Result<()> {
+        let returned_masks = PrivacyMask::list_for_camera(&pool, 1).await?;
+
+        assert_eq!(returned_masks.len(), 2);
+        assert_eq!(
+            returned_masks.first().map(|mask| mask.points.0.len()),
+            Some(4)
+        );
+
+        assert!(PrivacyMask::list_for_camera(&pool, 2).await?.is_empty());
+
+        Ok(())
+    }
+
+    #[sqlx::test(fixtures(path = "../../fixtures", scripts("users", "cameras", "privacy_masks")))]
+    async fn deleted_camera(pool: SqlitePool) -> Result<()> {
+        crate::db::Camera::delete_using_id(&pool, 1).await?;
+
+        assert!(PrivacyMask::list_for_camera(&pool, 1).await?.is_empty());
+
+        Ok(())
+    }
+}
@@ -0,0 +1,129 @@
+use serde::{Deserialize, Serialize};
+use sqlx::{Result, SqlitePool};
+use time::OffsetDateTime;
+
+/// How the connection to the SMTP relay is secured.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, sqlx::Type)]
+#[serde(rename_all = "lowercase")]
+#[sqlx(rename_all = "lowercase")]
+pub enum SmtpSecurity {
+    /// Plain text, only for relays on the same machine/network
+    None,
+    /// Upgraded with `STARTTLS`, usually on port 587
+    Starttls,
+    /// TLS from the start, usually on port 465
+    Tls,
+}
+
+/// The relay email alerts are sent through, there is only ever one row of this.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SmtpSettings {
+    pub smtp_id: i64,
+    /// `None` disables email alerts
+    pub host: Option<String>,
+    pub port: i64,
+    pub security: SmtpSecurity,
+    pub username: Option<String>,
+    /// Never sent to clients
+    #[serde(skip)]
+    pub password: Option<String>,
+    pub from_address: Option<String>,
+    /// At most one alert is sent per camera in this many seconds
+    pub min_alert_interval_seconds: i64,
+    pub last_modified: OffsetDateTime,
+    pub modified_by: Option<i64>,
+}
+
+pub struct Default {
+    pub smtp_id: i64,
+}
+
+impl Default {
+    #[allow(clippy::unused_self)]
+    pub fn last_modified(&self) -> OffsetDateTime {
+        OffsetDateTime::now_utc()
+    }
+}
+
+impl SmtpSettings {
+    pub const DEFAULT: Default = Default { smtp_id: 1 };
+
+    pub async fn get(pool: &SqlitePool) -> Result<Self> {
+        sqlx::query_as!(
+            SmtpSettings,
+            r#"
+            SELECT smtp_id, host, port, security as "security: SmtpSecurity", username, password,
+                   from_address, min_alert_interval_seconds, last_modified, modified_by
+            FROM smtp_settings WHERE smtp_id = ?
+            "#,
+            Self::DEFAULT.smtp_id
+        )
+        .fetch_one(pool)
+        .await
+    }
+
+    pub async fn update_using_self(&self, pool: &SqlitePool) -> Result<()> {
+        sqlx::query!(
+            r#"
+            UPDATE smtp_settings
+            SET host = ?, port = ?, security = ?, username = ?, password = ?, from_address = ?,
+                min_alert_interval_seconds = ?, last_modified = ?, modified_by = ?
+            WHERE smtp_id = ?
+            RETURNING smtp_id
+            "#,
+            self.host,
+            self.port,
+            self.security,
+            self.username,
+            self.password,
+            self.from_address,
+            self.min_alert_interval_seconds,
+            self.last_modified,
+            self.modified_by,
+            self.smtp_id
+        )
+        .fetch_one(pool)
+        .await?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[sqlx::test(fixtures(path = "../../fixtures", scripts("users")))]
+    async fn get(pool: SqlitePool) -> Result<()> {
+        let returned_settings = SmtpSettings::get(&pool).await?;
+
+        assert_eq!(returned_settings.host, None);
+        assert_eq!(returned_settings.port, 587);
+        assert_eq!(returned_settings.security, SmtpSecurity::Starttls);
+        assert_eq!(returned_settings.min_alert_interval_seconds, 300);
+
+        Ok(())
+    }
+
+    #[sqlx::test(fixtures(path = "../../fixtures", scripts("users")))]
+    async fn update(pool: SqlitePool) -> Result<()> {
+        let mut settings = SmtpSettings::get(&pool).await?;
+
+        settings.host = Some("smtp.example.com".to_string());
+        settings.port = 465;
+        settings.security = SmtpSecurity::Tls;
+        settings.password = Some("hunter42".to_string());
+        settings.modified_by = Some(1);
+        settings.update_using_self(&pool).await?;
+
+        let returned_settings = SmtpSettings::get(&pool).await?;
+
+        assert_eq!(returned_settings.host, settings.host);
+        assert_eq!(returned_settings.port, 465);
+        assert_eq!(returned_settings.security, SmtpSecurity::Tls);
+        assert_eq!(returned_settings.password, settings.password);
+        assert_eq!(returned_settings.modified_by, Some(1));
+
+        Ok(())
+    }
+}
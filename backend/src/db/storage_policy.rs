@@ -0,0 +1,100 @@
+use serde::{Deserialize, Serialize};
+use sqlx::{Result, SqlitePool};
+use time::OffsetDateTime;
+
+/// The global storage policy, there is only ever one row of this.
+///
+/// Per-camera retention lives in `CameraSetting::max_video_age_days`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StoragePolicy {
+    pub policy_id: i64,
+    /// Oldest videos are deleted once all videos and their images take up more than this, `None` means no quota
+    pub max_total_size_bytes: Option<i64>,
+    pub last_modified: OffsetDateTime,
+    pub modified_by: Option<i64>,
+}
+
+pub struct Default {
+    pub policy_id: i64,
+}
+
+impl Default {
+    #[allow(clippy::unused_self)]
+    pub fn last_modified(&self) -> OffsetDateTime {
+        OffsetDateTime::now_utc()
+    }
+}
+
+impl StoragePolicy {
+    pub const DEFAULT: Default = Default { policy_id: 1 };
+
+    pub async fn get(pool: &SqlitePool) -> Result<Self> {
+        sqlx::query_as!(
+            StoragePolicy,
+            r#"
+            SELECT policy_id, max_total_size_bytes, last_modified, modified_by
+            FROM storage_policy WHERE policy_id = ?
+            "#,
+            Self::DEFAULT.policy_id
+        )
+        .fetch_one(pool)
+        .await
+    }
+
+    pub async fn update_using_self(&self, pool: &SqlitePool) -> Result<()> {
+        sqlx::query!(
+            r#"
+            UPDATE storage_policy
+            SET max_total_size_bytes = ?, last_modified = ?, modified_by = ?
+            WHERE policy_id = ?
+            RETURNING policy_id
+            "#,
+            self.max_total_size_bytes,
+            self.last_modified,
+            self.modified_by,
+            self.policy_id
+        )
+        .fetch_one(pool)
+        .await?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[sqlx::test(fixtures(path = "../../fixtures", scripts("users")))]
+    async fn get(pool: SqlitePool) -> Result<()> {
+        let returned_policy = StoragePolicy::get(&pool).await?;
+
+        assert_eq!(returned_policy.policy_id, StoragePolicy::DEFAULT.policy_id);
+        assert_eq!(returned_policy.max_total_size_bytes, None);
+        assert_eq!(returned_policy.modified_by, None);
+
+        Ok(())
+    }
+
+    #[sqlx::test(fixtures(path = "../../fixtures", scripts("users")))]
+    async fn update(pool: SqlitePool) -> Result<(), Box<dyn std::error::Error>> {
+        let new_policy = StoragePolicy {
+            policy_id: StoragePolicy::DEFAULT.policy_id,
+            max_total_size_bytes: Some(1024 * 1024),
+            last_modified: OffsetDateTime::from_unix_timestamp(1_729_526_553)?,
+            modified_by: Some(1),
+        };
+
+        new_policy.update_using_self(&pool).await?;
+
+        let returned_policy = StoragePolicy::get(&pool).await?;
+        assert_eq!(
+            returned_policy.max_total_size_bytes,
+            new_policy.max_total_size_bytes
+        );
+        assert_eq!(returned_policy.last_modified, new_policy.last_modified);
+        assert_eq!(returned_policy.modified_by, new_policy.modified_by);
+
+        Ok(())
+    }
+}
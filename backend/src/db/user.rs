@@ -5,12 +5,25 @@ use time::OffsetDateTime;
 
 use super::Model;
 
+/// Roles are ordered by privilege, so `role >= Role::Operator` means "operator or above".
+#[derive(
+    Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize, sqlx::Type,
+)]
+#[serde(rename_all = "lowercase")]
+#[sqlx(rename_all = "lowercase")]
+pub enum Role {
+    Viewer,
+    Operator,
+    Admin,
+}
+
 #[derive(Clone, Serialize, Deserialize)]
 pub struct User {
     pub user_id: i64,
     pub username: String,
     pub password_hash: String,
     pub created_at: OffsetDateTime,
+    pub role: Role,
 }
 
 // Here we've implemented `Debug` manually to avoid accidentally logging the
@@ -22,6 +35,7 @@ impl std::fmt::Debug for User {
             .field("username", &self.username)
             .field("password_hash", &"[redacted]")
             .field("created_at", &self.created_at)
+            .field("role", &self.role)
             .finish()
     }
 }
@@ -44,6 +58,7 @@ impl AuthUser for User {
 #[allow(dead_code)]
 pub struct Default {
     pub user_id: i64,
+    pub role: Role,
 }
 
 impl Default {
@@ -55,18 +70,22 @@ impl Default {
 
 impl Model for User {
     type Default = Default;
-    const DEFAULT: Default = Default { user_id: -1 };
+    const DEFAULT: Default = Default {
+        user_id: -1,
+        role: Role::Viewer,
+    };
 
     async fn create_using_self(&mut self, pool: &SqlitePool) -> Result<()> {
         let result = sqlx::query!(
             r#"
-            INSERT INTO users (username, password_hash, created_at)
-            VALUES (?, ?, ?)
+            INSERT INTO users (username, password_hash, created_at, role)
+            VALUES (?, ?, ?, ?)
             RETURNING user_id
             "#,
             self.username,
             self.password_hash,
-            self.created_at
+            self.created_at,
+            self.role
         )
         .fetch_one(pool)
         .await?;
@@ -80,7 +99,7 @@ impl Model for User {
         sqlx::query_as!(
             User,
             r#"
-            SELECT *
+            SELECT user_id, username, password_hash, created_at, role as "role: Role"
             FROM users
             WHERE user_id = ?
             "#,
@@ -94,13 +113,14 @@ impl Model for User {
         sqlx::query!(
             r#"
             UPDATE users
-            SET username = ?, password_hash = ?, created_at = ?
+            SET username = ?, password_hash = ?, created_at = ?, role = ?
             WHERE user_id = ?
             RETURNING user_id
             "#,
             self.username,
             self.password_hash,
             self.created_at,
+            self.role,
             self.user_id
         )
         .fetch_one(pool)
@@ -131,7 +151,7 @@ impl User {
         sqlx::query_as!(
             User,
             r#"
-            SELECT *
+            SELECT user_id, username, password_hash, created_at, role as "role: Role"
             FROM users
             WHERE username = ?
             "#,
@@ -145,7 +165,7 @@ impl User {
         sqlx::query_as!(
             User,
             r#"
-            SELECT *
+            SELECT user_id, username, password_hash, created_at, role as "role: Role"
             FROM users
             "#,
         )
@@ -153,6 +173,67 @@ impl User {
         .await
     }
 
+    pub async fn count_with_role(pool: &SqlitePool, role: Role) -> Result<i64> {
+        sqlx::query_scalar!(
+            r#"
+            SELECT COUNT(*) as "count!: i64"
+            FROM users
+            WHERE role = ?
+            "#,
+            role
+        )
+        .fetch_one(pool)
+        .await
+    }
+
+    /// Saves `self` like `update_using_self`, also letting it view and control every camera
+    /// (like new admins) in the same transaction.
+    pub async fn update_granting_all_cameras(&self, pool: &SqlitePool) -> Result<()> {
+        let mut transaction = pool.begin().await?;
+
+        sqlx::query!(
+            r#"
+            UPDATE users
+            SET username = ?, password_hash = ?, created_at = ?, role = ?
+            WHERE user_id = ?
+            RETURNING user_id
+            "#,
+            self.username,
+            self.password_hash,
+            self.created_at,
+            self.role,
+            self.user_id
+        )
+        .fetch_one(&mut *transaction)
+        .await?;
+
+        sqlx::query!(
+            r#"
+            UPDATE camera_permissions
+            SET can_view = TRUE, can_control = TRUE
+            WHERE user_id = ?
+            "#,
+            self.user_id
+        )
+        .execute(&mut *transaction)
+        .await?;
+
+        sqlx::query!(
+            r#"
+            INSERT INTO camera_permissions (camera_id, user_id, can_view, can_control)
+            SELECT camera_id, ?, TRUE, TRUE
+            FROM cameras
+            WHERE camera_id NOT IN (SELECT camera_id FROM camera_permissions WHERE user_id = ?)
+            "#,
+            self.user_id,
+            self.user_id
+        )
+        .execute(&mut *transaction)
+        .await?;
+
+        transaction.commit().await
+    }
+
     #[must_use]
     pub fn to_redacted_clone(&self) -> Self {
         Self {
@@ -160,6 +241,7 @@ impl User {
             username: self.username.clone(),
             password_hash: "[redacted]".to_string(),
             created_at: self.created_at,
+            role: self.role,
         }
     }
 }
@@ -167,6 +249,7 @@ impl User {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::db::{Camera, CameraPermission};
 
     #[sqlx::test(fixtures(path = "../../fixtures", scripts("users")))]
     async fn create(pool: SqlitePool) -> Result<()> {
@@ -175,6 +258,7 @@ mod tests {
             username: "test_user".to_string(),
             password_hash: "test_hash".to_string(),
             created_at: User::DEFAULT.created_at(),
+            role: User::DEFAULT.role,
         };
 
         user.create_using_self(&pool).await?;
@@ -186,6 +270,7 @@ mod tests {
         assert_eq!(returned_user.username, user.username);
         assert_eq!(returned_user.password_hash, user.password_hash);
         assert_eq!(returned_user.created_at, user.created_at);
+        assert_eq!(returned_user.role, Role::Viewer);
 
         Ok(())
     }
@@ -197,6 +282,7 @@ mod tests {
             username: "piotrpdev".to_string(),
             password_hash: "test_hash".to_string(),
             created_at: User::DEFAULT.created_at(),
+            role: User::DEFAULT.role,
         };
 
         let returned_user_result = user.create_using_self(&pool).await;
@@ -213,6 +299,7 @@ mod tests {
 
         assert_eq!(returned_user.user_id, user_id);
         assert_eq!(returned_user.username, "piotrpdev");
+        assert_eq!(returned_user.role, Role::Operator);
         assert_eq!(returned_user.password_hash, "$argon2id$v=19$m=19456,t=2,p=1$VE0e3g7DalWHgDwou3nuRA$uC6TER156UQpk0lNQ5+jHM0l5poVjPA1he/Tyn9J4Zw");
         assert_eq!(
             returned_user.created_at,
@@ -262,6 +349,7 @@ mod tests {
             username: "new_joedaly".to_string(),
             password_hash: old_user.password_hash,
             created_at: OffsetDateTime::from_unix_timestamp(1_729_530_138)?,
+            role: Role::Admin,
         };
 
         let updated = updated_user.update_using_self(&pool).await;
@@ -274,6 +362,7 @@ mod tests {
         assert_eq!(returned_user.username, updated_user.username);
         assert_eq!(returned_user.password_hash, updated_user.password_hash);
         assert_eq!(returned_user.created_at, updated_user.created_at);
+        assert_eq!(returned_user.role, updated_user.role);
 
         Ok(())
     }
@@ -296,6 +385,55 @@ mod tests {
         Ok(())
     }
 
+    #[sqlx::test(fixtures(path = "../../fixtures", scripts("users")))]
+    async fn count_with_role(pool: SqlitePool) -> Result<()> {
+        assert_eq!(User::count_with_role(&pool, Role::Admin).await?, 1);
+        assert_eq!(User::count_with_role(&pool, Role::Operator).await?, 1);
+        assert_eq!(User::count_with_role(&pool, Role::Viewer).await?, 2);
+
+        Ok(())
+    }
+
+    #[sqlx::test(fixtures(
+        path = "../../fixtures",
+        scripts("users", "cameras", "camera_permissions")
+    ))]
+    async fn update_granting_all_cameras(pool: SqlitePool) -> Result<()> {
+        // joedaly can view camera 1 only, and has no permission row for a new camera
+        let mut camera = Camera {
+            camera_id: Camera::DEFAULT.camera_id,
+            name: "Garage".to_string(),
+            ip_address: Camera::DEFAULT.ip_address,
+            last_connected: Camera::DEFAULT.last_connected,
+            is_active: Camera::DEFAULT.is_active,
+            is_online: Camera::DEFAULT.is_online,
+            token_hash: Camera::DEFAULT.token_hash,
+        };
+        camera.create_using_self(&pool).await?;
+
+        let mut user = User::get_using_id(&pool, 3).await?;
+        user.role = Role::Admin;
+        user.update_granting_all_cameras(&pool).await?;
+
+        assert_eq!(User::get_using_id(&pool, 3).await?.role, Role::Admin);
+
+        for camera_id in [1, 2, camera.camera_id] {
+            let permissions = CameraPermission::list_for_camera(&pool, camera_id).await?;
+            let permission = permissions.iter().find(|p| p.user_id == 3);
+
+            assert!(permission.is_some_and(|p| p.can_view && p.can_control));
+            assert_eq!(permissions.iter().filter(|p| p.user_id == 3).count(), 1);
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn role_ordering() {
+        assert!(Role::Admin > Role::Operator);
+        assert!(Role::Operator > Role::Viewer);
+    }
+
     #[sqlx::test(fixtures(path = "../../fixtures", scripts("users")))]
     async fn to_redacted_clone(pool: SqlitePool) -> Result<(), Box<dyn std::error::Error>> {
         let user_id = 2;
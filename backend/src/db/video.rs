@@ -6,6 +6,37 @@ use crate::db::VideoCameraView;
 
 use super::Model;
 
+/// Container and codec of a video file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, sqlx::Type)]
+#[serde(rename_all = "lowercase")]
+#[sqlx(rename_all = "lowercase")]
+pub enum VideoFormat {
+    /// H.264 in an MP4 container, can be played by browsers
+    Mp4,
+    /// Motion JPEG in an AVI container, every frame is stored as a JPEG so there is (almost) no quality loss
+    Mjpeg,
+    /// MPEG-4 Part 2 in an AVI container, what every video was recorded as before formats were configurable
+    Mpeg4,
+}
+
+impl VideoFormat {
+    #[must_use]
+    pub const fn extension(self) -> &'static str {
+        match self {
+            Self::Mp4 => "mp4",
+            Self::Mjpeg | Self::Mpeg4 => "avi",
+        }
+    }
+
+    #[must_use]
+    pub const fn content_type(self) -> &'static str {
+        match self {
+            Self::Mp4 => "video/mp4",
+            Self::Mjpeg | Self::Mpeg4 => "video/x-msvideo",
+        }
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Video {
     pub video_id: i64,
@@ -14,11 +45,21 @@ pub struct Video {
     pub start_time: OffsetDateTime,
     pub end_time: Option<OffsetDateTime>,
     pub file_size: Option<i64>,
+    /// Protects the video from being deleted by the retention policy
+    pub keep_forever: bool,
+    pub format: VideoFormat,
+    /// Measured from the frame timestamps, the video itself always plays at the camera's framerate
+    pub average_fps: Option<f64>,
+    /// JPEG shown before the video is played, see `VideoThumbnail` for the rest
+    pub poster_path: Option<String>,
 }
 
 pub struct Default {
     pub video_id: i64,
     pub end_time: Option<OffsetDateTime>,
+    pub keep_forever: bool,
+    pub average_fps: Option<f64>,
+    pub poster_path: Option<String>,
     pub file_name_format: &'static [time::format_description::BorrowedFormatItem<'static>],
 }
 
@@ -34,6 +75,9 @@ impl Model for Video {
     const DEFAULT: Default = Default {
         video_id: -1,
         end_time: None,
+        keep_forever: false,
+        average_fps: None,
+        poster_path: None,
         file_name_format: format_description!(
             "[year]-[month]-[day]_[hour]-[minute]-[second]_[subsecond digits:9]Z"
         ),
@@ -42,15 +86,21 @@ impl Model for Video {
     async fn create_using_self(&mut self, pool: &SqlitePool) -> Result<()> {
         let result = sqlx::query!(
             r#"
-            INSERT INTO videos (camera_id, file_path, start_time, end_time, file_size)
-            VALUES (?, ?, ?, ?, ?)
+            INSERT INTO videos
+            (camera_id, file_path, start_time, end_time, file_size, keep_forever, format, average_fps,
+             poster_path)
+            VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?)
             RETURNING video_id
             "#,
             self.camera_id,
             self.file_path,
             self.start_time,
             self.end_time,
-            self.file_size
+            self.file_size,
+            self.keep_forever,
+            self.format,
+            self.average_fps,
+            self.poster_path
         )
         .fetch_one(pool)
         .await?;
@@ -64,7 +114,8 @@ impl Model for Video {
         sqlx::query_as!(
             Video,
             r#"
-            SELECT video_id, camera_id, file_path, start_time, end_time, file_size
+            SELECT video_id, camera_id, file_path, start_time, end_time, file_size, keep_forever,
+                   format as "format: VideoFormat", average_fps, poster_path
             FROM videos WHERE video_id = ?
             "#,
             id
@@ -77,13 +128,17 @@ impl Model for Video {
         sqlx::query!(
             r#"
             UPDATE videos
-            SET camera_id = ?, end_time = ?, file_size = ?
+            SET camera_id = ?, end_time = ?, file_size = ?, keep_forever = ?, average_fps = ?,
+                poster_path = ?
             WHERE video_id = ?
             RETURNING video_id
             "#,
             self.camera_id,
             self.end_time,
             self.file_size,
+            self.keep_forever,
+            self.average_fps,
+            self.poster_path,
             self.video_id
         )
         .fetch_one(pool)
@@ -117,7 +172,8 @@ impl Video {
         sqlx::query_as!(
             VideoCameraView,
             r#"
-            SELECT v.video_id, v.camera_id, c.name as camera_name, v.file_path, v.file_size
+            SELECT v.video_id, v.camera_id, c.name as camera_name, v.file_path, v.file_size,
+                   v.keep_forever, v.format as "format: VideoFormat", v.poster_path
             FROM videos v
             JOIN cameras c ON v.camera_id = c.camera_id
             WHERE c.camera_id = ?
@@ -127,6 +183,21 @@ impl Video {
         .fetch_all(db)
         .await
     }
+
+    /// Lists every video, oldest first
+    pub async fn list(db: &sqlx::Pool<sqlx::Sqlite>) -> Result<Vec<Self>> {
+        sqlx::query_as!(
+            Video,
+            r#"
+            SELECT video_id, camera_id, file_path, start_time, end_time, file_size, keep_forever,
+                   format as "format: VideoFormat", average_fps, poster_path
+            FROM videos
+            ORDER BY start_time ASC, video_id ASC
+            "#
+        )
+        .fetch_all(db)
+        .await
+    }
 }
 
 #[allow(clippy::unwrap_used)]
@@ -143,6 +214,10 @@ mod tests {
             start_time: Video::DEFAULT.start_time(),
             end_time: Video::DEFAULT.end_time,
             file_size: Some(1024),
+            keep_forever: Video::DEFAULT.keep_forever,
+            format: VideoFormat::Mp4,
+            average_fps: Video::DEFAULT.average_fps,
+            poster_path: Some("/path/to/video_poster.jpg".to_string()),
         };
 
         video.create_using_self(&pool).await?;
@@ -156,6 +231,10 @@ mod tests {
         assert_eq!(returned_video.start_time, video.start_time);
         assert_eq!(returned_video.end_time, video.end_time);
         assert_eq!(returned_video.file_size, video.file_size);
+        assert_eq!(returned_video.keep_forever, video.keep_forever);
+        assert_eq!(returned_video.format, video.format);
+        assert_eq!(returned_video.average_fps, video.average_fps);
+        assert_eq!(returned_video.poster_path, video.poster_path);
 
         Ok(())
     }
@@ -176,6 +255,10 @@ mod tests {
             OffsetDateTime::from_unix_timestamp(1_729_479_512)?
         );
         assert_eq!(returned_video.file_size, Some(6_762_403));
+        assert!(!returned_video.keep_forever);
+        assert_eq!(returned_video.format, VideoFormat::Mp4);
+        assert_eq!(returned_video.average_fps, None);
+        assert_eq!(returned_video.poster_path, None);
 
         Ok(())
     }
@@ -191,6 +274,10 @@ mod tests {
             start_time: old_video.start_time,
             end_time: Some(OffsetDateTime::now_utc()),
             file_size: Some(2048),
+            keep_forever: true,
+            format: old_video.format,
+            average_fps: Some(4.5),
+            poster_path: Some("/path/to/1_poster.jpg".to_string()),
         };
 
         let updated = updated_video.update_using_self(&pool).await;
@@ -202,6 +289,9 @@ mod tests {
         assert_eq!(returned_video.start_time, updated_video.start_time);
         assert_eq!(returned_video.end_time, updated_video.end_time);
         assert_eq!(returned_video.file_size, updated_video.file_size);
+        assert_eq!(returned_video.keep_forever, updated_video.keep_forever);
+        assert_eq!(returned_video.average_fps, updated_video.average_fps);
+        assert_eq!(returned_video.poster_path, updated_video.poster_path);
 
         Ok(())
     }
@@ -237,6 +327,17 @@ mod tests {
         );
         assert_eq!(returned_videos.first().unwrap().file_size, Some(6_762_403));
 
+        Ok(())
+    }
+    #[sqlx::test(fixtures(path = "../../fixtures", scripts("cameras", "videos")))]
+    async fn list(pool: SqlitePool) -> Result<()> {
+        let returned_videos = Video::list(&pool).await?;
+
+        assert_eq!(returned_videos.len(), 2);
+        // Video 2 started first
+        assert_eq!(returned_videos.first().unwrap().video_id, 2);
+        assert_eq!(returned_videos.get(1).unwrap().video_id, 1);
+
         Ok(())
     }
 }
@@ -1,5 +1,7 @@
 use serde::Serialize;
 
+use super::VideoFormat;
+
 #[derive(Debug, Serialize)]
 pub struct VideoCameraView {
     pub video_id: i64,
@@ -7,4 +9,7 @@ pub struct VideoCameraView {
     pub camera_name: String,
     pub file_path: String,
     pub file_size: Option<i64>,
+    pub keep_forever: bool,
+    pub format: VideoFormat,
+    pub poster_path: Option<String>,
 }
@@ -0,0 +1,170 @@
+use serde::{Deserialize, Serialize};
+use sqlx::{Result, SqlitePool};
+
+use super::Model;
+
+/// A small JPEG taken during a video, used for scrubbing.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct VideoThumbnail {
+    pub thumbnail_id: i64,
+    pub video_id: i64,
+    pub file_path: String,
+    /// When the thumbnail was taken, relative to the start of the video
+    pub offset_seconds: f64,
+}
+
+pub struct Default {
+    pub thumbnail_id: i64,
+}
+
+impl Model for VideoThumbnail {
+    type Default = Default;
+    const DEFAULT: Default = Default { thumbnail_id: -1 };
+
+    async fn create_using_self(&mut self, pool: &SqlitePool) -> Result<()> {
+        let result = sqlx::query!(
+            r#"
+            INSERT INTO video_thumbnails (video_id, file_path, offset_seconds)
+            VALUES (?, ?, ?)
+            RETURNING thumbnail_id
+            "#,
+            self.video_id,
+            self.file_path,
+            self.offset_seconds
+        )
+        .fetch_one(pool)
+        .await?;
+
+        self.thumbnail_id = result.thumbnail_id;
+
+        Ok(())
+    }
+
+    async fn get_using_id(pool: &SqlitePool, thumbnail_id: i64) -> Result<Self> {
+        sqlx::query_as!(
+            VideoThumbnail,
+            r#"
+            SELECT *
+            FROM video_thumbnails
+            WHERE thumbnail_id = ?
+            "#,
+            thumbnail_id
+        )
+        .fetch_one(pool)
+        .await
+    }
+
+    async fn update_using_self(&self, pool: &SqlitePool) -> Result<()> {
+        sqlx::query!(
+            r#"
+            UPDATE video_thumbnails
+            SET file_path = ?, offset_seconds = ?
+            WHERE thumbnail_id = ?
+            RETURNING thumbnail_id
+            "#,
+            self.file_path,
+            self.offset_seconds,
+            self.thumbnail_id
+        )
+        .fetch_one(pool)
+        .await?;
+
+        Ok(())
+    }
+
+    async fn delete_using_id(pool: &SqlitePool, thumbnail_id: i64) -> Result<()> {
+        sqlx::query!(
+            r#"
+            DELETE
+            FROM video_thumbnails
+            WHERE thumbnail_id = ?
+            RETURNING thumbnail_id
+            "#,
+            thumbnail_id
+        )
+        .fetch_one(pool)
+        .await?;
+
+        Ok(())
+    }
+}
+
+impl VideoThumbnail {
+    /// Lists the thumbnails of a video in the order they were taken
+    pub async fn list_for_video(pool: &SqlitePool, video_id: i64) -> Result<Vec<Self>> {
+        sqlx::query_as!(
+            VideoThumbnail,
+            r#"
+            SELECT *
+            FROM video_thumbnails
+            WHERE video_id = ?
+            ORDER BY offset_seconds ASC, thumbnail_id ASC
+            "#,
+            video_id
+        )
+        .fetch_all(pool)
+        .await
+    }
+}
+
+#[allow(clippy::unwrap_used)]
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::Video;
+
+    #[sqlx::test(fixtures(
+        path = "../../fixtures",
+        scripts("cameras", "videos", "video_thumbnails")
+    ))]
+    async fn create(pool: SqlitePool) -> Result<()> {
+        let mut thumbnail = VideoThumbnail {
+            thumbnail_id: VideoThumbnail::DEFAULT.thumbnail_id,
+            video_id: 2,
+            file_path: "/home/piotrpdev/oko/backend/videos/2_thumbnail_1.jpg".to_string(),
+            offset_seconds: 10.0,
+        };
+
+        thumbnail.create_using_self(&pool).await?;
+
+        assert_eq!(thumbnail.thumbnail_id, 4);
+
+        let returned_thumbnail = VideoThumbnail::get_using_id(&pool, 4).await?;
+
+        assert_eq!(returned_thumbnail.video_id, thumbnail.video_id);
+        assert_eq!(returned_thumbnail.file_path, thumbnail.file_path);
+        assert!(
+            (returned_thumbnail.offset_seconds - thumbnail.offset_seconds).abs() < f64::EPSILON
+        );
+
+        Ok(())
+    }
+
+    #[sqlx::test(fixtures(
+        path = "../../fixtures",
+        scripts("cameras", "videos", "video_thumbnails")
+    ))]
+    async fn list_for_video(pool: SqlitePool) -> Result<()> {
+        let returned_thumbnails = VideoThumbnail::list_for_video(&pool, 1).await?;
+
+        assert_eq!(returned_thumbnails.len(), 2);
+        assert_eq!(returned_thumbnails.first().unwrap().thumbnail_id, 1);
+        assert_eq!(returned_thumbnails.get(1).unwrap().thumbnail_id, 2);
+        assert!((returned_thumbnails.get(1).unwrap().offset_seconds - 10.0).abs() < f64::EPSILON);
+
+        Ok(())
+    }
+
+    #[sqlx::test(fixtures(
+        path = "../../fixtures",
+        scripts("cameras", "videos", "video_thumbnails")
+    ))]
+    async fn deleted_with_video(pool: SqlitePool) -> Result<()> {
+        Video::delete_using_id(&pool, 1).await?;
+
+        assert!(VideoThumbnail::list_for_video(&pool, 1).await?.is_empty());
+        assert!(VideoThumbnail::get_using_id(&pool, 3).await.is_ok());
+
+        Ok(())
+    }
+}
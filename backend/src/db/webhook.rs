@@ -0,0 +1,196 @@
+use serde::{Deserialize, Serialize};
+use sqlx::{Result, SqlitePool};
+use time::OffsetDateTime;
+
+use super::Model;
+
+/// A URL that is sent signed JSON payloads, see `crate::webhooks`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Webhook {
+    pub webhook_id: i64,
+    pub url: String,
+    /// Key used to sign payloads, only sent to clients when the webhook is added
+    #[serde(skip)]
+    pub secret: String,
+    pub enabled: bool,
+    pub created_at: OffsetDateTime,
+}
+
+pub struct Default {
+    pub webhook_id: i64,
+    pub enabled: bool,
+}
+
+impl Default {
+    #[allow(clippy::unused_self)]
+    pub fn created_at(&self) -> OffsetDateTime {
+        OffsetDateTime::now_utc()
+    }
+}
+
+impl Model for Webhook {
+    type Default = Default;
+    const DEFAULT: Default = Default {
+        webhook_id: -1,
+        enabled: true,
+    };
+
+    async fn create_using_self(&mut self, pool: &SqlitePool) -> Result<()> {
+        let result = sqlx::query!(
+            r#"
+            INSERT INTO webhooks (url, secret, enabled, created_at)
+            VALUES (?, ?, ?, ?)
+            RETURNING webhook_id
+            "#,
+            self.url,
+            self.secret,
+            self.enabled,
+            self.created_at
+        )
+        .fetch_one(pool)
+        .await?;
+
+        self.webhook_id = result.webhook_id;
+
+        Ok(())
+    }
+
+    async fn get_using_id(pool: &SqlitePool, id: i64) -> Result<Self> {
+        sqlx::query_as!(
+            Webhook,
+            r#"
+            SELECT *
+            FROM webhooks
+            WHERE webhook_id = ?
+            "#,
+            id
+        )
+        .fetch_one(pool)
+        .await
+    }
+
+    async fn update_using_self(&self, pool: &SqlitePool) -> Result<()> {
+        sqlx::query!(
+            r#"
+            UPDATE webhooks
+            SET url = ?, enabled = ?
+            WHERE webhook_id = ?
+            RETURNING webhook_id
+            "#,
+            self.url,
+            self.enabled,
+            self.webhook_id
+        )
+        .fetch_one(pool)
+        .await?;
+
+        Ok(())
+    }
+
+    async fn delete_using_id(pool: &SqlitePool, id: i64) -> Result<()> {
+        sqlx::query!(
+            r#"
+            DELETE
+            FROM webhooks
+            WHERE webhook_id = ?
+            RETURNING webhook_id
+            "#,
+            id
+        )
+        .fetch_one(pool)
+        .await?;
+
+        Ok(())
+    }
+}
+
+impl Webhook {
+    /// Random key for signing payloads.
+    #[must_use]
+    pub fn generate_secret() -> String {
+        hex::encode(rand::random::<[u8; 32]>())
+    }
+
+    pub async fn list(pool: &SqlitePool) -> Result<Vec<Self>> {
+        sqlx::query_as!(
+            Webhook,
+            r#"
+            SELECT *
+            FROM webhooks
+            ORDER BY webhook_id ASC
+            "#
+        )
+        .fetch_all(pool)
+        .await
+    }
+
+    /// Lists the webhooks that should be sent new payloads
+    pub async fn list_enabled(pool: &SqlitePool) -> Result<Vec<Self>> {
+        sqlx::query_as!(
+            Webhook,
+            r#"
+            SELECT *
+            FROM webhooks
+            WHERE enabled
+            ORDER BY webhook_id ASC
+            "#
+        )
+        .fetch_all(pool)
+        .await
+    }
+}
+
+#[allow(clippy::unwrap_used)]
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::WebhookDelivery;
+
+    #[sqlx::test(fixtures(path = "../../fixtures", scripts("webhooks")))]
+    async fn create(pool: SqlitePool) -> Result<()> {
+        let mut webhook = Webhook {
+            webhook_id: Webhook::DEFAULT.webhook_id,
+            url: "https://example.com/oko".to_string(),
+            secret: Webhook::generate_secret(),
+            enabled: Webhook::DEFAULT.enabled,
+            created_at: Webhook::DEFAULT.created_at(),
+        };
+
+        webhook.create_using_self(&pool).await?;
+
+        assert_eq!(webhook.webhook_id, 3);
+
+        let returned_webhook = Webhook::get_using_id(&pool, 3).await?;
+
+        assert_eq!(returned_webhook.url, webhook.url);
+        assert_eq!(returned_webhook.secret, webhook.secret);
+        assert_eq!(returned_webhook.secret.len(), 64);
+        assert!(returned_webhook.enabled);
+
+        Ok(())
+    }
+
+    #[sqlx::test(fixtures(path = "../../fixtures", scripts("webhooks")))]
+    async fn list_enabled(pool: SqlitePool) -> Result<()> {
+        assert_eq!(Webhook::list(&pool).await?.len(), 2);
+
+        let returned_webhooks = Webhook::list_enabled(&pool).await?;
+
+        assert_eq!(returned_webhooks.len(), 1);
+        assert_eq!(returned_webhooks.first().unwrap().webhook_id, 1);
+
+        Ok(())
+    }
+
+    #[sqlx::test(fixtures(path = "../../fixtures", scripts("webhooks")))]
+    async fn delete(pool: SqlitePool) -> Result<()> {
+        Webhook::delete_using_id(&pool, 1).await?;
+
+        assert!(Webhook::get_using_id(&pool, 1).await.is_err());
+        assert!(WebhookDelivery::list_for_webhook(&pool, 1)
+            .await?
+            .is_empty());
+
+        Ok(())
+    }
+}
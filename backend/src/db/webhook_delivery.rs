@@ -0,0 +1,239 @@
+use serde::{Deserialize, Serialize};
+use sqlx::{Result, SqlitePool};
+use time::OffsetDateTime;
+
+use super::Model;
+
+/// Most deliveries listed for a webhook
+const DELIVERY_LOG_LENGTH: i64 = 100;
+
+/// Why a payload was sent to a webhook.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, sqlx::Type)]
+#[serde(rename_all = "snake_case")]
+#[sqlx(rename_all = "snake_case")]
+pub enum WebhookKind {
+    CameraOffline,
+    Motion,
+    RecordingFinished,
+    /// Sent on request by an admin
+    Test,
+}
+
+impl WebhookKind {
+    #[must_use]
+    pub const fn as_str(self) -> &'static str {
+        match self {
+            Self::CameraOffline => "camera_offline",
+            Self::Motion => "motion",
+            Self::RecordingFinished => "recording_finished",
+            Self::Test => "test",
+        }
+    }
+}
+
+/// A payload sent (or being retried) to a webhook.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WebhookDelivery {
+    pub delivery_id: i64,
+    pub webhook_id: i64,
+    pub kind: WebhookKind,
+    /// JSON body, exactly as it was signed
+    pub payload: String,
+    pub attempts: i64,
+    /// Whether the webhook responded with a 2xx status
+    pub delivered: bool,
+    /// Of the last attempt, `None` if no response was received
+    pub status_code: Option<i64>,
+    /// Why the last attempt failed
+    pub error: Option<String>,
+    pub created_at: OffsetDateTime,
+    pub last_attempt_at: Option<OffsetDateTime>,
+}
+
+pub struct Default {
+    pub delivery_id: i64,
+    pub attempts: i64,
+    pub delivered: bool,
+    pub status_code: Option<i64>,
+    pub error: Option<String>,
+    pub last_attempt_at: Option<OffsetDateTime>,
+}
+
+impl Default {
+    #[allow(clippy::unused_self)]
+    pub fn created_at(&self) -> OffsetDateTime {
+        OffsetDateTime::now_utc()
+    }
+}
+
+impl Model for WebhookDelivery {
+    type Default = Default;
+    const DEFAULT: Default = Default {
+        delivery_id: -1,
+        attempts: 0,
+        delivered: false,
+        status_code: None,
+        error: None,
+        last_attempt_at: None,
+    };
+
+    async fn create_using_self(&mut self, pool: &SqlitePool) -> Result<()> {
+        let result = sqlx::query!(
+            r#"
+            INSERT INTO webhook_deliveries
+            (webhook_id, kind, payload, attempts, delivered, status_code, error, created_at,
+             last_attempt_at)
+            VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?)
+            RETURNING delivery_id
+            "#,
+            self.webhook_id,
+            self.kind,
+            self.payload,
+            self.attempts,
+            self.delivered,
+            self.status_code,
+            self.error,
+            self.created_at,
+            self.last_attempt_at
+        )
+        .fetch_one(pool)
+        .await?;
+
+        self.delivery_id = result.delivery_id;
+
+        Ok(())
+    }
+
+    async fn get_using_id(pool: &SqlitePool, id: i64) -> Result<Self> {
+        sqlx::query_as!(
+            WebhookDelivery,
+            r#"
+            SELECT delivery_id, webhook_id, kind as "kind: WebhookKind", payload, attempts,
+                   delivered, status_code, error, created_at, last_attempt_at
+            FROM webhook_deliveries
+            WHERE delivery_id = ?
+            "#,
+            id
+        )
+        .fetch_one(pool)
+        .await
+    }
+
+    async fn update_using_self(&self, pool: &SqlitePool) -> Result<()> {
+        sqlx::query!(
+            r#"
+            UPDATE webhook_deliveries
+            SET attempts = ?, delivered = ?, status_code = ?, error = ?, last_attempt_at = ?
+            WHERE delivery_id = ?
+            RETURNING delivery_id
+            "#,
+            self.attempts,
+            self.delivered,
+            self.status_code,
+            self.error,
+            self.last_attempt_at,
+            self.delivery_id
+        )
+        .fetch_one(pool)
+        .await?;
+
+        Ok(())
+    }
+
+    async fn delete_using_id(pool: &SqlitePool, id: i64) -> Result<()> {
+        sqlx::query!(
+            r#"
+            DELETE
+            FROM webhook_deliveries
+            WHERE delivery_id = ?
+            RETURNING delivery_id
+            "#,
+            id
+        )
+        .fetch_one(pool)
+        .await?;
+
+        Ok(())
+    }
+}
+
+impl WebhookDelivery {
+    /// Lists the latest deliveries to a webhook, newest first
+    pub async fn list_for_webhook(pool: &SqlitePool, webhook_id: i64) -> Result<Vec<Self>> {
+        sqlx::query_as!(
+            WebhookDelivery,
+            r#"
+            SELECT delivery_id, webhook_id, kind as "kind: WebhookKind", payload, attempts,
+                   delivered, status_code, error, created_at, last_attempt_at
+            FROM webhook_deliveries
+            WHERE webhook_id = ?
+            ORDER BY delivery_id DESC
+            LIMIT ?
+            "#,
+            webhook_id,
+            DELIVERY_LOG_LENGTH
+        )
+        .fetch_all(pool)
+        .await
+    }
+}
+
+#[allow(clippy::unwrap_used)]
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[sqlx::test(fixtures(path = "../../fixtures", scripts("webhooks")))]
+    async fn create_and_update(pool: SqlitePool) -> Result<()> {
+        let mut delivery = WebhookDelivery {
+            delivery_id: WebhookDelivery::DEFAULT.delivery_id,
+            webhook_id: 1,
+            kind: WebhookKind::Motion,
+            payload: r#"{"kind":"motion"}"#.to_string(),
+            attempts: WebhookDelivery::DEFAULT.attempts,
+            delivered: WebhookDelivery::DEFAULT.delivered,
+            status_code: WebhookDelivery::DEFAULT.status_code,
+            error: WebhookDelivery::DEFAULT.error,
+            created_at: WebhookDelivery::DEFAULT.created_at(),
+            last_attempt_at: WebhookDelivery::DEFAULT.last_attempt_at,
+        };
+
+        delivery.create_using_self(&pool).await?;
+
+        assert_eq!(delivery.delivery_id, 3);
+
+        delivery.attempts = 1;
+        delivery.delivered = true;
+        delivery.status_code = Some(204);
+        delivery.update_using_self(&pool).await?;
+
+        let returned_delivery = WebhookDelivery::get_using_id(&pool, 3).await?;
+
+        assert_eq!(returned_delivery.kind, WebhookKind::Motion);
+        assert_eq!(returned_delivery.payload, delivery.payload);
+        assert_eq!(returned_delivery.attempts, 1);
+        assert!(returned_delivery.delivered);
+        assert_eq!(returned_delivery.status_code, Some(204));
+
+        Ok(())
+    }
+
+    #[sqlx::test(fixtures(path = "../../fixtures", scripts("webhooks")))]
+    async fn list_for_webhook(pool: SqlitePool) -> Result<()> {
+        let returned_deliveries = WebhookDelivery::list_for_webhook(&pool, 1).await?;
+
+        assert_eq!(returned_deliveries.len(), 2);
+        assert_eq!(returned_deliveries.first().unwrap().delivery_id, 2);
+        assert_eq!(
+            returned_deliveries.first().unwrap().kind,
+            WebhookKind::CameraOffline
+        );
+        assert!(!returned_deliveries.first().unwrap().delivered);
+
+        assert!(WebhookDelivery::list_for_webhook(&pool, 2)
+            .await?
+            .is_empty());
+
+        Ok(())
+    }
+}
@@ -0,0 +1,41 @@
+use sqlx::SqlitePool;
+use tokio::sync::broadcast;
+use tracing::error;
+
+use crate::{
+    db::{Event, Model},
+    web::ApiChannelMessage,
+};
+
+/// Saves a new event and pushes it to viewers, returning it with its id.
+///
+/// Events are informational, so failures are only logged instead of interrupting the caller.
+pub async fn publish(
+    db: &SqlitePool,
+    api_channel: &broadcast::Sender<ApiChannelMessage>,
+    mut event: Event,
+) -> Option<Event> {
+    if let Err(e) = event.create_using_self(db).await {
+        error!("Error saving {:?} event: {e:?}", event.kind);
+        return None;
+    }
+
+    // Nobody might be connected
+    let _ = api_channel.send(ApiChannelMessage::EventCreated(event.clone()));
+
+    Some(event)
+}
+
+/// Saves changes to an event (e.g. its end time) and pushes it to viewers, failures are only logged.
+pub async fn publish_update(
+    db: &SqlitePool,
+    api_channel: &broadcast::Sender<ApiChannelMessage>,
+    event: Event,
+) {
+    if let Err(e) = event.update_using_self(db).await {
+        error!("Error updating event {}: {e:?}", event.event_id);
+        return;
+    }
+
+    let _ = api_channel.send(ApiChannelMessage::EventUpdated(event));
+}
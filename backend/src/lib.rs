@@ -1,17 +1,33 @@
+use clap::Parser;
 use futures_util::{Stream, StreamExt};
 use tokio_util::sync::CancellationToken;
 use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt};
 
-pub use crate::web::{ApiChannelMessage, App, ImageContainer};
+pub use crate::config::{Cli, Config};
+pub use crate::web::{
+    ApiChannelMessage, App, AppBuilder, ClientRole, FrameHeader, Hello, HelloReply, ImageContainer,
+    MIN_PROTOCOL_VERSION, PROTOCOL_VERSION,
+};
 
+mod alerts;
+mod config;
 mod db;
+mod events;
+mod mqtt;
+mod recording;
+mod retention;
 mod users;
 mod web;
+mod webhooks;
 
 pub use {
-    db::Camera, db::CameraPermission, db::CameraPermissionUserView, db::CameraPermissionView,
-    db::CameraSetting, db::CameraSettingNoMeta, db::Model, db::User, db::Video,
-    db::VideoCameraView,
+    db::AlertSubscription, db::ArmingMode, db::ArmingSchedule, db::ArmingState, db::Camera,
+    db::CameraPermission, db::CameraPermissionUserView, db::CameraPermissionView,
+    db::CameraSetting, db::CameraSettingNoMeta, db::Event, db::EventFilter, db::EventKind,
+    db::Model, db::MotionZone, db::MotionZoneKind, db::PolygonPoint, db::PrivacyMask,
+    db::RecordingMode, db::Role, db::SmtpSecurity, db::SmtpSettings, db::StoragePolicy, db::User,
+    db::Video, db::VideoCameraView, db::VideoFormat, db::VideoThumbnail, db::Webhook,
+    db::WebhookDelivery, db::WebhookKind, oko_protocol::Resolution,
 };
 
 // Taken from https://github.com/hyperium/hyper/issues/2787#issuecomment-1073229886
@@ -57,8 +73,11 @@ pub async fn run() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
         .with(tracing_subscriber::fmt::layer().compact().without_time())
         .try_init()?;
 
+    let cli = Cli::parse();
+    let config = Config::load(&cli)?;
+
     // TODO: Properly handle errors.
-    App::new().await?.serve().await?;
+    App::builder().config(config).build().await?.serve().await?;
 
     Ok(())
 }
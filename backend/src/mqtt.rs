@@ -0,0 +1,605 @@
+use std::collections::HashSet;
+
+use rumqttc::{
+    AsyncClient, Event as MqttEvent, EventLoop, LastWill, MqttOptions, Outgoing, Packet, QoS,
+};
+use serde_json::json;
+use sqlx::SqlitePool;
+use tokio::sync::{broadcast, mpsc};
+use tokio_util::sync::CancellationToken;
+use tracing::{debug, error, info, warn};
+
+use crate::{
+    db::{Camera, CameraSetting, CameraSettingNoMeta, EventKind, Model},
+    web::{ApiChannelMessage, CameraListChange, CameraMessage},
+    Resolution,
+};
+
+const CLIENT_ID: &str = "oko";
+const KEEP_ALIVE: tokio::time::Duration = tokio::time::Duration::from_secs(30);
+const RECONNECT_DELAY: tokio::time::Duration = tokio::time::Duration::from_secs(5);
+/// How long to wait for the last messages to be sent when shutting down
+const DISCONNECT_TIMEOUT: tokio::time::Duration = tokio::time::Duration::from_secs(1);
+/// Messages that can be queued while the broker is unreachable before new ones are dropped
+const REQUEST_CAPACITY: usize = 256;
+
+const ONLINE: &str = "online";
+const OFFLINE: &str = "offline";
+const ON: &str = "ON";
+const OFF: &str = "OFF";
+
+const RESOLUTIONS: [Resolution; 2] = [Resolution::Svga, Resolution::Vga];
+
+/// Home Assistant entities created for every camera, as `(component, object)`
+const ENTITIES: [(&str, &str); 6] = [
+    ("binary_sensor", "motion"),
+    ("binary_sensor", "recording"),
+    ("button", "restart"),
+    ("switch", "flashlight"),
+    ("select", "resolution"),
+    ("number", "framerate"),
+];
+
+/// Where to connect and which topics to use, see `Config::mqtt`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MqttConfig {
+    pub host: String,
+    pub port: u16,
+    /// Username and password
+    pub credentials: Option<(String, String)>,
+    pub topic_prefix: String,
+    pub discovery_prefix: String,
+}
+
+impl MqttConfig {
+    /// `online` while Oko is connected, set to `offline` by the broker otherwise
+    fn status_topic(&self) -> String {
+        format!("{}/status", self.topic_prefix)
+    }
+
+    fn camera_topic(&self, camera_id: i64, object: &str) -> String {
+        format!("{}/camera/{camera_id}/{object}", self.topic_prefix)
+    }
+
+    fn command_topic(&self, camera_id: i64, object: &str) -> String {
+        format!("{}/set", self.camera_topic(camera_id, object))
+    }
+
+    fn discovery_topic(&self, component: &str, camera_id: i64, object: &str) -> String {
+        format!(
+            "{}/{component}/oko_{camera_id}/{object}/config",
+            self.discovery_prefix
+        )
+    }
+
+    fn options(&self) -> MqttOptions {
+        let mut options = MqttOptions::new(CLIENT_ID, &self.host, self.port);
+        options.set_keep_alive(KEEP_ALIVE);
+        options.set_last_will(LastWill::new(
+            self.status_topic(),
+            OFFLINE,
+            QoS::AtLeastOnce,
+            true,
+        ));
+
+        if let Some((username, password)) = &self.credentials {
+            options.set_credentials(username, password);
+        }
+
+        options
+    }
+}
+
+/// Received on `<prefix>/camera/<camera_id>/<object>/set`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Command {
+    Restart,
+    Flashlight(bool),
+    Resolution(Resolution),
+    Framerate(i64),
+}
+
+/// The camera and command of a message on a command topic, `None` if it isn't a valid command.
+fn parse_command(config: &MqttConfig, topic: &str, payload: &[u8]) -> Option<(i64, Command)> {
+    let (camera_id, object) = topic
+        .strip_prefix(&config.topic_prefix)?
+        .strip_prefix("/camera/")?
+        .strip_suffix("/set")?
+        .split_once('/')?;
+
+    let camera_id = camera_id.parse().ok()?;
+    let payload = std::str::from_utf8(payload).ok()?.trim();
+
+    let command = match object {
+        "restart" => Command::Restart,
+        "flashlight" => match payload {
+            ON => Command::Flashlight(true),
+            OFF => Command::Flashlight(false),
+            _ => return None,
+        },
+        "resolution" => Command::Resolution(
+            RESOLUTIONS
+                .into_iter()
+                .find(|resolution| resolution.as_str() == payload)?,
+        ),
+        "framerate" => Command::Framerate(
+            payload
+                .parse()
+                .ok()
+                .filter(|framerate| (1..=60).contains(framerate))?,
+        ),
+        _ => return None,
+    };
+
+    Some((camera_id, command))
+}
+
+/// Home Assistant discovery payload for one of the `ENTITIES` of `camera`.
+fn entity_config(config: &MqttConfig, camera: &Camera, object: &str) -> serde_json::Value {
+    let camera_id = camera.camera_id;
+
+    let mut entity = json!({
+        "name": object,
+        "unique_id": format!("oko_{camera_id}_{object}"),
+        "availability": [
+            { "topic": config.status_topic() },
+            { "topic": config.camera_topic(camera_id, "status") },
+        ],
+        "availability_mode": "all",
+        "device": {
+            "identifiers": [format!("oko_{camera_id}")],
+            "name": camera.name,
+            "manufacturer": "Oko",
+        },
+    });
+
+    let extra = match object {
+        "motion" => json!({
+            "state_topic": config.camera_topic(camera_id, object),
+            "device_class": "motion",
+        }),
+        "recording" => json!({
+            "state_topic": config.camera_topic(camera_id, object),
+            "device_class": "running",
+        }),
+        "restart" => json!({
+            "command_topic": config.command_topic(camera_id, object),
+            "device_class": "restart",
+        }),
+        "flashlight" => json!({
+            "state_topic": config.camera_topic(camera_id, object),
+            "command_topic": config.command_topic(camera_id, object),
+        }),
+        "resolution" => json!({
+            "state_topic": config.camera_topic(camera_id, object),
+            "command_topic": config.command_topic(camera_id, object),
+            "options": RESOLUTIONS.map(Resolution::as_str),
+        }),
+        "framerate" => json!({
+            "state_topic": config.camera_topic(camera_id, object),
+            "command_topic": config.command_topic(camera_id, object),
+            "min": 1,
+            "max": 60,
+            "unit_of_measurement": "fps",
+        }),
+        _ => json!({}),
+    };
+
+    if let (Some(entity), serde_json::Value::Object(extra)) = (entity.as_object_mut(), extra) {
+        entity.extend(extra);
+    }
+
+    entity
+}
+
+/// Publishes to the broker, the messages are sent while polling the event loop.
+struct Publisher {
+    client: AsyncClient,
+    config: MqttConfig,
+    /// Cameras with a motion event that hasn't ended
+    motion: HashSet<i64>,
+    /// Cameras with a video being recorded
+    recording: HashSet<i64>,
+}
+
+impl Publisher {
+    fn publish(&self, topic: String, payload: impl Into<Vec<u8>>) {
+        if let Err(e) = self
+            .client
+            .try_publish(topic, QoS::AtLeastOnce, true, payload)
+        {
+            warn!("Error queueing MQTT message: {e:?}");
+        }
+    }
+
+    fn publish_state(&self, camera_id: i64, object: &str, state: bool) {
+        self.publish(
+            self.config.camera_topic(camera_id, object),
+            if state { ON } else { OFF },
+        );
+    }
+
+    fn publish_settings(&self, camera_id: i64, settings: &CameraSettingNoMeta) {
+        self.publish_state(camera_id, "flashlight", settings.flashlight_enabled);
+        self.publish(
+            self.config.camera_topic(camera_id, "resolution"),
+            settings.resolution.as_str(),
+        );
+        self.publish(
+            self.config.camera_topic(camera_id, "framerate"),
+            settings.framerate.to_string(),
+        );
+    }
+
+    /// Creates (or renames) the Home Assistant device of the camera and publishes its state.
+    fn publish_camera(&self, camera: &Camera, setting: Option<&CameraSetting>) {
+        let camera_id = camera.camera_id;
+
+        for (component, object) in ENTITIES {
+            self.publish(
+                self.config.discovery_topic(component, camera_id, object),
+                entity_config(&self.config, camera, object).to_string(),
+            );
+        }
+
+        self.publish(
+            self.config.camera_topic(camera_id, "status"),
+            if camera.is_online { ONLINE } else { OFFLINE },
+        );
+        self.publish_state(camera_id, "motion", self.motion.contains(&camera_id));
+        self.publish_state(camera_id, "recording", self.recording.contains(&camera_id));
+
+        if let Some(setting) = setting {
+            self.publish_settings(
+                camera_id,
+                &CameraSettingNoMeta {
+                    flashlight_enabled: setting.flashlight_enabled,
+                    resolution: setting.resolution,
+                    framerate: setting.framerate,
+                },
+            );
+        }
+    }
+
+    /// Removes the Home Assistant device and retained state of a deleted camera.
+    fn unpublish_camera(&self, camera_id: i64) {
+        for (component, object) in ENTITIES {
+            self.publish(
+                self.config.discovery_topic(component, camera_id, object),
+                "",
+            );
+        }
+
+        for object in [
+            "status",
+            "motion",
+            "recording",
+            "flashlight",
+            "resolution",
+            "framerate",
+        ] {
+            self.publish(self.config.camera_topic(camera_id, object), "");
+        }
+    }
+
+    /// Subscribes to commands and publishes every camera, done after every (re)connection.
+    async fn on_connected(&self, db: &SqlitePool) {
+        self.publish(self.config.status_topic(), ONLINE);
+
+        let command_topics = format!("{}/camera/+/+/set", self.config.topic_prefix);
+        if let Err(e) = self.client.try_subscribe(command_topics, QoS::AtLeastOnce) {
+            error!("Error subscribing to MQTT command topics: {e:?}");
+        }
+
+        let cameras = match Camera::list(db).await {
+            Ok(cameras) => cameras,
+            Err(e) => {
+                error!("Error listing cameras for MQTT: {e:?}");
+                return;
+            }
+        };
+
+        let settings = CameraSetting::list(db).await.unwrap_or_else(|e| {
+            error!("Error listing camera settings for MQTT: {e:?}");
+            Vec::new()
+        });
+
+        for camera in &cameras {
+            let setting = settings.iter().find(|s| s.camera_id == camera.camera_id);
+            self.publish_camera(camera, setting);
+        }
+    }
+
+    async fn on_api_message(&mut self, db: &SqlitePool, api_msg: ApiChannelMessage) {
+        match api_msg {
+            ApiChannelMessage::EventCreated(event) => {
+                let Some(camera_id) = event.camera_id else {
+                    return;
+                };
+
+                match event.kind {
+                    EventKind::CameraOnline => {
+                        self.publish(self.config.camera_topic(camera_id, "status"), ONLINE);
+                    }
+                    EventKind::CameraOffline => {
+                        self.publish(self.config.camera_topic(camera_id, "status"), OFFLINE);
+                    }
+                    EventKind::Motion => {
+                        self.motion.insert(camera_id);
+                        self.publish_state(camera_id, "motion", true);
+                    }
+                    EventKind::LoginFailed => (),
+                }
+            }
+            ApiChannelMessage::EventUpdated(event) => {
+                if event.kind != EventKind::Motion || event.end_time.is_none() {
+                    return;
+                }
+
+                if let Some(camera_id) = event.camera_id {
+                    self.motion.remove(&camera_id);
+                    self.publish_state(camera_id, "motion", false);
+                }
+            }
+            ApiChannelMessage::VideoStarted(video) => {
+                if let Some(camera_id) = video.camera_id {
+                    self.recording.insert(camera_id);
+                    self.publish_state(camera_id, "recording", true);
+                }
+            }
+            ApiChannelMessage::VideoFinished(video) => {
+                if let Some(camera_id) = video.camera_id {
+                    self.recording.remove(&camera_id);
+                    self.publish_state(camera_id, "recording", false);
+                }
+            }
+            ApiChannelMessage::CameraAction {
+                camera_id,
+                message: CameraMessage::SettingChanged(settings),
+            } => self.publish_settings(camera_id, &settings),
+            ApiChannelMessage::CameraAction {
+                message: CameraMessage::Restart,
+                ..
+            } => (),
+            ApiChannelMessage::CameraListChanged(
+                CameraListChange::Added { camera_id } | CameraListChange::Updated { camera_id },
+            ) => {
+                let Ok(camera) = Camera::get_using_id(db, camera_id).await else {
+                    error!("Error getting camera {camera_id} for MQTT");
+                    return;
+                };
+
+                let setting = CameraSetting::get_for_camera(db, camera_id).await.ok();
+                self.publish_camera(&camera, setting.as_ref());
+            }
+            ApiChannelMessage::CameraListChanged(CameraListChange::Removed { camera_id }) => {
+                self.unpublish_camera(camera_id);
+            }
+            ApiChannelMessage::ArmingChanged(_) | ApiChannelMessage::MotionZonesChanged { .. } => {}
+        }
+    }
+}
+
+/// Carries out a command received from the broker, setting changes are saved like any other.
+async fn run_command(
+    db: &SqlitePool,
+    api_channel: &broadcast::Sender<ApiChannelMessage>,
+    camera_id: i64,
+    command: Command,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let message = if command == Command::Restart {
+        CameraMessage::Restart
+    } else {
+        let mut setting = CameraSetting::get_for_camera(db, camera_id).await?;
+
+        match command {
+            Command::Flashlight(flashlight_enabled) => {
+                setting.flashlight_enabled = flashlight_enabled;
+            }
+            Command::Resolution(resolution) => setting.resolution = resolution,
+            Command::Framerate(framerate) => setting.framerate = framerate,
+            Command::Restart => (),
+        }
+
+        setting.last_modified = CameraSetting::DEFAULT.last_modified();
+        setting.modified_by = None;
+        setting.update_using_self(db).await?;
+
+        CameraMessage::SettingChanged(CameraSettingNoMeta {
+            flashlight_enabled: setting.flashlight_enabled,
+            resolution: setting.resolution,
+            framerate: setting.framerate,
+        })
+    };
+
+    api_channel.send(ApiChannelMessage::CameraAction { camera_id, message })?;
+
+    Ok(())
+}
+
+/// Publishes camera status, motion and recordings to the broker until shutdown, reconnecting
+/// when the connection is lost.
+///
+/// Commands received on `<prefix>/camera/<camera_id>/<object>/set` are sent over `api_channel`.
+pub async fn continuously_publish(
+    db: SqlitePool,
+    config: MqttConfig,
+    api_channel: broadcast::Sender<ApiChannelMessage>,
+    shutdown_token: CancellationToken,
+) {
+    let mut api_channel_rx = api_channel.subscribe();
+
+    let (client, event_loop) = AsyncClient::new(config.options(), REQUEST_CAPACITY);
+    let (incoming_tx, mut incoming_rx) = mpsc::unbounded_channel();
+    let mut event_loop_task = tokio::spawn(poll_event_loop(event_loop, incoming_tx));
+
+    let mut publisher = Publisher {
+        client,
+        config,
+        motion: HashSet::new(),
+        recording: HashSet::new(),
+    };
+
+    loop {
+        tokio::select! {
+            incoming = incoming_rx.recv() => match incoming {
+                Some(Packet::ConnAck(_)) => {
+                    info!(
+                        "Connected to MQTT broker {}:{}",
+                        publisher.config.host, publisher.config.port
+                    );
+                    publisher.on_connected(&db).await;
+                }
+                Some(Packet::Publish(publish)) => {
+                    let Some((camera_id, command)) =
+                        parse_command(&publisher.config, &publish.topic, &publish.payload)
+                    else {
+                        debug!("Ignoring invalid MQTT command on {}", publish.topic);
+                        continue;
+                    };
+
+                    if let Err(e) = run_command(&db, &api_channel, camera_id, command).await {
+                        error!("Error running MQTT command {command:?} for camera {camera_id}: {e:?}");
+                    }
+                }
+                Some(_) => (),
+                None => break,
+            },
+            api_msg = api_channel_rx.recv() => match api_msg {
+                Ok(api_msg) => publisher.on_api_message(&db, api_msg).await,
+                Err(broadcast::error::RecvError::Lagged(skipped)) => {
+                    warn!("MQTT publisher fell behind, skipped {skipped} messages");
+                }
+                Err(broadcast::error::RecvError::Closed) => break,
+            },
+            () = shutdown_token.cancelled() => break,
+        }
+    }
+
+    // The last will is only sent by the broker if the connection is lost, not on disconnect
+    publisher.publish(publisher.config.status_topic(), OFFLINE);
+    let _ = publisher.client.try_disconnect();
+
+    if tokio::time::timeout(DISCONNECT_TIMEOUT, &mut event_loop_task)
+        .await
+        .is_err()
+    {
+        event_loop_task.abort();
+    }
+}
+
+/// Drives the connection, forwarding incoming packets, until Oko disconnects.
+///
+/// Kept separate so that polling is never cancelled halfway through a packet.
+async fn poll_event_loop(mut event_loop: EventLoop, incoming_tx: mpsc::UnboundedSender<Packet>) {
+    loop {
+        match event_loop.poll().await {
+            Ok(MqttEvent::Incoming(packet)) => {
+                if incoming_tx.send(packet).is_err() {
+                    break;
+                }
+            }
+            Ok(MqttEvent::Outgoing(Outgoing::Disconnect)) => break,
+            Ok(MqttEvent::Outgoing(_)) => (),
+            Err(e) => {
+                warn!("MQTT connection error, reconnecting in {RECONNECT_DELAY:?}: {e:?}");
+                tokio::time::sleep(RECONNECT_DELAY).await;
+            }
+        }
+    }
+}
+
+#[allow(clippy::unwrap_used)]
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config() -> MqttConfig {
+        MqttConfig {
+            host: "localhost".to_string(),
+            port: 1883,
+            credentials: None,
+            topic_prefix: "oko".to_string(),
+            discovery_prefix: "homeassistant".to_string(),
+        }
+    }
+
+    #[test]
+    fn commands() {
+        let config = config();
+
+        assert_eq!(
+            parse_command(&config, "oko/camera/1/restart/set", b"PRESS"),
+            Some((1, Command::Restart))
+        );
+        assert_eq!(
+            parse_command(&config, "oko/camera/2/flashlight/set", b"ON"),
+            Some((2, Command::Flashlight(true)))
+        );
+        assert_eq!(
+            parse_command(&config, "oko/camera/2/resolution/set", b"VGA"),
+            Some((2, Command::Resolution(Resolution::Vga)))
+        );
+        assert_eq!(
+            parse_command(&config, "oko/camera/2/framerate/set", b"15"),
+            Some((2, Command::Framerate(15)))
+        );
+
+        assert!(parse_command(&config, "oko/camera/2/framerate/set", b"120").is_none());
+        assert!(parse_command(&config, "oko/camera/2/flashlight/set", b"maybe").is_none());
+        assert!(parse_command(&config, "oko/camera/two/restart/set", b"").is_none());
+        assert!(parse_command(&config, "oko/camera/2/motion/set", b"ON").is_none());
+        assert!(parse_command(&config, "other/camera/2/restart/set", b"").is_none());
+    }
+
+    #[test]
+    fn discovery() {
+        let config = config();
+        let camera = Camera {
+            camera_id: 2,
+            name: "Kitchen".to_string(),
+            ip_address: Camera::DEFAULT.ip_address,
+            last_connected: Camera::DEFAULT.last_connected,
+            is_active: Camera::DEFAULT.is_active,
+            is_online: Camera::DEFAULT.is_online,
+            token_hash: Camera::DEFAULT.token_hash,
+        };
+
+        assert_eq!(
+            config.discovery_topic("switch", 2, "flashlight"),
+            "homeassistant/switch/oko_2/flashlight/config"
+        );
+
+        let flashlight = entity_config(&config, &camera, "flashlight");
+
+        assert_eq!(
+            flashlight.pointer("/unique_id"),
+            Some(&json!("oko_2_flashlight"))
+        );
+        assert_eq!(
+            flashlight.pointer("/state_topic"),
+            Some(&json!("oko/camera/2/flashlight"))
+        );
+        assert_eq!(
+            flashlight.pointer("/command_topic"),
+            Some(&json!("oko/camera/2/flashlight/set"))
+        );
+        assert_eq!(flashlight.pointer("/device/name"), Some(&json!("Kitchen")));
+        assert_eq!(
+            flashlight.pointer("/availability/1/topic"),
+            Some(&json!("oko/camera/2/status"))
+        );
+
+        let resolution = entity_config(&config, &camera, "resolution");
+
+        assert_eq!(
+            resolution.pointer("/options"),
+            Some(&json!(["SVGA", "VGA"]))
+        );
+
+        let motion = entity_config(&config, &camera, "motion");
+
+        assert_eq!(motion.pointer("/device_class"), Some(&json!("motion")));
+        assert!(motion.get("command_topic").is_none());
+    }
+}
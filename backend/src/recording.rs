@@ -0,0 +1,844 @@
+use std::{
+    collections::VecDeque,
+    mem,
+    path::{Path, PathBuf},
+};
+
+use opencv::{
+    core::{Mat, MatTraitConst, Point, Size, Vector},
+    imgcodecs::{imdecode, imwrite, IMREAD_COLOR},
+    imgproc::{resize, INTER_AREA, INTER_LINEAR},
+    videoio::{VideoWriter, VideoWriterTrait},
+};
+use sqlx::SqlitePool;
+use time::{Duration, OffsetDateTime};
+use tokio::sync::broadcast;
+use tokio_util::sync::CancellationToken;
+use tracing::{debug, error, info, warn};
+
+use crate::{
+    db::{
+        ArmingState, CameraSetting, Event, EventKind, Model, MotionZone, PolygonPoint,
+        RecordingMode, Video, VideoFormat, VideoThumbnail,
+    },
+    events,
+    web::{ApiChannelMessage, CameraMessage, ImageContainer},
+    Resolution,
+};
+
+pub use motion::MotionDetector;
+pub use privacy::apply_privacy_masks;
+
+mod motion;
+mod privacy;
+
+/// Start a new video once the current one holds this many bytes of (encoded) frames
+const MAX_SEGMENT_SIZE_BYTES: usize = 512 * 1024 * 1024;
+/// How often a thumbnail is saved, in video time
+const THUMBNAIL_INTERVAL: Duration = Duration::seconds(10);
+/// Width of thumbnails in pixels, the height keeps the aspect ratio
+const THUMBNAIL_WIDTH: i32 = 160;
+/// How long whether a camera is armed is remembered for, so schedules starting/ending are noticed
+const ARMED_CHECK_INTERVAL: Duration = Duration::seconds(10);
+
+/// A single video file that is being written, along with its row in the `videos` table.
+struct Segment {
+    video: Video,
+    writer: VideoWriter,
+    total_bytes: usize,
+    /// Frames written to the file, including repeated ones
+    frames_written: u64,
+    /// Frames received from the camera, used for `Video::average_fps`
+    frames_received: u64,
+    first_frame_at: Option<OffsetDateTime>,
+    last_frame_at: Option<OffsetDateTime>,
+    /// Repeated to fill gaps between frames
+    last_frame: Option<Mat>,
+    /// Saved to the DB when the video is finished
+    thumbnails: Vec<VideoThumbnail>,
+    next_thumbnail_at: Duration,
+}
+
+impl Segment {
+    const fn new(video: Video, writer: VideoWriter) -> Self {
+        Self {
+            video,
+            writer,
+            total_bytes: 0,
+            frames_written: 0,
+            frames_received: 0,
+            first_frame_at: None,
+            last_frame_at: None,
+            last_frame: None,
+            thumbnails: Vec::new(),
+            next_thumbnail_at: Duration::ZERO,
+        }
+    }
+
+    /// Path of an image saved next to the video, e.g. `<video>_poster.jpg`
+    fn image_path(&self, name: &str) -> PathBuf {
+        let file_path = Path::new(&self.video.file_path);
+        let file_stem = file_path.file_stem().unwrap_or_default().to_string_lossy();
+
+        file_path.with_file_name(format!("{file_stem}_{name}.jpg"))
+    }
+
+    /// Failing to save the poster is only logged, the video itself is fine
+    fn save_poster(&mut self, frame: &Mat) {
+        let poster_path = self.image_path("poster");
+
+        match imwrite(&poster_path.to_string_lossy(), frame, &Vector::new()) {
+            Ok(true) => self.video.poster_path = Some(poster_path.to_string_lossy().to_string()),
+            result => warn!(
+                "Failed to save poster for video {}: {result:?}",
+                self.video.video_id
+            ),
+        }
+    }
+
+    /// Saves a thumbnail if one is due at `offset` into the video, failures are only logged
+    fn save_thumbnail_if_due(&mut self, frame: &Mat, offset: Duration) {
+        if offset < self.next_thumbnail_at {
+            return;
+        }
+
+        while self.next_thumbnail_at <= offset {
+            self.next_thumbnail_at += THUMBNAIL_INTERVAL;
+        }
+
+        let thumbnail_path = self.image_path(&format!("thumbnail_{}", self.thumbnails.len()));
+
+        match write_thumbnail(&thumbnail_path, frame) {
+            Ok(true) => self.thumbnails.push(VideoThumbnail {
+                thumbnail_id: VideoThumbnail::DEFAULT.thumbnail_id,
+                video_id: self.video.video_id,
+                file_path: thumbnail_path.to_string_lossy().to_string(),
+                offset_seconds: offset.as_seconds_f64(),
+            }),
+            result => warn!(
+                "Failed to save thumbnail for video {}: {result:?}",
+                self.video.video_id
+            ),
+        }
+    }
+
+    /// `None` if less than two frames were received
+    fn average_fps(&self) -> Option<f64> {
+        let (Some(first_frame_at), Some(last_frame_at)) = (self.first_frame_at, self.last_frame_at)
+        else {
+            return None;
+        };
+
+        let elapsed_seconds = (last_frame_at - first_frame_at).as_seconds_f64();
+
+        #[allow(clippy::cast_precision_loss)] // the precision loss is acceptable
+        let frame_intervals = self.frames_received.saturating_sub(1) as f64;
+
+        (elapsed_seconds > 0.0).then(|| frame_intervals / elapsed_seconds)
+    }
+}
+
+/// Writes a `THUMBNAIL_WIDTH` wide copy of `frame` as a JPEG.
+fn write_thumbnail(file_path: &Path, frame: &Mat) -> opencv::Result<bool> {
+    let frame_size = frame.size()?;
+    let thumbnail_size = Size::new(
+        THUMBNAIL_WIDTH,
+        frame_size.height * THUMBNAIL_WIDTH / frame_size.width.max(1),
+    );
+
+    let mut thumbnail = Mat::default();
+    resize(frame, &mut thumbnail, thumbnail_size, 0.0, 0.0, INTER_AREA)?;
+
+    imwrite(&file_path.to_string_lossy(), &thumbnail, &Vector::new())
+}
+
+/// How many times a frame taken `elapsed` after the start of a video should be written,
+/// so that a video with a fixed `framerate` that already has `frames_written` frames plays in real time.
+///
+/// 0 means the frame came too early and should be dropped, more than 1 means there is a gap before
+/// the frame that should be filled.
+fn frames_to_write(elapsed: Duration, framerate: f64, frames_written: u64) -> u64 {
+    // Index of the frame in the video, based on its timestamp
+    #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)] // clamped to 0 and rounded
+    let slot = (elapsed.as_seconds_f64() * framerate).round().max(0.0) as u64;
+
+    if slot < frames_written {
+        0
+    } else {
+        slot - frames_written + 1
+    }
+}
+
+/// Writes the frames of one camera to disk, every video it creates gets its own `Video` row.
+pub struct Recorder {
+    camera_id: i64,
+    video_path: PathBuf,
+    db: SqlitePool,
+    api_channel: broadcast::Sender<ApiChannelMessage>,
+    frame_size: Size,
+    framerate: f64,
+    segment_length: Duration,
+    format: VideoFormat,
+    segment: Option<Segment>,
+}
+
+/// Size of the frames the camera sends at `resolution`.
+#[allow(clippy::cast_possible_wrap)] // resolutions are nowhere near `i32::MAX`
+const fn frame_size(resolution: Resolution) -> Size {
+    let (width, height) = resolution.size();
+
+    Size::new(width as i32, height as i32)
+}
+
+/// The `OpenCV` codec used for each format.
+fn fourcc(format: VideoFormat) -> opencv::Result<i32> {
+    match format {
+        VideoFormat::Mp4 => VideoWriter::fourcc('a', 'v', 'c', '1'),
+        VideoFormat::Mjpeg => VideoWriter::fourcc('M', 'J', 'P', 'G'),
+        VideoFormat::Mpeg4 => VideoWriter::fourcc('m', 'p', '4', 'v'),
+    }
+}
+
+/// Where the corners of a polygon end up in a frame of `size`
+#[allow(clippy::cast_possible_truncation)] // points are between 0 and 1
+fn to_pixels(points: &[PolygonPoint], size: Size) -> Vector<Point> {
+    points
+        .iter()
+        .map(|point| {
+            Point::new(
+                (point.x * f64::from(size.width)).round() as i32,
+                (point.y * f64::from(size.height)).round() as i32,
+            )
+        })
+        .collect()
+}
+
+impl Recorder {
+    #[must_use]
+    pub fn new(
+        camera_id: i64,
+        video_path: PathBuf,
+        db: SqlitePool,
+        api_channel: broadcast::Sender<ApiChannelMessage>,
+        settings: Option<&CameraSetting>,
+    ) -> Self {
+        let segment_length = Duration::seconds(
+            settings.map_or(CameraSetting::DEFAULT.segment_length_seconds, |settings| {
+                settings.segment_length_seconds
+            }),
+        );
+
+        let format = settings.map_or(CameraSetting::DEFAULT.recording_format, |settings| {
+            settings.recording_format
+        });
+
+        let (frame_size, framerate) = settings.map_or_else(
+            || (frame_size(Resolution::default()), 12),
+            |settings| (frame_size(settings.resolution), settings.framerate),
+        );
+
+        Self {
+            camera_id,
+            video_path,
+            db,
+            api_channel,
+            frame_size,
+            #[allow(clippy::cast_precision_loss)] // the precision loss is acceptable
+            framerate: framerate as f64,
+            segment_length,
+            format,
+            segment: None,
+        }
+    }
+
+    pub const fn is_recording(&self) -> bool {
+        self.segment.is_some()
+    }
+
+    /// The video that is being recorded
+    pub fn video_id(&self) -> Option<i64> {
+        self.segment.as_ref().map(|segment| segment.video.video_id)
+    }
+
+    /// Uses `frame` as the poster of the current video instead of its first frame,
+    /// e.g. the frame that triggered a motion recording.
+    pub fn save_poster(&mut self, frame: &Mat) {
+        if let Some(segment) = self.segment.as_mut() {
+            segment.save_poster(frame);
+        }
+    }
+
+    /// Creates a new video file and `Video` row, does nothing if already recording.
+    pub async fn start(
+        &mut self,
+        start_time: OffsetDateTime,
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        if self.segment.is_some() {
+            return Ok(());
+        }
+
+        let formatted_start_time = start_time.format(Video::DEFAULT.file_name_format)?;
+        let file_pathbuf_for = |format: VideoFormat| {
+            self.video_path
+                .join(format!("{formatted_start_time}.{}", format.extension()))
+        };
+
+        let (format, writer) =
+            match self.open_writer(&file_pathbuf_for(self.format), self.format)? {
+                Some(writer) => (self.format, writer),
+                // Not every OpenCV build can encode H.264, but MJPEG is built-in
+                None if self.format != VideoFormat::Mjpeg => {
+                    warn!(
+                        "Can't record {:?} for camera {}, falling back to MJPEG",
+                        self.format, self.camera_id
+                    );
+
+                    let Some(writer) = self
+                        .open_writer(&file_pathbuf_for(VideoFormat::Mjpeg), VideoFormat::Mjpeg)?
+                    else {
+                        return Err("Failed to open video writer".into());
+                    };
+
+                    (VideoFormat::Mjpeg, writer)
+                }
+                None => return Err("Failed to open video writer".into()),
+            };
+
+        let file_pathbuf = file_pathbuf_for(format);
+
+        let mut video = Video {
+            video_id: Video::DEFAULT.video_id,
+            camera_id: Some(self.camera_id),
+            file_path: file_pathbuf.to_string_lossy().to_string(),
+            start_time,
+            end_time: Video::DEFAULT.end_time,
+            file_size: None,
+            keep_forever: Video::DEFAULT.keep_forever,
+            format,
+            average_fps: Video::DEFAULT.average_fps,
+            poster_path: Video::DEFAULT.poster_path,
+        };
+
+        video.create_using_self(&self.db).await?;
+
+        info!(
+            "Started recording video {} for camera {}",
+            video.video_id, self.camera_id
+        );
+
+        // Nobody might be listening, e.g. no MQTT broker or viewers
+        let _ = self
+            .api_channel
+            .send(ApiChannelMessage::VideoStarted(video.clone()));
+
+        self.segment = Some(Segment::new(video, writer));
+
+        Ok(())
+    }
+
+    /// Creates the video file, `None` if this `OpenCV` build can't write `format`.
+    fn open_writer(
+        &self,
+        file_path: &Path,
+        format: VideoFormat,
+    ) -> opencv::Result<Option<VideoWriter>> {
+        let writer = VideoWriter::new_def(
+            &file_path.to_string_lossy(),
+            fourcc(format)?,
+            self.framerate,
+            self.frame_size,
+        )?;
+
+        Ok(writer.is_opened()?.then_some(writer))
+    }
+
+    /// Whether the current video is long/big enough that a new one should be started.
+    pub fn is_segment_full(&self, now: OffsetDateTime) -> bool {
+        self.segment.as_ref().is_some_and(|segment| {
+            now - segment.video.start_time >= self.segment_length
+                || segment.total_bytes >= MAX_SEGMENT_SIZE_BYTES
+        })
+    }
+
+    /// Finishes the current video and starts a new one if it is full, see `is_segment_full`.
+    pub async fn rotate_if_full(
+        &mut self,
+        now: OffsetDateTime,
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        if self.is_segment_full(now) {
+            debug!("Segment full for camera {}, rotating...", self.camera_id);
+
+            self.finish(now).await?;
+            self.start(now).await?;
+        }
+
+        Ok(())
+    }
+
+    /// Applies new settings, if the resolution/framerate/format changed the current video is finished
+    /// and a new one is started since a video can't change those.
+    ///
+    /// A shorter segment length is picked up by the next `rotate_if_full`.
+    pub async fn change_settings(
+        &mut self,
+        settings: &CameraSetting,
+        now: OffsetDateTime,
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        self.segment_length = Duration::seconds(settings.segment_length_seconds);
+
+        let frame_size = frame_size(settings.resolution);
+        #[allow(clippy::cast_precision_loss)] // the precision loss is acceptable
+        let framerate = settings.framerate as f64;
+        let format = settings.recording_format;
+
+        #[allow(clippy::float_cmp)] // both come from integers
+        let unchanged =
+            frame_size == self.frame_size && framerate == self.framerate && format == self.format;
+
+        if unchanged {
+            return Ok(());
+        }
+
+        info!(
+            "Camera {} changed to {}x{} at {framerate} fps, recording {format:?}",
+            self.camera_id, frame_size.width, frame_size.height
+        );
+
+        let was_recording = self.is_recording();
+
+        self.finish(now).await?;
+
+        self.frame_size = frame_size;
+        self.framerate = framerate;
+        self.format = format;
+
+        if was_recording {
+            self.start(now).await?;
+        }
+
+        Ok(())
+    }
+
+    /// Writes a decoded frame taken at `timestamp` to the current video, frames are dropped if not recording.
+    ///
+    /// Videos have a fixed framerate, to keep them in real time frames that arrive faster than the
+    /// framerate are dropped and the previous frame is repeated when they arrive slower.
+    pub fn write(
+        &mut self,
+        frame: Mat,
+        encoded_size: usize,
+        timestamp: OffsetDateTime,
+    ) -> opencv::Result<()> {
+        let Some(segment) = self.segment.as_mut() else {
+            return Ok(());
+        };
+
+        // Frames in the old size keep coming for a bit after the resolution changes
+        let frame = if frame.size()? == self.frame_size {
+            frame
+        } else {
+            let mut resized_frame = Mat::default();
+            resize(
+                &frame,
+                &mut resized_frame,
+                self.frame_size,
+                0.0,
+                0.0,
+                INTER_LINEAR,
+            )?;
+            resized_frame
+        };
+
+        segment.frames_received += 1;
+        if segment.first_frame_at.is_none() {
+            segment.first_frame_at = Some(timestamp);
+        }
+        segment.last_frame_at = Some(timestamp);
+
+        let offset = timestamp - segment.video.start_time;
+        let frames_to_write = frames_to_write(offset, self.framerate, segment.frames_written);
+
+        if frames_to_write == 0 {
+            return Ok(());
+        }
+
+        if segment.video.poster_path.is_none() {
+            segment.save_poster(&frame);
+        }
+        segment.save_thumbnail_if_due(&frame, offset);
+
+        let gap_frame = segment.last_frame.as_ref().unwrap_or(&frame);
+
+        // ? Does calling this function too often/quickly risk a crash? Use a buffer/batch?
+        for _ in 1..frames_to_write {
+            segment.writer.write(gap_frame)?;
+        }
+
+        segment.writer.write(&frame)?;
+        segment.frames_written += frames_to_write;
+        segment.total_bytes += encoded_size;
+        segment.last_frame = Some(frame);
+
+        Ok(())
+    }
+
+    /// Closes the current video file and saves its end time and size, does nothing if not recording.
+    pub async fn finish(
+        &mut self,
+        end_time: OffsetDateTime,
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        let Some(mut segment) = self.segment.take() else {
+            return Ok(());
+        };
+
+        segment.writer.release()?;
+
+        segment.video.end_time = Some(end_time);
+        segment.video.file_size = Some(segment.total_bytes.try_into()?);
+        segment.video.average_fps = segment.average_fps();
+
+        segment.video.update_using_self(&self.db).await?;
+
+        for thumbnail in &mut segment.thumbnails {
+            thumbnail.create_using_self(&self.db).await?;
+        }
+
+        info!(
+            "Finished recording video {} for camera {}",
+            segment.video.video_id, self.camera_id
+        );
+
+        // Nobody might be listening, e.g. no webhooks or viewers
+        let _ = self
+            .api_channel
+            .send(ApiChannelMessage::VideoFinished(segment.video));
+
+        Ok(())
+    }
+}
+
+// TODO: Find out which is better, ingesting encoded or decoded images
+/// Records the frames of `camera_id` from `images_rx` until `recording_token` is cancelled or it closes.
+///
+/// Setting changes sent over `api_channel` are applied without reconnecting, see `Recorder::change_settings`,
+/// motion recordings are published to it as `Motion` events while the camera is armed.
+#[allow(clippy::too_many_lines)] // TODO: Refactor
+pub async fn record(
+    mut images_rx: broadcast::Receiver<ImageContainer>,
+    api_channel: broadcast::Sender<ApiChannelMessage>,
+    recording_token: CancellationToken,
+    db: SqlitePool,
+    video_path: PathBuf,
+    camera_id: i64,
+    settings: Option<CameraSetting>,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let mut api_channel_rx = api_channel.subscribe();
+    let mut recorder = Recorder::new(
+        camera_id,
+        video_path.clone(),
+        db.clone(),
+        api_channel.clone(),
+        settings.as_ref(),
+    );
+
+    let (mut recording_mode, motion_sensitivity, mut pre_roll, mut post_roll) =
+        motion_settings(settings.as_ref());
+
+    let mut motion_detector =
+        MotionDetector::new(motion_sensitivity, load_motion_zones(&db, camera_id).await);
+    // Encoded frames are kept instead of decoded ones to save memory
+    let mut pre_roll_frames: VecDeque<(OffsetDateTime, Vec<u8>)> = VecDeque::new();
+    let mut last_motion: Option<OffsetDateTime> = None;
+    let mut motion_event: Option<Event> = None;
+    let mut armed_check: Option<(OffsetDateTime, bool)> = None;
+
+    // TODO: Adding a sleep might be a good idea?
+    loop {
+        let message = tokio::select! {
+            image = images_rx.recv() => match image {
+                Ok(image) => image,
+                Err(broadcast::error::RecvError::Lagged(skipped)) => {
+                    warn!("Recorder for camera {camera_id} fell behind, skipped {skipped} images");
+                    continue;
+                }
+                Err(broadcast::error::RecvError::Closed) => break,
+            },
+            api_msg = api_channel_rx.recv() => {
+                match api_msg {
+                    Ok(ApiChannelMessage::CameraAction {
+                        camera_id: message_camera_id,
+                        message: CameraMessage::SettingChanged(_),
+                    }) if message_camera_id == camera_id => {
+                        // The message only has what the camera needs, the rest is in the DB
+                        let new_settings = match CameraSetting::get_for_camera(&db, camera_id).await {
+                            Ok(new_settings) => new_settings,
+                            Err(e) => {
+                                error!("Error reloading settings of camera {camera_id}: {e:?}");
+                                continue;
+                            }
+                        };
+
+                        let now = OffsetDateTime::now_utc();
+                        recorder.change_settings(&new_settings, now).await?;
+
+                        let (new_recording_mode, motion_sensitivity, new_pre_roll, new_post_roll) =
+                            motion_settings(Some(&new_settings));
+                        motion_detector.set_sensitivity(motion_sensitivity);
+                        pre_roll = new_pre_roll;
+                        post_roll = new_post_roll;
+
+                        if new_recording_mode != recording_mode {
+                            info!("Camera {camera_id} switched to {new_recording_mode:?} recording");
+
+                            recorder.finish(now).await?;
+
+                            if let Some(mut event) = motion_event.take() {
+                                event.end_time = Some(now);
+                                events::publish_update(&db, &api_channel, event).await;
+                            }
+
+                            pre_roll_frames.clear();
+                            last_motion = None;
+                            recording_mode = new_recording_mode;
+                        }
+                    }
+                    Ok(ApiChannelMessage::ArmingChanged(_)) => armed_check = None,
+                    Ok(ApiChannelMessage::MotionZonesChanged {
+                        camera_id: message_camera_id,
+                    }) if message_camera_id == camera_id => {
+                        motion_detector.set_zones(load_motion_zones(&db, camera_id).await);
+                    }
+                    Err(broadcast::error::RecvError::Lagged(skipped)) => {
+                        warn!("Recorder for camera {camera_id} fell behind, skipped {skipped} API messages");
+                    }
+                    Err(broadcast::error::RecvError::Closed) => break,
+                    Ok(_) => (),
+                }
+
+                continue;
+            },
+            () = recording_token.cancelled() => {
+                break;
+            }
+        };
+
+        debug!("Recording image from camera {camera_id}...");
+
+        let now = message
+            .received_at()
+            .unwrap_or_else(OffsetDateTime::now_utc);
+        let encoded_size = message.image_bytes.len();
+        // A single bad frame shouldn't stop the recording
+        let decoded_image = match imdecode(&message.image_bytes.as_slice(), IMREAD_COLOR) {
+            Ok(decoded_image) if !decoded_image.empty() => decoded_image,
+            Ok(_) => {
+                warn!("Skipping frame from camera {camera_id} that decoded to an empty image");
+                continue;
+            }
+            Err(e) => {
+                warn!("Skipping frame from camera {camera_id} that can't be decoded: {e:?}");
+                continue;
+            }
+        };
+
+        match recording_mode {
+            RecordingMode::Continuous => {
+                recorder.rotate_if_full(now).await?;
+                recorder.start(now).await?;
+                recorder.write(decoded_image, encoded_size, now)?;
+            }
+            RecordingMode::Motion => {
+                let motion_trigger = motion_detector.detect(&decoded_image).unwrap_or_else(|e| {
+                    warn!("Error detecting motion for camera {camera_id}: {e:?}");
+                    None
+                });
+                let motion_detected = motion_trigger.is_some();
+
+                if motion_detected {
+                    last_motion = Some(now);
+                }
+
+                if recorder.is_recording() {
+                    recorder.rotate_if_full(now).await?;
+                    recorder.write(decoded_image, encoded_size, now)?;
+
+                    if last_motion.is_some_and(|last_motion| now - last_motion > post_roll) {
+                        debug!("Motion stopped for camera {camera_id}...");
+                        recorder.finish(now).await?;
+
+                        if let Some(mut event) = motion_event.take() {
+                            event.end_time = Some(now);
+                            events::publish_update(&db, &api_channel, event).await;
+                        }
+                    }
+                } else if motion_detected && is_armed(&db, camera_id, now, &mut armed_check).await {
+                    debug!("Motion detected for camera {camera_id}...");
+
+                    let start_time = pre_roll_frames.front().map_or(now, |(time, _)| *time);
+                    recorder.start(start_time).await?;
+                    recorder.save_poster(&decoded_image);
+
+                    let mut event = Event::new(Some(camera_id), EventKind::Motion, now);
+                    event.video_id = recorder.video_id();
+                    event.zone_id = motion_trigger.and_then(|trigger| trigger.zone_id);
+                    event.snapshot_path = save_snapshot(&video_path, &message.image_bytes, now)
+                        .await
+                        .map(|path| path.to_string_lossy().to_string());
+                    motion_event = events::publish(&db, &api_channel, event).await;
+
+                    for (time, pre_roll_frame) in mem::take(&mut pre_roll_frames) {
+                        match imdecode(&pre_roll_frame.as_slice(), IMREAD_COLOR) {
+                            Ok(decoded_pre_roll_frame) if !decoded_pre_roll_frame.empty() => {
+                                recorder.write(
+                                    decoded_pre_roll_frame,
+                                    pre_roll_frame.len(),
+                                    time,
+                                )?;
+                            }
+                            result => warn!(
+                                "Skipping pre-roll frame from camera {camera_id}: {:?}",
+                                result.err()
+                            ),
+                        }
+                    }
+
+                    recorder.write(decoded_image, encoded_size, now)?;
+                } else {
+                    pre_roll_frames.push_back((now, message.image_bytes));
+
+                    while pre_roll_frames
+                        .front()
+                        .is_some_and(|(time, _)| now - *time > pre_roll)
+                    {
+                        pre_roll_frames.pop_front();
+                    }
+                }
+            }
+        }
+    }
+
+    let end_time = OffsetDateTime::now_utc();
+    recorder.finish(end_time).await?;
+
+    if let Some(mut event) = motion_event {
+        event.end_time = Some(end_time);
+        events::publish_update(&db, &api_channel, event).await;
+    }
+
+    Ok(())
+}
+
+/// Whether motion on `camera_id` should start a recording/event, see `ArmingState::is_camera_armed`.
+///
+/// The result is reused for `ARMED_CHECK_INTERVAL` unless `last_check` is cleared, errors are
+/// logged and treated as armed so motion isn't missed because of them.
+async fn is_armed(
+    db: &SqlitePool,
+    camera_id: i64,
+    now: OffsetDateTime,
+    last_check: &mut Option<(OffsetDateTime, bool)>,
+) -> bool {
+    if let Some((checked_at, armed)) = *last_check {
+        if now - checked_at < ARMED_CHECK_INTERVAL {
+            return armed;
+        }
+    }
+
+    let armed = ArmingState::is_camera_armed(db, camera_id, now)
+        .await
+        .unwrap_or_else(|e| {
+            error!("Error checking if camera {camera_id} is armed: {e:?}");
+            true
+        });
+
+    if !armed {
+        debug!("Ignoring motion on camera {camera_id}, it isn't armed");
+    }
+
+    *last_check = Some((now, armed));
+
+    armed
+}
+
+/// Recording mode, motion sensitivity and pre/post-roll from `settings`, the defaults without them
+fn motion_settings(settings: Option<&CameraSetting>) -> (RecordingMode, i64, Duration, Duration) {
+    let (recording_mode, motion_sensitivity, pre_roll_seconds, post_roll_seconds) = settings
+        .map_or(
+            (
+                CameraSetting::DEFAULT.recording_mode,
+                CameraSetting::DEFAULT.motion_sensitivity,
+                CameraSetting::DEFAULT.motion_pre_roll_seconds,
+                CameraSetting::DEFAULT.motion_post_roll_seconds,
+            ),
+            |s| {
+                (
+                    s.recording_mode,
+                    s.motion_sensitivity,
+                    s.motion_pre_roll_seconds,
+                    s.motion_post_roll_seconds,
+                )
+            },
+        );
+
+    (
+        recording_mode,
+        motion_sensitivity,
+        Duration::seconds(pre_roll_seconds),
+        Duration::seconds(post_roll_seconds),
+    )
+}
+
+/// The motion zones of `camera_id`, errors are logged and treated as there being none
+async fn load_motion_zones(db: &SqlitePool, camera_id: i64) -> Vec<MotionZone> {
+    MotionZone::list_for_camera(db, camera_id)
+        .await
+        .unwrap_or_else(|e| {
+            error!("Error getting motion zones of camera {camera_id}: {e:?}");
+            Vec::new()
+        })
+}
+
+/// Saves the (encoded) image that triggered an event next to the videos, failures are only logged
+async fn save_snapshot(
+    video_path: &Path,
+    image_bytes: &[u8],
+    taken_at: OffsetDateTime,
+) -> Option<PathBuf> {
+    let formatted_time = taken_at.format(Video::DEFAULT.file_name_format).ok()?;
+    let snapshot_path = video_path.join(format!("{formatted_time}_motion.jpg"));
+
+    match tokio::fs::write(&snapshot_path, image_bytes).await {
+        Ok(()) => Some(snapshot_path),
+        Err(e) => {
+            warn!("Failed to save event snapshot {snapshot_path:?}: {e:?}");
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn frames_in_real_time() {
+        // 10 fps, frame 5 is due at 0.5s
+        assert_eq!(frames_to_write(Duration::milliseconds(500), 10.0, 5), 1);
+        assert_eq!(frames_to_write(Duration::milliseconds(520), 10.0, 5), 1);
+        assert_eq!(frames_to_write(Duration::ZERO, 10.0, 0), 1);
+    }
+
+    #[test]
+    fn early_frames_are_dropped() {
+        assert_eq!(frames_to_write(Duration::milliseconds(420), 10.0, 5), 0);
+        // e.g. after the clock goes back
+        assert_eq!(frames_to_write(Duration::seconds(-1), 10.0, 5), 0);
+    }
+
+    #[test]
+    fn late_frames_fill_the_gap() {
+        // Frames 5 to 9 are missing
+        assert_eq!(frames_to_write(Duration::seconds(1), 10.0, 5), 6);
+        assert_eq!(frames_to_write(Duration::seconds(2), 5.0, 1), 10);
+    }
+}
@@ -0,0 +1,379 @@
+use opencv::{
+    core::{absdiff, bitwise_and_def, count_non_zero, Mat, Point, Scalar, Size, Vector, CV_8UC1},
+    imgproc::{
+        cvt_color_def, fill_poly_def, gaussian_blur_def, threshold, COLOR_BGR2GRAY, THRESH_BINARY,
+    },
+    prelude::*,
+};
+
+use super::to_pixels;
+use crate::db::{MotionZone, MotionZoneKind};
+
+/// How much a (blurred, grayscale) pixel has to change to count as changed
+const PIXEL_DIFF_THRESHOLD: f64 = 25.0;
+/// Fraction of the frame that has to change at the lowest sensitivity
+const LEAST_SENSITIVE_CHANGED_FRACTION: f64 = 0.1;
+/// Blurring gets rid of sensor noise and JPEG artifacts before diffing
+const BLUR_KERNEL_SIZE: i32 = 21;
+
+/// Why `MotionDetector::detect` found motion
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MotionTrigger {
+    /// The include zone enough of changed in, `None` if the camera has no include zones
+    pub zone_id: Option<i64>,
+}
+
+/// An include zone drawn onto a mask the size of the frames
+struct IncludeMask {
+    zone_id: i64,
+    /// 255 inside the zone (minus exclude zones), 0 elsewhere
+    mask: Mat,
+    pixels: i32,
+    min_changed_fraction: f64,
+}
+
+/// The zones of a camera drawn onto masks, redrawn when the frame size changes
+struct ZoneMasks {
+    size: Size,
+    /// 255 where motion is ignored, `None` without exclude zones
+    exclude: Option<Mat>,
+    include: Vec<IncludeMask>,
+}
+
+impl ZoneMasks {
+    fn new(zones: &[MotionZone], size: Size, sensitivity: i64) -> opencv::Result<Self> {
+        let blank_mask = || Mat::new_size_with_default(size, CV_8UC1, Scalar::all(0.0));
+
+        let exclude_polygons: Vector<Vector<Point>> = zones
+            .iter()
+            .filter(|zone| zone.kind == MotionZoneKind::Exclude)
+            .map(|zone| to_pixels(&zone.points, size))
+            .collect();
+
+        let exclude = if exclude_polygons.is_empty() {
+            None
+        } else {
+            let mut exclude = blank_mask()?;
+            fill_poly_def(&mut exclude, &exclude_polygons, Scalar::all(255.0))?;
+            Some(exclude)
+        };
+
+        let include = zones
+            .iter()
+            .filter(|zone| zone.kind == MotionZoneKind::Include)
+            .map(|zone| {
+                let polygon: Vector<Vector<Point>> =
+                    std::iter::once(to_pixels(&zone.points, size)).collect();
+
+                let mut mask = blank_mask()?;
+                fill_poly_def(&mut mask, &polygon, Scalar::all(255.0))?;
+
+                // Parts of the zone that are also excluded never change
+                if let Some(exclude) = &exclude {
+                    mask.set_to(&Scalar::all(0.0), exclude)?;
+                }
+
+                Ok(IncludeMask {
+                    zone_id: zone.zone_id,
+                    pixels: count_non_zero(&mask)?,
+                    mask,
+                    min_changed_fraction: changed_fraction_for_sensitivity(
+                        zone.sensitivity.unwrap_or(sensitivity),
+                    ),
+                })
+            })
+            .collect::<opencv::Result<Vec<_>>>()?;
+
+        Ok(Self {
+            size,
+            exclude,
+            include,
+        })
+    }
+}
+
+/// Frame differencing motion detector, one is used per camera.
+///
+/// Changes in exclude zones are ignored, and once there are include zones enough of one of them
+/// has to change, using its own sensitivity if it has one.
+pub struct MotionDetector {
+    previous_frame: Option<Mat>,
+    sensitivity: i64,
+    min_changed_fraction: f64,
+    zones: Vec<MotionZone>,
+    zone_masks: Option<ZoneMasks>,
+}
+
+impl MotionDetector {
+    #[must_use]
+    pub fn new(sensitivity: i64, zones: Vec<MotionZone>) -> Self {
+        Self {
+            previous_frame: None,
+            sensitivity,
+            min_changed_fraction: changed_fraction_for_sensitivity(sensitivity),
+            zones,
+            zone_masks: None,
+        }
+    }
+
+    /// Uses `zones` from the next frame on
+    pub fn set_zones(&mut self, zones: Vec<MotionZone>) {
+        self.zones = zones;
+        self.zone_masks = None;
+    }
+
+    /// Uses `sensitivity` from the next frame on, for zones without their own too
+    pub fn set_sensitivity(&mut self, sensitivity: i64) {
+        if sensitivity == self.sensitivity {
+            return;
+        }
+
+        self.sensitivity = sensitivity;
+        self.min_changed_fraction = changed_fraction_for_sensitivity(sensitivity);
+        self.zone_masks = None;
+    }
+
+    /// Compares a decoded BGR frame with the previous one, returns `Some` if enough of it changed.
+    pub fn detect(&mut self, frame: &Mat) -> opencv::Result<Option<MotionTrigger>> {
+        let mut gray_frame = Mat::default();
+        cvt_color_def(frame, &mut gray_frame, COLOR_BGR2GRAY)?;
+
+        let mut blurred_frame = Mat::default();
+        gaussian_blur_def(
+            &gray_frame,
+            &mut blurred_frame,
+            Size::new(BLUR_KERNEL_SIZE, BLUR_KERNEL_SIZE),
+            0.0,
+        )?;
+
+        let changed = match &self.previous_frame {
+            Some(previous_frame) if previous_frame.size()? == blurred_frame.size()? => {
+                let mut diff = Mat::default();
+                absdiff(previous_frame, &blurred_frame, &mut diff)?;
+
+                let mut changed = Mat::default();
+                threshold(
+                    &diff,
+                    &mut changed,
+                    PIXEL_DIFF_THRESHOLD,
+                    255.0,
+                    THRESH_BINARY,
+                )?;
+
+                Some(changed)
+            }
+            // First frame or the resolution changed, nothing to compare against yet
+            _ => None,
+        };
+
+        self.previous_frame = Some(blurred_frame);
+
+        changed.map_or(Ok(None), |changed| self.trigger(changed))
+    }
+
+    /// Whether enough of `changed` (255 where a pixel changed) is outside exclude zones/inside an include zone
+    fn trigger(&mut self, mut changed: Mat) -> opencv::Result<Option<MotionTrigger>> {
+        let size = changed.size()?;
+
+        if self
+            .zone_masks
+            .as_ref()
+            .map_or(true, |zone_masks| zone_masks.size != size)
+        {
+            self.zone_masks = Some(ZoneMasks::new(&self.zones, size, self.sensitivity)?);
+        }
+
+        let Some(zone_masks) = &self.zone_masks else {
+            return Ok(None);
+        };
+
+        if let Some(exclude) = &zone_masks.exclude {
+            changed.set_to(&Scalar::all(0.0), exclude)?;
+        }
+
+        if zone_masks.include.is_empty() {
+            let total_pixels = changed.total();
+
+            if total_pixels == 0 {
+                return Ok(None);
+            }
+
+            #[allow(clippy::cast_precision_loss)] // the precision loss is acceptable
+            let changed_fraction = f64::from(count_non_zero(&changed)?) / total_pixels as f64;
+
+            return Ok((changed_fraction >= self.min_changed_fraction)
+                .then_some(MotionTrigger { zone_id: None }));
+        }
+
+        for include in &zone_masks.include {
+            if include.pixels == 0 {
+                continue;
+            }
+
+            let mut changed_in_zone = Mat::default();
+            bitwise_and_def(&changed, &include.mask, &mut changed_in_zone)?;
+
+            let changed_fraction =
+                f64::from(count_non_zero(&changed_in_zone)?) / f64::from(include.pixels);
+
+            if changed_fraction >= include.min_changed_fraction {
+                return Ok(Some(MotionTrigger {
+                    zone_id: Some(include.zone_id),
+                }));
+            }
+        }
+
+        Ok(None)
+    }
+}
+
+/// Maps a 1-100 sensitivity onto the fraction of the frame that has to change,
+/// the higher the sensitivity the smaller the fraction.
+fn changed_fraction_for_sensitivity(sensitivity: i64) -> f64 {
+    #[allow(clippy::cast_precision_loss)] // always between 1 and 100
+    let inverse_sensitivity = (101 - sensitivity.clamp(1, 100)) as f64 / 100.0;
+
+    LEAST_SENSITIVE_CHANGED_FRACTION * inverse_sensitivity
+}
+
+#[cfg(test)]
+mod tests {
+    use opencv::{
+        core::{Rect, CV_8UC3},
+        imgproc::rectangle_def,
+    };
+    use sqlx::types::Json;
+
+    use super::*;
+    use crate::db::{Model, PolygonPoint};
+
+    fn blank_frame() -> opencv::Result<Mat> {
+        Mat::new_rows_cols_with_default(480, 640, CV_8UC3, Scalar::all(0.0))
+    }
+
+    fn frame_with_square(size: i32) -> opencv::Result<Mat> {
+        let mut frame = blank_frame()?;
+        rectangle_def(
+            &mut frame,
+            Rect::new(100, 100, size, size),
+            Scalar::all(255.0),
+        )?;
+
+        Ok(frame)
+    }
+
+    #[test]
+    fn identical_frames() -> opencv::Result<()> {
+        let mut detector = MotionDetector::new(100, Vec::new());
+
+        assert!(detector.detect(&blank_frame()?)?.is_none());
+        assert!(detector.detect(&blank_frame()?)?.is_none());
+
+        Ok(())
+    }
+
+    #[test]
+    fn large_change() -> opencv::Result<()> {
+        let mut detector = MotionDetector::new(50, Vec::new());
+
+        assert!(detector.detect(&blank_frame()?)?.is_none());
+        assert!(detector.detect(&frame_with_square(250)?)?.is_some());
+        assert!(detector.detect(&frame_with_square(250)?)?.is_none());
+
+        Ok(())
+    }
+
+    #[test]
+    fn sensitivity() -> opencv::Result<()> {
+        let mut sensitive_detector = MotionDetector::new(100, Vec::new());
+        let mut insensitive_detector = MotionDetector::new(1, Vec::new());
+
+        assert!(sensitive_detector.detect(&blank_frame()?)?.is_none());
+        assert!(insensitive_detector.detect(&blank_frame()?)?.is_none());
+
+        assert!(sensitive_detector
+            .detect(&frame_with_square(30)?)?
+            .is_some());
+        assert!(insensitive_detector
+            .detect(&frame_with_square(30)?)?
+            .is_none());
+
+        Ok(())
+    }
+
+    #[test]
+    fn resolution_change() -> opencv::Result<()> {
+        let mut detector = MotionDetector::new(100, Vec::new());
+
+        assert!(detector.detect(&blank_frame()?)?.is_none());
+
+        let smaller_frame = Mat::new_rows_cols_with_default(240, 320, CV_8UC3, Scalar::all(255.0))?;
+        assert!(detector.detect(&smaller_frame)?.is_none());
+
+        Ok(())
+    }
+
+    /// A rectangle from `(left, top)` to `(right, bottom)`, relative to the frame size
+    fn zone(
+        zone_id: i64,
+        kind: MotionZoneKind,
+        (left, top, right, bottom): (f64, f64, f64, f64),
+        sensitivity: Option<i64>,
+    ) -> MotionZone {
+        MotionZone {
+            zone_id,
+            camera_id: 1,
+            name: format!("Zone {zone_id}"),
+            kind,
+            points: Json(vec![
+                PolygonPoint { x: left, y: top },
+                PolygonPoint { x: right, y: top },
+                PolygonPoint {
+                    x: right,
+                    y: bottom,
+                },
+                PolygonPoint { x: left, y: bottom },
+            ]),
+            sensitivity,
+            created_at: MotionZone::DEFAULT.created_at(),
+        }
+    }
+
+    #[test]
+    fn exclude_zone() -> opencv::Result<()> {
+        // The square is drawn from (100, 100) to (350, 350) in a 640x480 frame
+        let tree = zone(1, MotionZoneKind::Exclude, (0.0, 0.0, 0.6, 0.8), None);
+        let mut detector = MotionDetector::new(50, vec![tree]);
+
+        assert!(detector.detect(&blank_frame()?)?.is_none());
+        assert!(detector.detect(&frame_with_square(250)?)?.is_none());
+
+        detector.set_zones(Vec::new());
+        assert!(detector.detect(&blank_frame()?)?.is_some());
+
+        Ok(())
+    }
+
+    #[test]
+    fn include_zones() -> opencv::Result<()> {
+        let right_half = zone(1, MotionZoneKind::Include, (0.5, 0.0, 1.0, 1.0), None);
+        let top_left = zone(2, MotionZoneKind::Include, (0.0, 0.0, 0.5, 0.5), Some(100));
+        let mut detector = MotionDetector::new(1, vec![right_half, top_left]);
+
+        assert!(detector.detect(&blank_frame()?)?.is_none());
+        // Only inside the top left zone, which is sensitive enough to notice
+        assert_eq!(
+            detector.detect(&frame_with_square(30)?)?,
+            Some(MotionTrigger { zone_id: Some(2) })
+        );
+
+        let mut right_half_only = MotionDetector::new(
+            100,
+            vec![zone(1, MotionZoneKind::Include, (0.5, 0.0, 1.0, 1.0), None)],
+        );
+        assert!(right_half_only.detect(&blank_frame()?)?.is_none());
+        assert!(right_half_only.detect(&frame_with_square(30)?)?.is_none());
+
+        Ok(())
+    }
+}
@@ -0,0 +1,114 @@
+use opencv::{
+    core::{Mat, Point, Scalar, Vector},
+    imgcodecs::{imdecode, imencode, IMREAD_COLOR},
+    imgproc::fill_poly_def,
+    prelude::*,
+};
+
+use super::to_pixels;
+use crate::db::PrivacyMask;
+
+/// Blacks out `masks` in a decoded frame.
+fn mask_frame(frame: &mut Mat, masks: &[PrivacyMask]) -> opencv::Result<()> {
+    if masks.is_empty() {
+        return Ok(());
+    }
+
+    let size = frame.size()?;
+    let polygons: Vector<Vector<Point>> = masks
+        .iter()
+        .map(|mask| to_pixels(&mask.points, size))
+        .collect();
+
+    fill_poly_def(frame, &polygons, Scalar::all(0.0))
+}
+
+/// Blacks out `masks` in an encoded JPEG, returning it as is if there are none.
+///
+/// Errors if the image can't be decoded, in which case it shouldn't be shown to anyone.
+pub fn apply_privacy_masks(image_bytes: Vec<u8>, masks: &[PrivacyMask]) -> opencv::Result<Vec<u8>> {
+    if masks.is_empty() {
+        return Ok(image_bytes);
+    }
+
+    let mut frame = imdecode(&image_bytes.as_slice(), IMREAD_COLOR)?;
+
+    if frame.empty() {
+        return Err(opencv::Error::new(
+            opencv::core::StsBadArg,
+            "Image couldn't be decoded",
+        ));
+    }
+
+    mask_frame(&mut frame, masks)?;
+
+    let mut masked_bytes = Vector::<u8>::new();
+    imencode(".jpg", &frame, &mut masked_bytes, &Vector::new())?;
+
+    Ok(masked_bytes.to_vec())
+}
+
+#[cfg(test)]
+mod tests {
+    use opencv::core::{Size, Vec3b, CV_8UC3};
+    use sqlx::types::Json;
+
+    use super::*;
+    use crate::db::{Model, PolygonPoint};
+
+    fn mask(points: &[(f64, f64)]) -> PrivacyMask {
+        PrivacyMask {
+            mask_id: PrivacyMask::DEFAULT.mask_id,
+            camera_id: 1,
+            name: "Window".to_string(),
+            points: Json(points.iter().map(|&(x, y)| PolygonPoint { x, y }).collect()),
+            created_at: PrivacyMask::DEFAULT.created_at(),
+        }
+    }
+
+    fn white_frame() -> opencv::Result<Mat> {
+        Mat::new_rows_cols_with_default(480, 640, CV_8UC3, Scalar::all(255.0))
+    }
+
+    #[test]
+    fn masked_region_is_black() -> opencv::Result<()> {
+        let mut frame = white_frame()?;
+        // The top left quarter
+        let masks = [mask(&[(0.0, 0.0), (0.5, 0.0), (0.5, 0.5), (0.0, 0.5)])];
+
+        mask_frame(&mut frame, &masks)?;
+
+        assert_eq!(*frame.at_2d::<Vec3b>(10, 10)?, Vec3b::from([0, 0, 0]));
+        assert_eq!(*frame.at_2d::<Vec3b>(200, 300)?, Vec3b::from([0, 0, 0]));
+        assert_eq!(
+            *frame.at_2d::<Vec3b>(300, 400)?,
+            Vec3b::from([255, 255, 255])
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn encoded_images() -> opencv::Result<()> {
+        let mut frame_bytes = Vector::<u8>::new();
+        imencode(".jpg", &white_frame()?, &mut frame_bytes, &Vector::new())?;
+        let frame_bytes = frame_bytes.to_vec();
+
+        assert_eq!(apply_privacy_masks(frame_bytes.clone(), &[])?, frame_bytes);
+
+        // The whole frame
+        let masks = [mask(&[(0.0, 0.0), (1.0, 0.0), (1.0, 1.0), (0.0, 1.0)])];
+        let masked_bytes = apply_privacy_masks(frame_bytes, &masks)?;
+        let masked_frame = imdecode(&masked_bytes.as_slice(), IMREAD_COLOR)?;
+
+        assert_eq!(masked_frame.size()?, Size::new(640, 480));
+        assert!(masked_frame
+            .at_2d::<Vec3b>(240, 320)?
+            .iter()
+            .all(|c| *c < 10));
+
+        assert!(apply_privacy_masks(vec![1], &masks).is_err());
+
+        Ok(())
+    }
+}
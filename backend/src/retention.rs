@@ -0,0 +1,423 @@
+use std::collections::HashMap;
+
+use sqlx::SqlitePool;
+use time::{Duration, OffsetDateTime};
+use tracing::{debug, error, info, warn};
+
+use crate::db::{CameraSetting, Event, Model, StoragePolicy, Video, VideoThumbnail};
+
+/// Periodically deletes videos according to the per-camera max age and the global disk quota.
+///
+/// Like the session `deletion_task`, this runs forever and is meant to be aborted on shutdown.
+pub async fn continuously_enforce(db: SqlitePool, period: tokio::time::Duration) {
+    let mut interval = tokio::time::interval(period);
+
+    loop {
+        interval.tick().await;
+
+        match enforce(&db, OffsetDateTime::now_utc()).await {
+            Ok(0) => debug!("Retention policy enforced, nothing to delete"),
+            Ok(deleted) => info!("Retention policy enforced, deleted {deleted} video(s)"),
+            Err(e) => error!("Error enforcing retention policy: {e:?}"),
+        }
+    }
+}
+
+/// Deletes expired videos, then the oldest videos until the disk quota is met.
+///
+/// The quota covers everything `delete_video` deletes, i.e. posters, thumbnails and event snapshots too.
+///
+/// Videos flagged as `keep_forever` and videos that are still being recorded are never deleted.
+/// Returns the amount of deleted videos.
+pub async fn enforce(
+    db: &SqlitePool,
+    now: OffsetDateTime,
+) -> Result<usize, Box<dyn std::error::Error + Send + Sync>> {
+    let videos = Video::list(db).await?;
+    let policy = StoragePolicy::get(db).await?;
+    let max_ages: HashMap<i64, Duration> = CameraSetting::list(db)
+        .await?
+        .into_iter()
+        .filter_map(|setting| {
+            setting
+                .max_video_age_days
+                .map(|days| (setting.camera_id, Duration::days(days)))
+        })
+        .collect();
+
+    let mut deleted = 0;
+    let mut remaining_videos = Vec::with_capacity(videos.len());
+
+    for video in videos {
+        let max_age = video.camera_id.and_then(|id| max_ages.get(&id));
+
+        let expired = match (video.end_time, max_age) {
+            (Some(end_time), Some(max_age)) => now - end_time > *max_age,
+            _ => false,
+        };
+
+        if is_prunable(&video) && expired {
+            debug!("Video {} is older than its max age", video.video_id);
+            delete_video(db, &video).await?;
+            deleted += 1;
+        } else {
+            remaining_videos.push(video);
+        }
+    }
+
+    let Some(max_total_size_bytes) = policy.max_total_size_bytes else {
+        return Ok(deleted);
+    };
+
+    let max_total_size_bytes = u64::try_from(max_total_size_bytes)?;
+
+    let mut sized_videos = Vec::with_capacity(remaining_videos.len());
+    for video in remaining_videos {
+        let size = size_on_disk(db, &video).await?;
+        sized_videos.push((video, size));
+    }
+
+    let mut total_size: u64 = sized_videos.iter().map(|(_, size)| size).sum();
+
+    // Oldest first, see `Video::list`
+    for (video, size) in &sized_videos {
+        if total_size <= max_total_size_bytes {
+            break;
+        }
+
+        if !is_prunable(video) {
+            continue;
+        }
+
+        debug!("Deleting video {} to meet the disk quota", video.video_id);
+        delete_video(db, video).await?;
+        total_size = total_size.saturating_sub(*size);
+        deleted += 1;
+    }
+
+    if total_size > max_total_size_bytes {
+        warn!("Disk quota exceeded but no more videos can be deleted");
+    }
+
+    Ok(deleted)
+}
+
+/// Deletes the video file, its poster and thumbnails, the events recorded in it and their snapshots,
+/// and its row. Missing files are not an error.
+pub async fn delete_video(
+    db: &SqlitePool,
+    video: &Video,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    for file_path in file_paths(db, video).await? {
+        match tokio::fs::remove_file(&file_path).await {
+            Ok(()) => {}
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                warn!(
+                    "File {file_path} of video {} was already deleted",
+                    video.video_id
+                );
+            }
+            Err(e) => return Err(e.into()),
+        }
+    }
+
+    // Without the video and snapshot there is nothing left to review
+    for event in Event::list_for_video(db, video.video_id).await? {
+        Event::delete_using_id(db, event.event_id).await?;
+    }
+
+    // Deletes the thumbnail rows too
+    Video::delete_using_id(db, video.video_id).await?;
+
+    Ok(())
+}
+
+/// Closes videos that were still being recorded when the server last stopped,
+/// using the modification time and size of their file, so they can be pruned like any other video.
+///
+/// Must run before any camera can connect. Returns the amount of closed videos.
+pub async fn close_unfinished(
+    db: &SqlitePool,
+) -> Result<usize, Box<dyn std::error::Error + Send + Sync>> {
+    let mut closed = 0;
+
+    for mut video in Video::list(db).await? {
+        if video.end_time.is_some() {
+            continue;
+        }
+
+        let (end_time, file_size) = match tokio::fs::metadata(&video.file_path).await {
+            Ok(metadata) => (
+                metadata
+                    .modified()
+                    .map_or(video.start_time, OffsetDateTime::from),
+                i64::try_from(metadata.len())?,
+            ),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                warn!("File of unfinished video {} is missing", video.video_id);
+                (video.start_time, 0)
+            }
+            Err(e) => return Err(e.into()),
+        };
+
+        info!("Closing unfinished video {}", video.video_id);
+        video.end_time = Some(end_time.max(video.start_time));
+        video.file_size = Some(file_size);
+        video.update_using_self(db).await?;
+        closed += 1;
+    }
+
+    Ok(closed)
+}
+
+const fn is_prunable(video: &Video) -> bool {
+    !video.keep_forever && video.end_time.is_some()
+}
+
+/// The video file, its poster and thumbnails, and the snapshots of the events recorded in it
+async fn file_paths(db: &SqlitePool, video: &Video) -> sqlx::Result<Vec<String>> {
+    let thumbnails = VideoThumbnail::list_for_video(db, video.video_id).await?;
+    let events = Event::list_for_video(db, video.video_id).await?;
+
+    Ok(std::iter::once(video.file_path.clone())
+        .chain(video.poster_path.clone())
+        .chain(thumbnails.into_iter().map(|thumbnail| thumbnail.file_path))
+        .chain(events.into_iter().filter_map(|event| event.snapshot_path))
+        .collect())
+}
+
+/// Size of all the files deleted along with the video, see `file_paths`
+async fn size_on_disk(db: &SqlitePool, video: &Video) -> sqlx::Result<u64> {
+    let mut size = 0;
+
+    for file_path in file_paths(db, video).await? {
+        size += tokio::fs::metadata(&file_path)
+            .await
+            .map_or(0, |metadata| metadata.len());
+    }
+
+    Ok(size)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use super::*;
+    use crate::db::{EventKind, VideoFormat};
+
+    async fn create_video(
+        pool: &SqlitePool,
+        dir: &Path,
+        camera_id: i64,
+        start_time: OffsetDateTime,
+        size: usize,
+        keep_forever: bool,
+    ) -> Result<Video, Box<dyn std::error::Error + Send + Sync>> {
+        let file_path = dir.join(format!("{}.avi", start_time.unix_timestamp()));
+        tokio::fs::write(&file_path, vec![0; size]).await?;
+
+        let mut video = Video {
+            video_id: Video::DEFAULT.video_id,
+            camera_id: Some(camera_id),
+            file_path: file_path.to_string_lossy().to_string(),
+            start_time,
+            end_time: Some(start_time + Duration::minutes(5)),
+            file_size: Some(size.try_into()?),
+            keep_forever,
+            format: VideoFormat::Mjpeg,
+            average_fps: Video::DEFAULT.average_fps,
+            poster_path: Video::DEFAULT.poster_path,
+        };
+
+        video.create_using_self(pool).await?;
+
+        Ok(video)
+    }
+
+    #[sqlx::test(fixtures(path = "../fixtures", scripts("users", "cameras", "camera_settings")))]
+    async fn max_age(pool: SqlitePool) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        let dir = tempfile::tempdir()?;
+        let now = OffsetDateTime::now_utc();
+
+        let mut camera_setting = CameraSetting::get_for_camera(&pool, 1).await?;
+        camera_setting.max_video_age_days = Some(7);
+        camera_setting.update_using_self(&pool).await?;
+
+        let old_video =
+            create_video(&pool, dir.path(), 1, now - Duration::days(8), 10, false).await?;
+        let kept_video =
+            create_video(&pool, dir.path(), 1, now - Duration::days(9), 10, true).await?;
+        let new_video =
+            create_video(&pool, dir.path(), 1, now - Duration::days(1), 10, false).await?;
+        // Camera 2 has no max age
+        let other_video =
+            create_video(&pool, dir.path(), 2, now - Duration::days(10), 10, false).await?;
+
+        assert_eq!(enforce(&pool, now).await?, 1);
+
+        assert!(Video::get_using_id(&pool, old_video.video_id)
+            .await
+            .is_err());
+        assert!(!Path::new(&old_video.file_path).exists());
+
+        for video in [kept_video, new_video, other_video] {
+            assert!(Video::get_using_id(&pool, video.video_id).await.is_ok());
+            assert!(Path::new(&video.file_path).exists());
+        }
+
+        Ok(())
+    }
+
+    #[sqlx::test(fixtures(path = "../fixtures", scripts("users", "cameras", "camera_settings")))]
+    async fn quota(pool: SqlitePool) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        let dir = tempfile::tempdir()?;
+        let now = OffsetDateTime::now_utc();
+
+        let mut policy = StoragePolicy::get(&pool).await?;
+        policy.max_total_size_bytes = Some(250);
+        policy.update_using_self(&pool).await?;
+
+        let kept_video =
+            create_video(&pool, dir.path(), 1, now - Duration::days(4), 100, true).await?;
+        let oldest_video =
+            create_video(&pool, dir.path(), 1, now - Duration::days(3), 100, false).await?;
+        let older_video =
+            create_video(&pool, dir.path(), 2, now - Duration::days(2), 100, false).await?;
+        let newest_video =
+            create_video(&pool, dir.path(), 2, now - Duration::days(1), 100, false).await?;
+
+        assert_eq!(enforce(&pool, now).await?, 2);
+
+        for video in [oldest_video, older_video] {
+            assert!(Video::get_using_id(&pool, video.video_id).await.is_err());
+            assert!(!Path::new(&video.file_path).exists());
+        }
+
+        for video in [kept_video, newest_video] {
+            assert!(Video::get_using_id(&pool, video.video_id).await.is_ok());
+            assert!(Path::new(&video.file_path).exists());
+        }
+
+        assert_eq!(enforce(&pool, now).await?, 0);
+
+        Ok(())
+    }
+
+    #[sqlx::test(fixtures(path = "../fixtures", scripts("users", "cameras", "camera_settings")))]
+    async fn quota_includes_images(
+        pool: SqlitePool,
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        let dir = tempfile::tempdir()?;
+        let now = OffsetDateTime::now_utc();
+
+        let mut policy = StoragePolicy::get(&pool).await?;
+        policy.max_total_size_bytes = Some(250);
+        policy.update_using_self(&pool).await?;
+
+        let older_video =
+            create_video(&pool, dir.path(), 1, now - Duration::days(2), 100, false).await?;
+        let mut newer_video =
+            create_video(&pool, dir.path(), 1, now - Duration::days(1), 100, false).await?;
+
+        let poster_path = dir.path().join("poster.jpg");
+        tokio::fs::write(&poster_path, [0; 100]).await?;
+        newer_video.poster_path = Some(poster_path.to_string_lossy().to_string());
+        newer_video.update_using_self(&pool).await?;
+
+        // 200 bytes of videos, 300 bytes with the poster
+        assert_eq!(enforce(&pool, now).await?, 1);
+
+        assert!(Video::get_using_id(&pool, older_video.video_id)
+            .await
+            .is_err());
+        assert!(Video::get_using_id(&pool, newer_video.video_id)
+            .await
+            .is_ok());
+        assert!(poster_path.exists());
+
+        Ok(())
+    }
+
+    #[sqlx::test(fixtures(path = "../fixtures", scripts("users", "cameras", "camera_settings")))]
+    async fn unfinished(pool: SqlitePool) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        let dir = tempfile::tempdir()?;
+        let now = OffsetDateTime::now_utc();
+
+        let mut camera_setting = CameraSetting::get_for_camera(&pool, 1).await?;
+        camera_setting.max_video_age_days = Some(7);
+        camera_setting.update_using_self(&pool).await?;
+
+        let mut video =
+            create_video(&pool, dir.path(), 1, now - Duration::days(8), 10, false).await?;
+        video.end_time = None;
+        video.file_size = None;
+        video.update_using_self(&pool).await?;
+
+        // Still being recorded as far as the database knows
+        assert_eq!(enforce(&pool, now).await?, 0);
+
+        assert_eq!(close_unfinished(&pool).await?, 1);
+        let video = Video::get_using_id(&pool, video.video_id).await?;
+        assert!(video.end_time.is_some());
+        assert_eq!(video.file_size, Some(10));
+        assert_eq!(close_unfinished(&pool).await?, 0);
+
+        // The file was just written, so it only expires later
+        assert_eq!(enforce(&pool, now + Duration::days(8)).await?, 1);
+        assert!(!Path::new(&video.file_path).exists());
+
+        Ok(())
+    }
+
+    #[sqlx::test(fixtures(path = "../fixtures", scripts("users", "cameras", "camera_settings")))]
+    async fn delete_images(
+        pool: SqlitePool,
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        let dir = tempfile::tempdir()?;
+        let mut video =
+            create_video(&pool, dir.path(), 1, OffsetDateTime::now_utc(), 10, false).await?;
+
+        let poster_path = dir.path().join("poster.jpg");
+        tokio::fs::write(&poster_path, [0]).await?;
+        video.poster_path = Some(poster_path.to_string_lossy().to_string());
+        video.update_using_self(&pool).await?;
+
+        let thumbnail_path = dir.path().join("thumbnail_0.jpg");
+        tokio::fs::write(&thumbnail_path, [0]).await?;
+        let mut thumbnail = VideoThumbnail {
+            thumbnail_id: VideoThumbnail::DEFAULT.thumbnail_id,
+            video_id: video.video_id,
+            file_path: thumbnail_path.to_string_lossy().to_string(),
+            offset_seconds: 0.0,
+        };
+        thumbnail.create_using_self(&pool).await?;
+
+        let snapshot_path = dir.path().join("motion.jpg");
+        tokio::fs::write(&snapshot_path, [0]).await?;
+        let mut event = Event::new(Some(1), EventKind::Motion, video.start_time);
+        event.video_id = Some(video.video_id);
+        event.snapshot_path = Some(snapshot_path.to_string_lossy().to_string());
+        event.create_using_self(&pool).await?;
+
+        // Not recorded in the video
+        let mut other_event = Event::new(Some(1), EventKind::CameraOffline, video.start_time);
+        other_event.create_using_self(&pool).await?;
+
+        delete_video(&pool, &video).await?;
+
+        assert!(!Path::new(&video.file_path).exists());
+        assert!(!poster_path.exists());
+        assert!(!thumbnail_path.exists());
+        assert!(!snapshot_path.exists());
+        assert!(VideoThumbnail::get_using_id(&pool, thumbnail.thumbnail_id)
+            .await
+            .is_err());
+        assert!(Event::get_using_id(&pool, event.event_id).await.is_err());
+        assert!(Event::get_using_id(&pool, other_event.event_id)
+            .await
+            .is_ok());
+
+        Ok(())
+    }
+}
@@ -1,11 +1,15 @@
+use std::marker::PhantomData;
+
 use async_trait::async_trait;
+use axum::extract::FromRequestParts;
 use axum_login::{AuthnBackend, UserId};
+use http::{request::Parts, StatusCode};
 use password_auth::verify_password;
 use serde::Deserialize;
 use sqlx::SqlitePool;
 use tokio::task;
 
-use crate::db::{Model, User};
+use crate::db::{Model, Role, User};
 
 // This allows us to extract the authentication fields from forms. We use this
 // to authenticate requests with the backend.
@@ -45,7 +49,12 @@ impl AuthnBackend for Backend {
         &self,
         creds: Self::Credentials,
     ) -> Result<Option<Self::User>, Self::Error> {
-        let user: Self::User = User::get_using_username(&self.db, &creds.username).await?;
+        let user: Self::User = match User::get_using_username(&self.db, &creds.username).await {
+            Ok(user) => user,
+            // Unknown usernames are failed logins too, not server errors
+            Err(sqlx::Error::RowNotFound) => return Ok(None),
+            Err(e) => return Err(e.into()),
+        };
 
         // Verifying the password is blocking and potentially slow, so we'll do so via
         // `spawn_blocking`.
@@ -69,3 +78,58 @@ impl AuthnBackend for Backend {
 //
 // Note that we've supplied our concrete backend here.
 pub type AuthSession = axum_login::AuthSession<Backend>;
+
+/// Marker for the least privileged [`Role`] allowed through a [`RequireRole`] extractor.
+pub trait MinimumRole {
+    const ROLE: Role;
+}
+
+pub struct Admin;
+
+impl MinimumRole for Admin {
+    const ROLE: Role = Role::Admin;
+}
+
+pub struct Operator;
+
+impl MinimumRole for Operator {
+    const ROLE: Role = Role::Operator;
+}
+
+/// Extracts the logged in user, rejecting the request with `401` if nobody is logged in
+/// and `403` if the user's role is below `R::ROLE`.
+pub struct RequireRole<R: MinimumRole> {
+    pub user: User,
+    role: PhantomData<R>,
+}
+
+#[async_trait]
+impl<S, R> FromRequestParts<S> for RequireRole<R>
+where
+    S: Send + Sync,
+    R: MinimumRole,
+{
+    type Rejection = StatusCode;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let auth_session = AuthSession::from_request_parts(parts, state)
+            .await
+            .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+        let Some(user) = auth_session.user else {
+            return Err(StatusCode::UNAUTHORIZED);
+        };
+
+        if user.role < R::ROLE {
+            return Err(StatusCode::FORBIDDEN);
+        }
+
+        Ok(Self {
+            user,
+            role: PhantomData,
+        })
+    }
+}
+
+pub type AdminUser = RequireRole<Admin>;
+pub type OperatorUser = RequireRole<Operator>;
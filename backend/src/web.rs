@@ -1,23 +1,64 @@
 pub use app::App;
+pub use app::AppBuilder;
 pub use app::AppState;
+pub use camera_hub::CameraHub;
 use serde::Deserialize;
 use serde::Serialize;
+use time::OffsetDateTime;
 
-use crate::CameraSettingNoMeta;
+use crate::db::{ArmingState, Event, Video};
+
+pub use oko_protocol::{
+    CameraMessage, ClientRole, FrameHeader, Hello, HelloReply, MIN_PROTOCOL_VERSION,
+    PROTOCOL_VERSION,
+};
 
 #[derive(Serialize, Deserialize, Clone)]
 pub struct ImageContainer {
     pub camera_id: i64,
+    /// When the image was received, in milliseconds since the Unix epoch
     pub timestamp: i64,
+    /// Position of the image among those received from the camera during its connection
+    pub sequence: u64,
     #[serde(with = "serde_bytes")]
     pub image_bytes: Vec<u8>,
 }
 
-// TODO: Use single shared definition for both camera and backend
-#[derive(Serialize, Deserialize, Debug, Clone)]
-pub enum CameraMessage {
-    SettingChanged(CameraSettingNoMeta),
-    Restart,
+impl ImageContainer {
+    #[must_use]
+    pub fn new(
+        camera_id: i64,
+        received_at: OffsetDateTime,
+        sequence: u64,
+        image_bytes: Vec<u8>,
+    ) -> Self {
+        let timestamp =
+            i64::try_from(received_at.unix_timestamp_nanos() / 1_000_000).unwrap_or(i64::MAX);
+
+        Self {
+            camera_id,
+            timestamp,
+            sequence,
+            image_bytes,
+        }
+    }
+
+    /// Binary message sent to viewers that negotiated `binary_frames`
+    #[must_use]
+    pub fn to_frame(&self) -> Vec<u8> {
+        FrameHeader {
+            camera_id: self.camera_id,
+            timestamp: self.timestamp,
+            sequence: self.sequence,
+        }
+        .encode_frame(&self.image_bytes)
+    }
+
+    /// `timestamp` as a date, `None` if it is out of range
+    #[must_use]
+    pub fn received_at(&self) -> Option<OffsetDateTime> {
+        OffsetDateTime::from_unix_timestamp_nanos(i128::from(self.timestamp) * 1_000_000).ok()
+    }
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
@@ -34,7 +75,20 @@ pub enum ApiChannelMessage {
         message: CameraMessage,
     },
     CameraListChanged(CameraListChange),
-    Initial,
+    /// Sent as is to viewers that can view the camera of the event
+    EventCreated(Event),
+    /// e.g. a motion event ended or was acknowledged
+    EventUpdated(Event),
+    /// A new video file was opened for a camera
+    VideoStarted(Video),
+    /// A video file was closed and its size/end time saved
+    VideoFinished(Video),
+    /// The system-wide arming mode was changed, sent as is to every viewer
+    ArmingChanged(ArmingState),
+    /// A motion zone of a camera was added/changed/removed, its recorder reloads them
+    MotionZonesChanged {
+        camera_id: i64,
+    },
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
@@ -45,4 +99,8 @@ pub enum MdnsChannelMessage {
 
 mod app;
 mod auth;
+mod camera_hub;
+mod mjpeg;
 mod protected;
+mod snapshot;
+mod video_file;
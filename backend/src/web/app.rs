@@ -15,20 +15,16 @@ use axum_login::{
 };
 use axum_server::tls_rustls::RustlsConfig;
 use futures_util::{SinkExt, StreamExt};
-use opencv::{
-    core::Size,
-    imgcodecs::{imdecode, IMREAD_COLOR},
-    videoio::{VideoWriter, VideoWriterTrait},
-};
 use rust_embed::RustEmbed;
 use sqlx::{sqlite::SqliteConnectOptions, SqlitePool};
 use time::{Duration, OffsetDateTime};
 use tokio::{
     net::TcpListener,
     signal,
-    sync::{watch, Mutex},
+    sync::{broadcast, watch, Mutex},
     task::{AbortHandle, JoinHandle},
 };
+use tokio_stream::{wrappers::BroadcastStream, StreamMap};
 use tokio_util::{sync::CancellationToken, task::TaskTracker};
 use tower_sessions::cookie::Key;
 use tower_sessions_sqlx_store::SqliteStore;
@@ -43,90 +39,176 @@ use axum::{
     response::IntoResponse,
 };
 use axum::{
-    extract::{ws::CloseFrame, State},
+    extract::{
+        ws::{close_code, CloseFrame},
+        State,
+    },
     Router,
 };
 use tracing::{debug, error, info, warn};
 
 use crate::{
+    config::Config,
+    mqtt::MqttConfig,
     users::{AuthSession, Backend},
-    web::{auth, protected, CameraListChange, CameraMessage},
-    ApiChannelMessage, Camera, CameraPermissionView, CameraSetting, CameraSettingNoMeta, Model,
-    User, Video,
+    web::{
+        auth::{self, FailedLogins},
+        protected, CameraListChange, CameraMessage, ClientRole, Hello, HelloReply,
+        MIN_PROTOCOL_VERSION, PROTOCOL_VERSION,
+    },
+    ApiChannelMessage, Camera, CameraPermissionView, CameraSetting, CameraSettingNoMeta, Event,
+    EventKind, Model, PrivacyMask, Role, User,
 };
 
-use super::{ImageContainer, MdnsChannelMessage};
+use super::{CameraHub, ImageContainer, MdnsChannelMessage};
 
 // TODO: Maybe use `std::future::pending::<()>();` instead of sleeping forever
 
 // TODO: Change default admin and guest hashes, remember to search and update where they're hardcoded
-const SQLITE_PROD_URL: &str = "sqlite://oko.db";
-const SQLITE_DEV_URL: &str = "sqlite://data.db";
-const VIDEO_PATH: &str = "./videos/";
 const DEFAULT_ADMIN_USERNAME: &str = "admin";
 const DEFAULT_ADMIN_PASS_HASH: &str = "$argon2id$v=19$m=19456,t=2,p=1$VE0e3g7DalWHgDwou3nuRA$uC6TER156UQpk0lNQ5+jHM0l5poVjPA1he/Tyn9J4Zw";
 const EXPIRED_SESSION_DELETION_INTERVAL: tokio::time::Duration =
     tokio::time::Duration::from_secs(60);
-const SESSION_DURATION: Duration = Duration::days(1);
-const CAMERA_INDICATOR_TEXT: &str = "camera";
-const CAMERA_ANY_PORT_INDICATOR_TEXT: &str = "camera_any_port";
+const RETENTION_INTERVAL: tokio::time::Duration = tokio::time::Duration::from_secs(10 * 60);
 const EMPTY_TASK_SLEEP_DURATION: tokio::time::Duration = tokio::time::Duration::from_millis(100);
+/// How many API messages a listener can fall behind by before it starts skipping them
+const API_CHANNEL_CAPACITY: usize = 64;
 
 #[derive(RustEmbed, Clone)]
 #[folder = "static/"]
 struct EmbeddedAssets;
 
 pub struct AppState {
-    pub images_tx: watch::Sender<ImageContainer>,
+    pub camera_hub: CameraHub,
     pub video_path: PathBuf,
-    pub api_channel: watch::Sender<ApiChannelMessage>,
+    pub api_channel: broadcast::Sender<ApiChannelMessage>,
     pub mdns_channel: watch::Sender<MdnsChannelMessage>,
     pub shutdown_token: CancellationToken,
     pub oko_private_socket_addr: Option<SocketAddr>,
+    pub failed_logins: FailedLogins,
     pub db_pool: SqlitePool,
 }
 
 pub struct App {
-    pub db: SqlitePool,
-    pub http_listener: TcpListener,
-    pub https_addr: Option<SocketAddr>,
-    pub video_path: PathBuf,
-    pub oko_private_socket_addr: Option<SocketAddr>,
+    db: SqlitePool,
+    http_listener: TcpListener,
+    https_addr: Option<SocketAddr>,
+    video_path: PathBuf,
+    oko_private_socket_addr: Option<SocketAddr>,
+    tls_cert_path: PathBuf,
+    tls_key_path: PathBuf,
+    session_duration: Duration,
+    mdns_service_name: String,
+    mqtt: Option<MqttConfig>,
 }
 
-impl App {
+/// Builds an `App` from a `Config`, values set directly on the builder take priority over it.
+#[must_use]
+#[derive(Default)]
+// `Some(None)` means the address was explicitly disabled instead of taken from the config
+#[allow(clippy::option_option)]
+pub struct AppBuilder {
+    config: Config,
+    db: Option<SqlitePool>,
+    http_listener: Option<TcpListener>,
+    https_addr: Option<Option<SocketAddr>>,
+    video_path: Option<PathBuf>,
+    oko_private_socket_addr: Option<Option<SocketAddr>>,
+}
+
+impl AppBuilder {
+    pub fn config(mut self, config: Config) -> Self {
+        self.config = config;
+        self
+    }
+
+    /// Use an existing (already migrated) database instead of connecting to `database_url`
+    pub fn db(mut self, db: SqlitePool) -> Self {
+        self.db = Some(db);
+        self
+    }
+
+    /// Use an existing listener instead of binding to `http_port`
+    pub fn http_listener(mut self, http_listener: TcpListener) -> Self {
+        self.http_listener = Some(http_listener);
+        self
+    }
+
+    /// `None` disables the HTTPS server
+    pub const fn https_addr(mut self, https_addr: Option<SocketAddr>) -> Self {
+        self.https_addr = Some(https_addr);
+        self
+    }
+
+    pub fn video_path(mut self, video_path: PathBuf) -> Self {
+        self.video_path = Some(video_path);
+        self
+    }
+
+    /// Address passed to cameras so they can connect back, `None` disables adding cameras
+    pub const fn oko_private_socket_addr(
+        mut self,
+        oko_private_socket_addr: Option<SocketAddr>,
+    ) -> Self {
+        self.oko_private_socket_addr = Some(oko_private_socket_addr);
+        self
+    }
+
     #[allow(clippy::cognitive_complexity)]
     #[allow(clippy::similar_names)]
-    pub async fn new() -> Result<Self, Box<dyn std::error::Error + Send + Sync>> {
-        let sqlite_connect_options = if cfg!(debug_assertions) {
-            SqliteConnectOptions::from_str(SQLITE_DEV_URL)?.create_if_missing(true)
+    pub async fn build(self) -> Result<App, Box<dyn std::error::Error + Send + Sync>> {
+        let config = self.config;
+
+        let db = if let Some(db) = self.db {
+            db
         } else {
-            let Ok(password) = std::env::var("OKO_DB_PASSWORD") else {
-                error!("No password provided for database. Please provide a password using the OKO_DB_PASSWORD environment variable.");
-                return Err("No password provided for database. Please provide a password using the OKO_DB_PASSWORD environment variable.".into());
+            let sqlite_connect_options = if cfg!(debug_assertions) {
+                SqliteConnectOptions::from_str(&config.database_url)?.create_if_missing(true)
+            } else {
+                let Ok(password) = std::env::var("OKO_DB_PASSWORD") else {
+                    error!("No password provided for database. Please provide a password using the OKO_DB_PASSWORD environment variable.");
+                    return Err("No password provided for database. Please provide a password using the OKO_DB_PASSWORD environment variable.".into());
+                };
+
+                SqliteConnectOptions::from_str(&config.database_url)?
+                    .create_if_missing(true)
+                    .pragma("key", password)
+                    .pragma("cipher_page_size", "1024")
+                    .pragma("kdf_iter", "64000")
+                    .pragma("cipher_hmac_algorithm", "HMAC_SHA1")
+                    .pragma("cipher_kdf_algorithm", "PBKDF2_HMAC_SHA1")
             };
 
-            SqliteConnectOptions::from_str(SQLITE_PROD_URL)?
-                .create_if_missing(true)
-                .pragma("key", password)
-                .pragma("cipher_page_size", "1024")
-                .pragma("kdf_iter", "64000")
-                .pragma("cipher_hmac_algorithm", "HMAC_SHA1")
-                .pragma("cipher_kdf_algorithm", "PBKDF2_HMAC_SHA1")
-        };
+            let db = SqlitePool::connect_with(sqlite_connect_options).await?;
 
-        let db = SqlitePool::connect_with(sqlite_connect_options).await?;
+            sqlx::migrate!().run(&db).await?;
 
-        sqlx::migrate!().run(&db).await?;
+            db
+        };
 
-        let http_addr = SocketAddr::from((Ipv4Addr::UNSPECIFIED, 3080));
-        let http_listener = tokio::net::TcpListener::bind(http_addr).await?;
+        let http_listener = if let Some(http_listener) = self.http_listener {
+            http_listener
+        } else {
+            let http_addr = SocketAddr::from((Ipv4Addr::UNSPECIFIED, config.http_port));
+            tokio::net::TcpListener::bind(http_addr).await?
+        };
 
-        let https_addr = SocketAddr::from((Ipv4Addr::UNSPECIFIED, 3443));
+        let https_addr = self.https_addr.unwrap_or_else(|| {
+            config
+                .https_port()
+                .map(|https_port| SocketAddr::from((Ipv4Addr::UNSPECIFIED, https_port)))
+        });
 
-        info!("Listening on: {} and {}", http_addr, https_addr);
+        info!(
+            "Listening on: {} and {:?}",
+            http_listener.local_addr()?,
+            https_addr
+        );
+
+        let session_duration = config.session_duration();
+        let mqtt = config.mqtt();
 
-        let video_path_relative = PathBuf::from(VIDEO_PATH);
+        let video_path_relative = self.video_path.unwrap_or(config.video_path);
 
         if !video_path_relative.exists() {
             std::fs::create_dir_all(&video_path_relative)?;
@@ -136,39 +218,66 @@ impl App {
 
         debug!("Video path: {:?}", video_path);
 
-        // Private IPv4 e.g. 192.168.x.x with port for passing to camera
-        let oko_private_socket_addr = SocketAddr::from((
-            local_ip_address::local_ip()?,
-            http_listener.local_addr()?.port(),
-        ));
+        let oko_private_socket_addr = match self.oko_private_socket_addr {
+            Some(oko_private_socket_addr) => oko_private_socket_addr,
+            // Private IPv4 e.g. 192.168.x.x with port for passing to camera
+            None => Some(SocketAddr::from((
+                local_ip_address::local_ip()?,
+                http_listener.local_addr()?.port(),
+            ))),
+        };
 
-        Ok(Self {
+        Ok(App {
             db,
             http_listener,
-            https_addr: Some(https_addr),
+            https_addr,
             video_path,
-            oko_private_socket_addr: Some(oko_private_socket_addr),
+            oko_private_socket_addr,
+            tls_cert_path: config.tls_cert_path,
+            tls_key_path: config.tls_key_path,
+            session_duration,
+            mdns_service_name: config.mdns_service_name,
+            mqtt,
         })
     }
+}
+
+impl App {
+    pub fn builder() -> AppBuilder {
+        AppBuilder::default()
+    }
+
+    /// Builds an `App` using the default `Config`
+    pub async fn new() -> Result<Self, Box<dyn std::error::Error + Send + Sync>> {
+        Self::builder().build().await
+    }
 
     #[allow(clippy::similar_names)]
     #[allow(clippy::too_many_lines)] // TODO: Refactor
     pub async fn serve(self) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
         // ? Maybe make this optional just in case
-        let admin_exists = User::get_using_username(&self.db, DEFAULT_ADMIN_USERNAME)
-            .await
-            .is_ok();
+        let admin_exists = User::count_with_role(&self.db, Role::Admin).await? > 0;
         if !admin_exists {
             let mut admin = User {
                 user_id: User::DEFAULT.user_id,
-                username: "admin".to_string(),
+                username: DEFAULT_ADMIN_USERNAME.to_string(),
                 password_hash: DEFAULT_ADMIN_PASS_HASH.to_owned(),
                 created_at: User::DEFAULT.created_at(),
+                role: Role::Admin,
             };
 
             admin.create_using_self(&self.db).await?;
         }
 
+        // No camera can be connected yet, even if the last run didn't get to mark them as offline
+        Camera::set_all_offline(&self.db).await?;
+
+        // Same for videos that were still being recorded, otherwise they could never be pruned
+        let closed = crate::retention::close_unfinished(&self.db).await?;
+        if closed > 0 {
+            warn!("Closed {closed} video(s) left unfinished by the last run");
+        }
+
         // Session layer.
         //
         // This uses `tower-sessions` to establish a layer that will provide the session
@@ -182,12 +291,17 @@ impl App {
                 .continuously_delete_expired(EXPIRED_SESSION_DELETION_INTERVAL),
         );
 
+        let retention_task = tokio::spawn(crate::retention::continuously_enforce(
+            self.db.clone(),
+            RETENTION_INTERVAL,
+        ));
+
         // Generate a cryptographic key to sign the session cookie.
         let key = Key::generate();
 
         let session_layer = SessionManagerLayer::new(session_store)
             .with_secure(false)
-            .with_expiry(Expiry::OnInactivity(SESSION_DURATION))
+            .with_expiry(Expiry::OnInactivity(self.session_duration))
             .with_signed(key);
 
         // Auth service.
@@ -199,31 +313,48 @@ impl App {
 
         let embedded_assets_service = ServeEmbed::<EmbeddedAssets>::new();
 
-        let tx = watch::Sender::new(ImageContainer {
-            camera_id: -1,
-            timestamp: -1,
-            image_bytes: vec![],
-        });
-
-        let api_channel = watch::Sender::new(ApiChannelMessage::Initial);
+        let api_channel = broadcast::Sender::new(API_CHANNEL_CAPACITY);
 
         let mdns_channel = watch::Sender::new(MdnsChannelMessage::Initial);
 
         let shutdown_token = CancellationToken::new();
 
         let app_state = Arc::new(AppState {
-            images_tx: tx,
+            camera_hub: CameraHub::default(),
             video_path: self.video_path,
             api_channel: api_channel.clone(),
             mdns_channel: mdns_channel.clone(),
             shutdown_token: shutdown_token.clone(),
             oko_private_socket_addr: self.oko_private_socket_addr,
+            failed_logins: FailedLogins::default(),
             db_pool: self.db,
         });
 
+        let webhooks_task = tokio::spawn(crate::webhooks::continuously_dispatch(
+            app_state.db_pool.clone(),
+            api_channel.subscribe(),
+            shutdown_token.clone(),
+        ));
+
+        let alerts_task = tokio::spawn(crate::alerts::continuously_send(
+            app_state.db_pool.clone(),
+            api_channel.subscribe(),
+            shutdown_token.clone(),
+        ));
+
+        let mqtt_task = self.mqtt.map(|mqtt_config| {
+            tokio::spawn(crate::mqtt::continuously_publish(
+                app_state.db_pool.clone(),
+                mqtt_config,
+                api_channel.clone(),
+                shutdown_token.clone(),
+            ))
+        });
+
+        let mdns_service_name = self.mdns_service_name;
         let mdns_task = tokio::spawn(async move {
             let Ok(mdns_discovery) = mdns::discover::interface(
-                "_http._tcp.local",
+                &mdns_service_name,
                 tokio::time::Duration::from_secs(5),
                 Ipv4Addr::UNSPECIFIED,
             ) else {
@@ -252,16 +383,18 @@ impl App {
             .with_state(app_state.clone());
 
         // TODO: Order of merge matters here, make sure the correct routes are protected and that fallback works as intended.
-        let app = protected::router(app_state)
+        let app = protected::router(app_state.clone())
             .fallback_service(embedded_assets_service)
             .route_layer(login_required!(Backend, login_url = "/api/login"))
             .merge(main_router)
-            .merge(auth::router())
+            .merge(auth::router(app_state))
             .layer(auth_layer);
 
         let axum_rustls_handle = axum_server::Handle::new();
 
         let https_addr_clone = self.https_addr;
+        let tls_cert_path = self.tls_cert_path;
+        let tls_key_path = self.tls_key_path;
         let app_clone = app.clone();
         let axum_rustls_handle_clone = axum_rustls_handle.clone();
 
@@ -270,13 +403,12 @@ impl App {
                 return Ok(());
             };
 
-            let Ok(tls_config) =
-                RustlsConfig::from_pem_file("./certs/oko.internal.crt", "./certs/oko.internal.key")
-                    .await
-                    .map_err(|e| {
-                        error!("Failed to load TLS config, no HTTPS server will be created: {e:?}");
-                        e
-                    })
+            let Ok(tls_config) = RustlsConfig::from_pem_file(tls_cert_path, tls_key_path)
+                .await
+                .map_err(|e| {
+                    error!("Failed to load TLS config, no HTTPS server will be created: {e:?}");
+                    e
+                })
             else {
                 return Ok(());
             };
@@ -296,26 +428,47 @@ impl App {
                 .await
         });
 
-        // Ensure we use a shutdown signal to abort the deletion task.
+        // Ensure we use a shutdown signal to abort the deletion and retention tasks.
         axum::serve(
             self.http_listener,
             app.into_make_service_with_connect_info::<SocketAddr>(),
         )
         .with_graceful_shutdown(shutdown_signal(
             deletion_task.abort_handle(),
+            retention_task.abort_handle(),
             mdns_task.abort_handle(),
             axum_rustls_handle,
             shutdown_token,
         ))
         .await?;
 
-        let (mdns_task_result, deletion_task, https_task) =
-            tokio::join!(mdns_task, deletion_task, https_task);
+        let (
+            mdns_task_result,
+            deletion_task,
+            retention_task,
+            webhooks_task,
+            alerts_task,
+            https_task,
+        ) = tokio::join!(
+            mdns_task,
+            deletion_task,
+            retention_task,
+            webhooks_task,
+            alerts_task,
+            https_task
+        );
 
         mdns_task_result?;
         deletion_task??;
+        retention_task?;
+        webhooks_task?;
+        alerts_task?;
         https_task??;
 
+        if let Some(mqtt_task) = mqtt_task {
+            mqtt_task.await?;
+        }
+
         Ok(())
     }
 }
@@ -333,6 +486,7 @@ pub async fn guest_exists_route(state: State<Arc<AppState>>) -> impl IntoRespons
 
 async fn shutdown_signal(
     deletion_task_abort_handle: AbortHandle,
+    retention_task_abort_handle: AbortHandle,
     mdns_task_abort_handle: AbortHandle,
     axum_rustls_handle: axum_server::Handle,
     shutdown_token: CancellationToken,
@@ -360,12 +514,14 @@ async fn shutdown_signal(
     tokio::select! {
         () = ctrl_c => {
             deletion_task_abort_handle.abort();
+            retention_task_abort_handle.abort();
             shutdown_token.cancel();
             mdns_task_abort_handle.abort();
             axum_rustls_handle.shutdown();
         },
         () = terminate => {
             deletion_task_abort_handle.abort();
+            retention_task_abort_handle.abort();
             shutdown_token.cancel();
             mdns_task_abort_handle.abort();
             axum_rustls_handle.shutdown();
@@ -406,101 +562,200 @@ async fn handle_socket(
 ) {
     info!("{who} connected to handle_socket.");
 
-    // TODO: Update camera in DB to be online
-
-    let mut images_rx_rec = state.images_tx.subscribe();
-
     let tracker = TaskTracker::new();
     let recording_token = CancellationToken::new();
     let recording_token_clone = recording_token.clone();
     let video_path = state.video_path.clone();
 
-    // TODO: Init watch message may be consumed by any of the tasks, find way to avoid being off by one.
-    //  Always ignoring the first message in every task is maybe not the best solution.
+    let Some(hello) = receive_hello(&mut socket, who).await else {
+        return;
+    };
 
-    let mut is_camera = false;
-    let mut camera_any_port = false;
+    let is_camera = hello.role == ClientRole::Camera;
     let mut camera_id: i64 = -1;
+    let mut initial_camera_settings = None;
+    let connected_at = OffsetDateTime::now_utc();
+    let (cameras_tx, mut cameras_rx) = watch::channel(Vec::<CameraPermissionView>::new());
+    let mut user_id = None;
+    let mut is_admin = false;
+    let mut reply = HelloReply {
+        // Clients newer than the server are downgraded
+        protocol_version: hello.protocol_version.min(PROTOCOL_VERSION),
+        camera_id: None,
+        settings: None,
+        binary_frames: false,
+    };
 
-    if let Some(msg) = socket.recv().await {
-        if let Ok(msg) = msg {
-            if process_message(msg.clone(), who).is_break() {
-                return;
-            }
+    if is_camera {
+        info!(
+            "{who} is a camera (any port: {}, firmware: {:?})...",
+            hello.any_port, hello.firmware_version
+        );
 
-            match msg {
-                Message::Text(msg_txt) => {
-                    if msg_txt == CAMERA_INDICATOR_TEXT {
-                        info!("{who} is a camera...");
-                        is_camera = true;
-                    } else if msg_txt == CAMERA_ANY_PORT_INDICATOR_TEXT {
-                        // TODO: Maybe find a better way to handle this
-                        info!("{who} is a camera (any port)...");
-                        is_camera = true;
-                        camera_any_port = true;
-                    } else {
-                        info!("{who} is not camera...");
-                    }
-                }
-                _ => {
-                    debug!("Ignoring first message from {who}...");
-                }
-            }
+        // TODO: Maybe find a better way to handle this
+        let camera_address = if hello.any_port {
+            who.ip().to_string() + ":*"
         } else {
-            warn!("client {who} abruptly disconnected");
+            who.to_string()
+        };
+
+        let Ok(db_camera) = Camera::get_using_ip(&state.db_pool, camera_address).await else {
+            error!("Camera not found in DB, aborting...");
+            close_socket(&mut socket, who, close_code::POLICY, "Unknown camera").await;
+            return;
+        };
+
+        if db_camera.token_hash.is_none() {
+            // Added before pairing tokens existed, see `post::camera_pairing_token`
+            warn!(
+                "Camera {camera_id} has no pairing token, regenerate it with POST /api/cameras/{camera_id}/pairing_token, aborting...",
+                camera_id = db_camera.camera_id
+            );
+            close_socket(
+                &mut socket,
+                who,
+                close_code::POLICY,
+                "No pairing token, ask an admin to regenerate it",
+            )
+            .await;
             return;
         }
-    }
 
-    let mut initial_camera_settings = None;
-    let cameras: Arc<Mutex<Vec<CameraPermissionView>>> = Arc::new(Mutex::new(Vec::new()));
-    let mut user_id = None;
+        if !db_camera.verify_pairing_token(hello.token.as_deref().unwrap_or_default()) {
+            warn!(
+                "{who} sent an invalid pairing token for camera {}, aborting...",
+                db_camera.camera_id
+            );
+            close_socket(
+                &mut socket,
+                who,
+                close_code::POLICY,
+                "Invalid pairing token",
+            )
+            .await;
+            return;
+        }
 
-    if is_camera {
-        // TODO: Maybe find a better way to handle this
-        if camera_any_port {
-            let Ok(db_camera) =
-                Camera::get_using_ip(&state.db_pool, who.ip().to_string() + ":*").await
-            else {
-                // TODO: Inform client/db if camera not found (both web user and ws connection), also find better way to exit here?
-                error!("Camera (any port) not found in DB, aborting...");
-                return;
-            };
+        camera_id = db_camera.camera_id;
 
-            camera_id = db_camera.camera_id;
-        } else {
-            let Ok(db_camera) = Camera::get_using_ip(&state.db_pool, who.to_string()).await else {
-                // TODO: Inform client/db if camera not found (both web user and ws connection), also find better way to exit here?
-                error!("Camera not found in DB, aborting...");
-                return;
-            };
+        let Ok(mut camera_settings) =
+            CameraSetting::get_for_camera(&state.db_pool, camera_id).await
+        else {
+            error!("Error getting initial camera settings for camera {camera_id}, aborting...");
+            close_socket(
+                &mut socket,
+                who,
+                close_code::ERROR,
+                "Could not load camera settings",
+            )
+            .await;
+            return;
+        };
 
-            camera_id = db_camera.camera_id;
+        // Cameras that can't capture the configured resolution use the first one they support
+        if let Some(supported_resolution) = hello.supported_resolutions.first() {
+            if !hello
+                .supported_resolutions
+                .contains(&camera_settings.resolution)
+            {
+                warn!(
+                    "Camera {camera_id} doesn't support {}, using {supported_resolution}",
+                    camera_settings.resolution
+                );
+                camera_settings.resolution = *supported_resolution;
+            }
         }
 
-        let Ok(camera_settings) = CameraSetting::get_for_camera(&state.db_pool, camera_id).await
+        // Frames mustn't be shown without their masks, so don't accept them at all
+        let Ok(privacy_masks) = PrivacyMask::list_for_camera(&state.db_pool, camera_id).await
         else {
-            error!("Error getting initial camera settings for camera {camera_id}, aborting...");
+            error!("Error getting privacy masks for camera {camera_id}, aborting...");
+            close_socket(
+                &mut socket,
+                who,
+                close_code::ERROR,
+                "Could not load privacy masks",
+            )
+            .await;
             return;
         };
+        state.camera_hub.set_privacy_masks(camera_id, privacy_masks);
 
+        reply.camera_id = Some(camera_id);
+        reply.settings = Some(CameraSettingNoMeta {
+            flashlight_enabled: camera_settings.flashlight_enabled,
+            resolution: camera_settings.resolution,
+            framerate: camera_settings.framerate,
+        });
         initial_camera_settings = Some(camera_settings);
     } else {
-        // TODO: Return errors to user
+        info!("{who} is a viewer...");
+
         let Some(ref user) = auth_session.user else {
             error!("User not found in auth session...");
+            close_socket(&mut socket, who, close_code::POLICY, "Not logged in").await;
             return;
         };
 
         user_id = Some(user.user_id);
+        is_admin = user.role == Role::Admin;
+        reply.binary_frames = hello.binary_frames;
 
         let Ok(i_cameras) = Camera::list_accessible_to_user(&state.db_pool, user.user_id).await
         else {
             error!("Error listing cameras for user...");
+            close_socket(
+                &mut socket,
+                who,
+                close_code::ERROR,
+                "Could not list cameras",
+            )
+            .await;
             return;
         };
 
-        *cameras.lock().await = i_cameras;
+        cameras_tx.send_replace(i_cameras);
+    }
+
+    let reply_message = match serde_json::to_string(&reply) {
+        Ok(reply_json) => Message::Text(reply_json),
+        Err(e) => {
+            error!("Error serializing hello reply for {who}: {e:?}");
+            close_socket(
+                &mut socket,
+                who,
+                close_code::ERROR,
+                "Could not reply to hello",
+            )
+            .await;
+            return;
+        }
+    };
+
+    if let Err(e) = socket.send(reply_message).await {
+        warn!("Could not send hello reply to {who} due to {e}");
+        return;
+    }
+
+    if is_camera {
+        if let Err(e) = Camera::set_online(&state.db_pool, camera_id, connected_at).await {
+            error!("Error marking camera {camera_id} as online: {e:?}");
+        } else if state
+            .api_channel
+            .send(ApiChannelMessage::CameraListChanged(
+                CameraListChange::Updated { camera_id },
+            ))
+            .is_err()
+        {
+            warn!("Failed to send camera {camera_id} online update to API channel");
+        }
+
+        crate::events::publish(
+            &state.db_pool,
+            &state.api_channel,
+            Event::new(Some(camera_id), EventKind::CameraOnline, connected_at),
+        )
+        .await;
     }
 
     let initial_camera_settings_clone = initial_camera_settings.clone();
@@ -509,106 +764,19 @@ async fn handle_socket(
     // ? Maybe assume is camera if IP belongs to camera in DB
     // TODO: Handle stopping recording properly
     // TODO: Inform client/db if recording fails
-    // TODO: Find out which is better, ingesting encoded or decoded images
     // ! Camera restart does not guarantee new recording, frames will keep going to the same video unless socket times out?
     let mut recording_task: JoinHandle<Result<(), Box<dyn std::error::Error + Send + Sync>>> =
         if is_camera {
-            let state_clone = state.clone();
             // TODO: Check if errors are returned properly here, had some issues with the ? operator being silent
-            tracker.spawn(async move {
-                let now = Video::DEFAULT.start_time();
-                let formatted_now = now.format(Video::DEFAULT.file_name_format)?;
-                let file_pathbuf = video_path.join(format!("{formatted_now}.avi"));
-
-                // TODO: Lookup camera_id from DB
-                // TODO: Use proper user customizable file path, ability to pass tempdir for tests would be nice
-                let mut video = Video {
-                    video_id: Video::DEFAULT.video_id,
-                    camera_id: Some(camera_id),
-                    file_path: file_pathbuf.to_string_lossy().to_string(),
-                    start_time: now,
-                    end_time: Video::DEFAULT.end_time,
-                    file_size: None,
-                };
-
-                // ? Maybe don't create video until first frame (or maybe doing this is actually a good approach)?
-                video.create_using_self(&state_clone.db_pool).await?;
-
-                let (frame_width, frame_height, framerate) = match initial_camera_settings_clone {
-                    #[allow(clippy::match_same_arms)] // readability
-                    Some(settings) => {
-                        let (frame_width, frame_height) = match settings.resolution.as_str() {
-                            "SVGA" => (800, 600),
-                            "VGA" => (640, 480),
-                            _ => (800, 600),
-                        };
-
-                        (frame_width, frame_height, settings.framerate)
-                    }
-                    None => (800, 600, 12),
-                };
-
-                // TODO: Don't hardcode these
-                let video_fourcc = VideoWriter::fourcc('m', 'p', '4', 'v')?;
-                let video_size = Size::new(frame_width, frame_height);
-                // TODO: Investigate why video is too fast
-                #[allow(clippy::cast_precision_loss)] // the precision loss is acceptable
-                let mut video_writer = VideoWriter::new_def(
-                    &video.file_path,
-                    video_fourcc,
-                    framerate as f64,
-                    video_size,
-                )?;
-
-                let mut total_bytes = 0;
-
-                let mut first_received = false;
-                // TODO: Adding a sleep might be a good idea?
-                // ! Camera restart does not guarantee new recording, frames will keep going to the same video unless socket times out?
-                // TODO: Handle if user changes resolution/framerate during recording (this is likely to happen)
-                loop {
-                    // TODO: this might not be the best way of doing this
-                    let message = (*images_rx_rec.borrow_and_update()).clone();
-
-                    if first_received {
-                        debug!("Recording image from {who}...");
-
-                        // ? Parsing JSON for every image just to see if the camera matches is wasteful, is there a better way?
-                        if message.camera_id == camera_id {
-                            let message_data_vec = message.image_bytes;
-                            // let message_data_vec = message.into_data();
-                            let message_data_vec_slice = message_data_vec.as_slice();
-                            let decoded_image = imdecode(&message_data_vec_slice, IMREAD_COLOR)?;
-
-                            // TODO: Handle error here
-                            // ? Does calling this function too often/quickly risk a crash? Use a buffer/batch?
-                            video_writer.write(&decoded_image)?;
-                            total_bytes += message_data_vec_slice.len();
-                        }
-                    }
-
-                    tokio::select! {
-                        c = images_rx_rec.changed() => {
-                            if c.is_err() {
-                                break;
-                            }
-                        },
-                        () = recording_token.cancelled() => {
-                            break;
-                        }
-                    }
-
-                    first_received = true;
-                }
-
-                video.end_time = Some(OffsetDateTime::now_utc());
-                video.file_size = Some(total_bytes.try_into()?);
-
-                video.update_using_self(&state_clone.db_pool).await?;
-                info!("Recording finished for {who}...");
-
-                Ok(())
-            })
+            tracker.spawn(crate::recording::record(
+                state.camera_hub.subscribe(camera_id),
+                state.api_channel.clone(),
+                recording_token,
+                state.db_pool.clone(),
+                video_path,
+                camera_id,
+                initial_camera_settings_clone,
+            ))
         } else {
             tracker.spawn(async move {
                 let mut interval = tokio::time::interval(EMPTY_TASK_SLEEP_DURATION);
@@ -637,45 +805,68 @@ async fn handle_socket(
     #[allow(clippy::if_not_else)]
     let mut send_task: JoinHandle<Result<(), Box<dyn std::error::Error + Send + Sync>>> =
         if !is_camera {
-            let cameras_clone = cameras.clone();
-            let mut images_rx = state.images_tx.subscribe();
+            let binary_frames = reply.binary_frames;
+            let send_state_clone = state.clone();
             let sender_mutex_clone = sender_mutex.clone();
             // TODO: Proper error handling
             tokio::spawn(async move {
-                let mut first_received = false;
-                // TODO: Adding a sleep might be a good idea?
-                loop {
-                    // TODO: this might not be the best way of doing this
-                    let message = (*images_rx.borrow_and_update()).clone();
+                let mut images = StreamMap::new();
+
+                'cameras_changed: loop {
+                    // Only subscribe to the cameras the user can view
+                    let camera_ids: Vec<i64> = cameras_rx
+                        .borrow_and_update()
+                        .iter()
+                        .map(|c| c.camera_id)
+                        .collect();
+                    let unsubscribed: Vec<i64> = images
+                        .keys()
+                        .filter(|camera_id| !camera_ids.contains(camera_id))
+                        .copied()
+                        .collect();
+                    for camera_id in unsubscribed {
+                        images.remove(&camera_id);
+                    }
+                    for camera_id in camera_ids {
+                        if !images.contains_key(&camera_id) {
+                            images.insert(
+                                camera_id,
+                                BroadcastStream::new(
+                                    send_state_clone.camera_hub.subscribe(camera_id),
+                                ),
+                            );
+                        }
+                    }
 
-                    if first_received {
-                        debug!("Sending message to {who}...");
+                    loop {
+                        tokio::select! {
+                            c = cameras_rx.changed() => {
+                                if c.is_err() {
+                                    break 'cameras_changed;
+                                }
 
-                        // TODO: locking this mutex in such a hot path is a horrible idea, absolutely find a better way to do this
-                        if cameras_clone
-                            .lock()
-                            .await
-                            .iter()
-                            .any(|c| c.camera_id == message.camera_id)
-                        {
-                            // TODO: look into bincode (fastest?) / rmp-serde (wide support) / flatbuffers (partial deserialization)
-                            let message_json = serde_json::to_string(&message)?;
-                            let message_json_msg = Message::Text(message_json.clone());
+                                continue 'cameras_changed;
+                            },
+                            Some((camera_id, image)) = images.next() => {
+                                let Ok(message) = image else {
+                                    debug!("{who} fell behind on camera {camera_id}, skipping images...");
+                                    continue;
+                                };
 
-                            // TODO: Handle error here
-                            sender_mutex_clone
-                                .lock()
-                                .await
-                                .send(message_json_msg)
-                                .await?;
-                        }
-                    }
+                                debug!("Sending message to {who}...");
 
-                    if images_rx.changed().await.is_err() {
-                        break;
-                    }
+                                // JSON turns the image into an array of numbers, only used by older viewers
+                                let image_msg = if binary_frames {
+                                    Message::Binary(message.to_frame())
+                                } else {
+                                    Message::Text(serde_json::to_string(&message)?)
+                                };
 
-                    first_received = true;
+                                // TODO: Handle error here
+                                sender_mutex_clone.lock().await.send(image_msg).await?;
+                            }
+                        }
+                    }
                 }
 
                 info!("Sending close to {who}...");
@@ -705,24 +896,44 @@ async fn handle_socket(
 
     // This second task will receive messages from client and print them on server console
     // TODO: Reduce amount of cloning in this function
-    let recv_state_clone = state.clone();
+    let images_tx = is_camera.then(|| state.camera_hub.sender(camera_id));
     let mut recv_task = tokio::spawn(async move {
+        let mut sequence: u64 = 0;
+
         while let Some(Ok(msg)) = receiver.next().await {
             process_message(msg.clone(), who);
 
             match msg.clone() {
                 Message::Binary(_) => {
-                    if !is_camera {
+                    let Some(ref images_tx) = images_tx else {
                         continue;
-                    }
+                    };
 
-                    let img_container = ImageContainer {
-                        camera_id,
-                        timestamp: OffsetDateTime::now_utc().unix_timestamp(),
-                        image_bytes: msg.into_data(),
+                    let received_at = OffsetDateTime::now_utc();
+                    let image_bytes = msg.into_data();
+                    let privacy_masks = images_tx.privacy_masks();
+
+                    let image_bytes = if privacy_masks.is_empty() {
+                        image_bytes
+                    } else {
+                        match tokio::task::spawn_blocking(move || {
+                            crate::recording::apply_privacy_masks(image_bytes, &privacy_masks)
+                        })
+                        .await
+                        {
+                            Ok(Ok(masked_bytes)) => masked_bytes,
+                            e => {
+                                warn!("Dropping image from {who}, couldn't apply privacy masks: {e:?}");
+                                continue;
+                            }
+                        }
                     };
 
-                    let _ = recv_state_clone.images_tx.send(img_container);
+                    let img_container =
+                        ImageContainer::new(camera_id, received_at, sequence, image_bytes);
+                    sequence = sequence.wrapping_add(1);
+
+                    images_tx.send(img_container);
                 }
                 Message::Close(_) => break,
                 _ => (),
@@ -731,8 +942,8 @@ async fn handle_socket(
     });
 
     let api_channel = state.api_channel.clone();
+    let api_state_clone = state.clone();
     let sender_mutex_clone = sender_mutex.clone();
-    let mut first_received = false;
 
     let mut api_listener_task: JoinHandle<Result<(), Box<dyn std::error::Error + Send + Sync>>> =
         if is_camera {
@@ -761,50 +972,39 @@ async fn handle_socket(
 
                 let mut api_channel_rx = api_channel.subscribe();
                 loop {
-                    let api_msg = (*api_channel_rx.borrow_and_update()).clone();
-
-                    if first_received {
-                        #[allow(clippy::single_match)] // will change in the future
-                        match api_msg {
-                            ApiChannelMessage::CameraAction {
-                                camera_id: message_camera_id,
-                                message,
-                            } => {
-                                if message_camera_id == camera_id {
-                                    info!("API channel message received for api_camera_id {message_camera_id} for {who}...");
-
-                                    if let Err(e) = sender_mutex_clone
-                                        .lock()
-                                        .await
-                                        .send(Message::Text(serde_json::to_string(&message)?))
-                                        .await
-                                    {
-                                        error!(
-                                            "Error sending API WebSocket message to {who}: {e:?}"
-                                        );
-                                    }
-                                }
-                            }
-                            ApiChannelMessage::CameraListChanged(change) => match change {
-                                CameraListChange::Removed {
-                                    camera_id: camera_id_removed,
-                                } => {
-                                    if camera_id_removed == camera_id {
-                                        info!("Closing WebSocket for {who} because camera was removed from DB");
-                                        return Err("Camera removed from DB".into());
-                                    }
-                                }
-                                _ => (),
-                            },
-                            ApiChannelMessage::Initial => (),
+                    let api_msg = match api_channel_rx.recv().await {
+                        Ok(api_msg) => api_msg,
+                        Err(broadcast::error::RecvError::Lagged(skipped)) => {
+                            warn!("API listener for {who} fell behind, skipped {skipped} messages");
+                            continue;
                         }
-                    }
+                        Err(broadcast::error::RecvError::Closed) => break,
+                    };
 
-                    if api_channel_rx.changed().await.is_err() {
-                        break;
-                    }
+                    match api_msg {
+                        ApiChannelMessage::CameraAction {
+                            camera_id: message_camera_id,
+                            message,
+                        } if message_camera_id == camera_id => {
+                            info!("API channel message received for api_camera_id {message_camera_id} for {who}...");
 
-                    first_received = true;
+                            if let Err(e) = sender_mutex_clone
+                                .lock()
+                                .await
+                                .send(Message::Text(serde_json::to_string(&message)?))
+                                .await
+                            {
+                                error!("Error sending API WebSocket message to {who}: {e:?}");
+                            }
+                        }
+                        ApiChannelMessage::CameraListChanged(CameraListChange::Removed {
+                            camera_id: camera_id_removed,
+                        }) if camera_id_removed == camera_id => {
+                            info!("Closing WebSocket for {who} because camera was removed from DB");
+                            return Err("Camera removed from DB".into());
+                        }
+                        _ => (),
+                    }
                 }
 
                 Ok(())
@@ -813,49 +1013,86 @@ async fn handle_socket(
             tokio::spawn(async move {
                 let mut api_channel_rx = api_channel.subscribe();
                 loop {
-                    let api_msg = (*api_channel_rx.borrow_and_update()).clone();
-
-                    if first_received {
-                        #[allow(clippy::single_match)] // will change in the future
-                        match api_msg {
-                            // TODO: every user task performing this is wasteful, global camera list mutex shared with api would be better
-                            ApiChannelMessage::CameraListChanged(change) => {
-                                info!("API channel message received for camera list changed for {who}...");
-
-                                let Some(user_id_some) = user_id else {
-                                    error!("Camera list changed but user was not found in auth_session. How is this even possible?");
-                                    break;
-                                };
+                    let api_msg = match api_channel_rx.recv().await {
+                        Ok(api_msg) => api_msg,
+                        Err(broadcast::error::RecvError::Lagged(skipped)) => {
+                            warn!("API listener for {who} fell behind, skipped {skipped} messages");
+                            continue;
+                        }
+                        Err(broadcast::error::RecvError::Closed) => break,
+                    };
 
-                                if let Err(e) = sender_mutex_clone
-                                    .lock()
-                                    .await
-                                    .send(Message::Text(serde_json::to_string(&change)?))
-                                    .await
-                                {
-                                    error!("Error sending API WebSocket message to {who}: {e:?}");
-                                }
+                    match api_msg {
+                        // TODO: every user task performing this is wasteful, global camera list mutex shared with api would be better
+                        ApiChannelMessage::CameraListChanged(change) => {
+                            info!(
+                                "API channel message received for camera list changed for {who}..."
+                            );
 
-                                let Ok(new_cameras) =
-                                    Camera::list_accessible_to_user(&state.db_pool, user_id_some)
-                                        .await
-                                else {
-                                    // TODO: prevent potential endless loop here from the DB call always failing
-                                    error!("Error listing new cameras for {who}...");
-                                    continue;
-                                };
+                            let Some(user_id_some) = user_id else {
+                                error!("Camera list changed but user was not found in auth_session. How is this even possible?");
+                                break;
+                            };
 
-                                *cameras.lock().await = new_cameras;
+                            if let Err(e) = sender_mutex_clone
+                                .lock()
+                                .await
+                                .send(Message::Text(serde_json::to_string(&change)?))
+                                .await
+                            {
+                                error!("Error sending API WebSocket message to {who}: {e:?}");
                             }
-                            _ => (),
+
+                            let Ok(new_cameras) = Camera::list_accessible_to_user(
+                                &api_state_clone.db_pool,
+                                user_id_some,
+                            )
+                            .await
+                            else {
+                                // TODO: prevent potential endless loop here from the DB call always failing
+                                error!("Error listing new cameras for {who}...");
+                                continue;
+                            };
+
+                            cameras_tx.send_replace(new_cameras);
                         }
-                    }
+                        ApiChannelMessage::EventCreated(ref event)
+                        | ApiChannelMessage::EventUpdated(ref event) => {
+                            let can_view = event.camera_id.map_or(is_admin, |event_camera_id| {
+                                cameras_tx
+                                    .borrow()
+                                    .iter()
+                                    .any(|c| c.camera_id == event_camera_id)
+                            });
+
+                            if !can_view {
+                                continue;
+                            }
 
-                    if api_channel_rx.changed().await.is_err() {
-                        break;
+                            if let Err(e) = sender_mutex_clone
+                                .lock()
+                                .await
+                                .send(Message::Text(serde_json::to_string(&api_msg)?))
+                                .await
+                            {
+                                error!("Error sending API WebSocket message to {who}: {e:?}");
+                            }
+                        }
+                        ApiChannelMessage::ArmingChanged(_) => {
+                            if let Err(e) = sender_mutex_clone
+                                .lock()
+                                .await
+                                .send(Message::Text(serde_json::to_string(&api_msg)?))
+                                .await
+                            {
+                                error!("Error sending API WebSocket message to {who}: {e:?}");
+                            }
+                        }
+                        ApiChannelMessage::CameraAction { .. }
+                        | ApiChannelMessage::VideoStarted(_)
+                        | ApiChannelMessage::VideoFinished(_)
+                        | ApiChannelMessage::MotionZonesChanged { .. } => (),
                     }
-
-                    first_received = true;
                 }
 
                 Ok(())
@@ -889,7 +1126,25 @@ async fn handle_socket(
                 Ok(_) => info!("recording_task finished for {who}"),
                 Err(c) => error!("Error recording images {c:?}")
             }
-            // ? Maybe do something if recording fails e.g. send a message to the client/DB
+            send_task.abort();
+            recv_task.abort();
+            api_listener_task.abort();
+            // The aborted tasks own the socket halves, wait for them to be dropped
+            let _ = send_task.await;
+            let _ = recv_task.await;
+            let _ = api_listener_task.await;
+            // Recording starts again once the camera reconnects
+            if let Err(e) = sender_mutex
+                .lock()
+                .await
+                .send(Message::Close(Some(CloseFrame {
+                    code: close_code::ERROR,
+                    reason: Cow::from("Recording stopped"),
+                })))
+                .await
+            {
+                warn!("Could not send Close to {who} due to {e}");
+            }
         },
         rv_d = (&mut api_listener_task) => {
             match rv_d {
@@ -904,12 +1159,106 @@ async fn handle_socket(
         }
     }
 
-    // TODO: Update camera in DB to be offline
+    // Releases our half of the socket, the aborted tasks release theirs
+    drop(sender_mutex);
+
+    if is_camera {
+        match Camera::set_offline(&state.db_pool, camera_id, connected_at).await {
+            Ok(true) => {
+                state.camera_hub.disconnected(camera_id);
+
+                if state
+                    .api_channel
+                    .send(ApiChannelMessage::CameraListChanged(
+                        CameraListChange::Updated { camera_id },
+                    ))
+                    .is_err()
+                {
+                    warn!("Failed to send camera {camera_id} offline update to API channel");
+                }
+
+                crate::events::publish(
+                    &state.db_pool,
+                    &state.api_channel,
+                    Event::new(
+                        Some(camera_id),
+                        EventKind::CameraOffline,
+                        OffsetDateTime::now_utc(),
+                    ),
+                )
+                .await;
+            }
+            Ok(false) => debug!("Camera {camera_id} reconnected, not marking as offline"),
+            Err(e) => error!("Error marking camera {camera_id} as offline: {e:?}"),
+        }
+    }
 
     // returning from the handler closes the websocket connection
     info!("Websocket context {who} destroyed");
 }
 
+/// Waits for the `Hello` that every client has to send first, closes the socket if it is missing or unsupported.
+///
+/// Returns `None` if the connection is over.
+async fn receive_hello(socket: &mut WebSocket, who: SocketAddr) -> Option<Hello> {
+    let msg = match socket.recv().await {
+        Some(Ok(msg)) => msg,
+        Some(Err(e)) => {
+            warn!("client {who} abruptly disconnected: {e}");
+            return None;
+        }
+        None => {
+            warn!("client {who} disconnected before sending hello");
+            return None;
+        }
+    };
+
+    // Not using `process_message` here, the hello contains the pairing token
+    let Message::Text(msg_txt) = msg else {
+        if process_message(msg, who).is_continue() {
+            close_socket(socket, who, close_code::PROTOCOL, "Expected hello").await;
+        }
+
+        return None;
+    };
+
+    let Ok(hello) = serde_json::from_str::<Hello>(&msg_txt) else {
+        warn!("{who} sent an invalid hello, aborting...");
+        close_socket(socket, who, close_code::PROTOCOL, "Expected hello").await;
+        return None;
+    };
+
+    if hello.protocol_version < MIN_PROTOCOL_VERSION {
+        warn!(
+            "{who} uses protocol version {}, at least {MIN_PROTOCOL_VERSION} is required",
+            hello.protocol_version
+        );
+        close_socket(
+            socket,
+            who,
+            close_code::PROTOCOL,
+            "Unsupported protocol version",
+        )
+        .await;
+        return None;
+    }
+
+    Some(hello)
+}
+
+/// Sends a Close frame, errors are only logged since the connection is being dropped anyway.
+async fn close_socket(socket: &mut WebSocket, who: SocketAddr, code: u16, reason: &'static str) {
+    if let Err(e) = socket
+        .send(Message::Close(Some(CloseFrame {
+            code,
+            reason: Cow::from(reason),
+        })))
+        .await
+    {
+        warn!("Could not send Close to {who} due to {e}");
+    }
+}
+
 /// helper to print contents of messages to stdout. Has special treatment for Close.
 #[allow(clippy::cognitive_complexity)]
 fn process_message(msg: Message, who: SocketAddr) -> ControlFlow<(), ()> {
@@ -1,28 +1,102 @@
+use std::{collections::HashMap, net::IpAddr, sync::Arc};
+
 use axum::{
     http::StatusCode,
     response::IntoResponse,
     routing::{get, post},
     Form, Router,
 };
+use time::{Duration, OffsetDateTime};
+use tokio::sync::Mutex;
 
 use crate::users::{AuthSession, Credentials};
+use crate::web::AppState;
+use crate::{Event, EventKind, Model};
+
+/// Failed logins from the same IP address within this long of the first are counted in one event
+const FAILED_LOGIN_COALESCE_INTERVAL: Duration = Duration::minutes(10);
+/// The username is whatever the client sent, so it is cut off in events
+const MAX_FAILED_LOGIN_USERNAME_CHARS: usize = 64;
+
+/// Coalesces failed logins per IP address, so guessing passwords doesn't flood events and webhooks.
+#[derive(Debug, Default)]
+pub struct FailedLogins {
+    /// Event id, when it started and the attempts counted in it
+    events: Mutex<HashMap<IpAddr, (i64, OffsetDateTime, u64)>>,
+}
+
+impl FailedLogins {
+    /// Publishes a `LoginFailed` event, or updates the attempts of the recent one from `ip`.
+    ///
+    /// Only new events are sent to webhooks and alerts, updates are only pushed to viewers.
+    pub async fn record(&self, state: &AppState, username: &str, ip: IpAddr, now: OffsetDateTime) {
+        let username: String = username
+            .chars()
+            .take(MAX_FAILED_LOGIN_USERNAME_CHARS)
+            .collect();
+
+        let mut events = self.events.lock().await;
+        events.retain(|_, (_, start_time, _)| now - *start_time < FAILED_LOGIN_COALESCE_INTERVAL);
+
+        if let Some((event_id, _, attempts)) = events.get_mut(&ip) {
+            // Reloaded so e.g. acknowledging it isn't undone
+            if let Ok(mut event) = Event::get_using_id(&state.db_pool, *event_id).await {
+                *attempts += 1;
+                event.end_time = Some(now);
+                event.details = Some(format!("{username} from {ip}, {attempts} attempts"));
+                drop(events);
+
+                crate::events::publish_update(&state.db_pool, &state.api_channel, event).await;
+                return;
+            }
+        }
 
-pub fn router() -> Router<()> {
+        let mut event = Event::new(None, EventKind::LoginFailed, now);
+        event.details = Some(format!("{username} from {ip}"));
+
+        if let Some(event) = crate::events::publish(&state.db_pool, &state.api_channel, event).await
+        {
+            events.insert(ip, (event.event_id, now, 1));
+        }
+    }
+}
+
+pub fn router(app_state: Arc<AppState>) -> Router<()> {
     Router::new()
         .route("/api/login", post(self::post::login))
         .route("/api/logout", get(self::get::logout))
+        .with_state(app_state)
 }
 
 mod post {
-    use super::{AuthSession, Credentials, Form, IntoResponse, StatusCode};
+    use std::{net::SocketAddr, sync::Arc};
+
+    use axum::extract::{ConnectInfo, State};
+    use time::OffsetDateTime;
+
+    use super::{AppState, AuthSession, Credentials, Form, IntoResponse, StatusCode};
 
     pub async fn login(
         mut auth_session: AuthSession,
+        state: State<Arc<AppState>>,
+        ConnectInfo(addr): ConnectInfo<SocketAddr>,
         Form(creds): Form<Credentials>,
     ) -> impl IntoResponse {
         let user = match auth_session.authenticate(creds.clone()).await {
             Ok(Some(user)) => user,
-            Ok(None) => return StatusCode::UNAUTHORIZED.into_response(),
+            Ok(None) => {
+                state
+                    .failed_logins
+                    .record(
+                        &state,
+                        &creds.username,
+                        addr.ip(),
+                        OffsetDateTime::now_utc(),
+                    )
+                    .await;
+
+                return StatusCode::UNAUTHORIZED.into_response();
+            }
             Err(_) => return StatusCode::INTERNAL_SERVER_ERROR.into_response(),
         };
 
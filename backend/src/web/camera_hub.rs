@@ -0,0 +1,196 @@
+use std::{
+    collections::HashMap,
+    sync::{Mutex, PoisonError},
+};
+
+use tokio::sync::{broadcast, watch};
+
+use super::ImageContainer;
+use crate::db::PrivacyMask;
+
+/// How many images a subscriber can fall behind by before it starts skipping them
+const CHANNEL_CAPACITY: usize = 16;
+
+/// Per-camera image channels, so images from different cameras never overwrite each other.
+///
+/// Channels are created when a camera connects (or a viewer subscribes to it first)
+/// and removed when the camera is deleted.
+#[derive(Default)]
+pub struct CameraHub {
+    channels: Mutex<HashMap<i64, CameraSender>>,
+}
+
+/// Sends the images of one camera to its subscribers
+#[derive(Clone)]
+pub struct CameraSender {
+    images: broadcast::Sender<ImageContainer>,
+    /// Kept for snapshots, `None` until the camera sends an image
+    latest: watch::Sender<Option<ImageContainer>>,
+    /// Applied to images before they are sent, see `crate::recording::apply_privacy_masks`
+    privacy_masks: watch::Sender<Vec<PrivacyMask>>,
+}
+
+impl CameraSender {
+    fn new() -> Self {
+        Self {
+            images: broadcast::Sender::new(CHANNEL_CAPACITY),
+            latest: watch::Sender::new(None),
+            privacy_masks: watch::Sender::new(Vec::new()),
+        }
+    }
+
+    /// The masks images have to go through before being sent
+    #[must_use]
+    pub fn privacy_masks(&self) -> Vec<PrivacyMask> {
+        self.privacy_masks.borrow().clone()
+    }
+
+    pub fn send(&self, image: ImageContainer) {
+        self.latest.send_replace(Some(image.clone()));
+        // Nobody might be subscribed, e.g. no viewers and recording already stopped
+        let _ = self.images.send(image);
+    }
+}
+
+impl CameraHub {
+    /// Where images from `camera_id` should be sent, created if needed
+    #[must_use]
+    pub fn sender(&self, camera_id: i64) -> CameraSender {
+        self.channels
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .entry(camera_id)
+            .or_insert_with(CameraSender::new)
+            .clone()
+    }
+
+    /// Receives images sent by `camera_id` from now on
+    #[must_use]
+    pub fn subscribe(&self, camera_id: i64) -> broadcast::Receiver<ImageContainer> {
+        self.sender(camera_id).images.subscribe()
+    }
+
+    /// Replaces the privacy masks of `camera_id`, used for images received from now on
+    pub fn set_privacy_masks(&self, camera_id: i64, masks: Vec<PrivacyMask>) {
+        self.sender(camera_id).privacy_masks.send_replace(masks);
+    }
+
+    /// Most recent image sent by `camera_id` since it connected
+    #[must_use]
+    pub fn latest(&self, camera_id: i64) -> Option<ImageContainer> {
+        self.channels
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .get(&camera_id)
+            .and_then(|sender| sender.latest.borrow().clone())
+    }
+
+    /// Forgets the latest image, so it isn't served after the camera goes offline
+    pub fn disconnected(&self, camera_id: i64) {
+        if let Some(sender) = self
+            .channels
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .get(&camera_id)
+        {
+            sender.latest.send_replace(None);
+        }
+    }
+
+    /// Subscribers are closed once the camera disconnects as well
+    pub fn remove(&self, camera_id: i64) {
+        self.channels
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .remove(&camera_id);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use time::OffsetDateTime;
+    use tokio::sync::broadcast::error::TryRecvError;
+
+    use super::*;
+
+    fn image(camera_id: i64, sequence: u64) -> ImageContainer {
+        ImageContainer::new(camera_id, OffsetDateTime::UNIX_EPOCH, sequence, vec![1])
+    }
+
+    #[test]
+    fn cameras_dont_overwrite_each_other() {
+        let hub = CameraHub::default();
+        let mut front_door_rx = hub.subscribe(1);
+        let mut kitchen_rx = hub.subscribe(2);
+
+        let front_door_tx = hub.sender(1);
+        let kitchen_tx = hub.sender(2);
+        front_door_tx.send(image(1, 0));
+        kitchen_tx.send(image(2, 0));
+        front_door_tx.send(image(1, 1));
+
+        let front_door_sequences: Vec<u64> = [front_door_rx.try_recv(), front_door_rx.try_recv()]
+            .into_iter()
+            .filter_map(|image| image.ok().map(|image| image.sequence))
+            .collect();
+        assert_eq!(front_door_sequences, [0, 1]);
+        assert!(front_door_rx.try_recv().is_err());
+
+        assert!(kitchen_rx
+            .try_recv()
+            .is_ok_and(|image| image.camera_id == 2));
+        assert!(kitchen_rx.try_recv().is_err());
+    }
+
+    #[test]
+    fn latest_image() {
+        let hub = CameraHub::default();
+        assert!(hub.latest(1).is_none());
+
+        let camera_tx = hub.sender(1);
+        assert!(hub.latest(1).is_none());
+
+        camera_tx.send(image(1, 0));
+        camera_tx.send(image(1, 1));
+        assert_eq!(hub.latest(1).map(|image| image.sequence), Some(1));
+        assert!(hub.latest(2).is_none());
+
+        hub.disconnected(1);
+        assert!(hub.latest(1).is_none());
+    }
+
+    #[test]
+    fn privacy_masks() {
+        let hub = CameraHub::default();
+        let camera_tx = hub.sender(1);
+        assert!(camera_tx.privacy_masks().is_empty());
+
+        let mask = PrivacyMask {
+            mask_id: 1,
+            camera_id: 1,
+            name: "Window".to_string(),
+            points: sqlx::types::Json(Vec::new()),
+            created_at: OffsetDateTime::UNIX_EPOCH,
+        };
+        hub.set_privacy_masks(1, vec![mask]);
+
+        assert_eq!(camera_tx.privacy_masks().len(), 1);
+        assert!(hub.sender(2).privacy_masks().is_empty());
+    }
+
+    #[test]
+    fn removed_camera_closes_after_disconnect() {
+        let hub = CameraHub::default();
+        let camera_tx = hub.sender(1);
+        let mut viewer_rx = hub.subscribe(1);
+
+        hub.remove(1);
+        assert!(matches!(viewer_rx.try_recv(), Err(TryRecvError::Empty)));
+
+        drop(camera_tx);
+        assert!(matches!(viewer_rx.try_recv(), Err(TryRecvError::Closed)));
+
+        // A new channel is created if the camera is added again
+        assert_eq!(hub.sender(1).images.receiver_count(), 0);
+    }
+}
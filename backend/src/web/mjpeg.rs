@@ -0,0 +1,64 @@
+use std::convert::Infallible;
+
+use axum::{
+    body::Body,
+    http::header,
+    response::{IntoResponse, Response},
+};
+use tokio::sync::broadcast;
+use tokio_stream::{wrappers::BroadcastStream, StreamExt};
+use tokio_util::sync::CancellationToken;
+
+use super::ImageContainer;
+
+/// Separates the images in the stream
+const BOUNDARY: &str = "oko-frame";
+
+/// An image as a part of a `multipart/x-mixed-replace` body
+pub fn part(image_bytes: &[u8]) -> Vec<u8> {
+    let headers = format!(
+        "--{BOUNDARY}\r\nContent-Type: image/jpeg\r\nContent-Length: {}\r\n\r\n",
+        image_bytes.len()
+    );
+
+    let mut part = Vec::with_capacity(headers.len() + image_bytes.len() + 2);
+    part.extend_from_slice(headers.as_bytes());
+    part.extend_from_slice(image_bytes);
+    part.extend_from_slice(b"\r\n");
+    part
+}
+
+/// MJPEG stream of the images received on `images_rx` until shutdown.
+///
+/// Clients that fall behind skip images instead of slowing down the camera.
+pub fn response(
+    images_rx: broadcast::Receiver<ImageContainer>,
+    shutdown_token: CancellationToken,
+) -> Response {
+    let parts = BroadcastStream::new(images_rx)
+        .filter_map(Result::ok)
+        .map(|image| Ok::<_, Infallible>(part(&image.image_bytes)));
+
+    (
+        [(
+            header::CONTENT_TYPE,
+            format!("multipart/x-mixed-replace; boundary={BOUNDARY}"),
+        )],
+        [(header::CACHE_CONTROL, "no-cache")],
+        Body::from_stream(crate::or_until_shutdown(parts, shutdown_token)),
+    )
+        .into_response()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn multipart_part() {
+        assert_eq!(
+            part(&[0xFF, 0xD8]),
+            b"--oko-frame\r\nContent-Type: image/jpeg\r\nContent-Length: 2\r\n\r\n\xFF\xD8\r\n"
+        );
+    }
+}
@@ -7,9 +7,10 @@ use axum::{
     Router,
 };
 
-use crate::users::AuthSession;
+use crate::users::{AdminUser, AuthSession, OperatorUser, RequireRole};
 use crate::web::AppState;
 
+#[allow(clippy::too_many_lines)]
 pub fn router(app_state: Arc<AppState>) -> Router<()> {
     Router::new()
         .route("/api/", get(self::get::protected))
@@ -20,11 +21,112 @@ pub fn router(app_state: Arc<AppState>) -> Router<()> {
             "/api/cameras/:camera_id/videos",
             get(self::get::videos_for_camera),
         )
+        .route(
+            "/api/cameras/:camera_id/stream.mjpeg",
+            get(self::get::camera_stream),
+        )
+        .route(
+            "/api/cameras/:camera_id/snapshot.jpg",
+            get(self::get::camera_snapshot),
+        )
         .route(
             "/api/cameras/:camera_id/permissions",
             get(self::get::camera_permissions),
         )
+        .route(
+            "/api/cameras/:camera_id/pairing_token",
+            post(self::post::camera_pairing_token),
+        )
         .route("/api/videos/:video_id", get(self::get::video))
+        .route(
+            "/api/videos/:video_id/poster.jpg",
+            get(self::get::video_poster),
+        )
+        .route(
+            "/api/videos/:video_id/thumbnails",
+            get(self::get::video_thumbnails),
+        )
+        .route(
+            "/api/videos/:video_id/thumbnails/:thumbnail_id",
+            get(self::get::video_thumbnail),
+        )
+        .route("/api/videos/:video_id", patch(self::patch::video))
+        .route("/api/events", get(self::get::events))
+        .route("/api/events/:event_id", patch(self::patch::event))
+        .route(
+            "/api/events/:event_id/snapshot.jpg",
+            get(self::get::event_snapshot),
+        )
+        .route("/api/webhooks", get(self::get::webhooks))
+        .route("/api/webhooks", post(self::post::webhooks))
+        .route("/api/webhooks/:webhook_id", patch(self::patch::webhooks))
+        .route("/api/webhooks/:webhook_id", delete(self::delete::webhooks))
+        .route(
+            "/api/webhooks/:webhook_id/deliveries",
+            get(self::get::webhook_deliveries),
+        )
+        .route(
+            "/api/webhooks/:webhook_id/test",
+            post(self::post::webhook_test),
+        )
+        .route("/api/smtp", get(self::get::smtp))
+        .route("/api/smtp", patch(self::patch::smtp))
+        .route(
+            "/api/alert_subscriptions",
+            get(self::get::alert_subscriptions),
+        )
+        .route(
+            "/api/alert_subscriptions",
+            post(self::post::alert_subscriptions),
+        )
+        .route(
+            "/api/alert_subscriptions/:subscription_id",
+            delete(self::delete::alert_subscriptions),
+        )
+        .route("/api/arming", get(self::get::arming))
+        .route("/api/arming", patch(self::patch::arming))
+        .route(
+            "/api/cameras/:camera_id/arming_schedules",
+            get(self::get::arming_schedules),
+        )
+        .route(
+            "/api/cameras/:camera_id/arming_schedules",
+            post(self::post::arming_schedules),
+        )
+        .route(
+            "/api/arming_schedules/:schedule_id",
+            delete(self::delete::arming_schedules),
+        )
+        .route(
+            "/api/cameras/:camera_id/privacy_masks",
+            get(self::get::privacy_masks),
+        )
+        .route(
+            "/api/cameras/:camera_id/privacy_masks",
+            post(self::post::privacy_masks),
+        )
+        .route(
+            "/api/privacy_masks/:mask_id",
+            patch(self::patch::privacy_masks),
+        )
+        .route(
+            "/api/privacy_masks/:mask_id",
+            delete(self::delete::privacy_masks),
+        )
+        .route(
+            "/api/cameras/:camera_id/motion_zones",
+            post(self::post::motion_zones),
+        )
+        .route(
+            "/api/motion_zones/:zone_id",
+            patch(self::patch::motion_zones),
+        )
+        .route(
+            "/api/motion_zones/:zone_id",
+            delete(self::delete::motion_zones),
+        )
+        .route("/api/storage_policy", get(self::get::storage_policy))
+        .route("/api/storage_policy", patch(self::patch::storage_policy))
         .route(
             "/api/permissions/:permission_id",
             patch(self::patch::permissions),
@@ -49,28 +151,82 @@ pub fn router(app_state: Arc<AppState>) -> Router<()> {
         .with_state(app_state)
 }
 
+/// Used by handlers in more than one of the modules below
+mod helpers {
+    use tracing::error;
+
+    use crate::web::AppState;
+    use crate::{CameraPermission, PrivacyMask};
+
+    /// Whether `user_id` can view `camera_id`, e.g. its live feed, masks and schedules
+    pub async fn can_view_camera(
+        state: &AppState,
+        user_id: i64,
+        camera_id: i64,
+    ) -> sqlx::Result<bool> {
+        let permissions = CameraPermission::list_for_camera(&state.db_pool, camera_id).await?;
+
+        Ok(permissions
+            .iter()
+            .any(|p| (p.user_id == user_id) && p.can_view))
+    }
+
+    /// Whether `user_id` can control `camera_id`, which changing its motion zones requires
+    pub async fn can_control_camera(
+        state: &AppState,
+        user_id: i64,
+        camera_id: i64,
+    ) -> sqlx::Result<bool> {
+        let permissions = CameraPermission::list_for_camera(&state.db_pool, camera_id).await?;
+
+        Ok(permissions
+            .iter()
+            .any(|p| (p.user_id == user_id) && p.can_control))
+    }
+
+    /// Makes the camera hub use the saved masks of `camera_id`, `false` if they couldn't be loaded
+    pub async fn reload_privacy_masks(state: &AppState, camera_id: i64) -> bool {
+        match PrivacyMask::list_for_camera(&state.db_pool, camera_id).await {
+            Ok(masks) => {
+                state.camera_hub.set_privacy_masks(camera_id, masks);
+                true
+            }
+            Err(e) => {
+                error!("Error reloading privacy masks for camera {camera_id}: {e:?}");
+                false
+            }
+        }
+    }
+}
+
 mod get {
     use std::{net::SocketAddr, sync::Arc, time::Duration};
 
     use axum::{
-        body::Body,
-        extract::{Path, State},
+        extract::{Path, Query, State},
         response::{sse, Sse},
         Json,
     };
-    use http::header;
-    use serde::Serialize;
+    use http::HeaderMap;
+    use serde::{Deserialize, Serialize};
     use tokio_stream::{wrappers::WatchStream, StreamExt};
-    use tokio_util::io::ReaderStream;
     use tracing::error;
 
     use crate::{
         db::Camera,
-        web::{AppState, MdnsChannelMessage},
-        CameraPermission, CameraPermissionView, CameraSetting, Model, User, Video,
+        web::{
+            mjpeg,
+            snapshot::{self, SnapshotQuery},
+            video_file::{self, Disposition},
+            AppState, MdnsChannelMessage,
+        },
+        AlertSubscription, ArmingSchedule, ArmingState, CameraPermission, CameraPermissionView,
+        CameraSetting, Event, EventFilter, Model, MotionZone, PrivacyMask, Role, SmtpSettings,
+        StoragePolicy, User, Video, VideoThumbnail, Webhook, WebhookDelivery,
     };
 
-    use super::{AuthSession, IntoResponse, StatusCode};
+    use super::helpers::can_view_camera;
+    use super::{AdminUser, AuthSession, IntoResponse, StatusCode};
 
     #[derive(Serialize)]
     struct ProtectedJson {
@@ -150,749 +306,1954 @@ mod get {
         }
     }
 
-    // Code copied from: https://github.com/tokio-rs/axum/discussions/608
-    pub async fn video(
+    /// Live MJPEG stream, for anything that can't use the WebSocket (e.g. VLC, ffmpeg, other dashboards)
+    pub async fn camera_stream(
         auth_session: AuthSession,
         state: State<Arc<AppState>>,
-        Path(video_id): Path<i64>,
+        Path(camera_id): Path<i64>,
     ) -> impl IntoResponse {
         match auth_session.user {
             Some(user) => {
-                let Ok(video) = Video::get_using_id(&state.db_pool, video_id).await else {
-                    return StatusCode::INTERNAL_SERVER_ERROR.into_response();
-                };
+                match can_view_camera(&state, user.user_id, camera_id).await {
+                    Ok(true) => (),
+                    Ok(false) => return StatusCode::FORBIDDEN.into_response(),
+                    Err(_) => return StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+                }
 
-                let Some(video_camera_id) = video.camera_id else {
-                    return StatusCode::INTERNAL_SERVER_ERROR.into_response();
-                };
+                mjpeg::response(
+                    state.camera_hub.subscribe(camera_id),
+                    state.shutdown_token.clone(),
+                )
+            }
+            None => StatusCode::UNAUTHORIZED.into_response(),
+        }
+    }
 
-                let Ok(cameras) =
-                    Camera::list_accessible_to_user(&state.db_pool, user.user_id).await
-                else {
-                    return StatusCode::INTERNAL_SERVER_ERROR.into_response();
-                };
+    /// Most recent image from the camera, optionally resized with `?width=` and/or `?height=`
+    pub async fn camera_snapshot(
+        auth_session: AuthSession,
+        state: State<Arc<AppState>>,
+        Path(camera_id): Path<i64>,
+        Query(snapshot_query): Query<SnapshotQuery>,
+    ) -> impl IntoResponse {
+        match auth_session.user {
+            Some(user) => {
+                match can_view_camera(&state, user.user_id, camera_id).await {
+                    Ok(true) => (),
+                    Ok(false) => return StatusCode::FORBIDDEN.into_response(),
+                    Err(_) => return StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+                }
 
-                if !cameras.iter().any(|c| c.camera_id == video_camera_id) {
-                    return StatusCode::FORBIDDEN.into_response();
+                if !snapshot_query.is_valid() {
+                    return StatusCode::BAD_REQUEST.into_response();
                 }
 
-                let Ok(file) = tokio::fs::File::open(video.file_path.clone()).await else {
-                    return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+                // Only set while the camera is connected
+                let Some(image) = state.camera_hub.latest(camera_id) else {
+                    return StatusCode::SERVICE_UNAVAILABLE.into_response();
                 };
 
-                let Some(filename) = video.file_path.split(std::path::MAIN_SEPARATOR).next_back()
-                else {
-                    return StatusCode::INTERNAL_SERVER_ERROR.into_response();
-                };
-                let content_type = "video/mp4";
+                snapshot::response(image, snapshot_query).await
+            }
+            None => StatusCode::UNAUTHORIZED.into_response(),
+        }
+    }
+
+    #[derive(Debug, Clone, Deserialize)]
+    pub struct VideoQuery {
+        /// Play the video in the browser instead of downloading it
+        #[serde(default)]
+        pub inline: bool,
+    }
 
-                let stream = ReaderStream::new(file);
-                let body = Body::from_stream(stream);
+    pub async fn video(
+        auth_session: AuthSession,
+        state: State<Arc<AppState>>,
+        Path(video_id): Path<i64>,
+        Query(video_query): Query<VideoQuery>,
+        headers: HeaderMap,
+    ) -> impl IntoResponse {
+        match auth_session.user {
+            Some(user) => {
+                let video = match viewable_video(&state, &user, video_id).await {
+                    Ok(video) => video,
+                    Err(status_code) => return status_code.into_response(),
+                };
 
-                let headers = [
-                    (header::CONTENT_TYPE, content_type),
-                    (
-                        header::CONTENT_DISPOSITION,
-                        &format!("attachment; filename={filename:?}"),
-                    ),
-                ];
+                let disposition = if video_query.inline {
+                    Disposition::Inline
+                } else {
+                    Disposition::Attachment
+                };
 
-                (headers, body).into_response()
+                video_file::response(
+                    std::path::Path::new(&video.file_path),
+                    video.format.content_type(),
+                    &headers,
+                    disposition,
+                )
+                .await
             }
             None => StatusCode::UNAUTHORIZED.into_response(),
         }
     }
 
-    pub async fn camera_permissions(
+    /// The video if `user` can view the camera that recorded it
+    async fn viewable_video(
+        state: &AppState,
+        user: &User,
+        video_id: i64,
+    ) -> Result<Video, StatusCode> {
+        let Ok(video) = Video::get_using_id(&state.db_pool, video_id).await else {
+            return Err(StatusCode::INTERNAL_SERVER_ERROR);
+        };
+
+        let Some(video_camera_id) = video.camera_id else {
+            return Err(StatusCode::INTERNAL_SERVER_ERROR);
+        };
+
+        let Ok(cameras) = Camera::list_accessible_to_user(&state.db_pool, user.user_id).await
+        else {
+            return Err(StatusCode::INTERNAL_SERVER_ERROR);
+        };
+
+        if !cameras.iter().any(|c| c.camera_id == video_camera_id) {
+            return Err(StatusCode::FORBIDDEN);
+        }
+
+        Ok(video)
+    }
+
+    pub async fn video_poster(
         auth_session: AuthSession,
         state: State<Arc<AppState>>,
-        Path(camera_id): Path<i64>,
+        Path(video_id): Path<i64>,
+        headers: HeaderMap,
     ) -> impl IntoResponse {
         match auth_session.user {
             Some(user) => {
-                if user.username != "admin" {
-                    return StatusCode::FORBIDDEN.into_response();
-                }
+                let video = match viewable_video(&state, &user, video_id).await {
+                    Ok(video) => video,
+                    Err(status_code) => return status_code.into_response(),
+                };
 
-                let Ok(permissions) =
-                    CameraPermission::list_for_camera_with_username(&state.db_pool, camera_id)
-                        .await
-                else {
-                    return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+                // Older videos and videos that are still being recorded have no poster
+                let Some(poster_path) = video.poster_path else {
+                    return StatusCode::NOT_FOUND.into_response();
                 };
 
-                Json(permissions).into_response()
+                video_file::response(
+                    std::path::Path::new(&poster_path),
+                    "image/jpeg",
+                    &headers,
+                    Disposition::Inline,
+                )
+                .await
             }
             None => StatusCode::UNAUTHORIZED.into_response(),
         }
     }
 
-    pub async fn camera_settings(
+    pub async fn video_thumbnails(
         auth_session: AuthSession,
         state: State<Arc<AppState>>,
-        Path(camera_id): Path<i64>,
+        Path(video_id): Path<i64>,
     ) -> impl IntoResponse {
         match auth_session.user {
-            Some(_) => {
-                let Ok(settings) = CameraSetting::get_for_camera(&state.db_pool, camera_id).await
+            Some(user) => {
+                if let Err(status_code) = viewable_video(&state, &user, video_id).await {
+                    return status_code.into_response();
+                }
+
+                let Ok(thumbnails) = VideoThumbnail::list_for_video(&state.db_pool, video_id).await
                 else {
                     return StatusCode::INTERNAL_SERVER_ERROR.into_response();
                 };
 
-                Json(settings).into_response()
+                Json(thumbnails).into_response()
             }
             None => StatusCode::UNAUTHORIZED.into_response(),
         }
     }
 
-    #[derive(Serialize)]
-    struct MdnsService {
-        hostname: String,
-        socket_address: SocketAddr,
-    }
-
-    pub async fn mdns_cameras_sse(
+    pub async fn video_thumbnail(
         auth_session: AuthSession,
         state: State<Arc<AppState>>,
+        Path((video_id, thumbnail_id)): Path<(i64, i64)>,
+        headers: HeaderMap,
     ) -> impl IntoResponse {
         match auth_session.user {
             Some(user) => {
-                if user.username != "admin" {
-                    return StatusCode::FORBIDDEN.into_response();
+                if let Err(status_code) = viewable_video(&state, &user, video_id).await {
+                    return status_code.into_response();
                 }
 
-                let mdns_channel_rx = state.mdns_channel.subscribe();
-                let mdns_stream = WatchStream::from_changes(mdns_channel_rx);
-
-                let mdns_sse_stream =
-                    mdns_stream.map(|mdns_channel_message| -> Result<sse::Event, &str> {
-                        match mdns_channel_message {
-                            MdnsChannelMessage::ServiceDiscovered { mdns_response } => {
-                                let (Some(hostname_str), Some(socket_address)) =
-                                    (mdns_response.hostname(), mdns_response.socket_address())
-                                else {
-                                    return Err("");
-                                };
-
-                                sse::Event::default()
-                                    .json_data(MdnsService {
-                                        hostname: hostname_str.to_owned(),
-                                        socket_address,
-                                    })
-                                    .map_err(|_| {
-                                        error!("Failed to serialize mDNS response JSON data");
-                                        ""
-                                    })
-                            }
-                            MdnsChannelMessage::Initial => Err(""),
-                        }
-                    });
-
-                let valid_mdns_sse_stream = mdns_sse_stream
-                    .skip_while(|event_result: &Result<sse::Event, &str>| event_result.is_err());
+                let Ok(thumbnail) =
+                    VideoThumbnail::get_using_id(&state.db_pool, thumbnail_id).await
+                else {
+                    return StatusCode::NOT_FOUND.into_response();
+                };
 
-                let valid_mdns_sse_stream_until_shutdown =
-                    crate::or_until_shutdown(valid_mdns_sse_stream, state.shutdown_token.clone());
+                if thumbnail.video_id != video_id {
+                    return StatusCode::NOT_FOUND.into_response();
+                }
 
-                Sse::new(valid_mdns_sse_stream_until_shutdown)
-                    .keep_alive(
-                        sse::KeepAlive::new()
-                            .interval(Duration::from_secs(1))
-                            .text("keep-alive-text"),
-                    )
-                    .into_response()
+                video_file::response(
+                    std::path::Path::new(&thumbnail.file_path),
+                    "image/jpeg",
+                    &headers,
+                    Disposition::Inline,
+                )
+                .await
             }
             None => StatusCode::UNAUTHORIZED.into_response(),
         }
     }
 
-    pub async fn users(
+    pub async fn events(
         auth_session: AuthSession,
         state: State<Arc<AppState>>,
+        Query(event_filter): Query<EventFilter>,
     ) -> impl IntoResponse {
         match auth_session.user {
             Some(user) => {
-                if user.username != "admin" {
-                    return StatusCode::FORBIDDEN.into_response();
-                }
-
-                let Ok(users) = User::get_all(&state.db_pool).await else {
+                let Ok(events) = Event::list_for_user(
+                    &state.db_pool,
+                    user.user_id,
+                    user.role == Role::Admin,
+                    event_filter,
+                )
+                .await
+                else {
                     return StatusCode::INTERNAL_SERVER_ERROR.into_response();
                 };
 
-                Json(users).into_response()
+                Json(events).into_response()
             }
             None => StatusCode::UNAUTHORIZED.into_response(),
         }
     }
-}
 
-// TODO: Don't always return the same error
+    /// The event if `user` can view its camera, events without one are only visible to admins
+    pub async fn viewable_event(
+        state: &AppState,
+        user: &User,
+        event_id: i64,
+    ) -> Result<Event, StatusCode> {
+        let Ok(event) = Event::get_using_id(&state.db_pool, event_id).await else {
+            return Err(StatusCode::NOT_FOUND);
+        };
+
+        let can_view = match event.camera_id {
+            Some(event_camera_id) => {
+                let Ok(cameras) =
+                    Camera::list_accessible_to_user(&state.db_pool, user.user_id).await
+                else {
+                    return Err(StatusCode::INTERNAL_SERVER_ERROR);
+                };
 
-mod post {
-    use std::net::{IpAddr, SocketAddr};
-    use std::sync::Arc;
+                cameras.iter().any(|c| c.camera_id == event_camera_id)
+            }
+            None => user.role == Role::Admin,
+        };
 
-    use super::{AuthSession, IntoResponse, StatusCode};
-    use crate::web::{AppState, CameraListChange};
-    use crate::{ApiChannelMessage, User};
-    use crate::{Camera, CameraPermission, CameraSetting, Model};
-    use axum::extract::{Path, State};
-    use axum::Form;
-    use axum::Json;
-    use password_auth::generate_hash;
-    use serde::Deserialize;
-    use tokio::task;
-    use tracing::debug;
+        if !can_view {
+            return Err(StatusCode::FORBIDDEN);
+        }
 
-    #[derive(Debug, Clone, Deserialize)]
-    pub struct AddCameraForm {
-        pub name: String,
-        pub address: String,
-        #[serde(default)]
-        pub skip_mdns_connect: bool,
+        Ok(event)
     }
 
-    pub enum MdnsConnectAddress {
-        IpAddr(IpAddr),
-        SocketAddr(SocketAddr),
-    }
+    pub async fn event_snapshot(
+        auth_session: AuthSession,
+        state: State<Arc<AppState>>,
+        Path(event_id): Path<i64>,
+        headers: HeaderMap,
+    ) -> impl IntoResponse {
+        match auth_session.user {
+            Some(user) => {
+                let event = match viewable_event(&state, &user, event_id).await {
+                    Ok(event) => event,
+                    Err(status_code) => return status_code.into_response(),
+                };
 
-    impl TryFrom<String> for MdnsConnectAddress {
-        type Error = ();
+                let Some(snapshot_path) = event.snapshot_path else {
+                    return StatusCode::NOT_FOUND.into_response();
+                };
 
-        fn try_from(address: String) -> Result<Self, Self::Error> {
-            #[allow(clippy::option_if_let_else)] // This is more readable
-            if let Ok(ip_addr) = address.parse::<std::net::IpAddr>() {
-                Ok(Self::IpAddr(ip_addr))
-            } else if let Ok(socket_addr) = address.parse::<std::net::SocketAddr>() {
-                Ok(Self::SocketAddr(socket_addr))
-            } else {
-                Err(())
+                video_file::response(
+                    std::path::Path::new(&snapshot_path),
+                    "image/jpeg",
+                    &headers,
+                    Disposition::Inline,
+                )
+                .await
             }
+            None => StatusCode::UNAUTHORIZED.into_response(),
         }
     }
 
-    #[allow(clippy::too_many_lines)] // TODO: Refactor
-    pub async fn cameras(
+    pub async fn camera_permissions(
+        _: AdminUser,
+        state: State<Arc<AppState>>,
+        Path(camera_id): Path<i64>,
+    ) -> impl IntoResponse {
+        let Ok(permissions) =
+            CameraPermission::list_for_camera_with_username(&state.db_pool, camera_id).await
+        else {
+            return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+        };
+
+        Json(permissions).into_response()
+    }
+
+    #[derive(Serialize)]
+    struct CameraSettingsWithZones {
+        #[serde(flatten)]
+        settings: CameraSetting,
+        motion_zones: Vec<MotionZone>,
+    }
+
+    pub async fn camera_settings(
         auth_session: AuthSession,
         state: State<Arc<AppState>>,
-        Form(camera_form): Form<AddCameraForm>,
+        Path(camera_id): Path<i64>,
     ) -> impl IntoResponse {
         match auth_session.user {
             Some(user) => {
-                if user.username != "admin" {
-                    return StatusCode::FORBIDDEN.into_response();
+                match can_view_camera(&state, user.user_id, camera_id).await {
+                    Ok(true) => (),
+                    Ok(false) => return StatusCode::FORBIDDEN.into_response(),
+                    Err(_) => return StatusCode::INTERNAL_SERVER_ERROR.into_response(),
                 }
 
-                let Ok(mdns_connect_address) = camera_form.address.try_into() else {
-                    return StatusCode::BAD_REQUEST.into_response();
+                let Ok(settings) = CameraSetting::get_for_camera(&state.db_pool, camera_id).await
+                else {
+                    return StatusCode::INTERNAL_SERVER_ERROR.into_response();
                 };
 
-                let mdns_connect_url = match mdns_connect_address {
-                    MdnsConnectAddress::IpAddr(ip_addr) => {
-                        format!("http://{ip_addr}:80/mdns_connect")
-                    } // Try default oko camera port
-                    MdnsConnectAddress::SocketAddr(socket_addr) => {
-                        format!("http://{socket_addr}/mdns_connect")
-                    }
+                let Ok(motion_zones) = MotionZone::list_for_camera(&state.db_pool, camera_id).await
+                else {
+                    return StatusCode::INTERNAL_SERVER_ERROR.into_response();
                 };
 
-                if !camera_form.skip_mdns_connect && state.oko_private_socket_addr.is_some() {
-                    let Some(oko_private_socket_addr) = state.oko_private_socket_addr else {
-                        return StatusCode::INTERNAL_SERVER_ERROR.into_response();
-                    };
+                Json(CameraSettingsWithZones {
+                    settings,
+                    motion_zones,
+                })
+                .into_response()
+            }
+            None => StatusCode::UNAUTHORIZED.into_response(),
+        }
+    }
+
+    #[derive(Serialize)]
+    struct MdnsService {
+        hostname: String,
+        socket_address: SocketAddr,
+    }
+
+    pub async fn mdns_cameras_sse(_: AdminUser, state: State<Arc<AppState>>) -> impl IntoResponse {
+        let mdns_channel_rx = state.mdns_channel.subscribe();
+        let mdns_stream = WatchStream::from_changes(mdns_channel_rx);
 
-                    let Ok(resp) = reqwest::Client::new()
-                        .post(mdns_connect_url)
-                        .form(&[("oko", oko_private_socket_addr.to_string())])
-                        .send()
-                        .await
+        let mdns_sse_stream = mdns_stream.map(|mdns_channel_message| -> Result<sse::Event, &str> {
+            match mdns_channel_message {
+                MdnsChannelMessage::ServiceDiscovered { mdns_response } => {
+                    let (Some(hostname_str), Some(socket_address)) =
+                        (mdns_response.hostname(), mdns_response.socket_address())
                     else {
-                        return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+                        return Err("");
                     };
 
-                    if resp.status() != StatusCode::OK {
-                        return StatusCode::INTERNAL_SERVER_ERROR.into_response();
-                    }
-                } else {
-                    debug!(
-                        "Skipping mDNS connect, Skip?: {}, Socket Address is Some?: {}",
-                        camera_form.skip_mdns_connect,
-                        state.oko_private_socket_addr.is_some()
-                    );
+                    sse::Event::default()
+                        .json_data(MdnsService {
+                            hostname: hostname_str.to_owned(),
+                            socket_address,
+                        })
+                        .map_err(|_| {
+                            error!("Failed to serialize mDNS response JSON data");
+                            ""
+                        })
                 }
+                MdnsChannelMessage::Initial => Err(""),
+            }
+        });
 
-                let internal_mdns_connect_address = match mdns_connect_address {
-                    MdnsConnectAddress::IpAddr(ip_addr) => {
-                        // If port is not specified, accept any port
-                        ip_addr.to_string() + ":*"
-                    }
-                    MdnsConnectAddress::SocketAddr(socket_addr) => socket_addr.to_string(),
-                };
+        let valid_mdns_sse_stream = mdns_sse_stream
+            .skip_while(|event_result: &Result<sse::Event, &str>| event_result.is_err());
 
-                let mut camera = Camera {
-                    camera_id: Camera::DEFAULT.camera_id,
-                    name: camera_form.name,
-                    ip_address: Some(internal_mdns_connect_address),
-                    last_connected: Camera::DEFAULT.last_connected,
-                    is_active: Camera::DEFAULT.is_active,
-                };
+        let valid_mdns_sse_stream_until_shutdown =
+            crate::or_until_shutdown(valid_mdns_sse_stream, state.shutdown_token.clone());
 
-                if (camera.create_using_self(&state.db_pool).await).is_err() {
-                    return StatusCode::INTERNAL_SERVER_ERROR.into_response();
-                }
+        Sse::new(valid_mdns_sse_stream_until_shutdown)
+            .keep_alive(
+                sse::KeepAlive::new()
+                    .interval(Duration::from_secs(1))
+                    .text("keep-alive-text"),
+            )
+            .into_response()
+    }
 
-                let mut camera_setting = CameraSetting {
-                    setting_id: CameraSetting::DEFAULT.setting_id,
-                    camera_id: camera.camera_id,
-                    flashlight_enabled: CameraSetting::DEFAULT.flashlight_enabled,
-                    resolution: "SVGA".to_string(),
-                    framerate: 5,
-                    last_modified: CameraSetting::DEFAULT.last_modified(),
-                    modified_by: Some(user.user_id),
-                };
+    pub async fn users(_: AdminUser, state: State<Arc<AppState>>) -> impl IntoResponse {
+        let Ok(users) = User::get_all(&state.db_pool).await else {
+            return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+        };
 
-                if (camera_setting.create_using_self(&state.db_pool).await).is_err() {
-                    return StatusCode::INTERNAL_SERVER_ERROR.into_response();
-                }
+        Json(users).into_response()
+    }
 
-                let mut admin_camera_permission = CameraPermission {
-                    permission_id: CameraPermission::DEFAULT.permission_id,
-                    camera_id: camera.camera_id,
-                    user_id: user.user_id,
-                    can_view: true,
-                    can_control: true,
-                };
+    pub async fn webhooks(_: AdminUser, state: State<Arc<AppState>>) -> impl IntoResponse {
+        let Ok(webhooks) = Webhook::list(&state.db_pool).await else {
+            return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+        };
 
-                if (admin_camera_permission
-                    .create_using_self(&state.db_pool)
-                    .await)
-                    .is_err()
-                {
-                    return StatusCode::INTERNAL_SERVER_ERROR.into_response();
-                }
+        Json(webhooks).into_response()
+    }
 
-                let Ok(all_users) = User::get_all(&state.db_pool).await else {
-                    return StatusCode::INTERNAL_SERVER_ERROR.into_response();
-                };
+    pub async fn webhook_deliveries(
+        _: AdminUser,
+        state: State<Arc<AppState>>,
+        Path(webhook_id): Path<i64>,
+    ) -> impl IntoResponse {
+        let Ok(deliveries) = WebhookDelivery::list_for_webhook(&state.db_pool, webhook_id).await
+        else {
+            return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+        };
 
-                // TODO: Add test for this
-                for user_from_list in all_users {
-                    if user_from_list.user_id == admin_camera_permission.user_id {
-                        continue;
-                    }
+        Json(deliveries).into_response()
+    }
 
-                    let mut camera_permission = CameraPermission {
-                        permission_id: CameraPermission::DEFAULT.permission_id,
-                        camera_id: camera.camera_id,
-                        user_id: user_from_list.user_id,
-                        can_view: false,
-                        can_control: false,
-                    };
+    pub async fn smtp(_: AdminUser, state: State<Arc<AppState>>) -> impl IntoResponse {
+        let Ok(settings) = SmtpSettings::get(&state.db_pool).await else {
+            return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+        };
 
-                    if (camera_permission.create_using_self(&state.db_pool).await).is_err() {
-                        return StatusCode::INTERNAL_SERVER_ERROR.into_response();
-                    }
-                }
+        Json(settings).into_response()
+    }
 
-                if state
-                    .api_channel
-                    .send(ApiChannelMessage::CameraListChanged(
-                        CameraListChange::Added {
-                            camera_id: camera.camera_id,
-                        },
-                    ))
-                    .is_err()
-                {
+    pub async fn alert_subscriptions(
+        auth_session: AuthSession,
+        state: State<Arc<AppState>>,
+    ) -> impl IntoResponse {
+        match auth_session.user {
+            Some(user) => {
+                let Ok(subscriptions) =
+                    AlertSubscription::list_for_user(&state.db_pool, user.user_id).await
+                else {
                     return StatusCode::INTERNAL_SERVER_ERROR.into_response();
-                }
+                };
 
-                Json(camera).into_response()
+                Json(subscriptions).into_response()
             }
             None => StatusCode::UNAUTHORIZED.into_response(),
         }
     }
 
-    pub async fn camera_restart(
+    pub async fn arming(
+        auth_session: AuthSession,
+        state: State<Arc<AppState>>,
+    ) -> impl IntoResponse {
+        match auth_session.user {
+            Some(_) => {
+                let Ok(arming_state) = ArmingState::get(&state.db_pool).await else {
+                    return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+                };
+
+                Json(arming_state).into_response()
+            }
+            None => StatusCode::UNAUTHORIZED.into_response(),
+        }
+    }
+
+    pub async fn arming_schedules(
         auth_session: AuthSession,
-        Path(camera_id): Path<i64>,
         state: State<Arc<AppState>>,
+        Path(camera_id): Path<i64>,
     ) -> impl IntoResponse {
         match auth_session.user {
             Some(user) => {
-                if user.username != "admin" {
-                    return StatusCode::FORBIDDEN.into_response();
+                match can_view_camera(&state, user.user_id, camera_id).await {
+                    Ok(true) => (),
+                    Ok(false) => return StatusCode::FORBIDDEN.into_response(),
+                    Err(_) => return StatusCode::INTERNAL_SERVER_ERROR.into_response(),
                 }
 
-                let api_message = ApiChannelMessage::CameraAction {
-                    camera_id,
-                    message: crate::web::CameraMessage::Restart,
+                let Ok(schedules) =
+                    ArmingSchedule::list_for_camera(&state.db_pool, camera_id).await
+                else {
+                    return StatusCode::INTERNAL_SERVER_ERROR.into_response();
                 };
 
-                if state.api_channel.send(api_message).is_err() {
-                    return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+                Json(schedules).into_response()
+            }
+            None => StatusCode::UNAUTHORIZED.into_response(),
+        }
+    }
+
+    pub async fn privacy_masks(
+        auth_session: AuthSession,
+        state: State<Arc<AppState>>,
+        Path(camera_id): Path<i64>,
+    ) -> impl IntoResponse {
+        match auth_session.user {
+            Some(user) => {
+                match can_view_camera(&state, user.user_id, camera_id).await {
+                    Ok(true) => (),
+                    Ok(false) => return StatusCode::FORBIDDEN.into_response(),
+                    Err(_) => return StatusCode::INTERNAL_SERVER_ERROR.into_response(),
                 }
 
-                StatusCode::OK.into_response()
+                let Ok(masks) = PrivacyMask::list_for_camera(&state.db_pool, camera_id).await
+                else {
+                    return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+                };
+
+                Json(masks).into_response()
             }
             None => StatusCode::UNAUTHORIZED.into_response(),
         }
     }
 
+    pub async fn storage_policy(_: AdminUser, state: State<Arc<AppState>>) -> impl IntoResponse {
+        let Ok(policy) = StoragePolicy::get(&state.db_pool).await else {
+            return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+        };
+
+        Json(policy).into_response()
+    }
+}
+
+// TODO: Don't always return the same error
+
+mod post {
+    use std::net::{IpAddr, SocketAddr};
+    use std::sync::Arc;
+
+    use super::helpers::{can_control_camera, reload_privacy_masks};
+    use super::{AdminUser, AuthSession, IntoResponse, OperatorUser, RequireRole, StatusCode};
+    use crate::web::{AppState, CameraListChange};
+    use crate::{
+        AlertSubscription, ApiChannelMessage, ArmingMode, ArmingSchedule, EventKind, MotionZone,
+        MotionZoneKind, PolygonPoint, PrivacyMask, Role, User,
+    };
+    use crate::{Camera, CameraPermission, CameraSetting, Model, Resolution, Webhook};
+    use axum::extract::{Path, State};
+    use axum::Form;
+    use axum::Json;
+    use password_auth::generate_hash;
+    use serde::{Deserialize, Serialize};
+    use tokio::task;
+    use tracing::{debug, error};
+
+    #[derive(Debug, Clone, Deserialize)]
+    pub struct AddCameraForm {
+        pub name: String,
+        pub address: String,
+        #[serde(default)]
+        pub skip_mdns_connect: bool,
+    }
+
+    /// The token is only ever returned here, e.g. for cameras added with `skip_mdns_connect`
+    #[derive(Debug, Clone, Serialize)]
+    pub struct AddedCamera {
+        #[serde(flatten)]
+        pub camera: Camera,
+        pub pairing_token: String,
+    }
+
+    pub enum MdnsConnectAddress {
+        IpAddr(IpAddr),
+        SocketAddr(SocketAddr),
+    }
+
+    impl TryFrom<String> for MdnsConnectAddress {
+        type Error = ();
+
+        fn try_from(address: String) -> Result<Self, Self::Error> {
+            #[allow(clippy::option_if_let_else)] // This is more readable
+            if let Ok(ip_addr) = address.parse::<std::net::IpAddr>() {
+                Ok(Self::IpAddr(ip_addr))
+            } else if let Ok(socket_addr) = address.parse::<std::net::SocketAddr>() {
+                Ok(Self::SocketAddr(socket_addr))
+            } else {
+                Err(())
+            }
+        }
+    }
+
+    #[allow(clippy::too_many_lines)] // TODO: Refactor
+    pub async fn cameras(
+        RequireRole { user, .. }: AdminUser,
+        state: State<Arc<AppState>>,
+        Form(camera_form): Form<AddCameraForm>,
+    ) -> impl IntoResponse {
+        let Ok(mdns_connect_address) = camera_form.address.try_into() else {
+            return StatusCode::BAD_REQUEST.into_response();
+        };
+
+        let mdns_connect_url = match mdns_connect_address {
+            MdnsConnectAddress::IpAddr(ip_addr) => {
+                format!("http://{ip_addr}:80/mdns_connect")
+            } // Try default oko camera port
+            MdnsConnectAddress::SocketAddr(socket_addr) => {
+                format!("http://{socket_addr}/mdns_connect")
+            }
+        };
+
+        let pairing_token = Camera::generate_pairing_token();
+
+        if !camera_form.skip_mdns_connect && state.oko_private_socket_addr.is_some() {
+            let Some(oko_private_socket_addr) = state.oko_private_socket_addr else {
+                return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+            };
+
+            let Ok(resp) = reqwest::Client::new()
+                .post(mdns_connect_url)
+                .form(&[
+                    ("oko", oko_private_socket_addr.to_string()),
+                    ("token", pairing_token.clone()),
+                ])
+                .send()
+                .await
+            else {
+                return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+            };
+
+            if resp.status() != StatusCode::OK {
+                return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+            }
+        } else {
+            debug!(
+                "Skipping mDNS connect, Skip?: {}, Socket Address is Some?: {}",
+                camera_form.skip_mdns_connect,
+                state.oko_private_socket_addr.is_some()
+            );
+        }
+
+        let internal_mdns_connect_address = match mdns_connect_address {
+            MdnsConnectAddress::IpAddr(ip_addr) => {
+                // If port is not specified, accept any port
+                ip_addr.to_string() + ":*"
+            }
+            MdnsConnectAddress::SocketAddr(socket_addr) => socket_addr.to_string(),
+        };
+
+        let mut camera = Camera {
+            camera_id: Camera::DEFAULT.camera_id,
+            name: camera_form.name,
+            ip_address: Some(internal_mdns_connect_address),
+            last_connected: Camera::DEFAULT.last_connected,
+            is_active: Camera::DEFAULT.is_active,
+            is_online: Camera::DEFAULT.is_online,
+            token_hash: Some(Camera::hash_pairing_token(&pairing_token)),
+        };
+
+        if (camera.create_using_self(&state.db_pool).await).is_err() {
+            return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+        }
+
+        let mut camera_setting = CameraSetting {
+            setting_id: CameraSetting::DEFAULT.setting_id,
+            camera_id: camera.camera_id,
+            flashlight_enabled: CameraSetting::DEFAULT.flashlight_enabled,
+            resolution: Resolution::Svga,
+            framerate: 5,
+            recording_mode: CameraSetting::DEFAULT.recording_mode,
+            motion_sensitivity: CameraSetting::DEFAULT.motion_sensitivity,
+            motion_pre_roll_seconds: CameraSetting::DEFAULT.motion_pre_roll_seconds,
+            motion_post_roll_seconds: CameraSetting::DEFAULT.motion_post_roll_seconds,
+            segment_length_seconds: CameraSetting::DEFAULT.segment_length_seconds,
+            max_video_age_days: CameraSetting::DEFAULT.max_video_age_days,
+            recording_format: CameraSetting::DEFAULT.recording_format,
+            last_modified: CameraSetting::DEFAULT.last_modified(),
+            modified_by: Some(user.user_id),
+        };
+
+        if (camera_setting.create_using_self(&state.db_pool).await).is_err() {
+            return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+        }
+
+        let mut admin_camera_permission = CameraPermission {
+            permission_id: CameraPermission::DEFAULT.permission_id,
+            camera_id: camera.camera_id,
+            user_id: user.user_id,
+            can_view: true,
+            can_control: true,
+        };
+
+        if (admin_camera_permission
+            .create_using_self(&state.db_pool)
+            .await)
+            .is_err()
+        {
+            return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+        }
+
+        let Ok(all_users) = User::get_all(&state.db_pool).await else {
+            return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+        };
+
+        // TODO: Add test for this
+        for user_from_list in all_users {
+            if user_from_list.user_id == admin_camera_permission.user_id {
+                continue;
+            }
+
+            let is_admin = user_from_list.role == Role::Admin;
+
+            let mut camera_permission = CameraPermission {
+                permission_id: CameraPermission::DEFAULT.permission_id,
+                camera_id: camera.camera_id,
+                user_id: user_from_list.user_id,
+                can_view: is_admin,
+                can_control: is_admin,
+            };
+
+            if (camera_permission.create_using_self(&state.db_pool).await).is_err() {
+                return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+            }
+        }
+
+        if state
+            .api_channel
+            .send(ApiChannelMessage::CameraListChanged(
+                CameraListChange::Added {
+                    camera_id: camera.camera_id,
+                },
+            ))
+            .is_err()
+        {
+            return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+        }
+
+        Json(AddedCamera {
+            camera,
+            pairing_token,
+        })
+        .into_response()
+    }
+
+    pub async fn camera_restart(
+        RequireRole { user, .. }: OperatorUser,
+        Path(camera_id): Path<i64>,
+        state: State<Arc<AppState>>,
+    ) -> impl IntoResponse {
+        match can_control_camera(&state, user.user_id, camera_id).await {
+            Ok(true) => (),
+            Ok(false) => return StatusCode::FORBIDDEN.into_response(),
+            Err(_) => return StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+        }
+
+        let api_message = ApiChannelMessage::CameraAction {
+            camera_id,
+            message: crate::web::CameraMessage::Restart,
+        };
+
+        if state.api_channel.send(api_message).is_err() {
+            return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+        }
+
+        StatusCode::OK.into_response()
+    }
+
+    /// Like `AddedCamera`, the token is only ever returned here
+    #[derive(Debug, Clone, Serialize)]
+    pub struct NewPairingToken {
+        pub pairing_token: String,
+    }
+
+    /// Replaces the pairing token of a camera, e.g. one added before pairing tokens existed.
+    ///
+    /// A connected camera isn't disconnected, the new token is only checked once it reconnects.
+    pub async fn camera_pairing_token(
+        _: AdminUser,
+        Path(camera_id): Path<i64>,
+        state: State<Arc<AppState>>,
+    ) -> impl IntoResponse {
+        let Ok(mut camera) = Camera::get_using_id(&state.db_pool, camera_id).await else {
+            return StatusCode::NOT_FOUND.into_response();
+        };
+
+        let pairing_token = Camera::generate_pairing_token();
+        camera.token_hash = Some(Camera::hash_pairing_token(&pairing_token));
+
+        if let Err(e) = camera.update_using_self(&state.db_pool).await {
+            error!("Error updating pairing token of camera {camera_id}: {e:?}");
+            return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+        }
+
+        Json(NewPairingToken { pairing_token }).into_response()
+    }
+
     #[derive(Debug, Clone, Deserialize)]
     pub struct UserForm {
         pub username: String,
         pub password: String,
+        #[serde(default)]
+        pub role: Option<Role>,
     }
 
     pub async fn users(
-        auth_session: AuthSession,
+        RequireRole { user, .. }: AdminUser,
         state: State<Arc<AppState>>,
         Form(user_form): Form<UserForm>,
+    ) -> impl IntoResponse {
+        if !user_form.username.is_ascii()
+            || !user_form
+                .username
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric())
+        {
+            return StatusCode::BAD_REQUEST.into_response();
+        }
+
+        let Err(sqlx::Error::RowNotFound) =
+            User::get_using_username(&state.db_pool, &user_form.username).await
+        else {
+            return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+        };
+
+        let user_form = if user_form.username == "guest" {
+            UserForm {
+                username: "guest".to_string(),
+                password: "hunter42".to_string(), // TODO: move this
+                role: Some(Role::Viewer),
+            }
+        } else {
+            user_form
+        };
+
+        if user_form.password.len() > 254
+            || user_form.password.is_empty()
+            || user_form.password.contains(char::is_whitespace)
+        {
+            return StatusCode::BAD_REQUEST.into_response();
+        }
+
+        let Ok(password_hash) = task::spawn_blocking(|| generate_hash(user_form.password)).await
+        else {
+            return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+        };
+
+        let mut new_user = User {
+            user_id: User::DEFAULT.user_id,
+            username: user_form.username,
+            password_hash,
+            created_at: User::DEFAULT.created_at(),
+            role: user_form.role.unwrap_or(User::DEFAULT.role),
+        };
+
+        if (new_user.create_using_self(&state.db_pool).await).is_err() {
+            return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+        }
+
+        let Ok(all_cameras) = Camera::list_accessible_to_user(&state.db_pool, user.user_id).await
+        else {
+            return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+        };
+
+        // Admins can see and control every camera by default
+        let is_admin = new_user.role == Role::Admin;
+
+        for camera in all_cameras {
+            let mut camera_permission = CameraPermission {
+                permission_id: CameraPermission::DEFAULT.permission_id,
+                camera_id: camera.camera_id,
+                user_id: new_user.user_id,
+                can_view: is_admin,
+                can_control: is_admin,
+            };
+
+            if (camera_permission.create_using_self(&state.db_pool).await).is_err() {
+                return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+            }
+        }
+
+        Json(new_user).into_response()
+    }
+
+    #[derive(Debug, Clone, Deserialize)]
+    pub struct AddWebhookForm {
+        pub url: String,
+    }
+
+    /// The secret is only ever returned here, receivers need it to verify signatures
+    #[derive(Debug, Clone, Serialize)]
+    pub struct AddedWebhook {
+        #[serde(flatten)]
+        pub webhook: Webhook,
+        pub secret: String,
+    }
+
+    pub async fn webhooks(
+        _: AdminUser,
+        state: State<Arc<AppState>>,
+        Form(webhook_form): Form<AddWebhookForm>,
+    ) -> impl IntoResponse {
+        if !crate::webhooks::is_valid_url(&webhook_form.url) {
+            return StatusCode::BAD_REQUEST.into_response();
+        }
+
+        let mut webhook = Webhook {
+            webhook_id: Webhook::DEFAULT.webhook_id,
+            url: webhook_form.url,
+            secret: Webhook::generate_secret(),
+            enabled: Webhook::DEFAULT.enabled,
+            created_at: Webhook::DEFAULT.created_at(),
+        };
+
+        if (webhook.create_using_self(&state.db_pool).await).is_err() {
+            return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+        }
+
+        let secret = webhook.secret.clone();
+
+        Json(AddedWebhook { webhook, secret }).into_response()
+    }
+
+    /// Sends a test payload right away, without retrying, and returns the delivery
+    pub async fn webhook_test(
+        _: AdminUser,
+        state: State<Arc<AppState>>,
+        Path(webhook_id): Path<i64>,
+    ) -> impl IntoResponse {
+        let Ok(webhook) = Webhook::get_using_id(&state.db_pool, webhook_id).await else {
+            return StatusCode::NOT_FOUND.into_response();
+        };
+
+        match crate::webhooks::send_test(&state.db_pool, &webhook).await {
+            Ok(delivery) => Json(delivery).into_response(),
+            Err(e) => {
+                error!("Error sending test to webhook {webhook_id}: {e:?}");
+                StatusCode::INTERNAL_SERVER_ERROR.into_response()
+            }
+        }
+    }
+
+    #[derive(Debug, Clone, Deserialize)]
+    pub struct AddAlertSubscriptionForm {
+        /// Leaving this out subscribes to every camera the user can view
+        #[serde(default)]
+        pub camera_id: Option<i64>,
+        pub kind: EventKind,
+        pub email: String,
+    }
+
+    pub async fn alert_subscriptions(
+        auth_session: AuthSession,
+        state: State<Arc<AppState>>,
+        Form(subscription_form): Form<AddAlertSubscriptionForm>,
     ) -> impl IntoResponse {
         match auth_session.user {
             Some(user) => {
-                if user.username != "admin" {
-                    return StatusCode::FORBIDDEN.into_response();
-                }
-
-                if !user_form.username.is_ascii()
-                    || !user_form
-                        .username
-                        .chars()
-                        .all(|c| c.is_ascii_alphanumeric())
+                if !AlertSubscription::is_supported(subscription_form.kind)
+                    || !crate::alerts::is_valid_email(&subscription_form.email)
                 {
                     return StatusCode::BAD_REQUEST.into_response();
                 }
 
-                let Err(sqlx::Error::RowNotFound) =
-                    User::get_using_username(&state.db_pool, &user_form.username).await
+                if let Some(camera_id) = subscription_form.camera_id {
+                    let Ok(cameras) =
+                        Camera::list_accessible_to_user(&state.db_pool, user.user_id).await
+                    else {
+                        return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+                    };
+
+                    if !cameras.iter().any(|c| c.camera_id == camera_id) {
+                        return StatusCode::FORBIDDEN.into_response();
+                    }
+                }
+
+                let mut subscription = AlertSubscription {
+                    subscription_id: AlertSubscription::DEFAULT.subscription_id,
+                    user_id: user.user_id,
+                    camera_id: subscription_form.camera_id,
+                    kind: subscription_form.kind,
+                    email: subscription_form.email,
+                    created_at: AlertSubscription::DEFAULT.created_at(),
+                };
+
+                if (subscription.create_using_self(&state.db_pool).await).is_err() {
+                    return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+                }
+
+                Json(subscription).into_response()
+            }
+            None => StatusCode::UNAUTHORIZED.into_response(),
+        }
+    }
+
+    #[derive(Debug, Clone, Deserialize)]
+    pub struct AddArmingScheduleForm {
+        pub mode: ArmingMode,
+        /// 0 is Monday, of the UTC date
+        pub day_of_week: i64,
+        /// Minutes since midnight UTC, not the server's or the user's time zone
+        pub start_minute: i64,
+        /// Minutes since midnight UTC, exclusive
+        pub end_minute: i64,
+    }
+
+    pub async fn arming_schedules(
+        RequireRole { user, .. }: OperatorUser,
+        state: State<Arc<AppState>>,
+        Path(camera_id): Path<i64>,
+        Form(schedule_form): Form<AddArmingScheduleForm>,
+    ) -> impl IntoResponse {
+        let mut schedule = ArmingSchedule {
+            schedule_id: ArmingSchedule::DEFAULT.schedule_id,
+            camera_id,
+            mode: schedule_form.mode,
+            day_of_week: schedule_form.day_of_week,
+            start_minute: schedule_form.start_minute,
+            end_minute: schedule_form.end_minute,
+            created_at: ArmingSchedule::DEFAULT.created_at(),
+        };
+
+        if !schedule.is_valid() {
+            return StatusCode::BAD_REQUEST.into_response();
+        }
+
+        match Camera::get_using_id(&state.db_pool, camera_id).await {
+            Ok(_) => (),
+            Err(sqlx::Error::RowNotFound) => return StatusCode::NOT_FOUND.into_response(),
+            Err(_) => return StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+        }
+
+        match can_control_camera(&state, user.user_id, camera_id).await {
+            Ok(true) => (),
+            Ok(false) => return StatusCode::FORBIDDEN.into_response(),
+            Err(_) => return StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+        }
+
+        if (schedule.create_using_self(&state.db_pool).await).is_err() {
+            return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+        }
+
+        Json(schedule).into_response()
+    }
+
+    /// Sent as JSON since form data can't hold the points
+    #[derive(Debug, Clone, Deserialize)]
+    pub struct AddPrivacyMaskForm {
+        pub name: String,
+        pub points: Vec<PolygonPoint>,
+    }
+
+    pub async fn privacy_masks(
+        _: AdminUser,
+        state: State<Arc<AppState>>,
+        Path(camera_id): Path<i64>,
+        Json(mask_form): Json<AddPrivacyMaskForm>,
+    ) -> impl IntoResponse {
+        let mut mask = PrivacyMask {
+            mask_id: PrivacyMask::DEFAULT.mask_id,
+            camera_id,
+            name: mask_form.name,
+            points: sqlx::types::Json(mask_form.points),
+            created_at: PrivacyMask::DEFAULT.created_at(),
+        };
+
+        if !mask.is_valid() {
+            return StatusCode::BAD_REQUEST.into_response();
+        }
+
+        if Camera::get_using_id(&state.db_pool, camera_id)
+            .await
+            .is_err()
+        {
+            return StatusCode::NOT_FOUND.into_response();
+        }
+
+        if (mask.create_using_self(&state.db_pool).await).is_err() {
+            return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+        }
+
+        if !reload_privacy_masks(&state, camera_id).await {
+            return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+        }
+
+        Json(mask).into_response()
+    }
+
+    /// Sent as JSON since form data can't hold the points
+    #[derive(Debug, Clone, Deserialize)]
+    pub struct AddMotionZoneForm {
+        pub name: String,
+        pub kind: MotionZoneKind,
+        pub points: Vec<PolygonPoint>,
+        #[serde(default)]
+        pub sensitivity: Option<i64>,
+    }
+
+    pub async fn motion_zones(
+        RequireRole { user, .. }: OperatorUser,
+        state: State<Arc<AppState>>,
+        Path(camera_id): Path<i64>,
+        Json(zone_form): Json<AddMotionZoneForm>,
+    ) -> impl IntoResponse {
+        match can_control_camera(&state, user.user_id, camera_id).await {
+            Ok(true) => (),
+            Ok(false) => return StatusCode::FORBIDDEN.into_response(),
+            Err(_) => return StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+        }
+
+        let mut zone = MotionZone {
+            zone_id: MotionZone::DEFAULT.zone_id,
+            camera_id,
+            name: zone_form.name,
+            kind: zone_form.kind,
+            points: sqlx::types::Json(zone_form.points),
+            sensitivity: zone_form.sensitivity,
+            created_at: MotionZone::DEFAULT.created_at(),
+        };
+
+        if !zone.is_valid() {
+            return StatusCode::BAD_REQUEST.into_response();
+        }
+
+        if (zone.create_using_self(&state.db_pool).await).is_err() {
+            return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+        }
+
+        // The camera might not be connected
+        let _ = state
+            .api_channel
+            .send(ApiChannelMessage::MotionZonesChanged { camera_id });
+
+        Json(zone).into_response()
+    }
+}
+
+// TODO: Don't always return the same error
+
+mod patch {
+    use std::sync::Arc;
+
+    use super::{
+        get::viewable_event,
+        helpers::{can_control_camera, reload_privacy_masks},
+        post::UserForm,
+        AdminUser, AuthSession, IntoResponse, OperatorUser, RequireRole, StatusCode,
+    };
+    use crate::{
+        web::{AppState, CameraListChange, CameraMessage},
+        ApiChannelMessage, ArmingMode, ArmingState, CameraPermission, CameraSetting,
+        CameraSettingNoMeta, Model, MotionZone, MotionZoneKind, PolygonPoint, PrivacyMask,
+        RecordingMode, Resolution, Role, SmtpSecurity, SmtpSettings, StoragePolicy, User, Video,
+        VideoFormat, Webhook,
+    };
+    use axum::{
+        extract::{Path, State},
+        Form, Json,
+    };
+    use password_auth::generate_hash;
+    use serde::Deserialize;
+    use tracing::warn;
+
+    #[derive(Debug, Clone, Deserialize)]
+    pub struct UpdatePermissionForm {
+        pub can_view: bool,
+        pub can_control: bool,
+    }
+
+    pub async fn permissions(
+        _: AdminUser,
+        state: State<Arc<AppState>>,
+        Path(permission_id): Path<i64>,
+        Form(permission_form): Form<UpdatePermissionForm>,
+    ) -> impl IntoResponse {
+        let Ok(mut permission) =
+            CameraPermission::get_using_id(&state.db_pool, permission_id).await
+        else {
+            return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+        };
+
+        permission.can_view = permission_form.can_view;
+        permission.can_control = permission_form.can_control;
+
+        if (permission.update_using_self(&state.db_pool).await).is_err() {
+            return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+        }
+
+        if state
+            .api_channel
+            .send(ApiChannelMessage::CameraListChanged(
+                CameraListChange::Updated {
+                    camera_id: permission.camera_id,
+                },
+            ))
+            .is_err()
+        {
+            return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+        }
+
+        Json(permission).into_response()
+    }
+
+    #[derive(Debug, Clone, Deserialize)]
+    pub struct UpdateSettingsForm {
+        pub flashlight_enabled: bool,
+        pub resolution: Resolution,
+        pub framerate: i64,
+        #[serde(default)]
+        pub recording_mode: Option<RecordingMode>,
+        #[serde(default)]
+        pub motion_sensitivity: Option<i64>,
+        #[serde(default)]
+        pub motion_pre_roll_seconds: Option<i64>,
+        #[serde(default)]
+        pub motion_post_roll_seconds: Option<i64>,
+        #[serde(default)]
+        pub segment_length_seconds: Option<i64>,
+        #[serde(default)]
+        pub recording_format: Option<VideoFormat>,
+        /// 0 means videos are never deleted because of their age
+        #[serde(default)]
+        pub max_video_age_days: Option<i64>,
+    }
+
+    pub async fn camera_settings(
+        auth_session: AuthSession,
+        state: State<Arc<AppState>>,
+        Path(setting_id): Path<i64>,
+        Form(settings_form): Form<UpdateSettingsForm>,
+    ) -> impl IntoResponse {
+        match auth_session.user {
+            Some(user) => {
+                let Ok(mut setting) = CameraSetting::get_using_id(&state.db_pool, setting_id).await
                 else {
                     return StatusCode::INTERNAL_SERVER_ERROR.into_response();
                 };
 
-                let user_form = if user_form.username == "guest" {
-                    UserForm {
-                        username: "guest".to_string(),
-                        password: "hunter42".to_string(), // TODO: move this
-                    }
-                } else {
-                    user_form
+                let Ok(permissions) =
+                    CameraPermission::list_for_camera(&state.db_pool, setting.camera_id).await
+                else {
+                    return StatusCode::INTERNAL_SERVER_ERROR.into_response();
                 };
 
-                if user_form.password.len() > 254
-                    || user_form.password.is_empty()
-                    || user_form.password.contains(char::is_whitespace)
+                if !permissions
+                    .iter()
+                    .any(|p| (p.user_id == user.user_id) && p.can_control)
                 {
-                    return StatusCode::BAD_REQUEST.into_response();
+                    return StatusCode::FORBIDDEN.into_response();
                 }
 
-                let Ok(password_hash) =
-                    task::spawn_blocking(|| generate_hash(user_form.password)).await
+                // TODO: resolution
+                setting.flashlight_enabled = settings_form.flashlight_enabled;
+
+                // ? Maybe allow any framerate/resolution for operators but give warning
+                if user.role >= Role::Operator {
+                    if (settings_form.framerate < 1) || (settings_form.framerate > 60) {
+                        return StatusCode::BAD_REQUEST.into_response();
+                    }
+
+                    setting.resolution = settings_form.resolution;
+                    setting.framerate = settings_form.framerate;
+
+                    if let Some(recording_mode) = settings_form.recording_mode {
+                        setting.recording_mode = recording_mode;
+                    }
+
+                    if let Some(motion_sensitivity) = settings_form.motion_sensitivity {
+                        if !(1..=100).contains(&motion_sensitivity) {
+                            return StatusCode::BAD_REQUEST.into_response();
+                        }
+
+                        setting.motion_sensitivity = motion_sensitivity;
+                    }
+
+                    if let Some(motion_pre_roll_seconds) = settings_form.motion_pre_roll_seconds {
+                        if !(0..=60).contains(&motion_pre_roll_seconds) {
+                            return StatusCode::BAD_REQUEST.into_response();
+                        }
+
+                        setting.motion_pre_roll_seconds = motion_pre_roll_seconds;
+                    }
+
+                    if let Some(motion_post_roll_seconds) = settings_form.motion_post_roll_seconds {
+                        if !(0..=300).contains(&motion_post_roll_seconds) {
+                            return StatusCode::BAD_REQUEST.into_response();
+                        }
+
+                        setting.motion_post_roll_seconds = motion_post_roll_seconds;
+                    }
+
+                    if let Some(segment_length_seconds) = settings_form.segment_length_seconds {
+                        if !(10..=3600).contains(&segment_length_seconds) {
+                            return StatusCode::BAD_REQUEST.into_response();
+                        }
+
+                        setting.segment_length_seconds = segment_length_seconds;
+                    }
+
+                    if let Some(recording_format) = settings_form.recording_format {
+                        setting.recording_format = recording_format;
+                    }
+                }
+
+                if user.role >= Role::Admin {
+                    if let Some(max_video_age_days) = settings_form.max_video_age_days {
+                        if max_video_age_days < 0 {
+                            return StatusCode::BAD_REQUEST.into_response();
+                        }
+
+                        setting.max_video_age_days =
+                            (max_video_age_days > 0).then_some(max_video_age_days);
+                    }
+                }
+
+                setting.last_modified = CameraSetting::DEFAULT.last_modified();
+                setting.modified_by = Some(user.user_id);
+
+                if (setting.update_using_self(&state.db_pool).await).is_err() {
+                    return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+                }
+
+                let api_message = ApiChannelMessage::CameraAction {
+                    camera_id: setting.camera_id,
+                    message: CameraMessage::SettingChanged(CameraSettingNoMeta {
+                        flashlight_enabled: setting.flashlight_enabled,
+                        resolution: setting.resolution,
+                        framerate: setting.framerate,
+                    }),
+                };
+
+                if state.api_channel.send(api_message).is_err() {
+                    warn!("Failed to send camera_settings update to API channel");
+                }
+
+                Json(setting).into_response()
+            }
+            None => StatusCode::UNAUTHORIZED.into_response(),
+        }
+    }
+
+    #[derive(Debug, Clone, Deserialize)]
+    pub struct UpdateEventForm {
+        pub acknowledged: bool,
+    }
+
+    pub async fn event(
+        auth_session: AuthSession,
+        state: State<Arc<AppState>>,
+        Path(event_id): Path<i64>,
+        Form(event_form): Form<UpdateEventForm>,
+    ) -> impl IntoResponse {
+        match auth_session.user {
+            Some(user) => {
+                let mut event = match viewable_event(&state, &user, event_id).await {
+                    Ok(event) => event,
+                    Err(status_code) => return status_code.into_response(),
+                };
+
+                event.acknowledged = event_form.acknowledged;
+
+                if (event.update_using_self(&state.db_pool).await).is_err() {
+                    return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+                }
+
+                // Other viewers might not be connected
+                let _ = state
+                    .api_channel
+                    .send(ApiChannelMessage::EventUpdated(event.clone()));
+
+                Json(event).into_response()
+            }
+            None => StatusCode::UNAUTHORIZED.into_response(),
+        }
+    }
+
+    #[derive(Debug, Clone, Deserialize)]
+    pub struct UpdateVideoForm {
+        pub keep_forever: bool,
+    }
+
+    pub async fn video(
+        auth_session: AuthSession,
+        state: State<Arc<AppState>>,
+        Path(video_id): Path<i64>,
+        Form(video_form): Form<UpdateVideoForm>,
+    ) -> impl IntoResponse {
+        match auth_session.user {
+            Some(user) => {
+                let Ok(mut video) = Video::get_using_id(&state.db_pool, video_id).await else {
+                    return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+                };
+
+                let Some(video_camera_id) = video.camera_id else {
+                    return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+                };
+
+                let Ok(permissions) =
+                    CameraPermission::list_for_camera(&state.db_pool, video_camera_id).await
                 else {
                     return StatusCode::INTERNAL_SERVER_ERROR.into_response();
                 };
 
-                let mut new_user = User {
-                    user_id: User::DEFAULT.user_id,
-                    username: user_form.username,
-                    password_hash,
-                    created_at: User::DEFAULT.created_at(),
-                };
+                if user.role < Role::Operator
+                    || !permissions
+                        .iter()
+                        .any(|p| (p.user_id == user.user_id) && p.can_control)
+                {
+                    return StatusCode::FORBIDDEN.into_response();
+                }
+
+                video.keep_forever = video_form.keep_forever;
+
+                if (video.update_using_self(&state.db_pool).await).is_err() {
+                    return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+                }
+
+                Json(video).into_response()
+            }
+            None => StatusCode::UNAUTHORIZED.into_response(),
+        }
+    }
+
+    /// Sent as JSON like `post::AddPrivacyMaskForm`, only the given fields are changed
+    #[derive(Debug, Clone, Deserialize)]
+    pub struct UpdatePrivacyMaskForm {
+        #[serde(default)]
+        pub name: Option<String>,
+        #[serde(default)]
+        pub points: Option<Vec<PolygonPoint>>,
+    }
+
+    pub async fn privacy_masks(
+        _: AdminUser,
+        state: State<Arc<AppState>>,
+        Path(mask_id): Path<i64>,
+        Json(mask_form): Json<UpdatePrivacyMaskForm>,
+    ) -> impl IntoResponse {
+        let Ok(mut mask) = PrivacyMask::get_using_id(&state.db_pool, mask_id).await else {
+            return StatusCode::NOT_FOUND.into_response();
+        };
+
+        if let Some(name) = mask_form.name {
+            mask.name = name;
+        }
+
+        if let Some(points) = mask_form.points {
+            mask.points = sqlx::types::Json(points);
+        }
+
+        if !mask.is_valid() {
+            return StatusCode::BAD_REQUEST.into_response();
+        }
+
+        if (mask.update_using_self(&state.db_pool).await).is_err() {
+            return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+        }
+
+        if !reload_privacy_masks(&state, mask.camera_id).await {
+            return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+        }
+
+        Json(mask).into_response()
+    }
+
+    /// `sensitivity` can't be cleared once set, the zone has to be recreated instead
+    #[derive(Debug, Clone, Deserialize)]
+    pub struct UpdateMotionZoneForm {
+        #[serde(default)]
+        pub name: Option<String>,
+        #[serde(default)]
+        pub kind: Option<MotionZoneKind>,
+        #[serde(default)]
+        pub points: Option<Vec<PolygonPoint>>,
+        #[serde(default)]
+        pub sensitivity: Option<i64>,
+    }
+
+    pub async fn motion_zones(
+        RequireRole { user, .. }: OperatorUser,
+        state: State<Arc<AppState>>,
+        Path(zone_id): Path<i64>,
+        Json(zone_form): Json<UpdateMotionZoneForm>,
+    ) -> impl IntoResponse {
+        let Ok(mut zone) = MotionZone::get_using_id(&state.db_pool, zone_id).await else {
+            return StatusCode::NOT_FOUND.into_response();
+        };
+
+        match can_control_camera(&state, user.user_id, zone.camera_id).await {
+            Ok(true) => (),
+            Ok(false) => return StatusCode::FORBIDDEN.into_response(),
+            Err(_) => return StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+        }
 
-                if (new_user.create_using_self(&state.db_pool).await).is_err() {
-                    return StatusCode::INTERNAL_SERVER_ERROR.into_response();
-                }
+        if let Some(name) = zone_form.name {
+            zone.name = name;
+        }
 
-                let Ok(all_cameras) =
-                    Camera::list_accessible_to_user(&state.db_pool, user.user_id).await
-                else {
-                    return StatusCode::INTERNAL_SERVER_ERROR.into_response();
-                };
+        if let Some(kind) = zone_form.kind {
+            zone.kind = kind;
+        }
 
-                for camera in all_cameras {
-                    let mut camera_permission = CameraPermission {
-                        permission_id: CameraPermission::DEFAULT.permission_id,
-                        camera_id: camera.camera_id,
-                        user_id: new_user.user_id,
-                        can_view: false,
-                        can_control: false,
-                    };
+        if let Some(points) = zone_form.points {
+            zone.points = sqlx::types::Json(points);
+        }
 
-                    if (camera_permission.create_using_self(&state.db_pool).await).is_err() {
-                        return StatusCode::INTERNAL_SERVER_ERROR.into_response();
-                    }
-                }
+        if let Some(sensitivity) = zone_form.sensitivity {
+            zone.sensitivity = Some(sensitivity);
+        }
 
-                Json(new_user).into_response()
-            }
-            None => StatusCode::UNAUTHORIZED.into_response(),
+        if !zone.is_valid() {
+            return StatusCode::BAD_REQUEST.into_response();
         }
-    }
-}
 
-// TODO: Don't always return the same error
+        if (zone.update_using_self(&state.db_pool).await).is_err() {
+            return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+        }
 
-mod patch {
-    use std::sync::Arc;
+        // The camera might not be connected
+        let _ = state
+            .api_channel
+            .send(ApiChannelMessage::MotionZonesChanged {
+                camera_id: zone.camera_id,
+            });
 
-    use super::{post::UserForm, AuthSession, IntoResponse, StatusCode};
-    use crate::{
-        web::{AppState, CameraListChange, CameraMessage},
-        ApiChannelMessage, CameraPermission, CameraSetting, CameraSettingNoMeta, Model, User,
-    };
-    use axum::{
-        extract::{Path, State},
-        Form, Json,
-    };
-    use password_auth::generate_hash;
-    use serde::Deserialize;
-    use tracing::warn;
+        Json(zone).into_response()
+    }
 
     #[derive(Debug, Clone, Deserialize)]
-    pub struct UpdatePermissionForm {
-        pub can_view: bool,
-        pub can_control: bool,
+    pub struct UpdateArmingForm {
+        pub mode: ArmingMode,
     }
 
-    pub async fn permissions(
-        auth_session: AuthSession,
+    pub async fn arming(
+        RequireRole { user, .. }: OperatorUser,
         state: State<Arc<AppState>>,
-        Path(permission_id): Path<i64>,
-        Form(permission_form): Form<UpdatePermissionForm>,
+        Form(arming_form): Form<UpdateArmingForm>,
     ) -> impl IntoResponse {
-        match auth_session.user {
-            Some(user) => {
-                if user.username != "admin" {
-                    return StatusCode::FORBIDDEN.into_response();
-                }
+        let Ok(mut arming_state) = ArmingState::get(&state.db_pool).await else {
+            return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+        };
 
-                let Ok(mut permission) =
-                    CameraPermission::get_using_id(&state.db_pool, permission_id).await
-                else {
-                    return StatusCode::INTERNAL_SERVER_ERROR.into_response();
-                };
+        arming_state.mode = arming_form.mode;
+        arming_state.last_modified = ArmingState::DEFAULT.last_modified();
+        arming_state.modified_by = Some(user.user_id);
 
-                permission.can_view = permission_form.can_view;
-                permission.can_control = permission_form.can_control;
+        if (arming_state.update_using_self(&state.db_pool).await).is_err() {
+            return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+        }
 
-                if (permission.update_using_self(&state.db_pool).await).is_err() {
-                    return StatusCode::INTERNAL_SERVER_ERROR.into_response();
-                }
+        // Nobody might be connected
+        let _ = state
+            .api_channel
+            .send(ApiChannelMessage::ArmingChanged(arming_state.clone()));
 
-                if state
-                    .api_channel
-                    .send(ApiChannelMessage::CameraListChanged(
-                        CameraListChange::Updated {
-                            camera_id: permission.camera_id,
-                        },
-                    ))
-                    .is_err()
-                {
-                    return StatusCode::INTERNAL_SERVER_ERROR.into_response();
-                }
+        Json(arming_state).into_response()
+    }
 
-                Json(permission).into_response()
-            }
-            None => StatusCode::UNAUTHORIZED.into_response(),
+    #[derive(Debug, Clone, Deserialize)]
+    pub struct UpdateStoragePolicyForm {
+        /// Leaving this out removes the disk quota
+        #[serde(default)]
+        pub max_total_size_bytes: Option<i64>,
+    }
+
+    pub async fn storage_policy(
+        RequireRole { user, .. }: AdminUser,
+        state: State<Arc<AppState>>,
+        Form(policy_form): Form<UpdateStoragePolicyForm>,
+    ) -> impl IntoResponse {
+        if policy_form
+            .max_total_size_bytes
+            .is_some_and(|size| size < 0)
+        {
+            return StatusCode::BAD_REQUEST.into_response();
         }
+
+        let Ok(mut policy) = StoragePolicy::get(&state.db_pool).await else {
+            return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+        };
+
+        policy.max_total_size_bytes = policy_form.max_total_size_bytes;
+        policy.last_modified = StoragePolicy::DEFAULT.last_modified();
+        policy.modified_by = Some(user.user_id);
+
+        if (policy.update_using_self(&state.db_pool).await).is_err() {
+            return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+        }
+
+        Json(policy).into_response()
     }
 
+    /// Only the given fields are changed, empty strings clear optional fields
     #[derive(Debug, Clone, Deserialize)]
-    pub struct UpdateSettingsForm {
-        pub flashlight_enabled: bool,
-        pub resolution: String,
-        pub framerate: i64,
+    pub struct UpdateSmtpSettingsForm {
+        #[serde(default)]
+        pub host: Option<String>,
+        #[serde(default)]
+        pub port: Option<i64>,
+        #[serde(default)]
+        pub security: Option<SmtpSecurity>,
+        #[serde(default)]
+        pub username: Option<String>,
+        #[serde(default)]
+        pub password: Option<String>,
+        #[serde(default)]
+        pub from_address: Option<String>,
+        #[serde(default)]
+        pub min_alert_interval_seconds: Option<i64>,
     }
 
-    pub async fn camera_settings(
-        auth_session: AuthSession,
+    pub async fn smtp(
+        RequireRole { user, .. }: AdminUser,
         state: State<Arc<AppState>>,
-        Path(setting_id): Path<i64>,
-        Form(settings_form): Form<UpdateSettingsForm>,
+        Form(smtp_form): Form<UpdateSmtpSettingsForm>,
     ) -> impl IntoResponse {
-        match auth_session.user {
-            Some(user) => {
-                let Ok(mut setting) = CameraSetting::get_using_id(&state.db_pool, setting_id).await
-                else {
-                    return StatusCode::INTERNAL_SERVER_ERROR.into_response();
-                };
-
-                let Ok(permissions) =
-                    CameraPermission::list_for_camera(&state.db_pool, setting.camera_id).await
-                else {
-                    return StatusCode::INTERNAL_SERVER_ERROR.into_response();
-                };
+        let non_empty = |value: String| (!value.is_empty()).then_some(value);
+
+        if smtp_form
+            .port
+            .is_some_and(|port| !(1..=65535).contains(&port))
+            || smtp_form
+                .min_alert_interval_seconds
+                .is_some_and(|interval| interval < 0)
+            || smtp_form.from_address.as_ref().is_some_and(|from_address| {
+                !from_address.is_empty() && !crate::alerts::is_valid_email(from_address)
+            })
+        {
+            return StatusCode::BAD_REQUEST.into_response();
+        }
 
-                if !permissions
-                    .iter()
-                    .any(|p| (p.user_id == user.user_id) && p.can_control)
-                {
-                    return StatusCode::FORBIDDEN.into_response();
-                }
+        let Ok(mut settings) = SmtpSettings::get(&state.db_pool).await else {
+            return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+        };
 
-                // TODO: resolution
-                setting.flashlight_enabled = settings_form.flashlight_enabled;
+        if let Some(host) = smtp_form.host {
+            settings.host = non_empty(host);
+        }
 
-                // ? Maybe allow any framerate/resolution for admin but give warning
-                if user.username == "admin" {
-                    if (settings_form.framerate < 1) || (settings_form.framerate > 60) {
-                        return StatusCode::BAD_REQUEST.into_response();
-                    }
+        if let Some(port) = smtp_form.port {
+            settings.port = port;
+        }
 
-                    if !["SVGA", "VGA"].contains(&settings_form.resolution.as_str()) {
-                        return StatusCode::BAD_REQUEST.into_response();
-                    }
+        if let Some(security) = smtp_form.security {
+            settings.security = security;
+        }
 
-                    setting.resolution = settings_form.resolution;
-                    setting.framerate = settings_form.framerate;
-                }
+        if let Some(username) = smtp_form.username {
+            settings.username = non_empty(username);
+        }
 
-                setting.last_modified = CameraSetting::DEFAULT.last_modified();
-                setting.modified_by = Some(user.user_id);
+        if let Some(password) = smtp_form.password {
+            settings.password = non_empty(password);
+        }
 
-                if (setting.update_using_self(&state.db_pool).await).is_err() {
-                    return StatusCode::INTERNAL_SERVER_ERROR.into_response();
-                }
+        if let Some(from_address) = smtp_form.from_address {
+            settings.from_address = non_empty(from_address);
+        }
 
-                let api_message = ApiChannelMessage::CameraAction {
-                    camera_id: setting.camera_id,
-                    message: CameraMessage::SettingChanged(CameraSettingNoMeta {
-                        flashlight_enabled: setting.flashlight_enabled,
-                        resolution: setting.resolution.clone(),
-                        framerate: setting.framerate,
-                    }),
-                };
+        if let Some(min_alert_interval_seconds) = smtp_form.min_alert_interval_seconds {
+            settings.min_alert_interval_seconds = min_alert_interval_seconds;
+        }
 
-                if state.api_channel.send(api_message).is_err() {
-                    warn!("Failed to send camera_settings update to API channel");
-                }
+        settings.last_modified = SmtpSettings::DEFAULT.last_modified();
+        settings.modified_by = Some(user.user_id);
 
-                Json(setting).into_response()
-            }
-            None => StatusCode::UNAUTHORIZED.into_response(),
+        if (settings.update_using_self(&state.db_pool).await).is_err() {
+            return StatusCode::INTERNAL_SERVER_ERROR.into_response();
         }
+
+        Json(settings).into_response()
     }
 
     pub async fn users(
-        auth_session: AuthSession,
+        _: AdminUser,
         state: State<Arc<AppState>>,
         Path(user_id): Path<i64>,
         Form(user_form): Form<UserForm>,
     ) -> impl IntoResponse {
-        match auth_session.user {
-            Some(user) => {
-                if user.username != "admin" {
-                    return StatusCode::FORBIDDEN.into_response();
-                }
+        let Ok(mut updated_user) = User::get_using_id(&state.db_pool, user_id).await else {
+            return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+        };
+
+        if !user_form.username.is_ascii()
+            || !user_form
+                .username
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric())
+        {
+            return StatusCode::BAD_REQUEST.into_response();
+        }
 
-                let Ok(mut updated_user) = User::get_using_id(&state.db_pool, user_id).await else {
+        let was_admin = updated_user.role == Role::Admin;
+
+        if let Some(role) = user_form.role {
+            if updated_user.username == "guest" && role != Role::Viewer {
+                return StatusCode::BAD_REQUEST.into_response();
+            }
+
+            // Don't allow locking everyone out of the admin interface
+            if updated_user.role == Role::Admin && role != Role::Admin {
+                let Ok(admin_count) = User::count_with_role(&state.db_pool, Role::Admin).await
+                else {
                     return StatusCode::INTERNAL_SERVER_ERROR.into_response();
                 };
 
-                if !user_form.username.is_ascii()
-                    || !user_form
-                        .username
-                        .chars()
-                        .all(|c| c.is_ascii_alphanumeric())
-                {
+                if admin_count <= 1 {
                     return StatusCode::BAD_REQUEST.into_response();
                 }
+            }
 
-                if updated_user.username != "admin" && updated_user.username != "guest" {
-                    updated_user.username = user_form.username;
-                }
+            updated_user.role = role;
+        }
 
-                if !user_form.password.is_empty() {
-                    if user_form.password.len() > 254
-                        || user_form.password.contains(char::is_whitespace)
-                    {
-                        return StatusCode::BAD_REQUEST.into_response();
-                    }
+        if updated_user.username != "guest" {
+            updated_user.username = user_form.username;
+        }
 
-                    let Ok(password_hash) =
-                        tokio::task::spawn_blocking(|| generate_hash(user_form.password)).await
-                    else {
-                        return StatusCode::INTERNAL_SERVER_ERROR.into_response();
-                    };
+        if !user_form.password.is_empty() {
+            if user_form.password.len() > 254 || user_form.password.contains(char::is_whitespace) {
+                return StatusCode::BAD_REQUEST.into_response();
+            }
 
-                    updated_user.password_hash = password_hash;
-                }
+            let Ok(password_hash) =
+                tokio::task::spawn_blocking(|| generate_hash(user_form.password)).await
+            else {
+                return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+            };
 
-                if (updated_user.update_using_self(&state.db_pool).await).is_err() {
-                    return StatusCode::INTERNAL_SERVER_ERROR.into_response();
-                }
+            updated_user.password_hash = password_hash;
+        }
+
+        // Admins can see and control every camera, like when they're added
+        let updated = if updated_user.role == Role::Admin && !was_admin {
+            updated_user
+                .update_granting_all_cameras(&state.db_pool)
+                .await
+        } else {
+            updated_user.update_using_self(&state.db_pool).await
+        };
+
+        if updated.is_err() {
+            return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+        }
+
+        Json(updated_user).into_response()
+    }
+
+    #[derive(Debug, Clone, Deserialize)]
+    pub struct UpdateWebhookForm {
+        #[serde(default)]
+        pub url: Option<String>,
+        #[serde(default)]
+        pub enabled: Option<bool>,
+    }
 
-                Json(updated_user).into_response()
+    pub async fn webhooks(
+        _: AdminUser,
+        state: State<Arc<AppState>>,
+        Path(webhook_id): Path<i64>,
+        Form(webhook_form): Form<UpdateWebhookForm>,
+    ) -> impl IntoResponse {
+        let Ok(mut webhook) = Webhook::get_using_id(&state.db_pool, webhook_id).await else {
+            return StatusCode::NOT_FOUND.into_response();
+        };
+
+        if let Some(url) = webhook_form.url {
+            if !crate::webhooks::is_valid_url(&url) {
+                return StatusCode::BAD_REQUEST.into_response();
             }
-            None => StatusCode::UNAUTHORIZED.into_response(),
+
+            webhook.url = url;
+        }
+
+        if let Some(enabled) = webhook_form.enabled {
+            webhook.enabled = enabled;
+        }
+
+        if (webhook.update_using_self(&state.db_pool).await).is_err() {
+            return StatusCode::INTERNAL_SERVER_ERROR.into_response();
         }
+
+        Json(webhook).into_response()
     }
 }
 
 mod delete {
     use std::sync::Arc;
 
-    use super::{AuthSession, IntoResponse, StatusCode};
+    use super::{
+        helpers::{can_control_camera, reload_privacy_masks},
+        AdminUser, AuthSession, IntoResponse, OperatorUser, RequireRole, StatusCode,
+    };
     use crate::{
         web::{AppState, CameraListChange},
-        ApiChannelMessage, Camera, Model, User,
+        AlertSubscription, ApiChannelMessage, ArmingSchedule, Camera, Model, MotionZone,
+        PrivacyMask, Role, User, Webhook,
     };
     use axum::{
         extract::{Path, State},
         Json,
     };
 
-    pub async fn cameras(
+    pub async fn privacy_masks(
+        _: AdminUser,
+        state: State<Arc<AppState>>,
+        Path(mask_id): Path<i64>,
+    ) -> impl IntoResponse {
+        let Ok(mask) = PrivacyMask::get_using_id(&state.db_pool, mask_id).await else {
+            return StatusCode::NOT_FOUND.into_response();
+        };
+
+        if (PrivacyMask::delete_using_id(&state.db_pool, mask_id).await).is_err() {
+            return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+        }
+
+        if !reload_privacy_masks(&state, mask.camera_id).await {
+            return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+        }
+
+        Json(mask_id).into_response()
+    }
+
+    pub async fn motion_zones(
+        RequireRole { user, .. }: OperatorUser,
+        state: State<Arc<AppState>>,
+        Path(zone_id): Path<i64>,
+    ) -> impl IntoResponse {
+        let Ok(zone) = MotionZone::get_using_id(&state.db_pool, zone_id).await else {
+            return StatusCode::NOT_FOUND.into_response();
+        };
+
+        match can_control_camera(&state, user.user_id, zone.camera_id).await {
+            Ok(true) => (),
+            Ok(false) => return StatusCode::FORBIDDEN.into_response(),
+            Err(_) => return StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+        }
+
+        if (MotionZone::delete_using_id(&state.db_pool, zone_id).await).is_err() {
+            return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+        }
+
+        // The camera might not be connected
+        let _ = state
+            .api_channel
+            .send(ApiChannelMessage::MotionZonesChanged {
+                camera_id: zone.camera_id,
+            });
+
+        Json(zone_id).into_response()
+    }
+
+    pub async fn arming_schedules(
+        RequireRole { user, .. }: OperatorUser,
+        state: State<Arc<AppState>>,
+        Path(schedule_id): Path<i64>,
+    ) -> impl IntoResponse {
+        let schedule = match ArmingSchedule::get_using_id(&state.db_pool, schedule_id).await {
+            Ok(schedule) => schedule,
+            Err(sqlx::Error::RowNotFound) => return StatusCode::NOT_FOUND.into_response(),
+            Err(_) => return StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+        };
+
+        match can_control_camera(&state, user.user_id, schedule.camera_id).await {
+            Ok(true) => (),
+            Ok(false) => return StatusCode::FORBIDDEN.into_response(),
+            Err(_) => return StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+        }
+
+        if (ArmingSchedule::delete_using_id(&state.db_pool, schedule_id).await).is_err() {
+            return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+        }
+
+        Json(schedule_id).into_response()
+    }
+
+    pub async fn alert_subscriptions(
         auth_session: AuthSession,
         state: State<Arc<AppState>>,
-        Path(camera_id): Path<i64>,
+        Path(subscription_id): Path<i64>,
     ) -> impl IntoResponse {
         match auth_session.user {
             Some(user) => {
-                if user.username != "admin" {
-                    return StatusCode::FORBIDDEN.into_response();
-                }
+                let Ok(subscription) =
+                    AlertSubscription::get_using_id(&state.db_pool, subscription_id).await
+                else {
+                    return StatusCode::NOT_FOUND.into_response();
+                };
 
-                if (Camera::delete_using_id(&state.db_pool, camera_id).await).is_err() {
-                    return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+                if subscription.user_id != user.user_id && user.role < Role::Admin {
+                    return StatusCode::FORBIDDEN.into_response();
                 }
 
-                if state
-                    .api_channel
-                    .send(ApiChannelMessage::CameraListChanged(
-                        CameraListChange::Removed { camera_id },
-                    ))
+                if (AlertSubscription::delete_using_id(&state.db_pool, subscription_id).await)
                     .is_err()
                 {
                     return StatusCode::INTERNAL_SERVER_ERROR.into_response();
                 }
 
-                Json(camera_id).into_response()
+                Json(subscription_id).into_response()
             }
             None => StatusCode::UNAUTHORIZED.into_response(),
         }
     }
 
-    pub async fn users(
-        auth_session: AuthSession,
+    pub async fn webhooks(
+        _: AdminUser,
         state: State<Arc<AppState>>,
-        Path(user_id): Path<i64>,
+        Path(webhook_id): Path<i64>,
     ) -> impl IntoResponse {
-        match auth_session.user {
-            Some(user) => {
-                if user.username != "admin" {
-                    return StatusCode::FORBIDDEN.into_response();
-                }
+        if (Webhook::delete_using_id(&state.db_pool, webhook_id).await).is_err() {
+            return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+        }
 
-                if (User::delete_using_id(&state.db_pool, user_id).await).is_err() {
-                    return StatusCode::INTERNAL_SERVER_ERROR.into_response();
-                }
+        Json(webhook_id).into_response()
+    }
+
+    pub async fn cameras(
+        _: AdminUser,
+        state: State<Arc<AppState>>,
+        Path(camera_id): Path<i64>,
+    ) -> impl IntoResponse {
+        if (Camera::delete_using_id(&state.db_pool, camera_id).await).is_err() {
+            return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+        }
+
+        state.camera_hub.remove(camera_id);
+
+        if state
+            .api_channel
+            .send(ApiChannelMessage::CameraListChanged(
+                CameraListChange::Removed { camera_id },
+            ))
+            .is_err()
+        {
+            return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+        }
+
+        Json(camera_id).into_response()
+    }
 
-                Json(user_id).into_response()
+    pub async fn users(
+        _: AdminUser,
+        state: State<Arc<AppState>>,
+        Path(user_id): Path<i64>,
+    ) -> impl IntoResponse {
+        let Ok(deleted_user) = User::get_using_id(&state.db_pool, user_id).await else {
+            return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+        };
+
+        // Don't allow locking everyone out of the admin interface
+        if deleted_user.role == Role::Admin {
+            let Ok(admin_count) = User::count_with_role(&state.db_pool, Role::Admin).await else {
+                return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+            };
+
+            if admin_count <= 1 {
+                return StatusCode::BAD_REQUEST.into_response();
             }
-            None => StatusCode::UNAUTHORIZED.into_response(),
         }
+
+        if (User::delete_using_id(&state.db_pool, user_id).await).is_err() {
+            return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+        }
+
+        Json(user_id).into_response()
     }
 }
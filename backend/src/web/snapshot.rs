@@ -0,0 +1,151 @@
+use axum::{
+    http::{header, HeaderName, StatusCode},
+    response::{IntoResponse, Response},
+};
+use opencv::{
+    core::{Mat, MatTraitConst, Size, Vector},
+    imgcodecs::{imdecode, imencode, IMREAD_COLOR},
+    imgproc::{resize, INTER_AREA},
+};
+use serde::Deserialize;
+use tracing::error;
+
+use super::{video_file::format_http_date, ImageContainer};
+
+/// When the image was received, in milliseconds since the Unix epoch
+const TIMESTAMP_HEADER: &str = "x-timestamp";
+/// Largest width/height a snapshot can be resized to
+const MAX_SIZE: i32 = 4096;
+
+#[derive(Debug, Clone, Copy, Deserialize)]
+pub struct SnapshotQuery {
+    pub width: Option<i32>,
+    pub height: Option<i32>,
+}
+
+impl SnapshotQuery {
+    pub const fn is_resized(self) -> bool {
+        self.width.is_some() || self.height.is_some()
+    }
+
+    pub fn is_valid(self) -> bool {
+        [self.width, self.height]
+            .into_iter()
+            .flatten()
+            .all(|side| (1..=MAX_SIZE).contains(&side))
+    }
+
+    /// Size to resize an `image_size` image to, `None` if it shouldn't be resized.
+    ///
+    /// The aspect ratio is kept if only one side is given.
+    pub fn resized_size(self, image_size: Size) -> Option<Size> {
+        let scaled = |side: i32, numerator: i32, denominator: i32| {
+            let scaled = i64::from(side) * i64::from(numerator) / i64::from(denominator).max(1);
+            i32::try_from(scaled).unwrap_or(i32::MAX).max(1)
+        };
+
+        match (self.width, self.height) {
+            (None, None) => None,
+            (Some(width), Some(height)) => Some(Size::new(width, height)),
+            (Some(width), None) => Some(Size::new(
+                width,
+                scaled(width, image_size.height, image_size.width),
+            )),
+            (None, Some(height)) => Some(Size::new(
+                scaled(height, image_size.width, image_size.height),
+                height,
+            )),
+        }
+        .filter(|size| *size != image_size)
+    }
+}
+
+fn resize_jpeg(image_bytes: &[u8], snapshot_query: SnapshotQuery) -> opencv::Result<Vec<u8>> {
+    let image = imdecode(&image_bytes, IMREAD_COLOR)?;
+
+    let Some(size) = snapshot_query.resized_size(image.size()?) else {
+        return Ok(image_bytes.to_vec());
+    };
+
+    let mut resized_image = Mat::default();
+    resize(&image, &mut resized_image, size, 0.0, 0.0, INTER_AREA)?;
+
+    let mut resized_bytes = Vector::<u8>::new();
+    imencode(".jpg", &resized_image, &mut resized_bytes, &Vector::new())?;
+
+    Ok(resized_bytes.to_vec())
+}
+
+/// The image as a JPEG, resized if `snapshot_query` asks for it
+pub async fn response(image: ImageContainer, snapshot_query: SnapshotQuery) -> Response {
+    let received_at = image.received_at();
+    let timestamp = image.timestamp;
+
+    let image_bytes = if snapshot_query.is_resized() {
+        match tokio::task::spawn_blocking(move || resize_jpeg(&image.image_bytes, snapshot_query))
+            .await
+        {
+            Ok(Ok(resized_bytes)) => resized_bytes,
+            e => {
+                error!("Error resizing snapshot: {e:?}");
+                return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+            }
+        }
+    } else {
+        image.image_bytes
+    };
+
+    let last_modified = received_at.and_then(format_http_date);
+
+    (
+        [
+            (header::CONTENT_TYPE, "image/jpeg".to_string()),
+            (header::CACHE_CONTROL, "no-store".to_string()),
+            (
+                HeaderName::from_static(TIMESTAMP_HEADER),
+                timestamp.to_string(),
+            ),
+        ],
+        last_modified.map(|last_modified| [(header::LAST_MODIFIED, last_modified)]),
+        image_bytes,
+    )
+        .into_response()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SVGA: Size = Size::new(800, 600);
+
+    #[test]
+    fn resized_size() {
+        let query = |width, height| SnapshotQuery { width, height };
+
+        assert_eq!(query(None, None).resized_size(SVGA), None);
+        assert_eq!(
+            query(Some(320), Some(320)).resized_size(SVGA),
+            Some(Size::new(320, 320))
+        );
+        assert_eq!(
+            query(Some(400), None).resized_size(SVGA),
+            Some(Size::new(400, 300))
+        );
+        assert_eq!(
+            query(None, Some(240)).resized_size(SVGA),
+            Some(Size::new(320, 240))
+        );
+        assert_eq!(query(Some(800), None).resized_size(SVGA), None);
+    }
+
+    #[test]
+    fn valid_query() {
+        let query = |width, height| SnapshotQuery { width, height };
+
+        assert!(query(None, None).is_valid());
+        assert!(query(Some(1), Some(MAX_SIZE)).is_valid());
+        assert!(!query(Some(0), None).is_valid());
+        assert!(!query(None, Some(-1)).is_valid());
+        assert!(!query(Some(320), Some(MAX_SIZE + 1)).is_valid());
+    }
+}
@@ -0,0 +1,321 @@
+use std::{io::SeekFrom, path::Path};
+
+use axum::{
+    body::Body,
+    http::{header, HeaderMap, StatusCode},
+    response::{AppendHeaders, IntoResponse, Response},
+};
+use time::{macros::format_description, OffsetDateTime, PrimitiveDateTime};
+use tokio::io::{AsyncReadExt, AsyncSeekExt};
+use tokio_util::io::ReaderStream;
+
+/// `Last-Modified`/`If-Modified-Since` date format, always in GMT
+const HTTP_DATE_FORMAT: &[time::format_description::BorrowedFormatItem<'static>] = format_description!(
+    "[weekday repr:short], [day] [month repr:short] [year] [hour]:[minute]:[second] GMT"
+);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Disposition {
+    /// Let the browser play the video
+    Inline,
+    /// Make the browser download the video
+    Attachment,
+}
+
+/// An inclusive range of bytes
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ByteRange {
+    pub start: u64,
+    pub end: u64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RangeRequest {
+    Full,
+    Partial(ByteRange),
+    Unsatisfiable,
+}
+
+/// Parses a `Range` header for a file of `len` bytes.
+///
+/// Only single ranges are supported, anything else (including invalid headers) gets the full file.
+pub fn parse_range(range_header: &str, len: u64) -> RangeRequest {
+    let Some(range) = range_header.trim().strip_prefix("bytes=") else {
+        return RangeRequest::Full;
+    };
+
+    if range.contains(',') {
+        return RangeRequest::Full;
+    }
+
+    let Some((start, end)) = range.trim().split_once('-') else {
+        return RangeRequest::Full;
+    };
+
+    let byte_range = match (start.is_empty(), end.is_empty()) {
+        // bytes=-500, the last 500 bytes
+        (true, false) => {
+            let Ok(suffix_len) = end.parse::<u64>() else {
+                return RangeRequest::Full;
+            };
+
+            if suffix_len == 0 || len == 0 {
+                return RangeRequest::Unsatisfiable;
+            }
+
+            ByteRange {
+                start: len.saturating_sub(suffix_len),
+                end: len - 1,
+            }
+        }
+        // bytes=500-, everything after the first 500 bytes
+        (false, true) => {
+            let Ok(start) = start.parse::<u64>() else {
+                return RangeRequest::Full;
+            };
+
+            if start >= len {
+                return RangeRequest::Unsatisfiable;
+            }
+
+            ByteRange {
+                start,
+                end: len - 1,
+            }
+        }
+        // bytes=500-999
+        (false, false) => {
+            let (Ok(start), Ok(end)) = (start.parse::<u64>(), end.parse::<u64>()) else {
+                return RangeRequest::Full;
+            };
+
+            if start > end {
+                return RangeRequest::Full;
+            }
+
+            if start >= len {
+                return RangeRequest::Unsatisfiable;
+            }
+
+            ByteRange {
+                start,
+                end: end.min(len - 1),
+            }
+        }
+        (true, true) => return RangeRequest::Full,
+    };
+
+    RangeRequest::Partial(byte_range)
+}
+
+pub fn format_http_date(date: OffsetDateTime) -> Option<String> {
+    date.to_offset(time::UtcOffset::UTC)
+        .format(HTTP_DATE_FORMAT)
+        .ok()
+}
+
+pub fn parse_http_date(date: &str) -> Option<OffsetDateTime> {
+    PrimitiveDateTime::parse(date.trim(), HTTP_DATE_FORMAT)
+        .ok()
+        .map(PrimitiveDateTime::assume_utc)
+}
+
+fn etag(len: u64, modified: OffsetDateTime) -> String {
+    format!("\"{len:x}-{:x}\"", modified.unix_timestamp_nanos())
+}
+
+fn etag_matches(header_value: &str, etag: &str) -> bool {
+    header_value.split(',').map(str::trim).any(|value| {
+        // Weak comparison, see RFC 9110 section 8.8.3.2
+        value == "*" || value.trim_start_matches("W/") == etag
+    })
+}
+
+/// Serves a video file with support for `Range`, `If-Range`, `If-None-Match` and `If-Modified-Since`.
+pub async fn response(
+    path: &Path,
+    content_type: &'static str,
+    request_headers: &HeaderMap,
+    disposition: Disposition,
+) -> Response {
+    let Ok(mut file) = tokio::fs::File::open(path).await else {
+        return StatusCode::NOT_FOUND.into_response();
+    };
+
+    let Ok(metadata) = file.metadata().await else {
+        return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+    };
+
+    let len = metadata.len();
+    let modified = metadata
+        .modified()
+        .map_or_else(|_| OffsetDateTime::now_utc(), OffsetDateTime::from);
+    // HTTP dates don't have sub-second precision
+    let Ok(modified) = modified.replace_nanosecond(0) else {
+        return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+    };
+
+    let etag = etag(len, modified);
+    let Some(last_modified) = format_http_date(modified) else {
+        return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+    };
+
+    let Some(filename) = path.file_name().map(|name| name.to_string_lossy()) else {
+        return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+    };
+
+    let content_disposition = match disposition {
+        Disposition::Inline => format!("inline; filename={filename:?}"),
+        Disposition::Attachment => format!("attachment; filename={filename:?}"),
+    };
+
+    let header_str = |name: header::HeaderName| {
+        request_headers
+            .get(name)
+            .and_then(|value| value.to_str().ok())
+    };
+
+    let not_modified = match (
+        header_str(header::IF_NONE_MATCH),
+        header_str(header::IF_MODIFIED_SINCE),
+    ) {
+        (Some(if_none_match), _) => etag_matches(if_none_match, &etag),
+        (None, Some(if_modified_since)) => {
+            parse_http_date(if_modified_since).is_some_and(|since| modified <= since)
+        }
+        (None, None) => false,
+    };
+
+    if not_modified {
+        return (
+            StatusCode::NOT_MODIFIED,
+            AppendHeaders([(header::ETAG, etag), (header::LAST_MODIFIED, last_modified)]),
+        )
+            .into_response();
+    }
+
+    // A range is only valid if the file hasn't changed since the client got the rest of it
+    let if_range_matches = header_str(header::IF_RANGE).map_or(true, |if_range| {
+        if_range == etag || parse_http_date(if_range).is_some_and(|date| date == modified)
+    });
+
+    let range_request = match header_str(header::RANGE) {
+        Some(range) if if_range_matches => parse_range(range, len),
+        _ => RangeRequest::Full,
+    };
+
+    let common_headers = [
+        (header::CONTENT_TYPE, content_type.to_string()),
+        (header::ACCEPT_RANGES, "bytes".to_string()),
+        (header::ETAG, etag),
+        (header::LAST_MODIFIED, last_modified),
+        (header::CONTENT_DISPOSITION, content_disposition),
+    ];
+
+    match range_request {
+        RangeRequest::Full => {
+            let body = Body::from_stream(ReaderStream::new(file));
+
+            (
+                StatusCode::OK,
+                AppendHeaders(common_headers),
+                [(header::CONTENT_LENGTH, len.to_string())],
+                body,
+            )
+                .into_response()
+        }
+        RangeRequest::Partial(ByteRange { start, end }) => {
+            if file.seek(SeekFrom::Start(start)).await.is_err() {
+                return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+            }
+
+            let range_len = end - start + 1;
+            let body = Body::from_stream(ReaderStream::new(file.take(range_len)));
+
+            (
+                StatusCode::PARTIAL_CONTENT,
+                AppendHeaders(common_headers),
+                [
+                    (header::CONTENT_LENGTH, range_len.to_string()),
+                    (header::CONTENT_RANGE, format!("bytes {start}-{end}/{len}")),
+                ],
+                body,
+            )
+                .into_response()
+        }
+        RangeRequest::Unsatisfiable => (
+            StatusCode::RANGE_NOT_SATISFIABLE,
+            [(header::CONTENT_RANGE, format!("bytes */{len}"))],
+        )
+            .into_response(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn range() {
+        assert_eq!(
+            parse_range("bytes=0-9", 100),
+            RangeRequest::Partial(ByteRange { start: 0, end: 9 })
+        );
+        assert_eq!(
+            parse_range("bytes=90-200", 100),
+            RangeRequest::Partial(ByteRange { start: 90, end: 99 })
+        );
+        assert_eq!(
+            parse_range("bytes=50-", 100),
+            RangeRequest::Partial(ByteRange { start: 50, end: 99 })
+        );
+        assert_eq!(
+            parse_range("bytes=-10", 100),
+            RangeRequest::Partial(ByteRange { start: 90, end: 99 })
+        );
+        assert_eq!(
+            parse_range("bytes=-200", 100),
+            RangeRequest::Partial(ByteRange { start: 0, end: 99 })
+        );
+    }
+
+    #[test]
+    fn unsatisfiable_range() {
+        assert_eq!(parse_range("bytes=100-", 100), RangeRequest::Unsatisfiable);
+        assert_eq!(
+            parse_range("bytes=100-200", 100),
+            RangeRequest::Unsatisfiable
+        );
+        assert_eq!(parse_range("bytes=-0", 100), RangeRequest::Unsatisfiable);
+        assert_eq!(parse_range("bytes=0-", 0), RangeRequest::Unsatisfiable);
+    }
+
+    #[test]
+    fn ignored_range() {
+        assert_eq!(parse_range("bytes=0-9,20-29", 100), RangeRequest::Full);
+        assert_eq!(parse_range("bytes=9-0", 100), RangeRequest::Full);
+        assert_eq!(parse_range("bytes=-", 100), RangeRequest::Full);
+        assert_eq!(parse_range("bytes=a-b", 100), RangeRequest::Full);
+        assert_eq!(parse_range("items=0-9", 100), RangeRequest::Full);
+    }
+
+    #[test]
+    fn http_date() -> Result<(), time::error::ComponentRange> {
+        let date = OffsetDateTime::from_unix_timestamp(1_729_479_512)?;
+        let formatted = format_http_date(date);
+
+        assert_eq!(formatted.as_deref(), Some("Mon, 21 Oct 2024 02:58:32 GMT"));
+        assert_eq!(formatted.as_deref().and_then(parse_http_date), Some(date));
+
+        Ok(())
+    }
+
+    #[test]
+    fn etags() {
+        assert!(etag_matches("\"a-b\"", "\"a-b\""));
+        assert!(etag_matches("W/\"a-b\"", "\"a-b\""));
+        assert!(etag_matches("\"c-d\", \"a-b\"", "\"a-b\""));
+        assert!(etag_matches("*", "\"a-b\""));
+        assert!(!etag_matches("\"c-d\"", "\"a-b\""));
+    }
+}
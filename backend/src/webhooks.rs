@@ -0,0 +1,316 @@
+use hmac::{Hmac, Mac};
+use serde::Serialize;
+use sha2::Sha256;
+use sqlx::SqlitePool;
+use time::OffsetDateTime;
+use tokio::sync::broadcast;
+use tokio_util::sync::CancellationToken;
+use tracing::{debug, error, warn};
+
+use crate::{
+    db::{Event, EventKind, Model, Video, Webhook, WebhookDelivery, WebhookKind},
+    web::ApiChannelMessage,
+};
+
+/// `sha256=` followed by the hex HMAC-SHA256 of the body, keyed with the webhook's secret
+pub const SIGNATURE_HEADER: &str = "x-oko-signature";
+/// ID of the delivery, the same for every retry
+pub const DELIVERY_HEADER: &str = "x-oko-delivery";
+/// `WebhookKind` of the payload
+pub const KIND_HEADER: &str = "x-oko-event";
+
+/// Attempts made before a delivery is given up on
+const MAX_ATTEMPTS: i64 = 5;
+/// Doubled after every failed attempt
+const FIRST_RETRY_DELAY: tokio::time::Duration = tokio::time::Duration::from_secs(1);
+const REQUEST_TIMEOUT: tokio::time::Duration = tokio::time::Duration::from_secs(10);
+
+#[derive(Serialize)]
+struct Payload<'a> {
+    kind: WebhookKind,
+    /// When the payload was created, in seconds since the Unix epoch
+    timestamp: i64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    event: Option<&'a Event>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    video: Option<&'a Video>,
+}
+
+impl<'a> Payload<'a> {
+    fn new(kind: WebhookKind) -> Self {
+        Self {
+            kind,
+            timestamp: OffsetDateTime::now_utc().unix_timestamp(),
+            event: None,
+            video: None,
+        }
+    }
+
+    /// The payload for `api_msg`, `None` if webhooks aren't sent for it
+    fn for_message(api_msg: &'a ApiChannelMessage) -> Option<Self> {
+        match api_msg {
+            ApiChannelMessage::EventCreated(event) => {
+                let kind = match event.kind {
+                    EventKind::CameraOffline => WebhookKind::CameraOffline,
+                    EventKind::Motion => WebhookKind::Motion,
+                    EventKind::CameraOnline | EventKind::LoginFailed => return None,
+                };
+
+                Some(Self {
+                    event: Some(event),
+                    ..Self::new(kind)
+                })
+            }
+            ApiChannelMessage::VideoFinished(video) => Some(Self {
+                video: Some(video),
+                ..Self::new(WebhookKind::RecordingFinished)
+            }),
+            _ => None,
+        }
+    }
+}
+
+/// Signature of `body` for the `x-oko-signature` header.
+pub fn sign(secret: &str, body: &[u8]) -> Option<String> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes()).ok()?;
+    mac.update(body);
+
+    Some(format!(
+        "sha256={}",
+        hex::encode(mac.finalize().into_bytes())
+    ))
+}
+
+/// Whether `url` can be used for a webhook, i.e. it's an absolute HTTP(S) URL.
+pub fn is_valid_url(url: &str) -> bool {
+    reqwest::Url::parse(url).is_ok_and(|url| matches!(url.scheme(), "http" | "https"))
+}
+
+pub fn client() -> reqwest::Result<reqwest::Client> {
+    reqwest::Client::builder().timeout(REQUEST_TIMEOUT).build()
+}
+
+/// Sends webhooks for the events and finished recordings on `api_channel_rx` until shutdown.
+///
+/// Every delivery is retried in the background, so slow webhooks don't hold up the others.
+pub async fn continuously_dispatch(
+    db: SqlitePool,
+    mut api_channel_rx: broadcast::Receiver<ApiChannelMessage>,
+    shutdown_token: CancellationToken,
+) {
+    let client = match client() {
+        Ok(client) => client,
+        Err(e) => {
+            error!("Error creating webhook client, webhooks won't be sent: {e:?}");
+            return;
+        }
+    };
+
+    loop {
+        let api_msg = tokio::select! {
+            api_msg = api_channel_rx.recv() => match api_msg {
+                Ok(api_msg) => api_msg,
+                Err(broadcast::error::RecvError::Lagged(skipped)) => {
+                    warn!("Webhook dispatcher fell behind, skipped {skipped} messages");
+                    continue;
+                }
+                Err(broadcast::error::RecvError::Closed) => break,
+            },
+            () = shutdown_token.cancelled() => break,
+        };
+
+        let Some(payload) = Payload::for_message(&api_msg) else {
+            continue;
+        };
+
+        let payload_json = match serde_json::to_string(&payload) {
+            Ok(payload_json) => payload_json,
+            Err(e) => {
+                error!(
+                    "Error serializing {:?} webhook payload: {e:?}",
+                    payload.kind
+                );
+                continue;
+            }
+        };
+
+        let webhooks = match Webhook::list_enabled(&db).await {
+            Ok(webhooks) => webhooks,
+            Err(e) => {
+                error!("Error listing webhooks: {e:?}");
+                continue;
+            }
+        };
+
+        for webhook in webhooks {
+            let mut delivery = new_delivery(&webhook, payload.kind, payload_json.clone());
+
+            if let Err(e) = delivery.create_using_self(&db).await {
+                error!(
+                    "Error saving delivery for webhook {}: {e:?}",
+                    webhook.webhook_id
+                );
+                continue;
+            }
+
+            tokio::spawn(deliver_with_retries(
+                db.clone(),
+                client.clone(),
+                webhook,
+                delivery,
+            ));
+        }
+    }
+}
+
+/// Sends a test payload to `webhook` once, returning how it went.
+pub async fn send_test(
+    db: &SqlitePool,
+    webhook: &Webhook,
+) -> Result<WebhookDelivery, Box<dyn std::error::Error + Send + Sync>> {
+    let payload_json = serde_json::to_string(&Payload::new(WebhookKind::Test))?;
+
+    let mut delivery = new_delivery(webhook, WebhookKind::Test, payload_json);
+    delivery.create_using_self(db).await?;
+
+    attempt(db, &client()?, webhook, &mut delivery).await;
+
+    Ok(delivery)
+}
+
+fn new_delivery(webhook: &Webhook, kind: WebhookKind, payload: String) -> WebhookDelivery {
+    WebhookDelivery {
+        delivery_id: WebhookDelivery::DEFAULT.delivery_id,
+        webhook_id: webhook.webhook_id,
+        kind,
+        payload,
+        attempts: WebhookDelivery::DEFAULT.attempts,
+        delivered: WebhookDelivery::DEFAULT.delivered,
+        status_code: WebhookDelivery::DEFAULT.status_code,
+        error: WebhookDelivery::DEFAULT.error,
+        created_at: WebhookDelivery::DEFAULT.created_at(),
+        last_attempt_at: WebhookDelivery::DEFAULT.last_attempt_at,
+    }
+}
+
+async fn deliver_with_retries(
+    db: SqlitePool,
+    client: reqwest::Client,
+    webhook: Webhook,
+    mut delivery: WebhookDelivery,
+) {
+    let mut retry_delay = FIRST_RETRY_DELAY;
+
+    loop {
+        attempt(&db, &client, &webhook, &mut delivery).await;
+
+        if delivery.delivered {
+            break;
+        }
+
+        if delivery.attempts >= MAX_ATTEMPTS {
+            warn!(
+                "Giving up on delivery {} to webhook {} after {} attempts",
+                delivery.delivery_id, webhook.webhook_id, delivery.attempts
+            );
+            break;
+        }
+
+        tokio::time::sleep(retry_delay).await;
+        retry_delay *= 2;
+    }
+}
+
+/// Sends the delivery once and saves the outcome to the delivery log.
+async fn attempt(
+    db: &SqlitePool,
+    client: &reqwest::Client,
+    webhook: &Webhook,
+    delivery: &mut WebhookDelivery,
+) {
+    let Some(signature) = sign(&webhook.secret, delivery.payload.as_bytes()) else {
+        error!("Error signing payload for webhook {}", webhook.webhook_id);
+        return;
+    };
+
+    let result = client
+        .post(&webhook.url)
+        .header(reqwest::header::CONTENT_TYPE, "application/json")
+        .header(SIGNATURE_HEADER, signature)
+        .header(DELIVERY_HEADER, delivery.delivery_id)
+        .header(KIND_HEADER, delivery.kind.as_str())
+        .body(delivery.payload.clone())
+        .send()
+        .await;
+
+    delivery.attempts += 1;
+    delivery.last_attempt_at = Some(OffsetDateTime::now_utc());
+
+    match result {
+        Ok(response) => {
+            let status = response.status();
+
+            delivery.status_code = Some(status.as_u16().into());
+            delivery.delivered = status.is_success();
+            delivery.error = (!delivery.delivered).then(|| format!("Received {status}"));
+        }
+        Err(e) => {
+            delivery.status_code = None;
+            delivery.error = Some(e.to_string());
+        }
+    }
+
+    debug!(
+        "Delivery {} to webhook {} attempt {}: {:?}",
+        delivery.delivery_id, webhook.webhook_id, delivery.attempts, delivery.error
+    );
+
+    if let Err(e) = delivery.update_using_self(db).await {
+        error!("Error saving delivery {}: {e:?}", delivery.delivery_id);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn signature() {
+        // RFC 4231 test case 2
+        assert_eq!(
+            sign("Jefe", b"what do ya want for nothing?").as_deref(),
+            Some("sha256=5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843")
+        );
+    }
+
+    #[test]
+    fn valid_urls() {
+        assert!(is_valid_url("https://example.com/oko"));
+        assert!(is_valid_url("http://127.0.0.1:8080"));
+        assert!(!is_valid_url("ftp://example.com"));
+        assert!(!is_valid_url("/relative/path"));
+        assert!(!is_valid_url(""));
+    }
+
+    #[test]
+    fn payload_kinds() {
+        let event = |kind| {
+            ApiChannelMessage::EventCreated(Event::new(Some(1), kind, OffsetDateTime::UNIX_EPOCH))
+        };
+
+        let offline = event(EventKind::CameraOffline);
+        assert_eq!(
+            Payload::for_message(&offline).map(|payload| payload.kind),
+            Some(WebhookKind::CameraOffline)
+        );
+
+        let motion = event(EventKind::Motion);
+        assert_eq!(
+            Payload::for_message(&motion).map(|payload| payload.kind),
+            Some(WebhookKind::Motion)
+        );
+
+        assert!(Payload::for_message(&event(EventKind::CameraOnline)).is_none());
+        assert!(Payload::for_message(&event(EventKind::LoginFailed)).is_none());
+    }
+}
@@ -1 +1,1848 @@
 // TODO: API integration tests. Make sure the API makes the correct changes to the database, returns the correct responses/status code, etc.
+use futures_util::{SinkExt, Stream, StreamExt};
+use hmac::{Hmac, Mac};
+use oko::{
+    ApiChannelMessage, ArmingMode, ArmingSchedule, ArmingState, Camera, CameraSetting, ClientRole,
+    Event, EventKind, FrameHeader, Hello, HelloReply, ImageContainer, Model, MotionZone,
+    MotionZoneKind, PrivacyMask, RecordingMode, Resolution, Role, User, Video, VideoFormat,
+    VideoThumbnail, WebhookDelivery, WebhookKind, PROTOCOL_VERSION,
+};
+use opencv::{
+    core::{MatTraitConst, Vec3b},
+    imgcodecs::{imdecode, IMREAD_COLOR},
+};
+use reqwest::{header, StatusCode};
+use sha2::Sha256;
+use sqlx::SqlitePool;
+use std::sync::{Arc, Mutex};
+use tokio::time::{sleep, Duration};
+use ws_utils::Message;
+
+#[path = "./utils.rs"]
+mod utils;
+
+#[sqlx::test(fixtures(
+    path = "../fixtures",
+    scripts("users", "cameras", "camera_permissions", "videos", "camera_settings")
+))]
+async fn video_range(pool: SqlitePool) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let (addr_str, _addr, video_temp_dir) = utils::setup_app(&pool).await?;
+    let session_cookie = utils::login(&addr_str, "admin").await?;
+
+    let video_bytes: Vec<u8> = (0..=255).collect();
+    let video_pathbuf = video_temp_dir.path().join("range.avi");
+    tokio::fs::write(&video_pathbuf, &video_bytes).await?;
+
+    let mut video = Video {
+        video_id: Video::DEFAULT.video_id,
+        camera_id: Some(1),
+        file_path: video_pathbuf.to_string_lossy().to_string(),
+        start_time: Video::DEFAULT.start_time(),
+        end_time: Video::DEFAULT.end_time,
+        file_size: Some(256),
+        keep_forever: Video::DEFAULT.keep_forever,
+        format: VideoFormat::Mjpeg,
+        average_fps: Video::DEFAULT.average_fps,
+        poster_path: Video::DEFAULT.poster_path,
+    };
+    video.create_using_self(&pool).await?;
+
+    let video_url = format!("{addr_str}api/videos/{}", video.video_id);
+    let client = reqwest::Client::new();
+
+    let full_response = client
+        .get(&video_url)
+        .header(header::COOKIE, &session_cookie)
+        .send()
+        .await?;
+
+    assert_eq!(full_response.status(), StatusCode::OK);
+    assert_eq!(
+        full_response.headers().get(header::CONTENT_TYPE),
+        Some(&header::HeaderValue::from_static("video/x-msvideo"))
+    );
+    assert_eq!(
+        full_response.headers().get(header::ACCEPT_RANGES),
+        Some(&header::HeaderValue::from_static("bytes"))
+    );
+    assert_eq!(
+        full_response.headers().get(header::CONTENT_DISPOSITION),
+        Some(&header::HeaderValue::from_static(
+            "attachment; filename=\"range.avi\""
+        ))
+    );
+    assert!(full_response.headers().contains_key(header::LAST_MODIFIED));
+
+    let Some(etag) = full_response.headers().get(header::ETAG).cloned() else {
+        return Err("No ETag returned".into());
+    };
+
+    assert_eq!(
+        full_response.bytes().await?.as_ref(),
+        video_bytes.as_slice()
+    );
+
+    let partial_response = client
+        .get(&video_url)
+        .header(header::COOKIE, &session_cookie)
+        .header(header::RANGE, "bytes=10-19")
+        .send()
+        .await?;
+
+    assert_eq!(partial_response.status(), StatusCode::PARTIAL_CONTENT);
+    assert_eq!(
+        partial_response.headers().get(header::CONTENT_RANGE),
+        Some(&header::HeaderValue::from_static("bytes 10-19/256"))
+    );
+    assert_eq!(
+        partial_response.bytes().await?.as_ref(),
+        video_bytes.get(10..20).unwrap_or_default()
+    );
+
+    let suffix_response = client
+        .get(&video_url)
+        .header(header::COOKIE, &session_cookie)
+        .header(header::RANGE, "bytes=-6")
+        .send()
+        .await?;
+
+    assert_eq!(suffix_response.status(), StatusCode::PARTIAL_CONTENT);
+    assert_eq!(
+        suffix_response.bytes().await?.as_ref(),
+        video_bytes.get(250..).unwrap_or_default()
+    );
+
+    let unsatisfiable_response = client
+        .get(&video_url)
+        .header(header::COOKIE, &session_cookie)
+        .header(header::RANGE, "bytes=256-")
+        .send()
+        .await?;
+
+    assert_eq!(
+        unsatisfiable_response.status(),
+        StatusCode::RANGE_NOT_SATISFIABLE
+    );
+    assert_eq!(
+        unsatisfiable_response.headers().get(header::CONTENT_RANGE),
+        Some(&header::HeaderValue::from_static("bytes */256"))
+    );
+
+    let not_modified_response = client
+        .get(&video_url)
+        .header(header::COOKIE, &session_cookie)
+        .header(header::IF_NONE_MATCH, etag.clone())
+        .send()
+        .await?;
+
+    assert_eq!(not_modified_response.status(), StatusCode::NOT_MODIFIED);
+
+    // Stale If-Range means the whole file is sent again
+    let stale_range_response = client
+        .get(&video_url)
+        .header(header::COOKIE, &session_cookie)
+        .header(header::RANGE, "bytes=10-19")
+        .header(header::IF_RANGE, "\"stale\"")
+        .send()
+        .await?;
+
+    assert_eq!(stale_range_response.status(), StatusCode::OK);
+
+    let inline_response = client
+        .get(format!("{video_url}?inline=true"))
+        .header(header::COOKIE, &session_cookie)
+        .header(header::RANGE, "bytes=0-0")
+        .header(header::IF_RANGE, etag)
+        .send()
+        .await?;
+
+    assert_eq!(inline_response.status(), StatusCode::PARTIAL_CONTENT);
+    assert_eq!(
+        inline_response.headers().get(header::CONTENT_DISPOSITION),
+        Some(&header::HeaderValue::from_static(
+            "inline; filename=\"range.avi\""
+        ))
+    );
+
+    Ok(())
+}
+
+#[sqlx::test(fixtures(
+    path = "../fixtures",
+    scripts("users", "cameras", "camera_permissions", "videos", "camera_settings")
+))]
+async fn video_poster_and_thumbnails(
+    pool: SqlitePool,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let (addr_str, _addr, video_temp_dir) = utils::setup_app(&pool).await?;
+    let admin_session_cookie = utils::login(&addr_str, "admin").await?;
+    let joedaly_session_cookie = utils::login(&addr_str, "joedaly").await?;
+
+    let poster_bytes = b"poster".to_vec();
+    let poster_pathbuf = video_temp_dir.path().join("kitchen_poster.jpg");
+    tokio::fs::write(&poster_pathbuf, &poster_bytes).await?;
+
+    let thumbnail_bytes = b"thumbnail".to_vec();
+    let thumbnail_pathbuf = video_temp_dir.path().join("kitchen_thumbnail_0.jpg");
+    tokio::fs::write(&thumbnail_pathbuf, &thumbnail_bytes).await?;
+
+    let mut video = Video {
+        video_id: Video::DEFAULT.video_id,
+        camera_id: Some(2),
+        file_path: video_temp_dir
+            .path()
+            .join("kitchen.avi")
+            .to_string_lossy()
+            .to_string(),
+        start_time: Video::DEFAULT.start_time(),
+        end_time: Video::DEFAULT.end_time,
+        file_size: None,
+        keep_forever: Video::DEFAULT.keep_forever,
+        format: VideoFormat::Mjpeg,
+        average_fps: Video::DEFAULT.average_fps,
+        poster_path: Some(poster_pathbuf.to_string_lossy().to_string()),
+    };
+    video.create_using_self(&pool).await?;
+
+    let mut thumbnail = VideoThumbnail {
+        thumbnail_id: VideoThumbnail::DEFAULT.thumbnail_id,
+        video_id: video.video_id,
+        file_path: thumbnail_pathbuf.to_string_lossy().to_string(),
+        offset_seconds: 0.0,
+    };
+    thumbnail.create_using_self(&pool).await?;
+
+    let video_url = format!("{addr_str}api/videos/{}", video.video_id);
+    let client = reqwest::Client::new();
+
+    let poster_response = client
+        .get(format!("{video_url}/poster.jpg"))
+        .header(header::COOKIE, &admin_session_cookie)
+        .send()
+        .await?;
+
+    assert_eq!(poster_response.status(), StatusCode::OK);
+    assert_eq!(
+        poster_response.headers().get(header::CONTENT_TYPE),
+        Some(&header::HeaderValue::from_static("image/jpeg"))
+    );
+    assert_eq!(
+        poster_response.bytes().await?.as_ref(),
+        poster_bytes.as_slice()
+    );
+
+    let thumbnails_response = client
+        .get(format!("{video_url}/thumbnails"))
+        .header(header::COOKIE, &admin_session_cookie)
+        .send()
+        .await?;
+
+    assert_eq!(thumbnails_response.status(), StatusCode::OK);
+    let thumbnails: Vec<VideoThumbnail> = serde_json::from_str(&thumbnails_response.text().await?)?;
+    assert_eq!(thumbnails.len(), 1);
+    assert_eq!(
+        thumbnails.first().map(|thumbnail| thumbnail.thumbnail_id),
+        Some(thumbnail.thumbnail_id)
+    );
+
+    let thumbnail_response = client
+        .get(format!("{video_url}/thumbnails/{}", thumbnail.thumbnail_id))
+        .header(header::COOKIE, &admin_session_cookie)
+        .send()
+        .await?;
+
+    assert_eq!(thumbnail_response.status(), StatusCode::OK);
+    assert_eq!(
+        thumbnail_response.bytes().await?.as_ref(),
+        thumbnail_bytes.as_slice()
+    );
+
+    // Thumbnails of other videos aren't served through this one
+    let other_video_thumbnail_response = client
+        .get(format!(
+            "{addr_str}api/videos/1/thumbnails/{}",
+            thumbnail.thumbnail_id
+        ))
+        .header(header::COOKIE, &admin_session_cookie)
+        .send()
+        .await?;
+
+    assert_eq!(
+        other_video_thumbnail_response.status(),
+        StatusCode::NOT_FOUND
+    );
+
+    // joedaly can only view the front door camera
+    for path in [
+        "poster.jpg".to_string(),
+        "thumbnails".to_string(),
+        format!("thumbnails/{}", thumbnail.thumbnail_id),
+    ] {
+        let forbidden_response = client
+            .get(format!("{video_url}/{path}"))
+            .header(header::COOKIE, &joedaly_session_cookie)
+            .send()
+            .await?;
+
+        assert_eq!(forbidden_response.status(), StatusCode::FORBIDDEN);
+    }
+
+    let no_poster_response = client
+        .get(format!("{addr_str}api/videos/1/poster.jpg"))
+        .header(header::COOKIE, &admin_session_cookie)
+        .send()
+        .await?;
+
+    assert_eq!(no_poster_response.status(), StatusCode::NOT_FOUND);
+
+    Ok(())
+}
+
+#[sqlx::test(fixtures(
+    path = "../fixtures",
+    scripts("users", "cameras", "camera_permissions", "camera_settings")
+))]
+async fn camera_pairing(pool: SqlitePool) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let (addr_str, addr, _video_temp_dir) = utils::setup_app(&pool).await?;
+    let session_cookie = utils::login(&addr_str, "admin").await?;
+
+    let added_camera_json = reqwest::Client::new()
+        .post(format!("{addr_str}api/cameras"))
+        .header(header::COOKIE, &session_cookie)
+        .form(&[
+            ("name", "Backyard"),
+            ("address", "127.0.0.1"),
+            ("skip_mdns_connect", "true"),
+        ])
+        .send()
+        .await?
+        .error_for_status()?
+        .text()
+        .await?;
+    let added_camera: serde_json::Value = serde_json::from_str(&added_camera_json)?;
+
+    assert_eq!(
+        added_camera
+            .get("camera_id")
+            .and_then(serde_json::Value::as_i64),
+        Some(3)
+    );
+    assert!(added_camera.get("token_hash").is_none());
+
+    let Some(pairing_token) = added_camera
+        .get("pairing_token")
+        .and_then(serde_json::Value::as_str)
+    else {
+        return Err("No pairing token returned".into());
+    };
+
+    let mut wrong_token_ws_stream = utils::setup_ws_with_port(addr, 40010).await?;
+    wrong_token_ws_stream
+        .send(utils::hello_message(&Hello {
+            any_port: true,
+            ..utils::camera_hello("wrong_token")
+        }))
+        .await?;
+
+    assert_eq!(
+        close_code(wrong_token_ws_stream.next().await),
+        Some(POLICY_CLOSE_CODE)
+    );
+
+    sleep(Duration::from_millis(100)).await;
+    assert!(!Camera::get_using_id(&pool, 3).await?.is_online);
+
+    let mut ws_stream = utils::setup_ws_with_port(addr, 40011).await?;
+    ws_stream
+        .send(utils::hello_message(&Hello {
+            any_port: true,
+            ..utils::camera_hello(pairing_token)
+        }))
+        .await?;
+
+    let reply = hello_reply(ws_stream.next().await)?;
+    assert_eq!(reply.camera_id, Some(3));
+
+    sleep(Duration::from_millis(100)).await;
+    assert!(Camera::get_using_id(&pool, 3).await?.is_online);
+
+    Ok(())
+}
+
+#[sqlx::test(fixtures(
+    path = "../fixtures",
+    scripts("users", "cameras", "camera_permissions", "camera_settings")
+))]
+async fn camera_pairing_token(
+    pool: SqlitePool,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let (addr_str, addr, _video_temp_dir) = utils::setup_app(&pool).await?;
+    let admin_session_cookie = utils::login(&addr_str, "admin").await?;
+    let operator_session_cookie = utils::login(&addr_str, "piotrpdev").await?;
+    let client = reqwest::Client::new();
+
+    // Cameras added before pairing tokens existed
+    let mut camera = Camera::get_using_id(&pool, 1).await?;
+    camera.token_hash = None;
+    camera.update_using_self(&pool).await?;
+
+    let operator_response = client
+        .post(format!("{addr_str}api/cameras/1/pairing_token"))
+        .header(header::COOKIE, &operator_session_cookie)
+        .send()
+        .await?;
+    assert_eq!(operator_response.status(), StatusCode::FORBIDDEN);
+
+    let missing_camera_response = client
+        .post(format!("{addr_str}api/cameras/99/pairing_token"))
+        .header(header::COOKIE, &admin_session_cookie)
+        .send()
+        .await?;
+    assert_eq!(missing_camera_response.status(), StatusCode::NOT_FOUND);
+
+    let pairing_token_json = client
+        .post(format!("{addr_str}api/cameras/1/pairing_token"))
+        .header(header::COOKIE, &admin_session_cookie)
+        .send()
+        .await?
+        .error_for_status()?
+        .text()
+        .await?;
+    let pairing_token_value: serde_json::Value = serde_json::from_str(&pairing_token_json)?;
+    let Some(pairing_token) = pairing_token_value
+        .get("pairing_token")
+        .and_then(serde_json::Value::as_str)
+    else {
+        return Err("No pairing token returned".into());
+    };
+
+    let camera = Camera::get_using_id(&pool, 1).await?;
+    assert!(camera.verify_pairing_token(pairing_token));
+    assert!(!camera.verify_pairing_token(utils::TEST_CAMERA_1_TOKEN));
+
+    let mut ws_stream = utils::setup_ws_with_port(addr, 40000).await?;
+    ws_stream
+        .send(utils::hello_message(&utils::camera_hello(pairing_token)))
+        .await?;
+
+    let reply = hello_reply(ws_stream.next().await)?;
+    assert_eq!(reply.camera_id, Some(1));
+
+    Ok(())
+}
+
+const PROTOCOL_CLOSE_CODE: u16 = 1002;
+const POLICY_CLOSE_CODE: u16 = 1008;
+
+fn close_code<E>(message: Option<Result<Message, E>>) -> Option<u16> {
+    match message {
+        Some(Ok(Message::Close(Some(close_frame)))) => Some(close_frame.code.into()),
+        _ => None,
+    }
+}
+
+fn hello_reply<E: std::fmt::Debug>(
+    message: Option<Result<Message, E>>,
+) -> Result<HelloReply, Box<dyn std::error::Error + Send + Sync>> {
+    match message {
+        Some(Ok(Message::Text(reply_json))) => Ok(serde_json::from_str(&reply_json)?),
+        other => Err(format!("Expected hello reply, got {other:?}").into()),
+    }
+}
+
+#[sqlx::test(fixtures(
+    path = "../fixtures",
+    scripts("users", "cameras", "camera_permissions", "camera_settings")
+))]
+async fn hello_handshake(pool: SqlitePool) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let (_addr_str, addr, _video_temp_dir) = utils::setup_app(&pool).await?;
+
+    // Newer clients are downgraded, unsupported resolutions are replaced
+    let mut camera_ws_stream = utils::setup_ws_with_port(addr, 40001).await?;
+    camera_ws_stream
+        .send(utils::hello_message(&Hello {
+            protocol_version: PROTOCOL_VERSION + 1,
+            firmware_version: Some("0.2.0".to_string()),
+            supported_resolutions: vec![Resolution::Vga],
+            ..utils::camera_hello(utils::TEST_CAMERA_2_TOKEN)
+        }))
+        .await?;
+
+    let reply = hello_reply(camera_ws_stream.next().await)?;
+    assert_eq!(reply.protocol_version, PROTOCOL_VERSION);
+    assert_eq!(reply.camera_id, Some(2));
+
+    let Some(settings) = reply.settings else {
+        return Err("No settings in hello reply".into());
+    };
+    assert_eq!(settings.resolution, Resolution::Vga);
+    assert_eq!(settings.framerate, 5);
+
+    let mut legacy_ws_stream = utils::setup_ws_with_port(addr, 40020).await?;
+    legacy_ws_stream
+        .send(Message::Text(format!(
+            "camera {}",
+            utils::TEST_CAMERA_2_TOKEN
+        )))
+        .await?;
+    assert_eq!(
+        close_code(legacy_ws_stream.next().await),
+        Some(PROTOCOL_CLOSE_CODE)
+    );
+
+    let mut old_ws_stream = utils::setup_ws_with_port(addr, 40021).await?;
+    old_ws_stream
+        .send(utils::hello_message(&Hello {
+            protocol_version: 0,
+            ..utils::camera_hello(utils::TEST_CAMERA_2_TOKEN)
+        }))
+        .await?;
+    assert_eq!(
+        close_code(old_ws_stream.next().await),
+        Some(PROTOCOL_CLOSE_CODE)
+    );
+
+    let mut viewer_ws_stream = utils::setup_ws_with_port(addr, 40022).await?;
+    viewer_ws_stream
+        .send(utils::hello_message(&Hello {
+            role: ClientRole::Viewer,
+            token: None,
+            ..utils::camera_hello("")
+        }))
+        .await?;
+    assert_eq!(
+        close_code(viewer_ws_stream.next().await),
+        Some(POLICY_CLOSE_CODE)
+    );
+
+    Ok(())
+}
+
+/// Skips control messages like `CameraListChange`
+async fn next_image<S, E>(
+    ws_stream: &mut S,
+) -> Result<Message, Box<dyn std::error::Error + Send + Sync>>
+where
+    S: Stream<Item = Result<Message, E>> + Unpin,
+    E: std::fmt::Debug,
+{
+    loop {
+        match ws_stream.next().await {
+            Some(Ok(Message::Text(text)))
+                if serde_json::from_str::<ImageContainer>(&text).is_err() => {}
+            Some(Ok(message @ (Message::Text(_) | Message::Binary(_)))) => return Ok(message),
+            other => return Err(format!("Expected image, got {other:?}").into()),
+        }
+    }
+}
+
+#[sqlx::test(fixtures(
+    path = "../fixtures",
+    scripts("users", "cameras", "camera_permissions", "camera_settings")
+))]
+async fn binary_frames(pool: SqlitePool) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let (addr_str, addr, _video_temp_dir) = utils::setup_app(&pool).await?;
+    let session_cookie = utils::login(&addr_str, "admin").await?;
+
+    let viewer_hello = Hello {
+        role: ClientRole::Viewer,
+        token: None,
+        binary_frames: true,
+        ..utils::camera_hello("")
+    };
+
+    let mut binary_ws_stream = utils::setup_viewer_ws(addr, 40030, &session_cookie).await?;
+    binary_ws_stream
+        .send(utils::hello_message(&viewer_hello))
+        .await?;
+    assert!(hello_reply(binary_ws_stream.next().await)?.binary_frames);
+
+    // Viewers that don't ask for binary frames keep getting JSON
+    let mut json_ws_stream = utils::setup_viewer_ws(addr, 40031, &session_cookie).await?;
+    json_ws_stream
+        .send(utils::hello_message(&Hello {
+            binary_frames: false,
+            ..viewer_hello
+        }))
+        .await?;
+    assert!(!hello_reply(json_ws_stream.next().await)?.binary_frames);
+
+    let mut camera_ws_stream = utils::setup_ws(addr).await?;
+    camera_ws_stream
+        .send(utils::camera_handshake(utils::TEST_CAMERA_2_TOKEN))
+        .await?;
+    hello_reply(camera_ws_stream.next().await)?;
+
+    for image in [
+        utils::REAL_TEST_IMG_1.to_vec(),
+        utils::REAL_TEST_IMG_2.to_vec(),
+    ] {
+        camera_ws_stream.send(Message::Binary(image)).await?;
+        sleep(Duration::from_millis(100)).await;
+    }
+
+    let mut sequences = Vec::new();
+    for expected_image in [&utils::REAL_TEST_IMG_1[..], &utils::REAL_TEST_IMG_2[..]] {
+        let Message::Binary(frame) = next_image(&mut binary_ws_stream).await? else {
+            return Err("Expected binary frame".into());
+        };
+        let Some((header, image_bytes)) = FrameHeader::decode(&frame) else {
+            return Err("Invalid frame header".into());
+        };
+
+        assert_eq!(header.camera_id, 2);
+        assert_eq!(image_bytes, expected_image);
+        sequences.push(header.sequence);
+
+        let Message::Text(image_json) = next_image(&mut json_ws_stream).await? else {
+            return Err("Expected JSON image".into());
+        };
+        let image_container: ImageContainer = serde_json::from_str(&image_json)?;
+
+        assert_eq!(image_container.timestamp, header.timestamp);
+        assert_eq!(image_container.sequence, header.sequence);
+        assert_eq!(image_container.image_bytes, expected_image);
+    }
+
+    assert_eq!(sequences, [0, 1]);
+
+    Ok(())
+}
+
+#[sqlx::test(fixtures(
+    path = "../fixtures",
+    scripts("users", "cameras", "camera_permissions", "camera_settings")
+))]
+async fn frames_from_multiple_cameras(
+    pool: SqlitePool,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let (addr_str, addr, _video_temp_dir) = utils::setup_app(&pool).await?;
+    let session_cookie = utils::login(&addr_str, "admin").await?;
+
+    let mut viewer_ws_stream = utils::setup_viewer_ws(addr, 40032, &session_cookie).await?;
+    viewer_ws_stream
+        .send(utils::hello_message(&Hello {
+            role: ClientRole::Viewer,
+            token: None,
+            binary_frames: true,
+            ..utils::camera_hello("")
+        }))
+        .await?;
+    hello_reply(viewer_ws_stream.next().await)?;
+
+    let mut front_door_ws_stream = utils::setup_ws_with_port(addr, 40000).await?;
+    front_door_ws_stream
+        .send(utils::camera_handshake(utils::TEST_CAMERA_1_TOKEN))
+        .await?;
+    hello_reply(front_door_ws_stream.next().await)?;
+
+    let mut kitchen_ws_stream = utils::setup_ws(addr).await?;
+    kitchen_ws_stream
+        .send(utils::camera_handshake(utils::TEST_CAMERA_2_TOKEN))
+        .await?;
+    hello_reply(kitchen_ws_stream.next().await)?;
+
+    // Sent back to back, images from one camera used to overwrite the other's
+    for _ in 0..2 {
+        front_door_ws_stream
+            .send(Message::Binary(utils::REAL_TEST_IMG_1.to_vec()))
+            .await?;
+        kitchen_ws_stream
+            .send(Message::Binary(utils::REAL_TEST_IMG_2.to_vec()))
+            .await?;
+    }
+
+    let mut received = Vec::new();
+    for _ in 0..4 {
+        let Message::Binary(frame) = next_image(&mut viewer_ws_stream).await? else {
+            return Err("Expected binary frame".into());
+        };
+        let Some((header, image_bytes)) = FrameHeader::decode(&frame) else {
+            return Err("Invalid frame header".into());
+        };
+
+        let expected_image = if header.camera_id == 1 {
+            &utils::REAL_TEST_IMG_1[..]
+        } else {
+            &utils::REAL_TEST_IMG_2[..]
+        };
+        assert_eq!(image_bytes, expected_image);
+        received.push((header.camera_id, header.sequence));
+    }
+
+    received.sort_unstable();
+    assert_eq!(received, [(1, 0), (1, 1), (2, 0), (2, 1)]);
+
+    Ok(())
+}
+
+#[sqlx::test(fixtures(
+    path = "../fixtures",
+    scripts("users", "cameras", "camera_permissions", "camera_settings")
+))]
+async fn mjpeg_stream(pool: SqlitePool) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let (addr_str, addr, _video_temp_dir) = utils::setup_app(&pool).await?;
+    let admin_cookie = utils::login(&addr_str, "admin").await?;
+    let joedaly_cookie = utils::login(&addr_str, "joedaly").await?;
+    let client = reqwest::Client::new();
+
+    // joedaly can't view the kitchen camera
+    let forbidden_response = client
+        .get(format!("{addr_str}api/cameras/2/stream.mjpeg"))
+        .header(header::COOKIE, &joedaly_cookie)
+        .send()
+        .await?;
+    assert_eq!(forbidden_response.status(), StatusCode::FORBIDDEN);
+
+    let mut stream_response = client
+        .get(format!("{addr_str}api/cameras/2/stream.mjpeg"))
+        .header(header::COOKIE, &admin_cookie)
+        .send()
+        .await?
+        .error_for_status()?;
+    assert_eq!(
+        stream_response.headers().get(header::CONTENT_TYPE),
+        Some(&header::HeaderValue::from_static(
+            "multipart/x-mixed-replace; boundary=oko-frame"
+        ))
+    );
+
+    let mut camera_ws_stream = utils::setup_ws(addr).await?;
+    camera_ws_stream
+        .send(utils::camera_handshake(utils::TEST_CAMERA_2_TOKEN))
+        .await?;
+    hello_reply(camera_ws_stream.next().await)?;
+
+    for image in [&utils::REAL_TEST_IMG_1[..], &utils::REAL_TEST_IMG_2[..]] {
+        camera_ws_stream
+            .send(Message::Binary(image.to_vec()))
+            .await?;
+    }
+
+    let mut expected_body = Vec::new();
+    for image in [&utils::REAL_TEST_IMG_1[..], &utils::REAL_TEST_IMG_2[..]] {
+        expected_body.extend_from_slice(
+            format!(
+                "--oko-frame\r\nContent-Type: image/jpeg\r\nContent-Length: {}\r\n\r\n",
+                image.len()
+            )
+            .as_bytes(),
+        );
+        expected_body.extend_from_slice(image);
+        expected_body.extend_from_slice(b"\r\n");
+    }
+
+    let mut body = Vec::new();
+    while body.len() < expected_body.len() {
+        let Some(chunk) = stream_response.chunk().await? else {
+            break;
+        };
+        body.extend_from_slice(&chunk);
+    }
+    assert_eq!(body, expected_body);
+
+    Ok(())
+}
+
+#[sqlx::test(fixtures(
+    path = "../fixtures",
+    scripts("users", "cameras", "camera_permissions", "camera_settings")
+))]
+async fn camera_snapshot(pool: SqlitePool) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let (addr_str, addr, _video_temp_dir) = utils::setup_app(&pool).await?;
+    let admin_cookie = utils::login(&addr_str, "admin").await?;
+    let joedaly_cookie = utils::login(&addr_str, "joedaly").await?;
+    let client = reqwest::Client::new();
+    let snapshot_url = format!("{addr_str}api/cameras/2/snapshot.jpg");
+
+    let offline_response = client
+        .get(&snapshot_url)
+        .header(header::COOKIE, &admin_cookie)
+        .send()
+        .await?;
+    assert_eq!(offline_response.status(), StatusCode::SERVICE_UNAVAILABLE);
+
+    let mut camera_ws_stream = utils::setup_ws(addr).await?;
+    camera_ws_stream
+        .send(utils::camera_handshake(utils::TEST_CAMERA_2_TOKEN))
+        .await?;
+    hello_reply(camera_ws_stream.next().await)?;
+
+    for image in [&utils::REAL_TEST_IMG_1[..], &utils::REAL_TEST_IMG_2[..]] {
+        camera_ws_stream
+            .send(Message::Binary(image.to_vec()))
+            .await?;
+    }
+    sleep(Duration::from_millis(100)).await;
+
+    // joedaly can't view the kitchen camera
+    let forbidden_response = client
+        .get(&snapshot_url)
+        .header(header::COOKIE, &joedaly_cookie)
+        .send()
+        .await?;
+    assert_eq!(forbidden_response.status(), StatusCode::FORBIDDEN);
+
+    let snapshot_response = client
+        .get(&snapshot_url)
+        .header(header::COOKIE, &admin_cookie)
+        .send()
+        .await?
+        .error_for_status()?;
+    assert_eq!(
+        snapshot_response.headers().get(header::CONTENT_TYPE),
+        Some(&header::HeaderValue::from_static("image/jpeg"))
+    );
+    assert!(snapshot_response
+        .headers()
+        .get("x-timestamp")
+        .and_then(|timestamp| timestamp.to_str().ok())
+        .is_some_and(|timestamp| timestamp.parse::<i64>().is_ok()));
+    assert_eq!(
+        snapshot_response.bytes().await?,
+        &utils::REAL_TEST_IMG_2[..]
+    );
+
+    let resized_bytes = client
+        .get(format!("{snapshot_url}?width=80"))
+        .header(header::COOKIE, &admin_cookie)
+        .send()
+        .await?
+        .error_for_status()?
+        .bytes()
+        .await?;
+    let resized_image = imdecode(&resized_bytes.as_ref(), IMREAD_COLOR)?;
+    let original_image = imdecode(&utils::REAL_TEST_IMG_2.as_slice(), IMREAD_COLOR)?;
+    assert_eq!(resized_image.cols(), 80);
+    assert_eq!(
+        resized_image.rows(),
+        original_image.rows() * 80 / original_image.cols()
+    );
+
+    let invalid_response = client
+        .get(format!("{snapshot_url}?width=0"))
+        .header(header::COOKIE, &admin_cookie)
+        .send()
+        .await?;
+    assert_eq!(invalid_response.status(), StatusCode::BAD_REQUEST);
+
+    // The recording is finished before the camera is marked as offline
+    camera_ws_stream.close(None).await?;
+    sleep(Duration::from_secs(1)).await;
+
+    let disconnected_response = client
+        .get(&snapshot_url)
+        .header(header::COOKIE, &admin_cookie)
+        .send()
+        .await?;
+    assert_eq!(
+        disconnected_response.status(),
+        StatusCode::SERVICE_UNAVAILABLE
+    );
+
+    Ok(())
+}
+
+/// Skips messages until an event is pushed
+async fn next_event<S, E>(
+    ws_stream: &mut S,
+) -> Result<Event, Box<dyn std::error::Error + Send + Sync>>
+where
+    S: Stream<Item = Result<Message, E>> + Unpin,
+    E: std::fmt::Debug,
+{
+    loop {
+        match ws_stream.next().await {
+            Some(Ok(Message::Text(text))) => {
+                if let Ok(
+                    ApiChannelMessage::EventCreated(event) | ApiChannelMessage::EventUpdated(event),
+                ) = serde_json::from_str(&text)
+                {
+                    return Ok(event);
+                }
+            }
+            Some(Ok(Message::Binary(_))) => {}
+            other => return Err(format!("Expected event, got {other:?}").into()),
+        }
+    }
+}
+
+#[sqlx::test(fixtures(
+    path = "../fixtures",
+    scripts("users", "cameras", "camera_permissions", "camera_settings")
+))]
+async fn events(pool: SqlitePool) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let (addr_str, addr, _video_temp_dir) = utils::setup_app(&pool).await?;
+    let admin_session_cookie = utils::login(&addr_str, "admin").await?;
+    let joedaly_session_cookie = utils::login(&addr_str, "joedaly").await?;
+
+    let viewer_hello = Hello {
+        role: ClientRole::Viewer,
+        token: None,
+        ..utils::camera_hello("")
+    };
+
+    let mut admin_ws_stream = utils::setup_viewer_ws(addr, 40033, &admin_session_cookie).await?;
+    admin_ws_stream
+        .send(utils::hello_message(&viewer_hello))
+        .await?;
+    hello_reply(admin_ws_stream.next().await)?;
+
+    let mut joedaly_ws_stream =
+        utils::setup_viewer_ws(addr, 40034, &joedaly_session_cookie).await?;
+    joedaly_ws_stream
+        .send(utils::hello_message(&viewer_hello))
+        .await?;
+    hello_reply(joedaly_ws_stream.next().await)?;
+
+    let mut camera_ws_stream = utils::setup_ws(addr).await?;
+    camera_ws_stream
+        .send(utils::camera_handshake(utils::TEST_CAMERA_2_TOKEN))
+        .await?;
+    hello_reply(camera_ws_stream.next().await)?;
+
+    let online_event = next_event(&mut admin_ws_stream).await?;
+    assert_eq!(online_event.camera_id, Some(2));
+    assert_eq!(online_event.kind, EventKind::CameraOnline);
+
+    let client = reqwest::Client::new();
+    let failed_login_response = client
+        .post(format!("{addr_str}api/login"))
+        .form(&[("username", "joedaly"), ("password", "wrong")])
+        .send()
+        .await?;
+    assert_eq!(failed_login_response.status(), StatusCode::UNAUTHORIZED);
+
+    let login_failed_event = next_event(&mut admin_ws_stream).await?;
+    assert_eq!(login_failed_event.camera_id, None);
+    assert_eq!(login_failed_event.kind, EventKind::LoginFailed);
+    assert!(login_failed_event
+        .details
+        .is_some_and(|details| details.starts_with("joedaly")));
+
+    // Further attempts from the same address update the event instead of creating new ones
+    let long_username = "a".repeat(1000);
+    let repeated_login_response = client
+        .post(format!("{addr_str}api/login"))
+        .form(&[("username", long_username.as_str()), ("password", "wrong")])
+        .send()
+        .await?;
+    assert_eq!(repeated_login_response.status(), StatusCode::UNAUTHORIZED);
+
+    let updated_login_failed_event = next_event(&mut admin_ws_stream).await?;
+    assert_eq!(
+        updated_login_failed_event.event_id,
+        login_failed_event.event_id
+    );
+    assert!(updated_login_failed_event.end_time.is_some());
+    assert!(updated_login_failed_event.details.is_some_and(|details| {
+        details.ends_with("2 attempts") && !details.contains(&long_username)
+    }));
+
+    // joedaly can't view the kitchen camera and isn't an admin
+    assert!(tokio::time::timeout(
+        Duration::from_millis(500),
+        next_event(&mut joedaly_ws_stream)
+    )
+    .await
+    .is_err());
+
+    let events_response = client
+        .get(format!("{addr_str}api/events?kind=login_failed"))
+        .header(header::COOKIE, &admin_session_cookie)
+        .send()
+        .await?;
+    assert_eq!(events_response.status(), StatusCode::OK);
+    let events: Vec<Event> = serde_json::from_str(&events_response.text().await?)?;
+    assert_eq!(events.len(), 1);
+    assert_eq!(
+        events.first().map(|event| event.event_id),
+        Some(login_failed_event.event_id)
+    );
+
+    let joedaly_events_response = client
+        .get(format!("{addr_str}api/events"))
+        .header(header::COOKIE, &joedaly_session_cookie)
+        .send()
+        .await?;
+    let joedaly_events: Vec<Event> = serde_json::from_str(&joedaly_events_response.text().await?)?;
+    assert!(joedaly_events.is_empty());
+
+    let event_url = format!("{addr_str}api/events/{}", login_failed_event.event_id);
+
+    let forbidden_response = client
+        .patch(&event_url)
+        .header(header::COOKIE, &joedaly_session_cookie)
+        .form(&[("acknowledged", "true")])
+        .send()
+        .await?;
+    assert_eq!(forbidden_response.status(), StatusCode::FORBIDDEN);
+
+    let acknowledge_response = client
+        .patch(&event_url)
+        .header(header::COOKIE, &admin_session_cookie)
+        .form(&[("acknowledged", "true")])
+        .send()
+        .await?;
+    assert_eq!(acknowledge_response.status(), StatusCode::OK);
+
+    let acknowledged_event = next_event(&mut admin_ws_stream).await?;
+    assert_eq!(acknowledged_event.event_id, login_failed_event.event_id);
+    assert!(acknowledged_event.acknowledged);
+
+    let unacknowledged_response = client
+        .get(format!("{addr_str}api/events?acknowledged=false"))
+        .header(header::COOKIE, &admin_session_cookie)
+        .send()
+        .await?;
+    let unacknowledged_events: Vec<Event> =
+        serde_json::from_str(&unacknowledged_response.text().await?)?;
+    assert!(unacknowledged_events
+        .iter()
+        .all(|event| event.event_id != login_failed_event.event_id));
+
+    Ok(())
+}
+
+type ReceivedWebhooks = Arc<Mutex<Vec<(header::HeaderMap, String)>>>;
+
+/// Records every request it receives, failing every other one starting with the first
+async fn webhook_receiver(
+    axum::extract::State(received): axum::extract::State<ReceivedWebhooks>,
+    headers: header::HeaderMap,
+    body: String,
+) -> StatusCode {
+    let Ok(mut received) = received.lock() else {
+        return StatusCode::INTERNAL_SERVER_ERROR;
+    };
+
+    received.push((headers, body));
+
+    if received.len() % 2 == 1 {
+        StatusCode::INTERNAL_SERVER_ERROR
+    } else {
+        StatusCode::OK
+    }
+}
+
+#[sqlx::test(fixtures(
+    path = "../fixtures",
+    scripts(
+        "users",
+        "cameras",
+        "camera_permissions",
+        "camera_settings",
+        "webhooks"
+    )
+))]
+async fn webhooks(pool: SqlitePool) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let (addr_str, addr, _video_temp_dir) = utils::setup_app(&pool).await?;
+    let admin_session_cookie = utils::login(&addr_str, "admin").await?;
+    let joedaly_session_cookie = utils::login(&addr_str, "joedaly").await?;
+
+    let received = ReceivedWebhooks::default();
+    let receiver_listener = tokio::net::TcpListener::bind("127.0.0.1:0").await?;
+    let receiver_url = format!("http://{}/hook", receiver_listener.local_addr()?);
+    let receiver = axum::Router::new()
+        .route("/hook", axum::routing::post(webhook_receiver))
+        .with_state(received.clone());
+    tokio::spawn(async move { axum::serve(receiver_listener, receiver).await });
+
+    let client = reqwest::Client::new();
+
+    let forbidden_response = client
+        .get(format!("{addr_str}api/webhooks"))
+        .header(header::COOKIE, &joedaly_session_cookie)
+        .send()
+        .await?;
+    assert_eq!(forbidden_response.status(), StatusCode::FORBIDDEN);
+
+    let invalid_url_response = client
+        .post(format!("{addr_str}api/webhooks"))
+        .header(header::COOKIE, &admin_session_cookie)
+        .form(&[("url", "ftp://example.com")])
+        .send()
+        .await?;
+    assert_eq!(invalid_url_response.status(), StatusCode::BAD_REQUEST);
+
+    // Keep the fixture webhook out of the way
+    let disable_response = client
+        .patch(format!("{addr_str}api/webhooks/1"))
+        .header(header::COOKIE, &admin_session_cookie)
+        .form(&[("enabled", "false")])
+        .send()
+        .await?;
+    assert_eq!(disable_response.status(), StatusCode::OK);
+
+    let add_response = client
+        .post(format!("{addr_str}api/webhooks"))
+        .header(header::COOKIE, &admin_session_cookie)
+        .form(&[("url", receiver_url.as_str())])
+        .send()
+        .await?;
+    assert_eq!(add_response.status(), StatusCode::OK);
+    let added_webhook: serde_json::Value = serde_json::from_str(&add_response.text().await?)?;
+    let webhook_id = added_webhook
+        .get("webhook_id")
+        .and_then(serde_json::Value::as_i64)
+        .ok_or("No webhook_id")?;
+    let secret = added_webhook
+        .get("secret")
+        .and_then(serde_json::Value::as_str)
+        .ok_or("No secret")?
+        .to_string();
+
+    let list_response = client
+        .get(format!("{addr_str}api/webhooks"))
+        .header(header::COOKIE, &admin_session_cookie)
+        .send()
+        .await?;
+    assert!(!list_response.text().await?.contains(&secret));
+
+    let test_url = format!("{addr_str}api/webhooks/{webhook_id}/test");
+
+    let failed_test_response = client
+        .post(&test_url)
+        .header(header::COOKIE, &admin_session_cookie)
+        .send()
+        .await?;
+    let failed_test: WebhookDelivery = serde_json::from_str(&failed_test_response.text().await?)?;
+    assert!(!failed_test.delivered);
+    assert_eq!(failed_test.status_code, Some(500));
+
+    let test_response = client
+        .post(&test_url)
+        .header(header::COOKIE, &admin_session_cookie)
+        .send()
+        .await?;
+    let test: WebhookDelivery = serde_json::from_str(&test_response.text().await?)?;
+    assert!(test.delivered);
+    assert_eq!(test.kind, WebhookKind::Test);
+
+    let (headers, body) = received
+        .lock()
+        .map_err(|_| "Poisoned")?
+        .last()
+        .cloned()
+        .ok_or("Nothing received")?;
+
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes())?;
+    mac.update(body.as_bytes());
+    let signature = format!("sha256={}", hex::encode(mac.finalize().into_bytes()));
+
+    assert_eq!(
+        headers
+            .get("x-oko-signature")
+            .map(header::HeaderValue::as_bytes),
+        Some(signature.as_bytes())
+    );
+    assert_eq!(
+        headers
+            .get("x-oko-event")
+            .map(header::HeaderValue::as_bytes),
+        Some(b"test".as_slice())
+    );
+    assert_eq!(
+        headers
+            .get("x-oko-delivery")
+            .map(header::HeaderValue::as_bytes),
+        Some(test.delivery_id.to_string().as_bytes())
+    );
+
+    let mut camera_ws_stream = utils::setup_ws(addr).await?;
+    camera_ws_stream
+        .send(utils::camera_handshake(utils::TEST_CAMERA_2_TOKEN))
+        .await?;
+    hello_reply(camera_ws_stream.next().await)?;
+    camera_ws_stream.close(None).await?;
+
+    // The first attempt fails, the retry should go through a second later
+    let mut offline_delivery = None;
+    for _ in 0..50 {
+        let deliveries_response = client
+            .get(format!("{addr_str}api/webhooks/{webhook_id}/deliveries"))
+            .header(header::COOKIE, &admin_session_cookie)
+            .send()
+            .await?;
+        let deliveries: Vec<WebhookDelivery> =
+            serde_json::from_str(&deliveries_response.text().await?)?;
+
+        offline_delivery = deliveries
+            .into_iter()
+            .find(|delivery| delivery.kind == WebhookKind::CameraOffline && delivery.delivered);
+
+        if offline_delivery.is_some() {
+            break;
+        }
+
+        sleep(Duration::from_millis(100)).await;
+    }
+
+    let offline_delivery = offline_delivery.ok_or("Camera offline webhook wasn't delivered")?;
+    assert_eq!(offline_delivery.attempts, 2);
+    assert!(offline_delivery.payload.contains(r#""camera_id":2"#));
+
+    Ok(())
+}
+
+#[sqlx::test(fixtures(
+    path = "../fixtures",
+    scripts("users", "cameras", "camera_permissions", "camera_settings")
+))]
+async fn arming(pool: SqlitePool) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let (addr_str, addr, _video_temp_dir) = utils::setup_app(&pool).await?;
+    let admin_session_cookie = utils::login(&addr_str, "admin").await?;
+    let piotrpdev_session_cookie = utils::login(&addr_str, "piotrpdev").await?;
+    let joedaly_session_cookie = utils::login(&addr_str, "joedaly").await?;
+
+    let viewer_hello = Hello {
+        role: ClientRole::Viewer,
+        token: None,
+        ..utils::camera_hello("")
+    };
+
+    let mut admin_ws_stream = utils::setup_viewer_ws(addr, 40035, &admin_session_cookie).await?;
+    admin_ws_stream
+        .send(utils::hello_message(&viewer_hello))
+        .await?;
+    hello_reply(admin_ws_stream.next().await)?;
+
+    let client = reqwest::Client::new();
+
+    let arming_response = client
+        .get(format!("{addr_str}api/arming"))
+        .header(header::COOKIE, &joedaly_session_cookie)
+        .send()
+        .await?;
+    let arming_state: ArmingState = serde_json::from_str(&arming_response.text().await?)?;
+    assert_eq!(arming_state.mode, ArmingMode::Away);
+
+    // Viewers can't arm/disarm
+    let forbidden_response = client
+        .patch(format!("{addr_str}api/arming"))
+        .header(header::COOKIE, &joedaly_session_cookie)
+        .form(&[("mode", "disarmed")])
+        .send()
+        .await?;
+    assert_eq!(forbidden_response.status(), StatusCode::FORBIDDEN);
+
+    let night_response = client
+        .patch(format!("{addr_str}api/arming"))
+        .header(header::COOKIE, &piotrpdev_session_cookie)
+        .form(&[("mode", "night")])
+        .send()
+        .await?;
+    assert_eq!(night_response.status(), StatusCode::OK);
+
+    let changed_state = tokio::time::timeout(Duration::from_secs(5), async {
+        loop {
+            match admin_ws_stream.next().await {
+                Some(Ok(Message::Text(text))) => {
+                    if let Ok(ApiChannelMessage::ArmingChanged(changed_state)) =
+                        serde_json::from_str(&text)
+                    {
+                        return Ok(changed_state);
+                    }
+                }
+                Some(Ok(_)) => {}
+                other => return Err(format!("Expected arming change, got {other:?}")),
+            }
+        }
+    })
+    .await??;
+    assert_eq!(changed_state.mode, ArmingMode::Night);
+    assert_eq!(changed_state.modified_by, Some(2));
+
+    let schedules_url = format!("{addr_str}api/cameras/2/arming_schedules");
+
+    let forbidden_schedule_response = client
+        .post(&schedules_url)
+        .header(header::COOKIE, &joedaly_session_cookie)
+        .form(&[
+            ("mode", "night"),
+            ("day_of_week", "0"),
+            ("start_minute", "0"),
+            ("end_minute", "420"),
+        ])
+        .send()
+        .await?;
+    assert_eq!(forbidden_schedule_response.status(), StatusCode::FORBIDDEN);
+
+    let invalid_schedule_response = client
+        .post(&schedules_url)
+        .header(header::COOKIE, &piotrpdev_session_cookie)
+        .form(&[
+            ("mode", "night"),
+            ("day_of_week", "0"),
+            ("start_minute", "420"),
+            ("end_minute", "0"),
+        ])
+        .send()
+        .await?;
+    assert_eq!(invalid_schedule_response.status(), StatusCode::BAD_REQUEST);
+
+    let missing_camera_response = client
+        .post(format!("{addr_str}api/cameras/99/arming_schedules"))
+        .header(header::COOKIE, &piotrpdev_session_cookie)
+        .form(&[
+            ("mode", "night"),
+            ("day_of_week", "0"),
+            ("start_minute", "0"),
+            ("end_minute", "420"),
+        ])
+        .send()
+        .await?;
+    assert_eq!(missing_camera_response.status(), StatusCode::NOT_FOUND);
+
+    let schedule_response = client
+        .post(&schedules_url)
+        .header(header::COOKIE, &piotrpdev_session_cookie)
+        .form(&[
+            ("mode", "night"),
+            ("day_of_week", "0"),
+            ("start_minute", "0"),
+            ("end_minute", "420"),
+        ])
+        .send()
+        .await?;
+    assert_eq!(schedule_response.status(), StatusCode::OK);
+    let schedule: ArmingSchedule = serde_json::from_str(&schedule_response.text().await?)?;
+    assert_eq!(schedule.camera_id, 2);
+
+    // joedaly can't view the kitchen camera
+    let forbidden_list_response = client
+        .get(&schedules_url)
+        .header(header::COOKIE, &joedaly_session_cookie)
+        .send()
+        .await?;
+    assert_eq!(forbidden_list_response.status(), StatusCode::FORBIDDEN);
+
+    let list_response = client
+        .get(&schedules_url)
+        .header(header::COOKIE, &piotrpdev_session_cookie)
+        .send()
+        .await?;
+    let schedules: Vec<ArmingSchedule> = serde_json::from_str(&list_response.text().await?)?;
+    assert_eq!(schedules.len(), 1);
+
+    let schedule_url = format!("{addr_str}api/arming_schedules/{}", schedule.schedule_id);
+
+    // Operators also need to be able to control the camera
+    let set_control = |can_control: &'static str| {
+        client
+            .patch(format!("{addr_str}api/permissions/4"))
+            .header(header::COOKIE, &admin_session_cookie)
+            .form(&[("can_view", "true"), ("can_control", can_control)])
+            .send()
+    };
+    assert_eq!(set_control("false").await?.status(), StatusCode::OK);
+
+    let no_control_add_response = client
+        .post(&schedules_url)
+        .header(header::COOKIE, &piotrpdev_session_cookie)
+        .form(&[
+            ("mode", "away"),
+            ("day_of_week", "1"),
+            ("start_minute", "0"),
+            ("end_minute", "60"),
+        ])
+        .send()
+        .await?;
+    assert_eq!(no_control_add_response.status(), StatusCode::FORBIDDEN);
+
+    let no_control_delete_response = client
+        .delete(&schedule_url)
+        .header(header::COOKIE, &piotrpdev_session_cookie)
+        .send()
+        .await?;
+    assert_eq!(no_control_delete_response.status(), StatusCode::FORBIDDEN);
+
+    assert_eq!(set_control("true").await?.status(), StatusCode::OK);
+
+    let delete_response = client
+        .delete(&schedule_url)
+        .header(header::COOKIE, &piotrpdev_session_cookie)
+        .send()
+        .await?;
+    assert_eq!(delete_response.status(), StatusCode::OK);
+
+    let missing_delete_response = client
+        .delete(&schedule_url)
+        .header(header::COOKIE, &piotrpdev_session_cookie)
+        .send()
+        .await?;
+    assert_eq!(missing_delete_response.status(), StatusCode::NOT_FOUND);
+
+    Ok(())
+}
+
+#[sqlx::test(fixtures(
+    path = "../fixtures",
+    scripts("users", "cameras", "camera_permissions", "camera_settings")
+))]
+async fn privacy_masks(pool: SqlitePool) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let (addr_str, addr, _video_temp_dir) = utils::setup_app(&pool).await?;
+    let admin_cookie = utils::login(&addr_str, "admin").await?;
+    let piotrpdev_cookie = utils::login(&addr_str, "piotrpdev").await?;
+    let joedaly_cookie = utils::login(&addr_str, "joedaly").await?;
+    let client = reqwest::Client::new();
+    let masks_url = format!("{addr_str}api/cameras/2/privacy_masks");
+    let snapshot_url = format!("{addr_str}api/cameras/2/snapshot.jpg");
+
+    let mut camera_ws_stream = utils::setup_ws(addr).await?;
+    camera_ws_stream
+        .send(utils::camera_handshake(utils::TEST_CAMERA_2_TOKEN))
+        .await?;
+    hello_reply(camera_ws_stream.next().await)?;
+
+    camera_ws_stream
+        .send(Message::Binary(utils::REAL_TEST_IMG_1.to_vec()))
+        .await?;
+    sleep(Duration::from_millis(100)).await;
+
+    let unmasked_bytes = client
+        .get(&snapshot_url)
+        .header(header::COOKIE, &admin_cookie)
+        .send()
+        .await?
+        .bytes()
+        .await?;
+    assert_eq!(unmasked_bytes, &utils::REAL_TEST_IMG_1[..]);
+
+    let whole_frame = serde_json::json!({
+        "name": "Everything",
+        "points": [
+            {"x": 0.0, "y": 0.0},
+            {"x": 1.0, "y": 0.0},
+            {"x": 1.0, "y": 1.0},
+            {"x": 0.0, "y": 1.0}
+        ]
+    });
+
+    // Only admins can change masks
+    let forbidden_response = client
+        .post(&masks_url)
+        .header(header::COOKIE, &piotrpdev_cookie)
+        .header(header::CONTENT_TYPE, "application/json")
+        .body(whole_frame.to_string())
+        .send()
+        .await?;
+    assert_eq!(forbidden_response.status(), StatusCode::FORBIDDEN);
+
+    let invalid_response = client
+        .post(&masks_url)
+        .header(header::COOKIE, &admin_cookie)
+        .header(header::CONTENT_TYPE, "application/json")
+        .body(r#"{"name":"Line","points":[{"x":0.0,"y":0.0},{"x":1.0,"y":1.0}]}"#)
+        .send()
+        .await?;
+    assert_eq!(invalid_response.status(), StatusCode::BAD_REQUEST);
+
+    let add_response = client
+        .post(&masks_url)
+        .header(header::COOKIE, &admin_cookie)
+        .header(header::CONTENT_TYPE, "application/json")
+        .body(whole_frame.to_string())
+        .send()
+        .await?;
+    assert_eq!(add_response.status(), StatusCode::OK);
+    let mask: PrivacyMask = serde_json::from_str(&add_response.text().await?)?;
+    assert_eq!(mask.points.len(), 4);
+
+    // Applied to frames received from now on, without reconnecting
+    camera_ws_stream
+        .send(Message::Binary(utils::REAL_TEST_IMG_2.to_vec()))
+        .await?;
+    sleep(Duration::from_millis(200)).await;
+
+    let masked_bytes = client
+        .get(&snapshot_url)
+        .header(header::COOKIE, &admin_cookie)
+        .send()
+        .await?
+        .bytes()
+        .await?;
+    let masked_image = imdecode(&masked_bytes.as_ref(), IMREAD_COLOR)?;
+    let original_image = imdecode(&utils::REAL_TEST_IMG_2.as_slice(), IMREAD_COLOR)?;
+    assert_eq!(masked_image.size()?, original_image.size()?);
+    for (row, col) in [(0, 0), (masked_image.rows() / 2, masked_image.cols() / 2)] {
+        assert!(masked_image
+            .at_2d::<Vec3b>(row, col)?
+            .iter()
+            .all(|channel| *channel < 10));
+    }
+
+    let list_response = client
+        .get(&masks_url)
+        .header(header::COOKIE, &piotrpdev_cookie)
+        .send()
+        .await?;
+    let masks: Vec<PrivacyMask> = serde_json::from_str(&list_response.text().await?)?;
+    assert_eq!(masks.len(), 1);
+
+    // joedaly can't view the kitchen camera
+    let forbidden_list_response = client
+        .get(&masks_url)
+        .header(header::COOKIE, &joedaly_cookie)
+        .send()
+        .await?;
+    assert_eq!(forbidden_list_response.status(), StatusCode::FORBIDDEN);
+
+    let delete_response = client
+        .delete(format!("{addr_str}api/privacy_masks/{}", mask.mask_id))
+        .header(header::COOKIE, &admin_cookie)
+        .send()
+        .await?;
+    assert_eq!(delete_response.status(), StatusCode::OK);
+
+    camera_ws_stream
+        .send(Message::Binary(utils::REAL_TEST_IMG_1.to_vec()))
+        .await?;
+    sleep(Duration::from_millis(100)).await;
+
+    let unmasked_again_bytes = client
+        .get(&snapshot_url)
+        .header(header::COOKIE, &admin_cookie)
+        .send()
+        .await?
+        .bytes()
+        .await?;
+    assert_eq!(unmasked_again_bytes, &utils::REAL_TEST_IMG_1[..]);
+
+    Ok(())
+}
+
+#[sqlx::test(fixtures(
+    path = "../fixtures",
+    scripts(
+        "users",
+        "cameras",
+        "camera_permissions",
+        "camera_settings",
+        "motion_zones"
+    )
+))]
+async fn motion_zones(pool: SqlitePool) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let (addr_str, _addr, _video_temp_dir) = utils::setup_app(&pool).await?;
+    let piotrpdev_cookie = utils::login(&addr_str, "piotrpdev").await?;
+    let joedaly_cookie = utils::login(&addr_str, "joedaly").await?;
+    let client = reqwest::Client::new();
+    let zones_url = format!("{addr_str}api/cameras/2/motion_zones");
+
+    let road = serde_json::json!({
+        "name": "Road",
+        "kind": "exclude",
+        "points": [
+            {"x": 0.0, "y": 0.8},
+            {"x": 1.0, "y": 0.8},
+            {"x": 1.0, "y": 1.0},
+            {"x": 0.0, "y": 1.0}
+        ]
+    });
+
+    // Viewers can't change zones
+    let forbidden_response = client
+        .post(&zones_url)
+        .header(header::COOKIE, &joedaly_cookie)
+        .header(header::CONTENT_TYPE, "application/json")
+        .body(road.to_string())
+        .send()
+        .await?;
+    assert_eq!(forbidden_response.status(), StatusCode::FORBIDDEN);
+
+    let invalid_response = client
+        .post(&zones_url)
+        .header(header::COOKIE, &piotrpdev_cookie)
+        .header(header::CONTENT_TYPE, "application/json")
+        .body(r#"{"name":"Road","kind":"exclude","points":[{"x":0.0,"y":0.0},{"x":2.0,"y":0.0},{"x":1.0,"y":1.0}]}"#)
+        .send()
+        .await?;
+    assert_eq!(invalid_response.status(), StatusCode::BAD_REQUEST);
+
+    let add_response = client
+        .post(&zones_url)
+        .header(header::COOKIE, &piotrpdev_cookie)
+        .header(header::CONTENT_TYPE, "application/json")
+        .body(road.to_string())
+        .send()
+        .await?;
+    assert_eq!(add_response.status(), StatusCode::OK);
+    let zone: MotionZone = serde_json::from_str(&add_response.text().await?)?;
+    assert_eq!(zone.kind, MotionZoneKind::Exclude);
+    assert_eq!(zone.sensitivity, None);
+
+    let update_response = client
+        .patch(format!("{addr_str}api/motion_zones/{}", zone.zone_id))
+        .header(header::COOKIE, &piotrpdev_cookie)
+        .header(header::CONTENT_TYPE, "application/json")
+        .body(r#"{"kind":"include","sensitivity":90}"#)
+        .send()
+        .await?;
+    assert_eq!(update_response.status(), StatusCode::OK);
+    let updated_zone: MotionZone = serde_json::from_str(&update_response.text().await?)?;
+    assert_eq!(updated_zone.kind, MotionZoneKind::Include);
+    assert_eq!(updated_zone.sensitivity, Some(90));
+
+    // joedaly can't view camera 2
+    let forbidden_settings_response = client
+        .get(format!("{addr_str}api/cameras/2/settings"))
+        .header(header::COOKIE, &joedaly_cookie)
+        .send()
+        .await?;
+    assert_eq!(forbidden_settings_response.status(), StatusCode::FORBIDDEN);
+
+    let settings_response = client
+        .get(format!("{addr_str}api/cameras/2/settings"))
+        .header(header::COOKIE, &piotrpdev_cookie)
+        .send()
+        .await?;
+    assert_eq!(settings_response.status(), StatusCode::OK);
+    let settings: serde_json::Value = serde_json::from_str(&settings_response.text().await?)?;
+    assert_eq!(
+        settings
+            .get("camera_id")
+            .and_then(serde_json::Value::as_i64),
+        Some(2)
+    );
+    let zones: Vec<MotionZone> =
+        serde_json::from_value(settings.get("motion_zones").cloned().ok_or("No zones")?)?;
+    assert_eq!(
+        zones.iter().map(|zone| zone.zone_id).collect::<Vec<_>>(),
+        [2, 3, zone.zone_id]
+    );
+
+    // joedaly can't control camera 1 either
+    let forbidden_delete_response = client
+        .delete(format!("{addr_str}api/motion_zones/1"))
+        .header(header::COOKIE, &joedaly_cookie)
+        .send()
+        .await?;
+    assert_eq!(forbidden_delete_response.status(), StatusCode::FORBIDDEN);
+
+    let delete_response = client
+        .delete(format!("{addr_str}api/motion_zones/{}", zone.zone_id))
+        .header(header::COOKIE, &piotrpdev_cookie)
+        .send()
+        .await?;
+    assert_eq!(delete_response.status(), StatusCode::OK);
+
+    let missing_response = client
+        .delete(format!("{addr_str}api/motion_zones/{}", zone.zone_id))
+        .header(header::COOKIE, &piotrpdev_cookie)
+        .send()
+        .await?;
+    assert_eq!(missing_response.status(), StatusCode::NOT_FOUND);
+
+    Ok(())
+}
+
+#[sqlx::test(fixtures(
+    path = "../fixtures",
+    scripts("users", "cameras", "camera_permissions", "camera_settings")
+))]
+async fn role_guards(pool: SqlitePool) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let (addr_str, _addr, _video_temp_dir) = utils::setup_app(&pool).await?;
+    let admin_cookie = utils::login(&addr_str, "admin").await?;
+    let piotrpdev_cookie = utils::login(&addr_str, "piotrpdev").await?;
+    let joedaly_cookie = utils::login(&addr_str, "joedaly").await?;
+    let client = reqwest::Client::new();
+    // Logged out requests are redirected to the login route
+    let no_redirect_client = reqwest::Client::builder()
+        .redirect(reqwest::redirect::Policy::none())
+        .build()?;
+
+    // Admin only
+    let logged_out_response = no_redirect_client
+        .get(format!("{addr_str}api/users"))
+        .send()
+        .await?;
+    assert_eq!(logged_out_response.status(), StatusCode::TEMPORARY_REDIRECT);
+
+    let viewer_response = client
+        .get(format!("{addr_str}api/users"))
+        .header(header::COOKIE, &joedaly_cookie)
+        .send()
+        .await?;
+    assert_eq!(viewer_response.status(), StatusCode::FORBIDDEN);
+
+    // Operator only
+    let logged_out_restart_response = no_redirect_client
+        .post(format!("{addr_str}api/cameras/1/restart"))
+        .send()
+        .await?;
+    assert_eq!(
+        logged_out_restart_response.status(),
+        StatusCode::TEMPORARY_REDIRECT
+    );
+
+    let viewer_restart_response = client
+        .post(format!("{addr_str}api/cameras/1/restart"))
+        .header(header::COOKIE, &joedaly_cookie)
+        .send()
+        .await?;
+    assert_eq!(viewer_restart_response.status(), StatusCode::FORBIDDEN);
+
+    // Operators also need to be able to control the camera
+    let revoke_response = client
+        .patch(format!("{addr_str}api/permissions/4"))
+        .header(header::COOKIE, &admin_cookie)
+        .form(&[("can_view", "true"), ("can_control", "false")])
+        .send()
+        .await?;
+    assert_eq!(revoke_response.status(), StatusCode::OK);
+
+    let no_control_restart_response = client
+        .post(format!("{addr_str}api/cameras/2/restart"))
+        .header(header::COOKIE, &piotrpdev_cookie)
+        .send()
+        .await?;
+    assert_eq!(no_control_restart_response.status(), StatusCode::FORBIDDEN);
+
+    let operator_restart_response = client
+        .post(format!("{addr_str}api/cameras/1/restart"))
+        .header(header::COOKIE, &piotrpdev_cookie)
+        .send()
+        .await?;
+    assert_eq!(operator_restart_response.status(), StatusCode::OK);
+
+    // joedaly can only view camera 1
+    let no_control_response = client
+        .patch(format!("{addr_str}api/settings/1"))
+        .header(header::COOKIE, &joedaly_cookie)
+        .form(&[
+            ("flashlight_enabled", "true"),
+            ("resolution", "SVGA"),
+            ("framerate", "5"),
+        ])
+        .send()
+        .await?;
+    assert_eq!(no_control_response.status(), StatusCode::FORBIDDEN);
+
+    let permission_response = client
+        .patch(format!("{addr_str}api/permissions/5"))
+        .header(header::COOKIE, &admin_cookie)
+        .form(&[("can_view", "true"), ("can_control", "true")])
+        .send()
+        .await?;
+    assert_eq!(permission_response.status(), StatusCode::OK);
+
+    // Viewers that can control a camera only change the flashlight
+    let settings_response = client
+        .patch(format!("{addr_str}api/settings/1"))
+        .header(header::COOKIE, &joedaly_cookie)
+        .form(&[
+            ("flashlight_enabled", "true"),
+            ("resolution", "VGA"),
+            ("framerate", "30"),
+            ("recording_mode", "motion"),
+            ("motion_sensitivity", "90"),
+        ])
+        .send()
+        .await?;
+    assert_eq!(settings_response.status(), StatusCode::OK);
+    let settings: CameraSetting = serde_json::from_str(&settings_response.text().await?)?;
+    assert!(settings.flashlight_enabled);
+    assert_eq!(settings.resolution, Resolution::Svga);
+    assert_eq!(settings.framerate, 5);
+    assert_eq!(settings.recording_mode, RecordingMode::Continuous);
+    assert_eq!(
+        settings.motion_sensitivity,
+        CameraSetting::DEFAULT.motion_sensitivity
+    );
+
+    Ok(())
+}
+
+#[sqlx::test(fixtures(
+    path = "../fixtures",
+    scripts("users", "cameras", "camera_permissions", "camera_settings")
+))]
+async fn user_roles(pool: SqlitePool) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let (addr_str, _addr, _video_temp_dir) = utils::setup_app(&pool).await?;
+    let admin_cookie = utils::login(&addr_str, "admin").await?;
+    let client = reqwest::Client::new();
+
+    let add_response = client
+        .post(format!("{addr_str}api/users"))
+        .header(header::COOKIE, &admin_cookie)
+        .form(&[
+            ("username", "nightshift"),
+            ("password", "hunter42"),
+            ("role", "operator"),
+        ])
+        .send()
+        .await?;
+    assert_eq!(add_response.status(), StatusCode::OK);
+    let new_user: User = serde_json::from_str(&add_response.text().await?)?;
+    assert_eq!(new_user.role, Role::Operator);
+    assert_eq!(
+        User::get_using_id(&pool, new_user.user_id).await?.role,
+        Role::Operator
+    );
+
+    // The only admin can't be demoted or deleted
+    let demote_response = client
+        .patch(format!("{addr_str}api/users/1"))
+        .header(header::COOKIE, &admin_cookie)
+        .form(&[("username", "admin"), ("password", ""), ("role", "viewer")])
+        .send()
+        .await?;
+    assert_eq!(demote_response.status(), StatusCode::BAD_REQUEST);
+
+    let delete_response = client
+        .delete(format!("{addr_str}api/users/1"))
+        .header(header::COOKIE, &admin_cookie)
+        .send()
+        .await?;
+    assert_eq!(delete_response.status(), StatusCode::BAD_REQUEST);
+    assert_eq!(User::get_using_id(&pool, 1).await?.role, Role::Admin);
+
+    // Promoted admins can see and control every camera, like new ones
+    let promote_response = client
+        .patch(format!("{addr_str}api/users/3"))
+        .header(header::COOKIE, &admin_cookie)
+        .form(&[("username", "joedaly"), ("password", ""), ("role", "admin")])
+        .send()
+        .await?;
+    assert_eq!(promote_response.status(), StatusCode::OK);
+
+    let joedaly_cookie = utils::login(&addr_str, "joedaly").await?;
+    let cameras_response = client
+        .get(format!("{addr_str}api/cameras"))
+        .header(header::COOKIE, &joedaly_cookie)
+        .send()
+        .await?;
+    let cameras: Vec<serde_json::Value> = serde_json::from_str(&cameras_response.text().await?)?;
+    assert_eq!(cameras.len(), 2);
+    assert!(cameras.iter().all(|camera| camera["can_control"] == true));
+
+    let control_response = client
+        .patch(format!("{addr_str}api/settings/2"))
+        .header(header::COOKIE, &joedaly_cookie)
+        .form(&[
+            ("flashlight_enabled", "false"),
+            ("resolution", "SVGA"),
+            ("framerate", "10"),
+        ])
+        .send()
+        .await?;
+    assert_eq!(control_response.status(), StatusCode::OK);
+
+    // With two admins one of them can go
+    let demote_response = client
+        .patch(format!("{addr_str}api/users/1"))
+        .header(header::COOKIE, &joedaly_cookie)
+        .form(&[("username", "admin"), ("password", ""), ("role", "viewer")])
+        .send()
+        .await?;
+    assert_eq!(demote_response.status(), StatusCode::OK);
+
+    let last_admin_delete_response = client
+        .delete(format!("{addr_str}api/users/3"))
+        .header(header::COOKIE, &joedaly_cookie)
+        .send()
+        .await?;
+    assert_eq!(last_admin_delete_response.status(), StatusCode::BAD_REQUEST);
+
+    Ok(())
+}
@@ -0,0 +1,202 @@
+use futures_util::{SinkExt, StreamExt};
+use oko::{AlertSubscription, EventKind};
+use reqwest::{header, StatusCode};
+use sqlx::SqlitePool;
+use tokio::{
+    io::{AsyncBufReadExt, AsyncWriteExt, BufReader},
+    net::{TcpListener, TcpStream},
+    sync::mpsc,
+    time::Duration,
+};
+
+#[path = "./utils.rs"]
+mod utils;
+
+type TestResult<T> = Result<T, Box<dyn std::error::Error + Send + Sync>>;
+
+/// An email received by the SMTP sink
+#[derive(Debug)]
+struct ReceivedEmail {
+    recipients: Vec<String>,
+    data: String,
+}
+
+/// Talks just enough SMTP to accept a single email per connection
+async fn handle_smtp_connection(
+    stream: TcpStream,
+    received_tx: mpsc::UnboundedSender<ReceivedEmail>,
+) -> TestResult<()> {
+    let (reader, mut writer) = stream.into_split();
+    let mut lines = BufReader::new(reader).lines();
+
+    writer.write_all(b"220 sink ESMTP\r\n").await?;
+
+    let mut recipients = Vec::new();
+
+    while let Some(line) = lines.next_line().await? {
+        let command = line.to_ascii_uppercase();
+
+        if command.starts_with("RCPT TO:") {
+            recipients.push(line.get(8..).unwrap_or_default().trim().to_string());
+            writer.write_all(b"250 OK\r\n").await?;
+        } else if command.starts_with("DATA") {
+            writer.write_all(b"354 Go ahead\r\n").await?;
+
+            let mut data = String::new();
+            while let Some(data_line) = lines.next_line().await? {
+                if data_line == "." {
+                    break;
+                }
+
+                data.push_str(&data_line);
+                data.push('\n');
+            }
+
+            let _ = received_tx.send(ReceivedEmail {
+                recipients: std::mem::take(&mut recipients),
+                data,
+            });
+            writer.write_all(b"250 OK\r\n").await?;
+        } else if command.starts_with("QUIT") {
+            writer.write_all(b"221 Bye\r\n").await?;
+            break;
+        } else {
+            writer.write_all(b"250 sink\r\n").await?;
+        }
+    }
+
+    Ok(())
+}
+
+async fn smtp_sink() -> TestResult<(u16, mpsc::UnboundedReceiver<ReceivedEmail>)> {
+    let listener = TcpListener::bind("127.0.0.1:0").await?;
+    let port = listener.local_addr()?.port();
+    let (received_tx, received_rx) = mpsc::unbounded_channel();
+
+    tokio::spawn(async move {
+        while let Ok((stream, _)) = listener.accept().await {
+            tokio::spawn(handle_smtp_connection(stream, received_tx.clone()));
+        }
+    });
+
+    Ok((port, received_rx))
+}
+
+#[sqlx::test(fixtures(
+    path = "../fixtures",
+    scripts("users", "cameras", "camera_permissions", "camera_settings")
+))]
+async fn camera_offline_alert(pool: SqlitePool) -> TestResult<()> {
+    let (smtp_port, mut received_rx) = smtp_sink().await?;
+    let (addr_str, addr, _video_temp_dir) = utils::setup_app(&pool).await?;
+    let admin_session_cookie = utils::login(&addr_str, "admin").await?;
+    let joedaly_session_cookie = utils::login(&addr_str, "joedaly").await?;
+
+    let client = reqwest::Client::new();
+
+    let forbidden_response = client
+        .patch(format!("{addr_str}api/smtp"))
+        .header(header::COOKIE, &joedaly_session_cookie)
+        .form(&[("host", "127.0.0.1")])
+        .send()
+        .await?;
+    assert_eq!(forbidden_response.status(), StatusCode::FORBIDDEN);
+
+    let invalid_from_response = client
+        .patch(format!("{addr_str}api/smtp"))
+        .header(header::COOKIE, &admin_session_cookie)
+        .form(&[("from_address", "oko")])
+        .send()
+        .await?;
+    assert_eq!(invalid_from_response.status(), StatusCode::BAD_REQUEST);
+
+    let smtp_port = smtp_port.to_string();
+    let smtp_response = client
+        .patch(format!("{addr_str}api/smtp"))
+        .header(header::COOKIE, &admin_session_cookie)
+        .form(&[
+            ("host", "127.0.0.1"),
+            ("port", smtp_port.as_str()),
+            ("security", "none"),
+            ("password", "hunter42"),
+            ("from_address", "Oko <oko@example.com>"),
+        ])
+        .send()
+        .await?;
+    assert_eq!(smtp_response.status(), StatusCode::OK);
+    assert!(!smtp_response.text().await?.contains("hunter42"));
+
+    // joedaly can't view the kitchen camera
+    let forbidden_subscription_response = client
+        .post(format!("{addr_str}api/alert_subscriptions"))
+        .header(header::COOKIE, &joedaly_session_cookie)
+        .form(&[
+            ("camera_id", "2"),
+            ("kind", "camera_offline"),
+            ("email", "joedaly@example.com"),
+        ])
+        .send()
+        .await?;
+    assert_eq!(
+        forbidden_subscription_response.status(),
+        StatusCode::FORBIDDEN
+    );
+
+    let unsupported_kind_response = client
+        .post(format!("{addr_str}api/alert_subscriptions"))
+        .header(header::COOKIE, &admin_session_cookie)
+        .form(&[("kind", "login_failed"), ("email", "admin@example.com")])
+        .send()
+        .await?;
+    assert_eq!(unsupported_kind_response.status(), StatusCode::BAD_REQUEST);
+
+    let subscription_response = client
+        .post(format!("{addr_str}api/alert_subscriptions"))
+        .header(header::COOKIE, &admin_session_cookie)
+        .form(&[
+            ("camera_id", "2"),
+            ("kind", "camera_offline"),
+            ("email", "admin@example.com"),
+        ])
+        .send()
+        .await?;
+    assert_eq!(subscription_response.status(), StatusCode::OK);
+    let subscription: AlertSubscription =
+        serde_json::from_str(&subscription_response.text().await?)?;
+    assert_eq!(subscription.kind, EventKind::CameraOffline);
+
+    let forbidden_delete_response = client
+        .delete(format!(
+            "{addr_str}api/alert_subscriptions/{}",
+            subscription.subscription_id
+        ))
+        .header(header::COOKIE, &joedaly_session_cookie)
+        .send()
+        .await?;
+    assert_eq!(forbidden_delete_response.status(), StatusCode::FORBIDDEN);
+
+    let list_response = client
+        .get(format!("{addr_str}api/alert_subscriptions"))
+        .header(header::COOKIE, &admin_session_cookie)
+        .send()
+        .await?;
+    let subscriptions: Vec<AlertSubscription> = serde_json::from_str(&list_response.text().await?)?;
+    assert_eq!(subscriptions.len(), 1);
+
+    let mut camera_ws_stream = utils::setup_ws(addr).await?;
+    camera_ws_stream
+        .send(utils::camera_handshake(utils::TEST_CAMERA_2_TOKEN))
+        .await?;
+    camera_ws_stream.next().await.ok_or("No HelloReply")??;
+    camera_ws_stream.close(None).await?;
+
+    let email = tokio::time::timeout(Duration::from_secs(5), received_rx.recv())
+        .await?
+        .ok_or("SMTP sink stopped")?;
+
+    assert_eq!(email.recipients, ["<admin@example.com>"]);
+    assert!(email.data.contains("Subject: Kitchen went offline"));
+    assert!(email.data.contains("From: Oko <oko@example.com>"));
+
+    Ok(())
+}
@@ -0,0 +1,252 @@
+use std::net::SocketAddr;
+
+use futures_util::{SinkExt, StreamExt};
+use oko::{CameraSetting, Config};
+use sqlx::SqlitePool;
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::{tcp::OwnedReadHalf, TcpListener},
+    sync::mpsc,
+    time::Duration,
+};
+
+#[path = "./utils.rs"]
+mod utils;
+
+type TestResult<T> = Result<T, Box<dyn std::error::Error + Send + Sync>>;
+
+/// A message published by Oko
+#[derive(Debug)]
+struct Published {
+    topic: String,
+    payload: String,
+    retain: bool,
+}
+
+/// Remaining length of an MQTT packet
+fn encode_length(mut length: usize, buffer: &mut Vec<u8>) -> TestResult<()> {
+    loop {
+        let mut byte = u8::try_from(length % 128)?;
+        length /= 128;
+
+        if length > 0 {
+            byte |= 0x80;
+        }
+
+        buffer.push(byte);
+
+        if length == 0 {
+            return Ok(());
+        }
+    }
+}
+
+fn packet(header: u8, body: &[u8]) -> TestResult<Vec<u8>> {
+    let mut buffer = vec![header];
+    encode_length(body.len(), &mut buffer)?;
+    buffer.extend_from_slice(body);
+
+    Ok(buffer)
+}
+
+/// `PUBLISH` with `QoS` 0 sent to Oko
+fn publish_packet(topic: &str, payload: &str) -> TestResult<Vec<u8>> {
+    let mut body = u16::try_from(topic.len())?.to_be_bytes().to_vec();
+    body.extend_from_slice(topic.as_bytes());
+    body.extend_from_slice(payload.as_bytes());
+
+    packet(0x30, &body)
+}
+
+/// First byte and body of the next packet
+async fn read_packet(reader: &mut OwnedReadHalf) -> TestResult<(u8, Vec<u8>)> {
+    let header = reader.read_u8().await?;
+
+    let mut length = 0;
+    let mut multiplier = 1;
+    loop {
+        let byte = reader.read_u8().await?;
+        length += usize::from(byte & 0x7f) * multiplier;
+        multiplier *= 128;
+
+        if byte & 0x80 == 0 {
+            break;
+        }
+    }
+
+    let mut body = vec![0; length];
+    reader.read_exact(&mut body).await?;
+
+    Ok((header, body))
+}
+
+/// A single connection MQTT 3.1.1 broker, just enough to talk to Oko.
+///
+/// Messages published by Oko are sent to the returned receiver, `(topic, payload)` sent to the
+/// returned sender are published to Oko.
+async fn stand_in_broker() -> TestResult<(
+    SocketAddr,
+    mpsc::UnboundedReceiver<Published>,
+    mpsc::UnboundedSender<(String, String)>,
+)> {
+    let listener = TcpListener::bind("127.0.0.1:0").await?;
+    let addr = listener.local_addr()?;
+
+    let (published_tx, published_rx) = mpsc::unbounded_channel();
+    let (command_tx, mut command_rx) = mpsc::unbounded_channel::<(String, String)>();
+
+    tokio::spawn(async move {
+        let (stream, _) = listener.accept().await?;
+        let (mut reader, mut writer) = stream.into_split();
+
+        loop {
+            tokio::select! {
+                packet_result = read_packet(&mut reader) => {
+                    let (header, body) = packet_result?;
+
+                    match header >> 4 {
+                        // CONNECT
+                        1 => writer.write_all(&[0x20, 0x02, 0x00, 0x00]).await?,
+                        // PUBLISH
+                        3 => {
+                            let topic_length = usize::from(u16::from_be_bytes([
+                                *body.first().ok_or("Empty PUBLISH")?,
+                                *body.get(1).ok_or("Empty PUBLISH")?,
+                            ]));
+                            let topic = body.get(2..2 + topic_length).ok_or("Short PUBLISH")?;
+                            let qos = (header >> 1) & 0b11;
+                            let payload_start = 2 + topic_length + if qos > 0 { 2 } else { 0 };
+
+                            if qos > 0 {
+                                let packet_id = body
+                                    .get(2 + topic_length..payload_start)
+                                    .ok_or("Short PUBLISH")?;
+                                writer.write_all(&packet(0x40, packet_id)?).await?;
+                            }
+
+                            let _ = published_tx.send(Published {
+                                topic: String::from_utf8(topic.to_vec())?,
+                                payload: String::from_utf8(
+                                    body.get(payload_start..).unwrap_or_default().to_vec(),
+                                )?,
+                                retain: header & 1 == 1,
+                            });
+                        }
+                        // SUBSCRIBE, grant QoS 1 to the single topic filter Oko subscribes to
+                        8 => {
+                            let packet_id = body.get(..2).ok_or("Short SUBSCRIBE")?;
+                            let mut suback = packet_id.to_vec();
+                            suback.push(0x01);
+                            writer.write_all(&packet(0x90, &suback)?).await?;
+                        }
+                        // PINGREQ
+                        12 => writer.write_all(&[0xd0, 0x00]).await?,
+                        // DISCONNECT
+                        14 => break,
+                        _ => (),
+                    }
+                }
+                Some((topic, payload)) = command_rx.recv() => {
+                    writer.write_all(&publish_packet(&topic, &payload)?).await?;
+                }
+            }
+        }
+
+        Ok::<(), Box<dyn std::error::Error + Send + Sync>>(())
+    });
+
+    Ok((addr, published_rx, command_tx))
+}
+
+/// The next message published to `topic`, skipping messages to other topics
+async fn next_published(
+    published_rx: &mut mpsc::UnboundedReceiver<Published>,
+    topic: &str,
+) -> TestResult<Published> {
+    tokio::time::timeout(Duration::from_secs(5), async {
+        while let Some(published) = published_rx.recv().await {
+            if published.topic == topic {
+                return Ok(published);
+            }
+        }
+
+        Err(format!("Broker closed before {topic} was published").into())
+    })
+    .await?
+}
+
+#[sqlx::test(fixtures(
+    path = "../fixtures",
+    scripts("users", "cameras", "camera_permissions", "camera_settings")
+))]
+async fn publishes_and_runs_commands(pool: SqlitePool) -> TestResult<()> {
+    let (broker_addr, mut published_rx, command_tx) = stand_in_broker().await?;
+
+    let config = Config {
+        mqtt_host: Some(broker_addr.ip().to_string()),
+        mqtt_port: broker_addr.port(),
+        ..Config::default()
+    };
+    let (_addr_str, addr, _video_temp_dir) = utils::setup_app_with_config(&pool, config).await?;
+
+    let status = next_published(&mut published_rx, "oko/status").await?;
+    assert_eq!(status.payload, "online");
+    assert!(status.retain);
+
+    let discovery = next_published(
+        &mut published_rx,
+        "homeassistant/binary_sensor/oko_1/motion/config",
+    )
+    .await?;
+    let discovery_json: serde_json::Value = serde_json::from_str(&discovery.payload)?;
+    assert_eq!(
+        discovery_json.get("state_topic"),
+        Some(&serde_json::json!("oko/camera/1/motion"))
+    );
+    assert!(discovery.retain);
+
+    let kitchen_status = next_published(&mut published_rx, "oko/camera/2/status").await?;
+    assert_eq!(kitchen_status.payload, "offline");
+
+    let mut camera_ws_stream = utils::setup_ws(addr).await?;
+    camera_ws_stream
+        .send(utils::camera_handshake(utils::TEST_CAMERA_2_TOKEN))
+        .await?;
+    camera_ws_stream.next().await.ok_or("No HelloReply")??;
+
+    let kitchen_status = next_published(&mut published_rx, "oko/camera/2/status").await?;
+    assert_eq!(kitchen_status.payload, "online");
+
+    // Connecting republishes the camera's state, framerate is published last
+    let framerate = next_published(&mut published_rx, "oko/camera/2/framerate").await?;
+    assert_eq!(framerate.payload, "5");
+
+    command_tx.send(("oko/camera/2/flashlight/set".to_string(), "ON".to_string()))?;
+
+    let flashlight = next_published(&mut published_rx, "oko/camera/2/flashlight").await?;
+    assert_eq!(flashlight.payload, "ON");
+    assert!(
+        CameraSetting::get_for_camera(&pool, 2)
+            .await?
+            .flashlight_enabled
+    );
+
+    // Every setting change publishes all of them
+    let framerate = next_published(&mut published_rx, "oko/camera/2/framerate").await?;
+    assert_eq!(framerate.payload, "5");
+
+    // Out of range, ignored
+    command_tx.send(("oko/camera/2/framerate/set".to_string(), "500".to_string()))?;
+    command_tx.send(("oko/camera/2/framerate/set".to_string(), "10".to_string()))?;
+
+    let framerate = next_published(&mut published_rx, "oko/camera/2/framerate").await?;
+    assert_eq!(framerate.payload, "10");
+    assert_eq!(CameraSetting::get_for_camera(&pool, 2).await?.framerate, 10);
+
+    camera_ws_stream.close(None).await?;
+
+    let kitchen_status = next_published(&mut published_rx, "oko/camera/2/status").await?;
+    assert_eq!(kitchen_status.payload, "offline");
+
+    Ok(())
+}
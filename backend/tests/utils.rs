@@ -1,18 +1,71 @@
-use std::net::{Ipv4Addr, SocketAddr};
+use std::{
+    net::{Ipv4Addr, SocketAddr},
+    time::Duration,
+};
 
-use oko::App;
+use oko::{App, ClientRole, Config, Hello, PROTOCOL_VERSION};
 use playwright::{api::BrowserContext, Playwright};
 use sqlx::SqlitePool;
 use tempfile::{tempdir, TempDir};
 use tokio::net::{TcpListener, TcpStream};
-use ws_utils::{same_port_connect, IntoClientRequest, MaybeTlsStream, WebSocketStream};
+use ws_utils::{same_port_connect, IntoClientRequest, MaybeTlsStream, Message, WebSocketStream};
 
+#[allow(dead_code)]
 pub const TEST_IMG_1: [u8; 1] = [1];
+#[allow(dead_code)]
 pub const TEST_IMG_2: [u8; 1] = [2];
 
+#[allow(dead_code)]
 pub const REAL_TEST_IMG_1: &[u8; 8981] = include_bytes!("../fixtures/real_test_img_1.jpg");
+#[allow(dead_code)]
 pub const REAL_TEST_IMG_2: &[u8; 9059] = include_bytes!("../fixtures/real_test_img_2.jpg");
 
+/// Password of every user in `fixtures/users.sql`
+#[allow(dead_code)]
+pub const TEST_PASSWORD: &str = "hunter42";
+
+/// Pairing tokens of the cameras in `fixtures/cameras.sql`
+#[allow(dead_code)]
+pub const TEST_CAMERA_1_TOKEN: &str = "front_door_pairing_token";
+#[allow(dead_code)]
+pub const TEST_CAMERA_2_TOKEN: &str = "kitchen_pairing_token";
+
+#[allow(dead_code)]
+#[must_use]
+pub fn camera_hello(pairing_token: &str) -> Hello {
+    Hello {
+        role: ClientRole::Camera,
+        protocol_version: PROTOCOL_VERSION,
+        firmware_version: None,
+        supported_resolutions: Vec::new(),
+        token: Some(pairing_token.to_string()),
+        any_port: false,
+        binary_frames: false,
+    }
+}
+
+/// First message a camera sends after connecting
+#[allow(dead_code)]
+#[must_use]
+pub fn camera_handshake(pairing_token: &str) -> Message {
+    hello_message(&camera_hello(pairing_token))
+}
+
+#[allow(dead_code)]
+#[must_use]
+pub fn hello_message(hello: &Hello) -> Message {
+    Message::Text(serde_json::to_string(hello).unwrap_or_default())
+}
+
+/// Frames in a video recorded at `framerate` from `sent_frame_count` frames sent every `interval`.
+///
+/// The recorder repeats/drops frames so that videos play in real time.
+#[allow(dead_code)]
+#[must_use]
+pub fn expected_frame_count(sent_frame_count: u32, interval: Duration, framerate: f64) -> f64 {
+    (interval.as_secs_f64() * f64::from(sent_frame_count - 1)).mul_add(framerate, 1.0)
+}
+
 #[allow(dead_code)]
 struct TestCamera {
     camera_id: i32,
@@ -37,6 +90,7 @@ const TEST_CAMERA_3: TestCamera = TestCamera {
     name: "Backyard",
 };
 
+#[allow(dead_code)]
 pub async fn setup(
     pool: &SqlitePool,
 ) -> Result<
@@ -53,6 +107,25 @@ pub async fn setup(
         .build()
         .await?;
 
+    let (addr_str, addr, video_path) = setup_app(pool).await?;
+
+    Ok((playwright, context, addr_str, addr, video_path))
+}
+
+/// Starts the app without a browser, for tests that only use the API
+#[allow(dead_code)]
+pub async fn setup_app(
+    pool: &SqlitePool,
+) -> Result<(String, SocketAddr, TempDir), Box<dyn std::error::Error + Send + Sync>> {
+    setup_app_with_config(pool, Config::default()).await
+}
+
+/// Starts the app like `setup_app`, using `config` for anything the tests don't override
+#[allow(dead_code)]
+pub async fn setup_app_with_config(
+    pool: &SqlitePool,
+    config: Config,
+) -> Result<(String, SocketAddr, TempDir), Box<dyn std::error::Error + Send + Sync>> {
     let listener = TcpListener::bind(SocketAddr::from((Ipv4Addr::LOCALHOST, 0))).await?;
     let addr = listener.local_addr()?;
     let addr_str = format!("http://{addr}/");
@@ -60,16 +133,46 @@ pub async fn setup(
     let video_path = tempdir()?;
     let video_pathbuf = video_path.path().to_path_buf();
 
-    let app = App {
-        db: pool.clone(),
-        http_listener: listener,
-        https_addr: None,
-        video_path: video_pathbuf,
-        oko_private_socket_addr: None,
-    };
+    let app = App::builder()
+        .config(config)
+        .db(pool.clone())
+        .http_listener(listener)
+        .https_addr(None)
+        .video_path(video_pathbuf)
+        .oko_private_socket_addr(None)
+        .build()
+        .await?;
     tokio::spawn(app.serve());
 
-    Ok((playwright, context, addr_str, addr, video_path))
+    Ok((addr_str, addr, video_path))
+}
+
+/// Logs in using the API, returns the session cookie
+#[allow(dead_code)]
+pub async fn login(
+    addr_str: &str,
+    username: &str,
+) -> Result<String, Box<dyn std::error::Error + Send + Sync>> {
+    let response = reqwest::Client::new()
+        .post(format!("{addr_str}api/login"))
+        .form(&[("username", username), ("password", TEST_PASSWORD)])
+        .send()
+        .await?;
+
+    if !response.status().is_success() {
+        return Err(format!("Failed to log in as {username}: {}", response.status()).into());
+    }
+
+    let Some(session_cookie) = response
+        .headers()
+        .get(reqwest::header::SET_COOKIE)
+        .and_then(|cookie| cookie.to_str().ok())
+        .and_then(|cookie| cookie.split(';').next())
+    else {
+        return Err("No session cookie returned".into());
+    };
+
+    Ok(session_cookie.to_string())
 }
 
 pub async fn setup_ws_with_port(
@@ -84,6 +187,25 @@ pub async fn setup_ws_with_port(
     Ok(ws_stream)
 }
 
+/// Connects to the WebSocket with a session cookie, for viewers
+#[allow(dead_code)]
+pub async fn setup_viewer_ws(
+    addr: SocketAddr,
+    port: u16,
+    session_cookie: &str,
+) -> Result<WebSocketStream<MaybeTlsStream<TcpStream>>, Box<dyn std::error::Error + Send + Sync>> {
+    let mut request = format!("ws://{addr}/api/ws").into_client_request()?;
+    request
+        .headers_mut()
+        .insert(reqwest::header::COOKIE, session_cookie.parse()?);
+
+    let Ok((ws_stream, _)) = same_port_connect(request, port).await else {
+        return Err("Failed to connect to WebSocket".into());
+    };
+
+    Ok(ws_stream)
+}
+
 pub async fn setup_ws(
     addr: SocketAddr,
 ) -> Result<WebSocketStream<MaybeTlsStream<TcpStream>>, Box<dyn std::error::Error + Send + Sync>> {
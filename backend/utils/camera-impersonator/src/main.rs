@@ -4,6 +4,7 @@ use std::env;
 use std::process::ExitCode;
 
 use futures_util::SinkExt;
+use oko_protocol::{ClientRole, Hello, PROTOCOL_VERSION};
 use opencv::core::{Mat, Vector};
 use opencv::imgcodecs::imencode_def;
 use opencv::prelude::*;
@@ -12,7 +13,7 @@ use tokio::time::{sleep, Duration};
 use ws_utils::{IntoClientRequest, Message};
 
 const USAGE_MESSAGE: &str =
-    "Usage: camera-impersonator <send_interval_ms> <client_port> <path_to_video_file>";
+    "Usage: camera-impersonator <send_interval_ms> <client_port> <path_to_video_file> <pairing_token>";
 
 #[allow(clippy::unwrap_used)]
 #[allow(clippy::expect_used)]
@@ -20,7 +21,7 @@ const USAGE_MESSAGE: &str =
 async fn main() -> ExitCode {
     let args: Vec<String> = env::args().collect();
 
-    if args.len() != 4 {
+    if args.len() != 5 {
         eprintln!("{USAGE_MESSAGE}");
         return ExitCode::FAILURE;
     }
@@ -42,6 +43,10 @@ async fn main() -> ExitCode {
         eprintln!("{USAGE_MESSAGE}");
         return ExitCode::FAILURE;
     };
+    let Some(pairing_token) = args.get(4) else {
+        eprintln!("{USAGE_MESSAGE}");
+        return ExitCode::FAILURE;
+    };
 
     let mut cap = VideoCapture::from_file(video_arg, CAP_ANY).unwrap();
     if !cap.is_opened().unwrap() {
@@ -57,8 +62,18 @@ async fn main() -> ExitCode {
             .await
             .unwrap();
 
+    let hello = Hello {
+        role: ClientRole::Camera,
+        protocol_version: PROTOCOL_VERSION,
+        firmware_version: None,
+        supported_resolutions: Vec::new(),
+        token: Some(pairing_token.clone()),
+        any_port: false,
+        binary_frames: false,
+    };
+
     ws_stream
-        .send(Message::Text("camera".to_string()))
+        .send(Message::Text(serde_json::to_string(&hello).unwrap()))
         .await
         .unwrap();
 
@@ -0,0 +1,311 @@
+//! Messages exchanged between Oko and its cameras over `/api/ws`.
+//!
+//! Only needs `alloc` unless the `std` feature is enabled,
+//! so it can be used by camera firmware as well as the backend.
+#![cfg_attr(not(feature = "std"), no_std)]
+
+extern crate alloc;
+
+use alloc::{string::String, vec::Vec};
+use core::fmt;
+
+use serde::{Deserialize, Serialize};
+
+/// Version of the WebSocket protocol described by this crate, see `Hello`
+pub const PROTOCOL_VERSION: u32 = 1;
+/// Oldest `Hello::protocol_version` that is still accepted
+pub const MIN_PROTOCOL_VERSION: u32 = 1;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "sqlx", derive(sqlx::Type))]
+#[cfg_attr(feature = "sqlx", sqlx(rename_all = "UPPERCASE"))]
+#[serde(rename_all = "UPPERCASE")]
+pub enum Resolution {
+    /// 800x600
+    #[default]
+    Svga,
+    /// 640x480
+    Vga,
+}
+
+impl Resolution {
+    /// Width and height in pixels
+    #[must_use]
+    pub const fn size(self) -> (u32, u32) {
+        match self {
+            Self::Svga => (800, 600),
+            Self::Vga => (640, 480),
+        }
+    }
+
+    #[must_use]
+    pub const fn as_str(self) -> &'static str {
+        match self {
+            Self::Svga => "SVGA",
+            Self::Vga => "VGA",
+        }
+    }
+}
+
+impl fmt::Display for Resolution {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum ClientRole {
+    Camera,
+    Viewer,
+}
+
+/// First message every WebSocket client sends (as JSON text), answered with a `HelloReply`
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct Hello {
+    pub role: ClientRole,
+    pub protocol_version: u32,
+    #[serde(default)]
+    pub firmware_version: Option<String>,
+    /// Resolutions the camera can capture, empty if any resolution is fine
+    #[serde(default)]
+    pub supported_resolutions: Vec<Resolution>,
+    /// Pairing token, required for cameras
+    #[serde(default)]
+    pub token: Option<String>,
+    /// Whether the camera was added without a port, i.e. connects from any port
+    #[serde(default)]
+    pub any_port: bool,
+    /// Whether the viewer wants images as `FrameHeader` binary messages instead of JSON
+    #[serde(default)]
+    pub binary_frames: bool,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct HelloReply {
+    /// Version used for the rest of the connection, never newer than what the client sent
+    pub protocol_version: u32,
+    /// Only set for cameras
+    pub camera_id: Option<i64>,
+    /// Settings the camera should apply, only set for cameras
+    pub settings: Option<CameraSettingNoMeta>,
+    /// Whether images will be sent as `FrameHeader` binary messages, only set for viewers
+    #[serde(default)]
+    pub binary_frames: bool,
+}
+
+/// Header of the binary messages sent to viewers that negotiated `binary_frames`.
+///
+/// Encoded as a version byte followed by the fields as big-endian integers,
+/// the rest of the message is the JPEG image.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FrameHeader {
+    pub camera_id: i64,
+    /// When the image was received, in milliseconds since the Unix epoch
+    pub timestamp: i64,
+    /// Increases by one for every image received from the camera during a connection
+    pub sequence: u64,
+}
+
+impl FrameHeader {
+    /// Version byte every frame starts with
+    pub const VERSION: u8 = 1;
+    /// Length of the encoded header in bytes
+    pub const LEN: usize = 25;
+
+    #[must_use]
+    pub fn encode(&self) -> [u8; Self::LEN] {
+        let mut bytes = [0; Self::LEN];
+        let (version, fields) = bytes.split_at_mut(1);
+        let (camera_id, rest) = fields.split_at_mut(8);
+        let (timestamp, sequence) = rest.split_at_mut(8);
+
+        version.copy_from_slice(&[Self::VERSION]);
+        camera_id.copy_from_slice(&self.camera_id.to_be_bytes());
+        timestamp.copy_from_slice(&self.timestamp.to_be_bytes());
+        sequence.copy_from_slice(&self.sequence.to_be_bytes());
+
+        bytes
+    }
+
+    /// Header followed by the image
+    #[must_use]
+    pub fn encode_frame(&self, image_bytes: &[u8]) -> Vec<u8> {
+        let mut frame = Vec::with_capacity(Self::LEN + image_bytes.len());
+        frame.extend_from_slice(&self.encode());
+        frame.extend_from_slice(image_bytes);
+        frame
+    }
+
+    /// Splits a binary message into its header and image,
+    /// `None` if it is too short or uses another version
+    #[must_use]
+    pub fn decode(frame: &[u8]) -> Option<(Self, &[u8])> {
+        let (&version, rest) = frame.split_first()?;
+        if version != Self::VERSION {
+            return None;
+        }
+
+        let (camera_id, rest) = split_array(rest)?;
+        let (timestamp, rest) = split_array(rest)?;
+        let (sequence, image_bytes) = split_array(rest)?;
+
+        Some((
+            Self {
+                camera_id: i64::from_be_bytes(camera_id),
+                timestamp: i64::from_be_bytes(timestamp),
+                sequence: u64::from_be_bytes(sequence),
+            },
+            image_bytes,
+        ))
+    }
+}
+
+fn split_array(bytes: &[u8]) -> Option<([u8; 8], &[u8])> {
+    Some((bytes.get(..8)?.try_into().ok()?, bytes.get(8..)?))
+}
+
+/// The settings a camera applies itself, the rest are only used by the server
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct CameraSettingNoMeta {
+    pub flashlight_enabled: bool,
+    pub resolution: Resolution,
+    pub framerate: i64,
+}
+
+/// Sent by the server to cameras after the `HelloReply`
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub enum CameraMessage {
+    SettingChanged(CameraSettingNoMeta),
+    Restart,
+}
+
+#[allow(clippy::unwrap_used)]
+#[cfg(test)]
+mod tests {
+    use alloc::{string::ToString, vec};
+
+    use super::*;
+
+    fn round_trip<T>(value: &T, json: &str)
+    where
+        T: Serialize + for<'de> Deserialize<'de> + PartialEq + fmt::Debug,
+    {
+        assert_eq!(serde_json::to_string(value).unwrap(), json);
+        assert_eq!(&serde_json::from_str::<T>(json).unwrap(), value);
+    }
+
+    #[test]
+    fn resolution() {
+        round_trip(&Resolution::Svga, r#""SVGA""#);
+        round_trip(&Resolution::Vga, r#""VGA""#);
+
+        assert!(serde_json::from_str::<Resolution>(r#""1920x1080""#).is_err());
+        assert_eq!(Resolution::Vga.size(), (640, 480));
+        assert_eq!(Resolution::default().to_string(), "SVGA");
+    }
+
+    #[test]
+    fn hello() {
+        round_trip(
+            &Hello {
+                role: ClientRole::Camera,
+                protocol_version: PROTOCOL_VERSION,
+                firmware_version: Some("0.1.0".to_string()),
+                supported_resolutions: vec![Resolution::Svga, Resolution::Vga],
+                token: Some("token".to_string()),
+                any_port: true,
+                binary_frames: false,
+            },
+            r#"{"role":"camera","protocol_version":1,"firmware_version":"0.1.0","supported_resolutions":["SVGA","VGA"],"token":"token","any_port":true,"binary_frames":false}"#,
+        );
+
+        // Viewers only send the required fields
+        assert_eq!(
+            serde_json::from_str::<Hello>(r#"{"role":"viewer","protocol_version":1}"#).unwrap(),
+            Hello {
+                role: ClientRole::Viewer,
+                protocol_version: 1,
+                firmware_version: None,
+                supported_resolutions: vec![],
+                token: None,
+                any_port: false,
+                binary_frames: false,
+            }
+        );
+    }
+
+    #[test]
+    fn hello_reply() {
+        round_trip(
+            &HelloReply {
+                protocol_version: PROTOCOL_VERSION,
+                camera_id: Some(2),
+                settings: Some(CameraSettingNoMeta {
+                    flashlight_enabled: false,
+                    resolution: Resolution::Svga,
+                    framerate: 5,
+                }),
+                binary_frames: false,
+            },
+            r#"{"protocol_version":1,"camera_id":2,"settings":{"flashlight_enabled":false,"resolution":"SVGA","framerate":5},"binary_frames":false}"#,
+        );
+
+        round_trip(
+            &HelloReply {
+                protocol_version: PROTOCOL_VERSION,
+                camera_id: None,
+                settings: None,
+                binary_frames: true,
+            },
+            r#"{"protocol_version":1,"camera_id":null,"settings":null,"binary_frames":true}"#,
+        );
+
+        // Replies from servers without binary frames
+        assert!(
+            !serde_json::from_str::<HelloReply>(
+                r#"{"protocol_version":1,"camera_id":null,"settings":null}"#
+            )
+            .unwrap()
+            .binary_frames
+        );
+    }
+
+    #[test]
+    fn frame_header() {
+        let header = FrameHeader {
+            camera_id: 2,
+            timestamp: 1_729_123_200_000,
+            sequence: 7,
+        };
+        let frame = header.encode_frame(&[0xFF, 0xD8]);
+
+        assert_eq!(frame.len(), FrameHeader::LEN + 2);
+        assert_eq!(frame.first(), Some(&FrameHeader::VERSION));
+        assert_eq!(frame.get(1..9), Some(&2_i64.to_be_bytes()[..]));
+        assert_eq!(
+            FrameHeader::decode(&frame),
+            Some((header, &[0xFF, 0xD8][..]))
+        );
+
+        assert_eq!(FrameHeader::decode(frame.get(..24).unwrap()), None);
+        assert_eq!(FrameHeader::decode(&[2; FrameHeader::LEN]), None);
+        assert_eq!(
+            FrameHeader::decode(&header.encode()),
+            Some((header, &[][..]))
+        );
+    }
+
+    #[test]
+    fn camera_message() {
+        round_trip(
+            &CameraMessage::SettingChanged(CameraSettingNoMeta {
+                flashlight_enabled: true,
+                resolution: Resolution::Vga,
+                framerate: 10,
+            }),
+            r#"{"SettingChanged":{"flashlight_enabled":true,"resolution":"VGA","framerate":10}}"#,
+        );
+        round_trip(&CameraMessage::Restart, r#""Restart""#);
+    }
+}
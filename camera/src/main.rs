@@ -32,7 +32,7 @@ use esp_idf_svc::{
     },
 };
 use log::{error, info};
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 
 // TODO: Change import usage for easier reading
 // TODO: Display possible networks to connect to
@@ -57,6 +57,7 @@ const PREFERENCES_NAMESPACE: &str = "preferences";
 const PREFERENCES_KEY_SSID: &str = "ssid";
 const PREFERENCES_KEY_PASS: &str = "pass";
 const PREFERENCES_KEY_OKO: &str = "oko";
+const PREFERENCES_KEY_TOKEN: &str = "token";
 
 const CAMERA_SETTINGS_NAMESPACE: &str = "cam_settings";
 const CAMERA_SETTINGS_KEY_FLASHLIGHT_ENABLED: &str = "flash_enabled";
@@ -77,7 +78,7 @@ const AP_MAX_PAYLOAD_LEN: u64 = 256; // ! This might be too low, some browsers s
 
 const WS_TIMEOUT: Duration = Duration::from_secs(10);
 
-const CAMERA_ANY_PORT_INDICATOR_TEXT: &str = "camera_any_port";
+const PROTOCOL_VERSION: u32 = 1;
 const CAMERA_DEFAULT_XCLK_FREQ: i32 = 8 * 1_000_000;
 const CAMERA_DEFAULT_JPG_QUALITY: i32 = 12;
 const CAMERA_DEFAULT_FB_COUNT: usize = 2;
@@ -100,17 +101,39 @@ pub enum CameraMessage {
     Restart,
 }
 
+// TODO: Use single shared definition for both camera and backend
+#[derive(Serialize, Debug)]
+struct Hello<'a> {
+    role: &'a str,
+    protocol_version: u32,
+    firmware_version: &'a str,
+    supported_resolutions: &'a [&'a str],
+    token: &'a str,
+    any_port: bool,
+}
+
+// TODO: Use single shared definition for both camera and backend
+#[derive(Deserialize, Debug)]
+struct HelloReply {
+    protocol_version: u32,
+    camera_id: Option<i64>,
+    settings: Option<CameraSettingNoMeta>,
+}
+
 #[derive(Deserialize, Debug)]
 struct SetupFormData {
     ssid: String,
     pass: String,
     oko: String,
+    /// Pairing token, only set by `/mdns_connect`
+    #[serde(default)]
+    token: String,
 }
 
-// TODO: Require browser to send randomly generated passcode that will be used as auth for later
 #[derive(Deserialize, Debug)]
 struct MdnsFormData {
     oko: String,
+    token: String,
 }
 
 #[allow(clippy::too_many_lines)] // TODO: Split into smaller functions
@@ -403,11 +426,8 @@ fn start_http_server(
                 let mut buf = vec![0; len.try_into()?];
                 request.read_exact(&mut buf)?;
 
-                info!(
-                    "Received mdns_connect form data (length: {}): {:?}",
-                    len,
-                    String::from_utf8(buf.clone())?
-                );
+                // Not logging the payload, it contains the pairing token
+                info!("Received mdns_connect form data (length: {})", len);
 
                 let form = serde_urlencoded::from_bytes::<MdnsFormData>(&buf)?;
                 info!("Mdns form details: Oko: {}", form.oko);
@@ -415,7 +435,10 @@ fn start_http_server(
                 validate_oko_ip(&form.oko)?;
                 info!("Oko IP is valid");
 
-                save_oko_ip(&nvs_default_partition_clone, &form.oko)?;
+                validate_pairing_token(&form.token)?;
+                info!("Pairing token is valid");
+
+                save_mdns_connect_details(&nvs_default_partition_clone, &form)?;
 
                 let mut response = request.into_ok_response()?;
                 response.write_all(b"restarting")?;
@@ -568,6 +591,19 @@ fn validate_oko_ip(oko_str: &str) -> anyhow::Result<()> {
     Ok(())
 }
 
+// Pairing token e.g. 64 hex characters, generated by Oko when the camera is added
+fn validate_pairing_token(token: &str) -> anyhow::Result<()> {
+    if !(1..=NVS_MAX_STR_LEN).contains(&token.len()) {
+        bail!("Pairing token length is invalid");
+    }
+
+    if !token.chars().all(|c| c.is_ascii_hexdigit()) {
+        bail!("Pairing token contains non-hex characters");
+    }
+
+    Ok(())
+}
+
 fn validate_form_data(form: &SetupFormData) -> anyhow::Result<()> {
     // ? Maybe use <String>.chars().count() instead of .len()
     // https://paginas.fe.up.pt/~jaime/0506/SSR/802.11i-2004.pdf
@@ -597,11 +633,13 @@ fn get_setup_details(
     let mut ssid_buffer: [u8; NVS_MAX_STR_LEN] = [0; NVS_MAX_STR_LEN];
     let mut pass_buffer: [u8; NVS_MAX_STR_LEN] = [0; NVS_MAX_STR_LEN];
     let mut oko_buffer: [u8; NVS_MAX_STR_LEN] = [0; NVS_MAX_STR_LEN];
+    let mut token_buffer: [u8; NVS_MAX_STR_LEN] = [0; NVS_MAX_STR_LEN];
 
     info!("Getting raw setup detail data");
     nvs.get_raw(PREFERENCES_KEY_SSID, &mut ssid_buffer)?;
     nvs.get_raw(PREFERENCES_KEY_PASS, &mut pass_buffer)?;
     nvs.get_raw(PREFERENCES_KEY_OKO, &mut oko_buffer)?;
+    nvs.get_raw(PREFERENCES_KEY_TOKEN, &mut token_buffer)?;
 
     info!("Converting raw setup data to strings");
     let ssid = std::str::from_utf8(&ssid_buffer)?
@@ -613,23 +651,28 @@ fn get_setup_details(
     let oko = std::str::from_utf8(&oko_buffer)?
         .trim()
         .trim_matches(char::from(0));
+    let token = std::str::from_utf8(&token_buffer)?
+        .trim()
+        .trim_matches(char::from(0));
 
     Ok(SetupFormData {
         ssid: ssid.to_string(),
         pass: pass.to_string(),
         oko: oko.to_string(),
+        token: token.to_string(),
     })
 }
 
-fn save_oko_ip(
+fn save_mdns_connect_details(
     nvs_default_partition: &EspNvsPartition<NvsDefault>,
-    oko_ip: &str,
+    form: &MdnsFormData,
 ) -> anyhow::Result<()> {
-    info!("Saving Oko IP details");
+    info!("Saving Oko IP and pairing token details");
     let mut nvs = EspNvs::new(nvs_default_partition.clone(), PREFERENCES_NAMESPACE, true)?;
 
-    info!("Setting raw Oko ip data");
-    nvs.set_raw(PREFERENCES_KEY_OKO, oko_ip.trim().as_bytes())?;
+    info!("Setting raw Oko ip and pairing token data");
+    nvs.set_raw(PREFERENCES_KEY_OKO, form.oko.trim().as_bytes())?;
+    nvs.set_raw(PREFERENCES_KEY_TOKEN, form.token.trim().as_bytes())?;
 
     Ok(())
 }
@@ -659,6 +702,7 @@ fn clear_setup_details(nvs_default_partition: &EspNvsPartition<NvsDefault>) -> a
     nvs.set_raw(PREFERENCES_KEY_SSID, &empty)?;
     nvs.set_raw(PREFERENCES_KEY_PASS, &empty)?;
     nvs.set_raw(PREFERENCES_KEY_OKO, &empty)?;
+    nvs.set_raw(PREFERENCES_KEY_TOKEN, &empty)?;
 
     Ok(())
 }
@@ -907,10 +951,19 @@ fn websocket_client_task(
             std::thread::sleep(Duration::from_millis(100));
         }
 
-        info!("Sending camera indicator WebSocket message");
+        let hello = Hello {
+            role: "camera",
+            protocol_version: PROTOCOL_VERSION,
+            firmware_version: env!("CARGO_PKG_VERSION"),
+            supported_resolutions: &VALID_RESOLUTIONS,
+            token: &form.token,
+            any_port: true,
+        };
+
+        info!("Sending hello WebSocket message");
         ws_client.send(
             FrameType::Text(false),
-            CAMERA_ANY_PORT_INDICATOR_TEXT.as_bytes(),
+            serde_json::to_string(&hello)?.as_bytes(),
         )?;
 
         // TODO: Lower interval based on average time taken to capture, or maybe use a more accurate timer on a separate thread and a channel?
@@ -957,7 +1010,7 @@ fn handle_event(
     if let WebSocketEventType::Text(text) = ev.event_type {
         // TODO: look into bincode (fastest?) / rmp-serde (wide support) / flatbuffers (partial deserialization)
         let Ok(camera_message) = serde_json::from_str::<CameraMessage>(text) else {
-            info!("Failed to parse WebSocket text event");
+            handle_hello_reply(lamp_pin, nvs_default_partition, text);
             return;
         };
 
@@ -984,3 +1037,30 @@ fn handle_event(
         }
     }
 }
+
+/// The server answers the hello with the settings it expects the camera to use
+fn handle_hello_reply(
+    lamp_pin: &Arc<Mutex<PinDriver<'static, gpio::Gpio4, gpio::Output>>>, // TODO: Use a more generic type
+    nvs_default_partition: &EspNvsPartition<NvsDefault>,
+    text: &str,
+) {
+    let Ok(hello_reply) = serde_json::from_str::<HelloReply>(text) else {
+        info!("Failed to parse WebSocket text event");
+        return;
+    };
+
+    info!(
+        "Received WebSocket hello reply, protocol version: {}, camera ID: {:?}",
+        hello_reply.protocol_version, hello_reply.camera_id
+    );
+
+    if let Some(ref setting) = hello_reply.settings {
+        apply_camera_settings(lamp_pin, setting).unwrap_or_else(|e| {
+            error!("Failed to apply camera settings: {:#?}", e);
+        });
+
+        save_camera_settings(nvs_default_partition, setting).unwrap_or_else(|e| {
+            error!("Failed to save camera settings: {:#?}", e);
+        });
+    }
+}
@@ -1,8 +1,20 @@
+export type Role = "admin" | "operator" | "viewer";
+
 export type User = {
   user_id: number;
   username: string;
   password_hash: string;
   created_at: Array<number>;
+  role: Role;
+};
+
+// Keep in sync with `PROTOCOL_VERSION` in the backend
+export const PROTOCOL_VERSION = 1;
+
+export type Hello = {
+  role: "camera" | "viewer";
+  protocol_version: number;
+  binary_frames?: boolean;
 };
 
 export type MdnsCamera = {
@@ -24,6 +36,8 @@ export type Camera = {
   camera_id: number;
   camera_name: string;
   ip_address: string;
+  last_connected: Array<number> | null;
+  is_online: boolean;
   can_control: boolean;
   can_view: boolean;
 };
@@ -39,6 +53,16 @@ export type VideoCameraView = {
   camera_name: string;
   file_path: string;
   file_size: number;
+  keep_forever: boolean;
+  format: VideoFormat;
+  poster_path: string | null;
+};
+
+export type VideoThumbnail = {
+  thumbnail_id: number;
+  video_id: number;
+  file_path: string;
+  offset_seconds: number;
 };
 
 export type CameraPermission = {
@@ -58,24 +82,47 @@ export type CameraSetting = {
   modified_by: number;
   resolution: string;
   setting_id: number;
+  recording_mode: RecordingMode;
+  motion_sensitivity: number;
+  motion_pre_roll_seconds: number;
+  motion_post_roll_seconds: number;
+  segment_length_seconds: number;
+  max_video_age_days: number | null;
+  recording_format: VideoFormat;
 };
 
+export type RecordingMode = "continuous" | "motion";
+
+export type VideoFormat = "mp4" | "mjpeg" | "mpeg4";
+
 export type ImageContainer = {
   camera_id: number;
   timestamp: number;
-  image_bytes: Array<number>;
+  sequence: number;
+  image_bytes: Uint8Array;
 };
 
-export function isImageContainer(obj: unknown): obj is ImageContainer {
-  return (
-    obj instanceof Object &&
-    "camera_id" in obj &&
-    "timestamp" in obj &&
-    "image_bytes" in obj &&
-    typeof obj.camera_id === "number" &&
-    typeof obj.timestamp === "number" &&
-    Array.isArray(obj.image_bytes)
-  );
+// Keep in sync with `FrameHeader` in the backend
+const FRAME_HEADER_VERSION = 1;
+const FRAME_HEADER_LEN = 25;
+
+/** Parses a binary WebSocket message, `null` if it isn't an image frame */
+export function parseImageFrame(data: unknown): ImageContainer | null {
+  if (!(data instanceof ArrayBuffer) || data.byteLength < FRAME_HEADER_LEN) {
+    return null;
+  }
+
+  const view = new DataView(data);
+  if (view.getUint8(0) !== FRAME_HEADER_VERSION) {
+    return null;
+  }
+
+  return {
+    camera_id: Number(view.getBigInt64(1)),
+    timestamp: Number(view.getBigInt64(9)),
+    sequence: Number(view.getBigUint64(17)),
+    image_bytes: new Uint8Array(data, FRAME_HEADER_LEN),
+  };
 }
 
 export type CameraListChangeType = "Added" | "Removed" | "Updated";
@@ -105,3 +152,34 @@ export function isCameraListChange(obj: unknown): obj is CameraListChange {
         typeof obj.Updated.camera_id === "number"))
   );
 }
+
+export type EventKind =
+  | "motion"
+  | "camera_offline"
+  | "camera_online"
+  | "login_failed";
+
+export type OkoEvent = {
+  event_id: number;
+  camera_id: number | null;
+  kind: EventKind;
+  start_time: Array<number>;
+  end_time: Array<number> | null;
+  video_id: number | null;
+  snapshot_path: string | null;
+  details: string | null;
+  acknowledged: boolean;
+};
+
+export type EventMessage = {
+  EventCreated?: OkoEvent;
+  EventUpdated?: OkoEvent;
+};
+
+export function isEventMessage(obj: unknown): obj is EventMessage {
+  return (
+    obj instanceof Object &&
+    (("EventCreated" in obj && obj.EventCreated instanceof Object) ||
+      ("EventUpdated" in obj && obj.EventUpdated instanceof Object))
+  );
+}
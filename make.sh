@@ -32,7 +32,7 @@ case "$1" in
         cd ../$BACKEND_DIR
         mkdir -p static
         cp -r ../$FRONTEND_DIR/dist/* ./static
-        cargo test
+        cargo test --workspace
         ;;
     "coverage")
         cd $BACKEND_DIR
@@ -50,15 +50,15 @@ case "$1" in
         ;;
     "cam")
         cd $BACKEND_DIR
-        cargo run -p camera-impersonator $2 $3 $4
+        cargo run -p camera-impersonator $2 $3 $4 $5
         ;;
     "cam1")
         cd $BACKEND_DIR
-        cargo run -p camera-impersonator 80 40000 ./videos/1.mp4
+        cargo run -p camera-impersonator 80 40000 ./videos/1.mp4 front_door_pairing_token
         ;;
     "cam2")
         cd $BACKEND_DIR
-        cargo run -p camera-impersonator 80 40001 ./videos/2.mp4
+        cargo run -p camera-impersonator 80 40001 ./videos/2.mp4 kitchen_pairing_token
         ;;
     "dry_pub")
         cd $BACKEND_DIR